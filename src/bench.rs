@@ -0,0 +1,246 @@
+use crate::cli::BenchServeArgs;
+use crate::config::Config;
+use crate::control::local_url;
+use crate::services::request_stats::percentile;
+use colored::*;
+use futures_util::StreamExt;
+use reqwest::Client;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 一次请求的结果
+#[derive(Debug, Clone)]
+struct Sample {
+    /// 到收到第一个响应体字节为止的时间
+    ttfb: Duration,
+    /// 到读完响应体为止的时间
+    latency: Duration,
+    /// 失败原因，成功时为None
+    error: Option<String>,
+}
+
+/// 压测报告，TTFB和延迟只统计成功的请求
+#[derive(Debug, PartialEq)]
+struct BenchReport {
+    requests: usize,
+    succeeded: usize,
+    elapsed: Duration,
+    ttfb_p50_ms: f64,
+    ttfb_p95_ms: f64,
+    latency_p50_ms: f64,
+    latency_p95_ms: f64,
+    latency_max_ms: f64,
+    errors: BTreeMap<String, usize>,
+}
+
+impl BenchReport {
+    fn new(samples: &[Sample], elapsed: Duration) -> Self {
+        let succeeded: Vec<&Sample> = samples.iter().filter(|sample| sample.error.is_none()).collect();
+        let sorted = |value: fn(&Sample) -> Duration| {
+            let mut micros: Vec<u64> = succeeded.iter().map(|sample| value(sample).as_micros() as u64).collect();
+            micros.sort_unstable();
+            micros
+        };
+        let ttfb = sorted(|sample| sample.ttfb);
+        let latency = sorted(|sample| sample.latency);
+        let ms = |sorted: &[u64], p: f64| if sorted.is_empty() { 0.0 } else { percentile(sorted, p) as f64 / 1000.0 };
+
+        let mut errors = BTreeMap::new();
+        for error in samples.iter().filter_map(|sample| sample.error.clone()) {
+            *errors.entry(error).or_insert(0) += 1;
+        }
+        Self {
+            requests: samples.len(),
+            succeeded: succeeded.len(),
+            elapsed,
+            ttfb_p50_ms: ms(&ttfb, 50.0),
+            ttfb_p95_ms: ms(&ttfb, 95.0),
+            latency_p50_ms: ms(&latency, 50.0),
+            latency_p95_ms: ms(&latency, 95.0),
+            latency_max_ms: ms(&latency, 100.0),
+            errors,
+        }
+    }
+
+    /// 每秒完成的成功请求数
+    fn throughput(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.succeeded as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    fn print(&self) {
+        let failed = self.requests - self.succeeded;
+        let summary = format!(
+            "完成 {} 个请求：成功 {} 个，失败 {} 个，耗时 {:.2} 秒",
+            self.requests,
+            self.succeeded,
+            failed,
+            self.elapsed.as_secs_f64()
+        );
+        if failed == 0 {
+            println!("{}", summary.bright_green().bold());
+        } else {
+            println!("{}", summary.bright_red().bold());
+        }
+        println!("吞吐量: {:.2} 请求/秒", self.throughput());
+        println!("TTFB:   p50 {:.1} ms, p95 {:.1} ms", self.ttfb_p50_ms, self.ttfb_p95_ms);
+        println!(
+            "延迟:   p50 {:.1} ms, p95 {:.1} ms, 最大 {:.1} ms",
+            self.latency_p50_ms, self.latency_p95_ms, self.latency_max_ms
+        );
+        for (error, count) in &self.errors {
+            println!("{} {} × {}", "[FAIL]".bright_red().bold(), error, count);
+        }
+    }
+}
+
+/// 向运行中的实例发送合成聊天请求并打印报告，全部成功时返回true
+pub async fn bench_serve(args: &BenchServeArgs) -> anyhow::Result<bool> {
+    let local = args.control.url.is_none();
+    let base_url = match &args.control.url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => local_url(&Config::load()?),
+    };
+    let client = Client::builder()
+        .timeout(Duration::from_secs(args.timeout))
+        // 本机实例常用自签名证书
+        .danger_accept_invalid_certs(local)
+        .build()?;
+
+    match args.duration {
+        Some(secs) => println!("压测 {}：并发 {}，持续 {} 秒，模型 {}", base_url, args.concurrency, secs, args.model),
+        None => println!("压测 {}：并发 {}，共 {} 个请求，模型 {}", base_url, args.concurrency, args.requests, args.model),
+    }
+    let report = run(&client, &base_url, args).await;
+    report.print();
+    Ok(report.succeeded == report.requests)
+}
+
+/// 启动concurrency个工作任务，各自循环领取序号发送请求，直到请求数用完或到达持续时间
+async fn run(client: &Client, base_url: &str, args: &BenchServeArgs) -> BenchReport {
+    let next = Arc::new(AtomicU64::new(0));
+    let started_at = Instant::now();
+    let deadline = args.duration.map(|secs| started_at + Duration::from_secs(secs));
+    let limit = if deadline.is_some() { u64::MAX } else { args.requests };
+
+    let workers: Vec<_> = (0..args.concurrency)
+        .map(|_| {
+            let (client, next) = (client.clone(), next.clone());
+            let url = format!("{}/v1/chat/completions", base_url);
+            let (api_key, model, prompt, stream) = (args.api_key.clone(), args.model.clone(), args.prompt.clone(), args.stream);
+            tokio::spawn(async move {
+                let mut samples = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= limit || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return samples;
+                    }
+                    let body = json!({
+                        "model": model,
+                        "messages": [{"role": "user", "content": format!("{} #{}", prompt, index)}],
+                        "stream": stream,
+                    });
+                    samples.push(send(&client, &url, &api_key, &body).await);
+                }
+            })
+        })
+        .collect();
+
+    let mut samples = Vec::new();
+    for worker in workers {
+        samples.extend(worker.await.unwrap_or_default());
+    }
+    BenchReport::new(&samples, started_at.elapsed())
+}
+
+async fn send(client: &Client, url: &str, api_key: &str, body: &serde_json::Value) -> Sample {
+    let started_at = Instant::now();
+    let failed = |error: String| Sample {
+        ttfb: started_at.elapsed(),
+        latency: started_at.elapsed(),
+        error: Some(error),
+    };
+
+    let response = match client.post(url).bearer_auth(api_key).json(body).send().await {
+        Ok(response) => response,
+        Err(e) if e.is_timeout() => return failed("请求超时".to_string()),
+        Err(e) if e.is_connect() => return failed("连接失败".to_string()),
+        Err(e) => return failed(e.to_string()),
+    };
+    let status = response.status();
+    let mut chunks = response.bytes_stream();
+    let mut ttfb = None;
+    while let Some(chunk) = chunks.next().await {
+        if let Err(e) = chunk {
+            return failed(if e.is_timeout() { "读取响应超时".to_string() } else { format!("读取响应失败: {}", e) });
+        }
+        ttfb.get_or_insert_with(|| started_at.elapsed());
+    }
+
+    let latency = started_at.elapsed();
+    Sample {
+        ttfb: ttfb.unwrap_or(latency),
+        latency,
+        error: (!status.is_success()).then(|| format!("HTTP {}", status.as_u16())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::ControlArgs;
+    use crate::TestServer;
+
+    #[test]
+    fn test_report() {
+        let sample = |ms: u64, error: Option<&str>| Sample {
+            ttfb: Duration::from_millis(ms / 2),
+            latency: Duration::from_millis(ms),
+            error: error.map(str::to_string),
+        };
+        let mut samples: Vec<Sample> = (1..=20).map(|i| sample(i * 10, None)).collect();
+        samples.push(sample(5, Some("HTTP 429")));
+        samples.push(sample(5, Some("HTTP 429")));
+
+        let report = BenchReport::new(&samples, Duration::from_secs(4));
+        assert_eq!(report.requests, 22);
+        assert_eq!(report.succeeded, 20);
+        assert_eq!(report.throughput(), 5.0);
+        assert_eq!(report.latency_p50_ms, 100.0);
+        assert_eq!(report.latency_p95_ms, 190.0);
+        assert_eq!(report.latency_max_ms, 200.0);
+        assert_eq!(report.ttfb_p95_ms, 95.0);
+        assert_eq!(report.errors.get("HTTP 429"), Some(&2));
+    }
+
+    #[tokio::test]
+    async fn test_bench_against_mock() {
+        // 只有一个测试账号，放开单账号的并发和消息速率限制
+        let mut config = Config::default();
+        config.pool.account_max_concurrency = 3;
+        config.pool.account_messages_per_hour = 0;
+        let server = TestServer::with_config(config).await.unwrap();
+        let args = BenchServeArgs {
+            control: ControlArgs { url: Some(server.base_url().to_string()) },
+            api_key: server.create_api_key().await.unwrap(),
+            concurrency: 3,
+            requests: 10,
+            duration: None,
+            model: "deepseek".to_string(),
+            prompt: "hi".to_string(),
+            stream: true,
+            timeout: 30,
+        };
+
+        let report = run(&Client::new(), server.base_url(), &args).await;
+        assert_eq!(report.requests, 10);
+        assert_eq!(report.succeeded, 10, "{:?}", report.errors);
+        assert!(report.latency_p95_ms >= report.ttfb_p50_ms);
+        server.stop().await;
+    }
+}
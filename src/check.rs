@@ -0,0 +1,134 @@
+use crate::cli::CheckConfigArgs;
+use crate::config::{Config, HttpClientConfig};
+use crate::services::{ContentFilter, CredentialVault, PluginHost, ScriptHooks, SecretStore};
+use crate::tls;
+use colored::*;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// 单项检查结果
+struct CheckReport {
+    failures: usize,
+}
+
+impl CheckReport {
+    fn record(&mut self, name: &str, result: Result<String, String>) {
+        match result {
+            Ok(detail) => println!("{} {}: {}", "[PASS]".bright_green().bold(), name, detail),
+            Err(detail) => {
+                self.failures += 1;
+                println!("{} {}: {}", "[FAIL]".bright_red().bold(), name, detail);
+            }
+        }
+    }
+}
+
+/// 执行配置检查并打印报告，全部通过时返回true
+pub async fn check_config(args: &CheckConfigArgs) -> bool {
+    let mut report = CheckReport { failures: 0 };
+
+    let config = match Config::load() {
+        Ok(config) => {
+            report.record("配置", Ok("环境变量解析和取值校验通过".to_string()));
+            config
+        }
+        Err(e) => {
+            report.record("配置", Err(e.to_string()));
+            return false;
+        }
+    };
+
+    report.record("WASM文件", check_wasm_file(&config.deepseek.wasm_path));
+    report.record("API密钥存储", check_writable(&config.storage.api_keys_path));
+    if config.server.is_tls_enabled() {
+        let result = tls::build_server_config(&config.server)
+            .map(|_| format!("{} 可用", config.server.tls_cert_path.as_deref().unwrap_or_default()))
+            .map_err(|e| format!("{:#}", e));
+        report.record("TLS证书", result);
+    }
+    if config.credentials.master_key.is_some() || config.credentials.master_key_file.is_some() {
+        let result = CredentialVault::open(&config.credentials)
+            .map(|_| format!("{} 已解锁", config.credentials.path))
+            .map_err(|e| e.to_string());
+        report.record("凭据库", result);
+    }
+    if let Some(path) = &config.content_filter.rules_path {
+        let result = ContentFilter::load(&config.content_filter)
+            .map(|_| format!("{} 规则有效", path))
+            .map_err(|e| e.to_string());
+        report.record("内容过滤", result);
+    }
+    if !config.plugins.paths.is_empty() {
+        let result = PluginHost::load(&config.plugins)
+            .map(|_| format!("{} 个插件已加载", config.plugins.paths.len()))
+            .map_err(|e| e.to_string());
+        report.record("WASM插件", result);
+    }
+    if let Some(path) = &config.scripts.path {
+        let result = ScriptHooks::load(&config.scripts)
+            .map(|_| format!("{} 编译通过", path))
+            .map_err(|e| e.to_string());
+        report.record("钩子脚本", result);
+    }
+
+    if let Some(auth) = &config.deepseek.authorization {
+        let secrets = SecretStore::new(config.secrets.clone());
+        let result = secrets
+            .resolve(auth)
+            .await
+            .map(|_| "DEEP_SEEK_CHAT_AUTHORIZATION 可用".to_string())
+            .map_err(|e| e.to_string());
+        report.record("外部密钥", result);
+    }
+
+    if args.ping {
+        report.record("DeepSeek连通性", ping(&config.deepseek.base_url, &config.http).await);
+    }
+
+    if report.failures == 0 {
+        println!("{}", "配置检查通过".bright_green().bold());
+    } else {
+        println!("{}", format!("{} 项检查未通过", report.failures).bright_red().bold());
+    }
+
+    report.failures == 0
+}
+
+/// 检查WASM文件存在且格式正确
+pub(crate) fn check_wasm_file(path: &str) -> Result<String, String> {
+    let bytes = fs::read(path).map_err(|e| format!("无法读取 {}: {}", path, e))?;
+    if !bytes.starts_with(b"\0asm") {
+        return Err(format!("{} 不是有效的WASM文件", path));
+    }
+    Ok(format!("{} ({} bytes)", path, bytes.len()))
+}
+
+/// 检查存储文件所在目录可写
+pub(crate) fn check_writable(path: &str) -> Result<String, String> {
+    if path.is_empty() {
+        return Ok("未设置，只保存在内存中".to_string());
+    }
+    let dir = Path::new(path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(dir).map_err(|e| format!("无法创建目录 {}: {}", dir.display(), e))?;
+
+    let probe = dir.join(".check-config-write-test");
+    fs::write(&probe, b"ok").map_err(|e| format!("目录 {} 不可写: {}", dir.display(), e))?;
+    let _ = fs::remove_file(&probe);
+
+    Ok(format!("{} 可写", dir.display()))
+}
+
+/// 检查能否访问DeepSeek
+async fn ping(base_url: &str, http: &HttpClientConfig) -> Result<String, String> {
+    let client = http
+        .with_network(reqwest::Client::builder().timeout(Duration::from_secs(10)))
+        .and_then(|builder| builder.build())
+        .map_err(|e| e.to_string())?;
+
+    let response = client.get(base_url).send().await.map_err(|e| e.to_string())?;
+    Ok(format!("{} 返回 {}", base_url, response.status()))
+}
@@ -0,0 +1,240 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+/// DeepSeek Free API Server 命令行
+#[derive(Debug, Parser)]
+#[command(name = "deepseek-free-api", version, about = "DeepSeek Free API Server written in Rust")]
+pub struct Cli {
+    /// 配置文件路径（dotenv格式），默认读取当前目录下的 .env
+    #[arg(short, long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// 日志级别或过滤规则，例如 info、debug、deepseek_free_api=trace
+    #[arg(long, global = true, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+
+    /// 日志格式，覆盖 LOG_FORMAT；json适合Loki/ELK采集
+    #[arg(long, global = true, value_enum, value_name = "FORMAT")]
+    pub log_format: Option<LogFormat>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// 彩色文本，便于人工阅读
+    #[default]
+    Text,
+    /// 每行一个JSON对象，包含请求span中的字段
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// 启动HTTP服务（默认）
+    Serve(ServeArgs),
+    /// 检查配置、WASM文件和存储路径，不启动服务
+    CheckConfig(CheckConfigArgs),
+    /// 从CSV或JSONL文件批量登录账号并关联到API密钥
+    ImportAccounts(ImportAccountsArgs),
+    /// 读取审计日志，按API密钥、模型和日期汇总用量，用于离线计费
+    ExportUsage(ExportUsageArgs),
+    /// 查询运行中实例的就绪状态
+    Status(StatusArgs),
+    /// 让运行中的实例优雅退出
+    Stop(StopArgs),
+    /// 向运行中的实例并发发送聊天请求，报告吞吐量、TTFB和延迟百分位
+    BenchServe(BenchServeArgs),
+    /// 对运行中的实例执行OpenAI兼容性检查，逐项报告通过和失败
+    CompatCheck(CompatCheckArgs),
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ServeArgs {
+    /// 监听地址，覆盖 HOST
+    #[arg(long)]
+    pub host: Option<String>,
+
+    /// 监听端口，覆盖 PORT
+    #[arg(short, long)]
+    pub port: Option<u16>,
+
+    /// PID文件路径，覆盖 PID_FILE
+    #[arg(long, value_name = "PATH")]
+    pub pid_file: Option<String>,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct CheckConfigArgs {
+    /// 同时检查能否连接DeepSeek
+    #[arg(long)]
+    pub ping: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportAccountsArgs {
+    /// 账号文件：每行 `email,password` 或一个userToken；也可以是JSON行 {"email","password"} / {"token"}
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// 账号关联到的API密钥
+    #[arg(long, value_name = "KEY")]
+    pub api_key: String,
+
+    /// 同时进行的登录数
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..=32))]
+    pub concurrency: u16,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ControlArgs {
+    /// 实例地址，默认按 HOST/PORT 和TLS配置连接本机
+    #[arg(long, value_name = "URL")]
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct StatusArgs {
+    #[command(flatten)]
+    pub control: ControlArgs,
+
+    /// 管理令牌，默认使用 ADMIN_SECRET 或 ADMIN_TOKENS 中的第一个
+    #[arg(long, value_name = "TOKEN")]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct StopArgs {
+    #[command(flatten)]
+    pub control: ControlArgs,
+
+    /// 管理令牌，默认使用 ADMIN_SECRET 或 ADMIN_TOKENS 中的第一个
+    #[arg(long, value_name = "TOKEN")]
+    pub token: Option<String>,
+
+    /// 等待实例退出的最长秒数，0表示发出请求后立即返回
+    #[arg(long, default_value_t = 0, value_name = "SECS")]
+    pub wait: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct BenchServeArgs {
+    #[command(flatten)]
+    pub control: ControlArgs,
+
+    /// 发送请求使用的API密钥
+    #[arg(long, value_name = "KEY")]
+    pub api_key: String,
+
+    /// 同时进行的请求数
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    pub concurrency: u16,
+
+    /// 请求总数
+    #[arg(short = 'n', long, default_value_t = 100)]
+    pub requests: u64,
+
+    /// 持续发送的秒数，指定后忽略 --requests
+    #[arg(long, value_name = "SECS")]
+    pub duration: Option<u64>,
+
+    /// 请求的模型
+    #[arg(long, default_value = "deepseek")]
+    pub model: String,
+
+    /// 用户消息，每个请求末尾附加序号以避开响应缓存
+    #[arg(long, default_value = "你好")]
+    pub prompt: String,
+
+    /// 使用流式响应
+    #[arg(long)]
+    pub stream: bool,
+
+    /// 单个请求的超时秒数
+    #[arg(long, default_value_t = 120, value_name = "SECS")]
+    pub timeout: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct CompatCheckArgs {
+    /// 实例地址，可以带 /v1 后缀，如 http://localhost:3000/v1
+    #[arg(value_name = "BASE_URL")]
+    pub base_url: String,
+
+    /// 发送请求使用的API密钥
+    #[arg(value_name = "KEY")]
+    pub api_key: String,
+
+    /// 聊天请求使用的模型
+    #[arg(long, default_value = "deepseek")]
+    pub model: String,
+
+    /// 单个请求的超时秒数
+    #[arg(long, default_value_t = 120, value_name = "SECS")]
+    pub timeout: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportUsageArgs {
+    /// 审计日志文件，默认使用 AUDIT_LOG_PATH
+    #[arg(long, value_name = "PATH")]
+    pub input: Option<PathBuf>,
+
+    /// 汇总维度，逗号分隔
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "key,model,day")]
+    pub by: Vec<UsageDimension>,
+
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = UsageFormat::Csv)]
+    pub format: UsageFormat,
+
+    /// 输出文件，默认输出到标准输出
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// 只统计该日期（UTC，YYYY-MM-DD）及之后的记录
+    #[arg(long, value_name = "DATE")]
+    pub since: Option<chrono::NaiveDate>,
+
+    /// 只统计该日期（UTC，YYYY-MM-DD）及之前的记录
+    #[arg(long, value_name = "DATE")]
+    pub until: Option<chrono::NaiveDate>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UsageDimension {
+    /// API密钥ID
+    Key,
+    /// 请求的模型
+    Model,
+    /// 日期（UTC）
+    Day,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UsageFormat {
+    Csv,
+    Json,
+}
+
+impl Cli {
+    /// 未指定子命令时默认启动服务
+    pub fn command(&mut self) -> Command {
+        self.command
+            .take()
+            .unwrap_or_else(|| Command::Serve(ServeArgs::default()))
+    }
+
+    /// 日志格式，命令行参数优先于 LOG_FORMAT 环境变量
+    pub fn log_format(&self) -> anyhow::Result<LogFormat> {
+        if let Some(format) = self.log_format {
+            return Ok(format);
+        }
+        match std::env::var("LOG_FORMAT") {
+            Ok(value) => LogFormat::from_str(&value, true)
+                .map_err(|_| anyhow::anyhow!("Invalid LOG_FORMAT={:?}, expected text or json", value)),
+            Err(_) => Ok(LogFormat::default()),
+        }
+    }
+}
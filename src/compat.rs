@@ -0,0 +1,274 @@
+use crate::cli::CompatCheckArgs;
+use colored::*;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// 检查结果，可选检查失败时只给出警告
+#[derive(Debug)]
+struct CheckResult {
+    name: &'static str,
+    optional: bool,
+    result: Result<(), String>,
+}
+
+/// 对一个实例执行检查所需的连接信息
+struct Suite {
+    client: Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+}
+
+impl Suite {
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn get(&self, path: &str) -> Result<(StatusCode, Value), String> {
+        let response = self
+            .client
+            .get(self.url(path))
+            .bearer_auth(&self.api_key)
+            .send()
+            .await
+            .map_err(|e| format!("请求失败: {}", e))?;
+        let status = response.status();
+        let body = response.json().await.map_err(|e| format!("HTTP {}，响应不是JSON: {}", status, e))?;
+        Ok((status, body))
+    }
+
+    /// 发送非流式聊天请求，body中未给出的model和messages使用默认值
+    async fn chat(&self, api_key: &str, mut body: Value) -> Result<(StatusCode, Value), String> {
+        if body.get("model").is_none() {
+            body["model"] = json!(self.model);
+        }
+        if body.get("messages").is_none() {
+            body["messages"] = json!([{"role": "user", "content": "Reply with one short sentence."}]);
+        }
+        let response = self
+            .client
+            .post(self.url("/v1/chat/completions"))
+            .bearer_auth(api_key)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("请求失败: {}", e))?;
+        let status = response.status();
+        let body = response.json().await.map_err(|e| format!("HTTP {}，响应不是JSON: {}", status, e))?;
+        Ok((status, body))
+    }
+
+    /// 成功的非流式聊天请求，返回响应体
+    async fn completion(&self, body: Value) -> Result<Value, String> {
+        match self.chat(&self.api_key, body).await? {
+            (status, body) if status.is_success() => Ok(body),
+            (status, body) => Err(format!("HTTP {}: {}", status, body)),
+        }
+    }
+
+    async fn models(&self) -> Result<(), String> {
+        let (status, body) = self.get("/v1/models").await?;
+        expect(status == StatusCode::OK, format!("期望HTTP 200，实际为 {}", status))?;
+        expect(body["object"] == "list", "object不是list")?;
+        let data = body["data"].as_array().filter(|data| !data.is_empty()).ok_or("data不是非空数组")?;
+        for model in data {
+            expect(model["id"].is_string() && model["object"] == "model", format!("模型对象缺少id或object: {}", model))?;
+        }
+        Ok(())
+    }
+
+    async fn retrieve_model(&self) -> Result<(), String> {
+        let (status, body) = self.get(&format!("/v1/models/{}", self.model)).await?;
+        expect(status == StatusCode::OK, format!("期望HTTP 200，实际为 {}", status))?;
+        expect(body["id"] == self.model.as_str(), format!("id与请求的模型不一致: {}", body["id"]))
+    }
+
+    async fn chat_completion(&self) -> Result<(), String> {
+        let body = self.completion(json!({})).await?;
+        expect(body["object"] == "chat.completion", "object不是chat.completion")?;
+        expect(body["id"].is_string() && body["created"].is_u64() && body["model"].is_string(), "缺少id、created或model")?;
+        let choice = &body["choices"][0];
+        expect(choice["message"]["role"] == "assistant", "message.role不是assistant")?;
+        expect(choice["message"]["content"].as_str().is_some_and(|content| !content.is_empty()), "message.content为空")?;
+        expect(choice["finish_reason"].is_string(), "缺少finish_reason")?;
+        let usage = &body["usage"];
+        expect(
+            ["prompt_tokens", "completion_tokens", "total_tokens"].iter().all(|field| usage[field].is_u64()),
+            "usage缺少token计数",
+        )
+    }
+
+    async fn chat_completion_stream(&self) -> Result<(), String> {
+        let response = self
+            .client
+            .post(self.url("/v1/chat/completions"))
+            .bearer_auth(&self.api_key)
+            .json(&json!({
+                "model": self.model,
+                "messages": [{"role": "user", "content": "Reply with one short sentence."}],
+                "stream": true,
+            }))
+            .send()
+            .await
+            .map_err(|e| format!("请求失败: {}", e))?;
+        let status = response.status();
+        expect(status.is_success(), format!("HTTP {}", status))?;
+        let content_type = response.headers().get("content-type").and_then(|value| value.to_str().ok()).unwrap_or_default();
+        expect(content_type.starts_with("text/event-stream"), format!("content-type为 {}", content_type))?;
+        let text = response.text().await.map_err(|e| format!("读取流失败: {}", e))?;
+
+        let data: Vec<&str> = text.lines().filter_map(|line| line.strip_prefix("data:")).map(str::trim).collect();
+        expect(data.last() == Some(&"[DONE]"), "流没有以 data: [DONE] 结束")?;
+        let chunks = data[..data.len() - 1]
+            .iter()
+            .map(|data| serde_json::from_str::<Value>(data).map_err(|_| format!("数据块不是JSON: {}", data)))
+            .collect::<Result<Vec<_>, _>>()?;
+        expect(chunks.iter().all(|chunk| chunk["object"] == "chat.completion.chunk"), "object不是chat.completion.chunk")?;
+        expect(chunks.first().is_some_and(|chunk| chunk["choices"][0]["delta"]["role"] == "assistant"), "第一个数据块的delta.role不是assistant")?;
+        let content: String = chunks.iter().filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str()).collect();
+        expect(!content.is_empty(), "delta.content拼接后为空")?;
+        expect(chunks.iter().any(|chunk| chunk["choices"][0]["finish_reason"].is_string()), "没有带finish_reason的数据块")
+    }
+
+    async fn system_message(&self) -> Result<(), String> {
+        self.completion(json!({
+            "messages": [
+                {"role": "system", "content": "You are a helpful assistant."},
+                {"role": "user", "content": "Reply with one short sentence."}
+            ]
+        }))
+        .await
+        .map(drop)
+    }
+
+    async fn stop(&self) -> Result<(), String> {
+        let body = self
+            .completion(json!({
+                "messages": [{"role": "user", "content": "Repeat exactly: alpha beta gamma"}],
+                "stop": ["beta"],
+            }))
+            .await?;
+        let content = body["choices"][0]["message"]["content"].as_str().unwrap_or_default();
+        expect(!content.contains("beta"), format!("回复中包含停止序列: {:?}", content))?;
+        expect(body["choices"][0]["finish_reason"] == "stop", format!("finish_reason为 {}", body["choices"][0]["finish_reason"]))
+    }
+
+    async fn max_tokens(&self) -> Result<(), String> {
+        let body = self
+            .completion(json!({
+                "messages": [{"role": "user", "content": "Count from 1 to 50."}],
+                "max_tokens": 1,
+            }))
+            .await?;
+        expect(body["choices"][0]["finish_reason"] == "length", format!("finish_reason为 {}，期望length", body["choices"][0]["finish_reason"]))?;
+        expect(body["usage"]["completion_tokens"].as_u64().is_some_and(|tokens| tokens <= 1), "completion_tokens超过max_tokens")
+    }
+
+    async fn invalid_api_key(&self) -> Result<(), String> {
+        let (status, body) = self.chat("dsk-compat-check-invalid-key", json!({})).await?;
+        expect(status == StatusCode::UNAUTHORIZED, format!("期望HTTP 401，实际为 {}", status))?;
+        error_body(&body)
+    }
+
+    async fn empty_messages(&self) -> Result<(), String> {
+        let (status, body) = self.chat(&self.api_key, json!({ "messages": [] })).await?;
+        expect(status == StatusCode::BAD_REQUEST, format!("期望HTTP 400，实际为 {}", status))?;
+        error_body(&body)
+    }
+
+    async fn unknown_model(&self) -> Result<(), String> {
+        let (status, body) = self.get("/v1/models/compat-check-missing-model").await?;
+        expect(status == StatusCode::NOT_FOUND, format!("期望HTTP 404，实际为 {}", status))?;
+        error_body(&body)
+    }
+}
+
+fn expect(condition: bool, message: impl Into<String>) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(message.into())
+    }
+}
+
+/// OpenAI格式的错误体：{"error": {"message": ..., "type": ...}}
+fn error_body(body: &Value) -> Result<(), String> {
+    expect(
+        body["error"]["message"].is_string() && body["error"]["type"].is_string(),
+        format!("错误体不是OpenAI格式: {}", body),
+    )
+}
+
+/// 依次执行全部检查
+async fn run(suite: &Suite) -> Vec<CheckResult> {
+    let mut results = Vec::new();
+    let mut check = |name, optional, result| results.push(CheckResult { name, optional, result });
+    check("models", false, suite.models().await);
+    check("retrieve_model", false, suite.retrieve_model().await);
+    check("chat_completion", false, suite.chat_completion().await);
+    check("chat_completion_stream", false, suite.chat_completion_stream().await);
+    check("system_message", false, suite.system_message().await);
+    check("invalid_api_key", false, suite.invalid_api_key().await);
+    check("empty_messages", false, suite.empty_messages().await);
+    check("unknown_model", false, suite.unknown_model().await);
+    // DeepSeek网页接口本身不支持的参数，不影响结果
+    check("stop", true, suite.stop().await);
+    check("max_tokens", true, suite.max_tokens().await);
+    results
+}
+
+/// 对运行中的实例执行OpenAI兼容性检查并打印报告，必需的检查全部通过时返回true
+pub async fn compat_check(args: &CompatCheckArgs) -> anyhow::Result<bool> {
+    // OpenAI SDK的base_url通常带有/v1
+    let base_url = args.base_url.trim_end_matches('/');
+    let suite = Suite {
+        client: Client::builder().timeout(Duration::from_secs(args.timeout)).build()?,
+        base_url: base_url.strip_suffix("/v1").unwrap_or(base_url).to_string(),
+        api_key: args.api_key.clone(),
+        model: args.model.clone(),
+    };
+
+    println!("检查 {} 的OpenAI兼容性，模型 {}", suite.base_url, suite.model);
+    let results = run(&suite).await;
+    for check in &results {
+        match &check.result {
+            Ok(()) => println!("{} {}", "[PASS]".bright_green().bold(), check.name),
+            Err(e) if check.optional => println!("{} {}: {}", "[WARN]".bright_yellow().bold(), check.name, e),
+            Err(e) => println!("{} {}: {}", "[FAIL]".bright_red().bold(), check.name, e),
+        }
+    }
+
+    let failed = results.iter().filter(|check| check.result.is_err() && !check.optional).count();
+    let warned = results.iter().filter(|check| check.result.is_err() && check.optional).count();
+    let summary = format!("通过 {} 项，失败 {} 项，警告 {} 项", results.len() - failed - warned, failed, warned);
+    if failed == 0 {
+        println!("{}", summary.bright_green().bold());
+    } else {
+        println!("{}", summary.bright_red().bold());
+    }
+    Ok(failed == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestServer;
+
+    #[tokio::test]
+    async fn test_compat_check_against_mock() {
+        let server = TestServer::start().await.unwrap();
+        let suite = Suite {
+            client: Client::new(),
+            base_url: server.base_url().to_string(),
+            api_key: server.create_api_key().await.unwrap(),
+            model: "deepseek".to_string(),
+        };
+
+        let results = run(&suite).await;
+        for check in results.iter().filter(|check| !check.optional) {
+            assert!(check.result.is_ok(), "{}: {:?}", check.name, check.result);
+        }
+        server.stop().await;
+    }
+}
@@ -1,19 +1,76 @@
-use anyhow::Result;
+use crate::models::AccountOverrides;
+use crate::services::browser_profile::{is_known_profile, PROFILES};
+use crate::services::dns_resolver::DohResolver;
+use crate::services::secrets::SecretRef;
+use anyhow::{bail, Result};
+use reqwest::header::HeaderValue;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
+use ipnet::IpNet;
+use std::fmt::Display;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub environment: String,
+    pub identifier_seed: u64, // 非0时UUID、cookie和账号选择按种子确定性生成，只用于启用deterministic-ids特性的测试构建
     pub server: ServerConfig,
     pub deepseek: DeepSeekConfig,
+    pub http: HttpClientConfig,
+    pub pool: PoolConfig,
+    pub secrets: SecretsConfig,
+    pub storage: StorageConfig,
+    pub credentials: CredentialsConfig,
+    pub models: ModelConfig,
+    pub prompt: PromptTemplate,
+    pub audit: AuditConfig,
+    pub history: HistoryConfig,
+    pub threads: ThreadConfig,
+    pub generations: GenerationConfig,
+    pub metrics: MetricsConfig,
+    pub cache: CacheConfig,
+    pub access: AccessConfig,
+    pub content_filter: ContentFilterConfig,
+    pub plugins: PluginConfig,
+    pub scripts: ScriptConfig,
+    pub webhook: WebhookConfig,
+    pub logging: LoggingConfig,
+    pub cluster: ClusterConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
-    pub cors_origins: Vec<String>,
+    pub cors_origins: Vec<String>,       // /v1等公开接口允许的跨域来源，为空表示不允许跨域，*需显式配置
+    pub cors_allow_credentials: bool,
+    pub admin_cors_origins: Vec<String>, // 管理接口允许的跨域来源，默认不允许
+    pub max_in_flight_requests: usize, // 全局并发请求上限，0表示不限制
+    pub load_shed_retry_after_secs: u64,
+    pub error_locale: ErrorLocale,     // 错误消息语言，auto按Accept-Language选择
+    pub api_key_requests_per_minute: u32, // 每个API密钥默认的每分钟请求数上限，0表示不限制
+    pub max_request_body_bytes: usize, // 超出时返回413
+    pub max_messages: usize,           // 单个请求的消息条数上限
+    pub max_message_chars: usize,      // 单条消息的字符数上限
+    pub tls_cert_path: Option<String>, // 同时设置证书和私钥时以HTTPS提供服务
+    pub tls_key_path: Option<String>,
+    pub tls_reload_secs: u64,          // 检查证书文件变化的间隔，0表示不自动重新加载
+    pub tls_client_ca_path: Option<String>, // 设置后要求客户端证书（mTLS）
+    pub tls_client_identities: BTreeMap<String, String>, // 客户端证书CN/SAN（小写） -> API密钥（保留大小写）
+    pub pid_file: Option<String>,      // 启动后写入进程号，正常退出时删除
+    pub reuse_port: bool,              // 以SO_REUSEPORT绑定，新进程可以在旧进程退出前监听同一端口
+    pub drain_timeout_secs: u64,       // 停止时等待处理中的请求（包括SSE流）结束的最长时间，0表示一直等待
+    pub validate_accounts_on_start: bool, // 启动时验证账号token，失效的不放入账号池
+}
+
+impl ServerConfig {
+    pub fn is_tls_enabled(&self) -> bool {
+        self.tls_cert_path.is_some() && self.tls_key_path.is_some()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,62 +78,1381 @@ pub struct DeepSeekConfig {
     pub base_url: String,
     pub wasm_path: String,
     pub max_retry_count: u32,
-    pub retry_delay_ms: u64,
+    pub retry_delay_ms: u64,           // 指数退避的基础间隔
+    pub retry_max_delay_ms: u64,       // 单次退避上限，上游Retry-After超过该值时不再重试
     pub access_token_expires: u64,
     pub authorization: Option<String>, // 环境变量中的token
+    pub prewarm_sessions: usize,       // 每个空闲账号预热的会话数，0表示禁用
+    pub prewarm_interval_secs: u64,
+    pub prewarm_session_ttl_secs: u64,
+    pub challenge_reuse_secs: u64,     // POW答案复用窗口，0表示每次都重新求解
+    pub quota_cache_ttl_secs: u64,
+    pub quota_poll_interval_secs: u64, // 后台轮询各账号功能配额的间隔，0表示只在请求时查询
+    pub max_upstream_response_bytes: usize, // 单次上游响应最多缓冲的字节数
+    pub stream_coalesce_chars: usize,  // 流式响应合并到该字符数再发送一个数据块，0表示逐块发送
+    pub stream_tokens_per_sec: u32,    // 流式响应按该速率（估算的token/秒）发送，0表示不限速
+    pub browser_profiles: Vec<String>, // 可分配给账号的浏览器指纹
+    pub app_version: String,           // X-App-Version请求头，自动探测成功前使用
+    pub app_version_refresh_secs: u64, // 从网页探测X-App-Version的间隔，0表示固定使用app_version
+    pub mirrors: Vec<String>,          // 备用入口地址，与base_url一起按延迟选择
+    pub mirror_probe_interval_secs: u64,
+    pub pacing_min_gap_ms: u64,        // 同一账号连续上游操作的最小间隔，0表示不限制
+    pub pacing_jitter_ms: u64,         // 在最小间隔上叠加的随机延迟上限
+    pub mock: bool,                    // 使用内置的模拟上游，不访问DeepSeek，用于本地开发和集成测试
+    pub cassette_mode: CassetteMode,   // 录制或回放上游流量
+    pub cassette_path: Option<String>, // 录制/回放使用的JSON Lines文件
+    pub chaos: bool,                   // 故障注入模式，在上游请求中按比例注入超时、断流、429和损坏的SSE行
+    pub chaos_faults: FaultRates,      // 未通过X-Chaos请求头指定时使用的故障比例
+    pub chaos_seed: u64,               // 故障注入的随机种子，0表示每次启动随机
+    pub wire_log: bool,                // 启动时是否开启上游线路日志（脱敏后的完整请求、响应头和SSE行），运行时可通过管理接口切换
+}
+
+/// 故障注入模式下各类故障的发生概率（0到1），格式为 timeout=0.1,disconnect=0.05,rate_limit=0,malformed=0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FaultRates {
+    pub timeout: f64,    // 上游不响应，直到客户端超时
+    pub disconnect: f64, // SSE响应中途断开连接
+    pub rate_limit: f64, // 返回429
+    pub malformed: f64,  // SSE响应中插入无法解析的行
+}
+
+impl FaultRates {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl FromStr for FaultRates {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut rates = Self::default();
+        for entry in s.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (name, value) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("invalid entry {:?}, expected fault=rate", entry))?;
+            let value: f64 = value
+                .trim()
+                .parse()
+                .ok()
+                .filter(|value| (0.0..=1.0).contains(value))
+                .ok_or_else(|| format!("rate for {} must be between 0 and 1", name.trim()))?;
+            match name.trim().to_lowercase().as_str() {
+                "timeout" => rates.timeout = value,
+                "disconnect" => rates.disconnect = value,
+                "rate_limit" | "429" => rates.rate_limit = value,
+                "malformed" => rates.malformed = value,
+                other => return Err(format!("unknown fault {:?}, expected timeout, disconnect, rate_limit or malformed", other)),
+            }
+        }
+        Ok(rates)
+    }
+}
+
+impl Display for FaultRates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "timeout={},disconnect={},rate_limit={},malformed={}",
+            self.timeout, self.disconnect, self.rate_limit, self.malformed
+        )
+    }
+}
+
+/// 上游流量的录制/回放模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CassetteMode {
+    Off,
+    Record, // 转发到DeepSeek并把脱敏后的请求和响应写入文件
+    Replay, // 不访问DeepSeek，按录制的顺序返回响应
+}
+
+impl FromStr for CassetteMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" | "" => Ok(CassetteMode::Off),
+            "record" => Ok(CassetteMode::Record),
+            "replay" => Ok(CassetteMode::Replay),
+            _ => Err("expected off, record or replay".to_string()),
+        }
+    }
+}
+
+/// 访问DeepSeek的HTTP客户端参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpClientConfig {
+    pub pool_max_idle_per_host: usize,
+    pub pool_idle_timeout_secs: u64,  // 空闲连接保留时间，0表示不回收
+    pub tcp_keepalive_secs: u64,      // 0表示不启用TCP keepalive
+    pub http2: bool,                  // false时强制HTTP/1.1，否则通过ALPN优先协商HTTP/2
+    pub connect_timeout_secs: u64,
+    pub request_timeout_secs: u64,
+    pub proxy: Option<String>,        // 上游代理（http/https/socks5/socks5h），未设置时沿用HTTPS_PROXY等环境变量
+    pub no_proxy: Option<String>,     // 不经过代理的主机，逗号分隔
+    pub dns_overrides: BTreeMap<String, String>, // 域名 -> 固定IP，优先于DoH和系统DNS
+    pub doh_url: Option<String>,      // DNS-over-HTTPS服务（JSON API），未设置时使用系统DNS
+}
+
+impl HttpClientConfig {
+    /// 按配置构建reqwest客户端
+    pub fn build_client(&self) -> reqwest::Result<reqwest::Client> {
+        let seconds = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+        let mut builder = reqwest::Client::builder()
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(seconds(self.pool_idle_timeout_secs))
+            .tcp_keepalive(seconds(self.tcp_keepalive_secs))
+            .connect_timeout(Duration::from_secs(self.connect_timeout_secs))
+            .timeout(Duration::from_secs(self.request_timeout_secs));
+
+        builder = if self.http2 {
+            builder.http2_adaptive_window(true)
+        } else {
+            builder.http1_only()
+        };
+
+        self.with_network(builder)?.build()
+    }
+
+    /// 为客户端设置上游代理和DNS解析，登录、探测等独立客户端也通过它保持一致
+    pub fn with_network(&self, mut builder: reqwest::ClientBuilder) -> reqwest::Result<reqwest::ClientBuilder> {
+        if let Some(url) = &self.doh_url {
+            builder = builder.dns_resolver(Arc::new(DohResolver::new(url)));
+        }
+        for (host, ip) in &self.dns_overrides {
+            if let Ok(ip) = ip.parse::<IpAddr>() {
+                builder = builder.resolve(host, SocketAddr::new(ip, 0));
+            }
+        }
+
+        let Some(url) = &self.proxy else {
+            return Ok(builder);
+        };
+        let no_proxy = self.no_proxy.as_deref().and_then(reqwest::NoProxy::from_string);
+        Ok(builder.proxy(reqwest::Proxy::all(url)?.no_proxy(no_proxy)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolConfig {
+    pub cooldown_error_threshold: u32, // 窗口内错误次数达到该值后进入冷却
+    pub cooldown_window_secs: u64,
+    pub cooldown_secs: u64,
+    pub probe_interval_secs: u64,      // 冷却结束后重新探测账号的间隔
+    pub account_messages_per_hour: u32, // 每个账号每小时最多发送的消息数，0表示不限制
+    pub account_message_burst: u32,     // 允许的突发消息数，0按1处理
+    pub account_max_concurrency: usize, // 每个账号同时处理的请求数
+    pub fair_queue_timeout_secs: u64,   // 公平调度排队超时
+    pub preempt_low_priority: bool,     // 高优先级请求排队时抢占低优先级请求
+    pub warmup_new_accounts: bool,      // 新添加的账号先完成预热再加入轮换
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageConfig {
+    pub api_keys_path: String, // API密钥、账号和token，留空时只保存在内存中（重启后丢失）
+    pub cookies_path: String,  // 每个账号的cookie身份，留空时只保存在内存中
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialsConfig {
+    pub path: String,                    // 加密凭据库文件
+    pub master_key: Option<String>,      // 主密钥，与master_key_file二选一，都未设置时不保存凭据
+    pub master_key_file: Option<String>, // 从文件读取主密钥
+}
+
+/// 拼接提示词使用的角色标记，DeepSeek调整对话格式时可通过环境变量修改
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub user_prefix: String,
+    pub assistant_prefix: String,
+    pub assistant_suffix: String,
+    pub system_prefix: Option<String>, // None表示system消息按user消息处理
+    pub separator: String,             // 相邻消息块之间的分隔符
+    pub prefix_first_message: bool,    // 第一条user/system消息是否也加前缀
+    pub token_budget: usize,           // 提示词估算token上限，超出时丢弃最早的消息，0表示不限制
+    pub keep_last_messages: usize,     // 截断时始终保留的最近消息数（system消息总是保留）
+    pub markdown_images: MarkdownImageMode, // 消息中markdown图片的处理方式
+}
+
+/// 提示词中markdown图片 `![alt](url)` 的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkdownImageMode {
+    #[default]
+    Strip,       // 整体移除
+    Keep,        // 原样保留
+    Alt,         // 替换为alt文本
+    Placeholder, // 替换为[图片]
+}
+
+impl FromStr for MarkdownImageMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strip" => Ok(MarkdownImageMode::Strip),
+            "keep" => Ok(MarkdownImageMode::Keep),
+            "alt" => Ok(MarkdownImageMode::Alt),
+            "placeholder" => Ok(MarkdownImageMode::Placeholder),
+            _ => Err("expected strip, keep, alt or placeholder".to_string()),
+        }
+    }
+}
+
+impl Default for PromptTemplate {
+    fn default() -> Self {
+        Self {
+            user_prefix: "<｜User｜>".to_string(),
+            assistant_prefix: "<｜Assistant｜>".to_string(),
+            assistant_suffix: "<｜end▁of▁sentence｜>".to_string(),
+            system_prefix: None,
+            separator: String::new(),
+            prefix_first_message: false,
+            token_budget: 0,
+            keep_last_messages: 4,
+            markdown_images: MarkdownImageMode::Strip,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelConfig {
+    pub default_model: String,             // 请求未指定model时使用，可带search/think等后缀
+    pub aliases: BTreeMap<String, String>, // 模型别名，例如 gpt-4o -> deepseek-r1
+    pub account_groups: BTreeMap<String, String>, // 模型到账号分组的路由规则，`thinking` 匹配所有深度思考请求
+}
+
+impl PoolConfig {
+    /// 合并账号单独设置后的策略
+    pub fn for_account(&self, overrides: &AccountOverrides) -> PoolConfig {
+        PoolConfig {
+            account_max_concurrency: overrides.max_concurrency.unwrap_or(self.account_max_concurrency),
+            account_messages_per_hour: overrides.messages_per_hour.unwrap_or(self.account_messages_per_hour),
+            account_message_burst: overrides.message_burst.unwrap_or(self.account_message_burst),
+            cooldown_error_threshold: overrides.cooldown_error_threshold.unwrap_or(self.cooldown_error_threshold),
+            cooldown_window_secs: overrides.cooldown_window_secs.unwrap_or(self.cooldown_window_secs),
+            cooldown_secs: overrides.cooldown_secs.unwrap_or(self.cooldown_secs),
+            ..self.clone()
+        }
+    }
+}
+
+impl ModelConfig {
+    /// 将别名映射为实际模型，非别名原样返回
+    pub fn resolve_alias(&self, model: &str) -> String {
+        self.aliases
+            .get(model)
+            .cloned()
+            .unwrap_or_else(|| model.to_string())
+    }
+
+    /// 按路由规则选择账号分组：模型名的规则优先，其次深度思考请求使用 `thinking` 规则
+    pub fn account_group(&self, model: &str, thinking: bool) -> Option<String> {
+        self.account_groups
+            .get(model)
+            .or_else(|| thinking.then(|| self.account_groups.get("thinking")).flatten())
+            .cloned()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    pub stats_window_secs: u64,          // 延迟和错误率统计的滚动窗口
+    pub status_probe_interval_secs: u64, // 上游健康探测间隔，0表示禁用
+    pub status_canary_token: Option<String>, // 用于金丝雀补全的账号userToken
+    pub push_url: Option<String>,        // Prometheus Pushgateway地址，设置后定期推送指标
+    pub push_interval_secs: u64,
+    pub push_job: String,                // 推送分组使用的job标签
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheConfig {
+    pub response_ttl_secs: u64, // 相同非流式请求的响应缓存时间，0表示禁用
+    pub response_capacity: usize,
+    pub singleflight: bool,     // 合并同一调用方相同的并发非流式请求
+}
+
+/// IP地址或CIDR网段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IpRange(pub IpNet);
+
+impl IpRange {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.contains(ip)
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        s.parse::<IpNet>()
+            .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+            .map(IpRange)
+            .map_err(|_| format!("{:?} is not an IP address or CIDR", s))
+    }
+}
+
+/// 按来源IP限制访问，允许列表为空表示不限制，拒绝列表优先
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccessConfig {
+    pub chat_allow: Vec<IpRange>,
+    pub chat_deny: Vec<IpRange>,
+    pub admin_allow: Vec<IpRange>, // /api_keys 和 /auth 管理接口
+    pub admin_deny: Vec<IpRange>,
+    pub trusted_proxies: Vec<IpRange>, // 来自这些地址的请求使用X-Forwarded-For中的客户端IP
+    pub admin_tokens: BTreeMap<String, String>, // 标签 -> 管理令牌，为空时管理接口只接受本机直连的请求
+    pub api_key_signing_secret: Option<String>, // 设置后签发HMAC签名的自描述API密钥
+}
+
+/// 事件通知webhook配置（如账号被封禁后自动停用）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: Option<String>,    // 未设置时不发送通知
+    pub secret: Option<String>, // 设置后用HMAC-SHA256签名请求体，放在X-Webhook-Signature头
+}
+
+/// 多实例部署配置，多个副本共享同一个Redis时使用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterConfig {
+    pub redis_url: Option<String>, // 未设置时按单实例运行，本实例始终执行后台任务
+    pub instance_id: String,       // 本实例的标识，默认为主机名和进程号
+    pub leader_key: String,        // 保存领导者租约的Redis键
+    pub leader_lease_secs: u64,    // 租约有效期，领导者每1/3租约时间续约一次
+    pub rate_limit_prefix: String, // API密钥每分钟请求数令牌桶在Redis中的键前缀
+    pub key_cleanup_interval_secs: u64, // 定期清理过期API密钥的间隔，0表示只通过接口手动清理
+}
+
+/// 日志文件配置，未设置路径时只输出到控制台
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    pub file_path: Option<String>,       // 应用日志文件
+    pub access_log_path: Option<String>, // 访问日志单独写入该文件，不再写入应用日志
+    pub console: bool,                   // 同时输出到控制台
+    pub rotation: LogRotation,
+    pub max_bytes: u64,                  // 按大小轮转时单个文件的上限
+    pub max_files: usize,                // 保留的历史文件数，0表示不删除
+}
+
+/// 日志文件的轮转方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    Size, // 超过max_bytes时轮转
+    Never,
+}
+
+impl FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "minutely" => Ok(LogRotation::Minutely),
+            "hourly" => Ok(LogRotation::Hourly),
+            "daily" => Ok(LogRotation::Daily),
+            "size" => Ok(LogRotation::Size),
+            "never" => Ok(LogRotation::Never),
+            _ => Err("expected minutely, hourly, daily, size or never".to_string()),
+        }
+    }
+}
+
+/// 补全审计日志配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditConfig {
+    pub path: Option<String>, // JSON Lines文件路径，未设置时不记录
+    pub redaction: AuditRedaction,
+    pub prompt_max_chars: usize,
+    pub failed_request_capacity: usize, // 内存中保留的最近失败请求数，供POST /admin/replay/:id重放，0表示不保留
+    pub failed_request_max_chars: usize, // GET /admin/replay中每条消息显示的最大字符数，重放使用完整内容
+}
+
+/// 补全历史配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    pub path: Option<String>, // SQLite数据库路径，未设置时不保存
+    pub retention_days: u64,  // 保留天数，0表示不按时间清理
+    pub max_records: u64,     // 最多保留的记录数，0表示不限制
+}
+
+/// 服务端托管对话配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadConfig {
+    pub mode: ThreadMode,
+    pub idle_ttl_secs: u64, // 超过该时间未使用的对话被清除
+    pub max_threads: usize,  // 内存中保存的对话数上限，达到后清除最久未使用的对话
+    pub max_messages: usize, // 每个对话保留的消息数上限，超出时丢弃最早的消息，0为不限制
+}
+
+/// 轮询方式（transport=poll）的补全配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationConfig {
+    pub ttl_secs: u64,      // 补全结束或最后一次读取后保留的时间
+    pub max_wait_secs: u64, // 读取时没有新数据最多等待的时间，0表示立即返回
+}
+
+/// 按哪个字段识别服务端托管的对话
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThreadMode {
+    Off,      // 不托管对话，thread_id无效
+    #[default]
+    Explicit, // 只识别请求中的thread_id
+    User,     // 未传thread_id时以user字段作为对话标识
+}
+
+impl FromStr for ThreadMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(ThreadMode::Off),
+            "explicit" => Ok(ThreadMode::Explicit),
+            "user" => Ok(ThreadMode::User),
+            _ => Err("expected off, explicit or user".to_string()),
+        }
+    }
+}
+
+/// 审计日志中提示词的脱敏方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditRedaction {
+    Hash,     // 只记录哈希
+    Truncate, // 记录哈希和截断后的内容
+    None,     // 记录完整内容
+}
+
+impl FromStr for AuditRedaction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hash" => Ok(AuditRedaction::Hash),
+            "truncate" => Ok(AuditRedaction::Truncate),
+            "none" => Ok(AuditRedaction::None),
+            _ => Err("expected hash, truncate or none".to_string()),
+        }
+    }
+}
+
+/// 内容过滤配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContentFilterConfig {
+    pub rules_path: Option<String>, // 规则文件，每行一个关键词或 `re:正则`，未设置时不过滤
+    pub mode: FilterMode,           // 默认处理方式，API密钥可单独设置
+}
+
+/// WASM插件配置（需要以 plugins feature 编译）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginConfig {
+    pub paths: Vec<String>,      // 按顺序加载的插件（.wasm或.wat），各钩子依次经过每个插件
+    pub fuel: u64,               // 单次钩子调用可执行的指令数上限
+    pub max_memory_bytes: usize, // 单个插件实例的线性内存上限
+}
+
+/// Rhai钩子脚本配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptConfig {
+    pub path: Option<String>, // 定义pre_request/post_response函数的脚本文件
+    pub max_operations: u64,  // 单次调用可执行的操作数上限，防止死循环
+}
+
+/// 提示词或补全内容命中过滤规则时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+    #[default]
+    Reject, // 拒绝请求
+    Redact, // 将命中内容替换为***
+    Log,    // 只记录日志
+}
+
+impl FromStr for FilterMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(FilterMode::Reject),
+            "redact" => Ok(FilterMode::Redact),
+            "log" => Ok(FilterMode::Log),
+            _ => Err("expected reject, redact or log".to_string()),
+        }
+    }
+}
+
+/// 错误消息的语言
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorLocale {
+    #[default]
+    Auto, // 按请求的Accept-Language选择，无法识别时保持原文
+    Zh,
+    En,
+}
+
+impl FromStr for ErrorLocale {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(ErrorLocale::Auto),
+            "zh" | "zh-cn" => Ok(ErrorLocale::Zh),
+            "en" | "en-us" => Ok(ErrorLocale::En),
+            _ => Err("expected auto, zh or en".to_string()),
+        }
+    }
+}
+
+/// 外部密钥存储配置，配置值可写成 `vault:挂载点/路径#字段` 或 `aws-sm:密钥ID#字段`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SecretsConfig {
+    pub vault_addr: Option<String>,
+    pub vault_token: Option<String>,
+    pub vault_namespace: Option<String>,
+    pub aws_region: Option<String>,
+    pub aws_access_key_id: Option<String>,
+    pub aws_secret_access_key: Option<String>,
+    pub aws_session_token: Option<String>,
+    pub refresh_secs: u64, // 重新读取外部密钥的间隔，0表示不刷新
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             environment: "development".to_string(),
+            identifier_seed: 0,
             server: ServerConfig {
                 host: "0.0.0.0".to_string(),
                 port: 8000,
-                cors_origins: vec!["*".to_string()],
+                cors_origins: Vec::new(),
+                cors_allow_credentials: false,
+                admin_cors_origins: Vec::new(),
+                max_in_flight_requests: 256,
+                load_shed_retry_after_secs: 1,
+                error_locale: ErrorLocale::Auto,
+                api_key_requests_per_minute: 0,
+                max_request_body_bytes: 4 * 1024 * 1024,
+                max_messages: 512,
+                max_message_chars: 200_000,
+                tls_cert_path: None,
+                tls_key_path: None,
+                tls_reload_secs: 60,
+                tls_client_ca_path: None,
+                tls_client_identities: BTreeMap::new(),
+                pid_file: None,
+                reuse_port: false,
+                drain_timeout_secs: 0,
+                validate_accounts_on_start: false,
             },
             deepseek: DeepSeekConfig {
                 base_url: "https://chat.deepseek.com".to_string(),
                 wasm_path: "./sha3_wasm_bg.7b9ca65ddd.wasm".to_string(),
                 max_retry_count: 3,
-                retry_delay_ms: 5000,
+                retry_delay_ms: 1000,
+                retry_max_delay_ms: 30_000,
                 access_token_expires: 3600,
                 authorization: None,
+                prewarm_sessions: 0,
+                prewarm_interval_secs: 30,
+                prewarm_session_ttl_secs: 1800,
+                challenge_reuse_secs: 30,
+                quota_cache_ttl_secs: 300,
+                quota_poll_interval_secs: 240,
+                max_upstream_response_bytes: 16 * 1024 * 1024,
+                stream_coalesce_chars: 0,
+                stream_tokens_per_sec: 0,
+                browser_profiles: PROFILES.iter().map(|profile| profile.name.to_string()).collect(),
+                app_version: "20241129.1".to_string(),
+                app_version_refresh_secs: 3600,
+                mirrors: Vec::new(),
+                mirror_probe_interval_secs: 60,
+                pacing_min_gap_ms: 0,
+                pacing_jitter_ms: 0,
+                mock: false,
+                cassette_mode: CassetteMode::Off,
+                cassette_path: None,
+                chaos: false,
+                chaos_faults: FaultRates::default(),
+                chaos_seed: 0,
+                wire_log: false,
+            },
+            http: HttpClientConfig {
+                pool_max_idle_per_host: 32,
+                pool_idle_timeout_secs: 90,
+                tcp_keepalive_secs: 60,
+                http2: true,
+                connect_timeout_secs: 10,
+                request_timeout_secs: 120,
+                proxy: None,
+                no_proxy: None,
+                dns_overrides: BTreeMap::new(),
+                doh_url: None,
+            },
+            pool: PoolConfig {
+                cooldown_error_threshold: 3,
+                cooldown_window_secs: 300,
+                cooldown_secs: 600,
+                probe_interval_secs: 30,
+                account_messages_per_hour: 0,
+                account_message_burst: 0,
+                account_max_concurrency: 1,
+                fair_queue_timeout_secs: 60,
+                preempt_low_priority: false,
+                warmup_new_accounts: false,
+            },
+            secrets: SecretsConfig {
+                refresh_secs: 300,
+                ..SecretsConfig::default()
+            },
+            storage: StorageConfig {
+                api_keys_path: "./data/api_keys.json".to_string(),
+                cookies_path: "./data/cookies.json".to_string(),
+            },
+            credentials: CredentialsConfig {
+                path: "./data/credentials.enc".to_string(),
+                master_key: None,
+                master_key_file: None,
+            },
+            models: ModelConfig {
+                default_model: "deepseek".to_string(),
+                aliases: BTreeMap::new(),
+                account_groups: BTreeMap::new(),
+            },
+            prompt: PromptTemplate::default(),
+            audit: AuditConfig {
+                path: None,
+                redaction: AuditRedaction::Hash,
+                prompt_max_chars: 200,
+                failed_request_capacity: 0,
+                failed_request_max_chars: 2000,
+            },
+            history: HistoryConfig {
+                path: None,
+                retention_days: 30,
+                max_records: 0,
+            },
+            threads: ThreadConfig {
+                mode: ThreadMode::Explicit,
+                idle_ttl_secs: 86400,
+                max_threads: 10000,
+                max_messages: 200,
+            },
+            generations: GenerationConfig {
+                ttl_secs: 600,
+                max_wait_secs: 30,
+            },
+            metrics: MetricsConfig {
+                stats_window_secs: 3600,
+                status_probe_interval_secs: 60,
+                status_canary_token: None,
+                push_url: None,
+                push_interval_secs: 15,
+                push_job: "deepseek_free_api".to_string(),
+            },
+            cache: CacheConfig {
+                response_ttl_secs: 0,
+                response_capacity: 1000,
+                singleflight: true,
+            },
+            access: AccessConfig::default(),
+            webhook: WebhookConfig::default(),
+            cluster: ClusterConfig {
+                redis_url: None,
+                instance_id: format!(
+                    "{}-{}",
+                    env::var("HOSTNAME").unwrap_or_else(|_| "instance".to_string()),
+                    std::process::id()
+                ),
+                leader_key: "deepseek-free-api:leader".to_string(),
+                leader_lease_secs: 30,
+                rate_limit_prefix: "deepseek-free-api:ratelimit".to_string(),
+                key_cleanup_interval_secs: 0,
+            },
+            content_filter: ContentFilterConfig::default(),
+            plugins: PluginConfig {
+                paths: Vec::new(),
+                fuel: 100_000_000,
+                max_memory_bytes: 64 * 1024 * 1024,
+            },
+            scripts: ScriptConfig {
+                path: None,
+                max_operations: 100_000,
+            },
+            logging: LoggingConfig {
+                file_path: None,
+                access_log_path: None,
+                console: true,
+                rotation: LogRotation::Daily,
+                max_bytes: 100 * 1024 * 1024,
+                max_files: 7,
             },
         }
     }
 }
 
+/// 环境变量读取器，收集所有解析错误以便一次性报告
+#[derive(Default)]
+struct EnvReader {
+    errors: Vec<String>,
+}
+
+impl EnvReader {
+    /// 读取字符串变量
+    fn string(&mut self, name: &str, target: &mut String) {
+        if let Ok(value) = env::var(name) {
+            *target = value;
+        }
+    }
+
+    /// 读取模板片段，`\n` 转换为换行
+    fn template(&mut self, name: &str, target: &mut String) {
+        if let Ok(value) = env::var(name) {
+            *target = value.replace("\\n", "\n");
+        }
+    }
+
+    /// 读取并解析变量，解析失败时记录错误并保留默认值
+    fn parse<T>(&mut self, name: &str, target: &mut T)
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if let Ok(value) = env::var(name) {
+            match value.trim().parse() {
+                Ok(parsed) => *target = parsed,
+                Err(e) => self.errors.push(format!("{}={:?}: {}", name, value, e)),
+            }
+        }
+    }
+
+    /// 读取开关，除true/false外也接受1/0、yes/no、on/off
+    fn flag(&mut self, name: &str, target: &mut bool) {
+        if let Ok(value) = env::var(name) {
+            match value.trim().to_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => *target = true,
+                "0" | "false" | "no" | "off" | "" => *target = false,
+                _ => self.errors.push(format!("{}={:?}: expected true or false", name, value)),
+            }
+        }
+    }
+
+    /// 读取可选字符串变量，空值视为未设置
+    fn optional(&mut self, name: &str, target: &mut Option<String>) {
+        if let Ok(value) = env::var(name) {
+            *target = Some(value).filter(|value| !value.is_empty());
+        }
+    }
+
+    /// 读取逗号分隔的 key=value 映射，键和值统一转为小写
+    fn map(&mut self, name: &str, target: &mut BTreeMap<String, String>) {
+        self.pairs(name, target, true);
+    }
+
+    /// 与map相同，但保留值的大小写（用于令牌等敏感值）
+    fn secret_map(&mut self, name: &str, target: &mut BTreeMap<String, String>) {
+        self.pairs(name, target, false);
+    }
+
+    fn pairs(&mut self, name: &str, target: &mut BTreeMap<String, String>, lowercase_values: bool) {
+        if let Ok(value) = env::var(name) {
+            for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+                match entry.split_once('=') {
+                    Some((key, value)) if !key.trim().is_empty() && !value.trim().is_empty() => {
+                        let value = if lowercase_values { value.trim().to_lowercase() } else { value.trim().to_string() };
+                        target.insert(key.trim().to_lowercase(), value);
+                    }
+                    _ => self.errors.push(format!("{}: invalid entry {:?}, expected name=value", name, entry)),
+                }
+            }
+        }
+    }
+
+    /// 读取逗号分隔的列表并逐项解析
+    fn list<T>(&mut self, name: &str, target: &mut Vec<T>)
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if let Ok(value) = env::var(name) {
+            let mut items = Vec::new();
+            for item in value.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+                match item.parse() {
+                    Ok(parsed) => items.push(parsed),
+                    Err(e) => self.errors.push(format!("{}: {}", name, e)),
+                }
+            }
+            *target = items;
+        }
+    }
+}
+
 impl Config {
     pub fn load() -> Result<Self> {
         let mut config = Config::default();
-        
-        // 从环境变量加载配置
-        if let Ok(port) = env::var("PORT") {
-            config.server.port = port.parse()?;
+        let mut reader = EnvReader::default();
+
+        // 服务器配置
+        reader.string("ENVIRONMENT", &mut config.environment);
+        reader.parse("IDENTIFIER_SEED", &mut config.identifier_seed);
+        reader.string("HOST", &mut config.server.host);
+        reader.parse("PORT", &mut config.server.port);
+        reader.list("CORS_ORIGINS", &mut config.server.cors_origins);
+        reader.flag("CORS_ALLOW_CREDENTIALS", &mut config.server.cors_allow_credentials);
+        reader.list("ADMIN_CORS_ORIGINS", &mut config.server.admin_cors_origins);
+        reader.parse("MAX_IN_FLIGHT_REQUESTS", &mut config.server.max_in_flight_requests);
+        reader.parse("LOAD_SHED_RETRY_AFTER_SECS", &mut config.server.load_shed_retry_after_secs);
+        reader.parse("ERROR_LOCALE", &mut config.server.error_locale);
+        reader.parse("API_KEY_REQUESTS_PER_MINUTE", &mut config.server.api_key_requests_per_minute);
+        reader.parse("MAX_REQUEST_BODY_BYTES", &mut config.server.max_request_body_bytes);
+        reader.parse("MAX_MESSAGES", &mut config.server.max_messages);
+        reader.parse("MAX_MESSAGE_CHARS", &mut config.server.max_message_chars);
+        reader.optional("TLS_CERT_PATH", &mut config.server.tls_cert_path);
+        reader.optional("TLS_KEY_PATH", &mut config.server.tls_key_path);
+        reader.parse("TLS_RELOAD_SECS", &mut config.server.tls_reload_secs);
+        reader.optional("TLS_CLIENT_CA_PATH", &mut config.server.tls_client_ca_path);
+        reader.secret_map("TLS_CLIENT_IDENTITIES", &mut config.server.tls_client_identities);
+        reader.optional("PID_FILE", &mut config.server.pid_file);
+        reader.flag("REUSE_PORT", &mut config.server.reuse_port);
+        reader.parse("DRAIN_TIMEOUT_SECS", &mut config.server.drain_timeout_secs);
+        reader.flag("VALIDATE_ACCOUNTS_ON_START", &mut config.server.validate_accounts_on_start);
+
+        // DeepSeek相关配置
+        reader.optional("DEEP_SEEK_CHAT_AUTHORIZATION", &mut config.deepseek.authorization);
+        reader.string("DEEPSEEK_BASE_URL", &mut config.deepseek.base_url);
+        reader.string("WASM_PATH", &mut config.deepseek.wasm_path);
+        reader.parse("MAX_RETRY_COUNT", &mut config.deepseek.max_retry_count);
+        reader.parse("RETRY_DELAY_MS", &mut config.deepseek.retry_delay_ms);
+        reader.parse("RETRY_MAX_DELAY_MS", &mut config.deepseek.retry_max_delay_ms);
+        reader.parse("ACCESS_TOKEN_EXPIRES", &mut config.deepseek.access_token_expires);
+        reader.parse("PREWARM_SESSIONS", &mut config.deepseek.prewarm_sessions);
+        reader.parse("PREWARM_INTERVAL_SECS", &mut config.deepseek.prewarm_interval_secs);
+        reader.parse("PREWARM_SESSION_TTL_SECS", &mut config.deepseek.prewarm_session_ttl_secs);
+        reader.parse("CHALLENGE_REUSE_SECS", &mut config.deepseek.challenge_reuse_secs);
+        reader.parse("QUOTA_CACHE_TTL_SECS", &mut config.deepseek.quota_cache_ttl_secs);
+        reader.parse("QUOTA_POLL_INTERVAL_SECS", &mut config.deepseek.quota_poll_interval_secs);
+        reader.parse("MAX_UPSTREAM_RESPONSE_BYTES", &mut config.deepseek.max_upstream_response_bytes);
+        reader.parse("STREAM_COALESCE_CHARS", &mut config.deepseek.stream_coalesce_chars);
+        reader.parse("STREAM_TOKENS_PER_SEC", &mut config.deepseek.stream_tokens_per_sec);
+        reader.list("BROWSER_PROFILES", &mut config.deepseek.browser_profiles);
+        reader.string("X_APP_VERSION", &mut config.deepseek.app_version);
+        reader.parse("APP_VERSION_REFRESH_SECS", &mut config.deepseek.app_version_refresh_secs);
+        reader.list("DEEPSEEK_MIRRORS", &mut config.deepseek.mirrors);
+        reader.parse("MIRROR_PROBE_INTERVAL_SECS", &mut config.deepseek.mirror_probe_interval_secs);
+        reader.parse("PACING_MIN_GAP_MS", &mut config.deepseek.pacing_min_gap_ms);
+        reader.parse("PACING_JITTER_MS", &mut config.deepseek.pacing_jitter_ms);
+        reader.flag("DEEPSEEK_MOCK", &mut config.deepseek.mock);
+        reader.parse("UPSTREAM_CASSETTE_MODE", &mut config.deepseek.cassette_mode);
+        reader.optional("UPSTREAM_CASSETTE_PATH", &mut config.deepseek.cassette_path);
+        reader.flag("CHAOS_MODE", &mut config.deepseek.chaos);
+        reader.parse("CHAOS_FAULTS", &mut config.deepseek.chaos_faults);
+        reader.parse("CHAOS_SEED", &mut config.deepseek.chaos_seed);
+        reader.flag("UPSTREAM_WIRE_LOG", &mut config.deepseek.wire_log);
+
+        // 账号池配置
+        reader.parse("COOLDOWN_ERROR_THRESHOLD", &mut config.pool.cooldown_error_threshold);
+        reader.parse("COOLDOWN_WINDOW_SECS", &mut config.pool.cooldown_window_secs);
+        reader.parse("COOLDOWN_SECS", &mut config.pool.cooldown_secs);
+        reader.parse("PROBE_INTERVAL_SECS", &mut config.pool.probe_interval_secs);
+        reader.parse("ACCOUNT_MESSAGES_PER_HOUR", &mut config.pool.account_messages_per_hour);
+        reader.parse("ACCOUNT_MESSAGE_BURST", &mut config.pool.account_message_burst);
+        reader.parse("ACCOUNT_MAX_CONCURRENCY", &mut config.pool.account_max_concurrency);
+        reader.parse("FAIR_QUEUE_TIMEOUT_SECS", &mut config.pool.fair_queue_timeout_secs);
+        reader.flag("PREEMPT_LOW_PRIORITY", &mut config.pool.preempt_low_priority);
+        reader.flag("WARMUP_NEW_ACCOUNTS", &mut config.pool.warmup_new_accounts);
+
+        // HTTP客户端
+        reader.parse("HTTP_POOL_MAX_IDLE_PER_HOST", &mut config.http.pool_max_idle_per_host);
+        reader.parse("HTTP_POOL_IDLE_TIMEOUT_SECS", &mut config.http.pool_idle_timeout_secs);
+        reader.parse("HTTP_TCP_KEEPALIVE_SECS", &mut config.http.tcp_keepalive_secs);
+        reader.flag("HTTP2_ENABLED", &mut config.http.http2);
+        reader.parse("HTTP_CONNECT_TIMEOUT_SECS", &mut config.http.connect_timeout_secs);
+        reader.parse("HTTP_REQUEST_TIMEOUT_SECS", &mut config.http.request_timeout_secs);
+        reader.optional("UPSTREAM_PROXY", &mut config.http.proxy);
+        reader.optional("UPSTREAM_NO_PROXY", &mut config.http.no_proxy);
+        reader.map("DNS_OVERRIDES", &mut config.http.dns_overrides);
+        reader.optional("DNS_DOH_URL", &mut config.http.doh_url);
+
+        // 模型配置
+        reader.string("DEFAULT_MODEL", &mut config.models.default_model);
+        config.models.default_model = config.models.default_model.trim().to_lowercase();
+        reader.map("MODEL_ALIASES", &mut config.models.aliases);
+        reader.map("MODEL_ACCOUNT_GROUPS", &mut config.models.account_groups);
+
+        // 提示词模板，取值中的 \n 表示换行
+        reader.template("PROMPT_USER_PREFIX", &mut config.prompt.user_prefix);
+        reader.template("PROMPT_ASSISTANT_PREFIX", &mut config.prompt.assistant_prefix);
+        reader.template("PROMPT_ASSISTANT_SUFFIX", &mut config.prompt.assistant_suffix);
+        reader.optional("PROMPT_SYSTEM_PREFIX", &mut config.prompt.system_prefix);
+        if let Some(prefix) = config.prompt.system_prefix.as_mut() {
+            *prefix = prefix.replace("\\n", "\n");
+        }
+        reader.template("PROMPT_SEPARATOR", &mut config.prompt.separator);
+        reader.flag("PROMPT_PREFIX_FIRST_MESSAGE", &mut config.prompt.prefix_first_message);
+        reader.parse("PROMPT_TOKEN_BUDGET", &mut config.prompt.token_budget);
+        reader.parse("PROMPT_KEEP_LAST_MESSAGES", &mut config.prompt.keep_last_messages);
+        reader.parse("PROMPT_MARKDOWN_IMAGES", &mut config.prompt.markdown_images);
+
+        // 审计日志
+        reader.optional("AUDIT_LOG_PATH", &mut config.audit.path);
+        reader.parse("AUDIT_REDACTION", &mut config.audit.redaction);
+        reader.parse("AUDIT_PROMPT_MAX_CHARS", &mut config.audit.prompt_max_chars);
+        reader.parse("FAILED_REQUEST_CAPACITY", &mut config.audit.failed_request_capacity);
+        reader.parse("FAILED_REQUEST_MAX_CHARS", &mut config.audit.failed_request_max_chars);
+
+        // 补全历史
+        reader.optional("HISTORY_DB_PATH", &mut config.history.path);
+        reader.parse("HISTORY_RETENTION_DAYS", &mut config.history.retention_days);
+        reader.parse("HISTORY_MAX_RECORDS", &mut config.history.max_records);
+
+        // 服务端托管对话
+        reader.parse("THREAD_MODE", &mut config.threads.mode);
+        reader.parse("THREAD_IDLE_TTL_SECS", &mut config.threads.idle_ttl_secs);
+        reader.parse("THREAD_MAX_COUNT", &mut config.threads.max_threads);
+        reader.parse("THREAD_MAX_MESSAGES", &mut config.threads.max_messages);
+
+        // 轮询方式的补全
+        reader.parse("GENERATION_TTL_SECS", &mut config.generations.ttl_secs);
+        reader.parse("GENERATION_MAX_WAIT_SECS", &mut config.generations.max_wait_secs);
+
+        // 日志文件
+        reader.optional("LOG_FILE", &mut config.logging.file_path);
+        reader.optional("ACCESS_LOG_FILE", &mut config.logging.access_log_path);
+        reader.flag("LOG_CONSOLE", &mut config.logging.console);
+        reader.parse("LOG_ROTATION", &mut config.logging.rotation);
+        reader.parse("LOG_MAX_BYTES", &mut config.logging.max_bytes);
+        reader.parse("LOG_MAX_FILES", &mut config.logging.max_files);
+
+        // 事件通知
+        reader.optional("WEBHOOK_URL", &mut config.webhook.url);
+        reader.optional("WEBHOOK_SECRET", &mut config.webhook.secret);
+
+        // 多实例部署
+        reader.optional("REDIS_URL", &mut config.cluster.redis_url);
+        reader.string("INSTANCE_ID", &mut config.cluster.instance_id);
+        reader.string("LEADER_KEY", &mut config.cluster.leader_key);
+        reader.parse("LEADER_LEASE_SECS", &mut config.cluster.leader_lease_secs);
+        reader.string("RATE_LIMIT_KEY_PREFIX", &mut config.cluster.rate_limit_prefix);
+        reader.parse("KEY_CLEANUP_INTERVAL_SECS", &mut config.cluster.key_cleanup_interval_secs);
+
+        // 内容过滤
+        reader.optional("CONTENT_FILTER_PATH", &mut config.content_filter.rules_path);
+        reader.parse("CONTENT_FILTER_MODE", &mut config.content_filter.mode);
+
+        // WASM插件
+        reader.list("PLUGIN_PATHS", &mut config.plugins.paths);
+        reader.parse("PLUGIN_FUEL", &mut config.plugins.fuel);
+        reader.parse("PLUGIN_MAX_MEMORY_BYTES", &mut config.plugins.max_memory_bytes);
+
+        // 钩子脚本
+        reader.optional("SCRIPT_PATH", &mut config.scripts.path);
+        reader.parse("SCRIPT_MAX_OPERATIONS", &mut config.scripts.max_operations);
+
+        // 统计
+        reader.parse("STATS_WINDOW_SECS", &mut config.metrics.stats_window_secs);
+        reader.parse("STATUS_PROBE_INTERVAL_SECS", &mut config.metrics.status_probe_interval_secs);
+        reader.optional("STATUS_CANARY_TOKEN", &mut config.metrics.status_canary_token);
+        reader.optional("METRICS_PUSH_URL", &mut config.metrics.push_url);
+        reader.parse("METRICS_PUSH_INTERVAL_SECS", &mut config.metrics.push_interval_secs);
+        reader.string("METRICS_PUSH_JOB", &mut config.metrics.push_job);
+
+        // 响应缓存
+        reader.parse("RESPONSE_CACHE_TTL_SECS", &mut config.cache.response_ttl_secs);
+        reader.parse("RESPONSE_CACHE_CAPACITY", &mut config.cache.response_capacity);
+        reader.flag("SINGLEFLIGHT_ENABLED", &mut config.cache.singleflight);
+
+        // IP访问控制
+        reader.list("CHAT_IP_ALLOWLIST", &mut config.access.chat_allow);
+        reader.list("CHAT_IP_DENYLIST", &mut config.access.chat_deny);
+        reader.list("ADMIN_IP_ALLOWLIST", &mut config.access.admin_allow);
+        reader.list("ADMIN_IP_DENYLIST", &mut config.access.admin_deny);
+        reader.list("TRUSTED_PROXIES", &mut config.access.trusted_proxies);
+
+        // 管理接口认证：ADMIN_SECRET以default标签加入ADMIN_TOKENS
+        let mut admin_secret = None;
+        reader.optional("ADMIN_SECRET", &mut admin_secret);
+        if let Some(secret) = admin_secret {
+            config.access.admin_tokens.insert("default".to_string(), secret);
         }
-        
-        if let Ok(host) = env::var("HOST") {
-            config.server.host = host;
+        reader.secret_map("ADMIN_TOKENS", &mut config.access.admin_tokens);
+        reader.optional("API_KEY_SIGNING_SECRET", &mut config.access.api_key_signing_secret);
+
+        // 数据存储
+        reader.string("API_KEYS_STORAGE_PATH", &mut config.storage.api_keys_path);
+        reader.string("COOKIES_STORAGE_PATH", &mut config.storage.cookies_path);
+        reader.string("CREDENTIALS_STORAGE_PATH", &mut config.credentials.path);
+        reader.optional("CREDENTIALS_MASTER_KEY", &mut config.credentials.master_key);
+        reader.optional("CREDENTIALS_MASTER_KEY_FILE", &mut config.credentials.master_key_file);
+
+        // 外部密钥存储
+        reader.optional("VAULT_ADDR", &mut config.secrets.vault_addr);
+        reader.optional("VAULT_TOKEN", &mut config.secrets.vault_token);
+        reader.optional("VAULT_NAMESPACE", &mut config.secrets.vault_namespace);
+        reader.optional("AWS_REGION", &mut config.secrets.aws_region);
+        reader.optional("AWS_ACCESS_KEY_ID", &mut config.secrets.aws_access_key_id);
+        reader.optional("AWS_SECRET_ACCESS_KEY", &mut config.secrets.aws_secret_access_key);
+        reader.optional("AWS_SESSION_TOKEN", &mut config.secrets.aws_session_token);
+        reader.parse("SECRETS_REFRESH_SECS", &mut config.secrets.refresh_secs);
+
+        let mut errors = reader.errors;
+        errors.extend(config.validate());
+        if !errors.is_empty() {
+            bail!("Invalid configuration:\n  - {}", errors.join("\n  - "));
         }
-        
-        if let Ok(env_type) = env::var("ENVIRONMENT") {
-            config.environment = env_type;
+
+        Ok(config)
+    }
+
+    /// 检查配置取值范围，返回所有不合法项
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut check = |ok: bool, message: String| {
+            if !ok {
+                errors.push(message);
+            }
+        };
+
+        check(
+            self.identifier_seed == 0 || cfg!(any(test, feature = "deterministic-ids")),
+            "IDENTIFIER_SEED requires a build with the deterministic-ids feature".to_string(),
+        );
+        check(
+            self.identifier_seed == 0 || self.environment != "production",
+            "IDENTIFIER_SEED makes identifiers predictable and must not be set in production".to_string(),
+        );
+        check(!self.server.host.trim().is_empty(), "HOST must not be empty".to_string());
+        check(self.server.port != 0, "PORT must be between 1 and 65535".to_string());
+        for (name, origins) in [
+            ("CORS_ORIGINS", &self.server.cors_origins),
+            ("ADMIN_CORS_ORIGINS", &self.server.admin_cors_origins),
+        ] {
+            for origin in origins {
+                check(
+                    origin == "*" || origin.starts_with("http://") || origin.starts_with("https://"),
+                    format!("{}: {:?} must be * or an http(s) origin", name, origin),
+                );
+            }
+            check(
+                !(self.server.cors_allow_credentials && origins.iter().any(|origin| origin == "*")),
+                format!("{}=* cannot be combined with CORS_ALLOW_CREDENTIALS", name),
+            );
         }
-        
-        // DeepSeek相关配置
-        if let Ok(auth) = env::var("DEEP_SEEK_CHAT_AUTHORIZATION") {
-            config.deepseek.authorization = Some(auth);
+
+        // 外部密钥引用在启动解析后检查长度
+        for (label, token) in &self.access.admin_tokens {
+            check(
+                token.len() >= 16 || SecretRef::parse(token).is_some(),
+                format!("admin token {:?} (ADMIN_SECRET/ADMIN_TOKENS) must be at least 16 characters", label),
+            );
+        }
+
+        if let Some(secret) = &self.access.api_key_signing_secret {
+            check(secret.len() >= 32, "API_KEY_SIGNING_SECRET must be at least 32 characters".to_string());
         }
-        
-        if let Ok(base_url) = env::var("DEEPSEEK_BASE_URL") {
-            config.deepseek.base_url = base_url;
+
+        check(
+            self.server.load_shed_retry_after_secs >= 1,
+            "LOAD_SHED_RETRY_AFTER_SECS must be at least 1".to_string(),
+        );
+        check(
+            self.server.max_request_body_bytes >= 1024,
+            format!("MAX_REQUEST_BODY_BYTES must be at least 1024 (got {})", self.server.max_request_body_bytes),
+        );
+        check(self.server.max_messages >= 1, "MAX_MESSAGES must be at least 1".to_string());
+        check(self.server.max_message_chars >= 1, "MAX_MESSAGE_CHARS must be at least 1".to_string());
+        check(
+            self.server.tls_cert_path.is_some() == self.server.tls_key_path.is_some(),
+            "TLS_CERT_PATH and TLS_KEY_PATH must be set together".to_string(),
+        );
+        check(
+            self.server.tls_client_ca_path.is_none() || self.server.is_tls_enabled(),
+            "TLS_CLIENT_CA_PATH requires TLS_CERT_PATH and TLS_KEY_PATH".to_string(),
+        );
+        check(
+            self.server.tls_client_identities.is_empty() || self.server.tls_client_ca_path.is_some(),
+            "TLS_CLIENT_IDENTITIES requires TLS_CLIENT_CA_PATH".to_string(),
+        );
+
+        let deepseek = &self.deepseek;
+        check(
+            deepseek.base_url.starts_with("http://") || deepseek.base_url.starts_with("https://"),
+            format!("DEEPSEEK_BASE_URL must start with http:// or https:// (got {:?})", deepseek.base_url),
+        );
+        if deepseek.cassette_mode != CassetteMode::Off {
+            check(
+                deepseek.cassette_path.is_some(),
+                "UPSTREAM_CASSETTE_PATH is required when UPSTREAM_CASSETTE_MODE is record or replay".to_string(),
+            );
+            check(
+                !(deepseek.mock && deepseek.cassette_mode == CassetteMode::Replay),
+                "DEEPSEEK_MOCK cannot be combined with UPSTREAM_CASSETTE_MODE=replay".to_string(),
+            );
         }
-        
-        if let Ok(wasm_path) = env::var("WASM_PATH") {
-            config.deepseek.wasm_path = wasm_path;
+        check(
+            deepseek.chaos || deepseek.chaos_faults.is_empty(),
+            "CHAOS_FAULTS requires CHAOS_MODE=1".to_string(),
+        );
+        for mirror in &deepseek.mirrors {
+            check(
+                mirror.starts_with("http://") || mirror.starts_with("https://"),
+                format!("DEEPSEEK_MIRRORS entries must start with http:// or https:// (got {:?})", mirror),
+            );
         }
-        
-        Ok(config)
+        check(
+            deepseek.mirrors.is_empty() || deepseek.mirror_probe_interval_secs >= 1,
+            "MIRROR_PROBE_INTERVAL_SECS must be at least 1 when DEEPSEEK_MIRRORS is set".to_string(),
+        );
+        check(!deepseek.wasm_path.trim().is_empty(), "WASM_PATH must not be empty".to_string());
+        check(!deepseek.browser_profiles.is_empty(), "BROWSER_PROFILES must not be empty".to_string());
+        for name in &deepseek.browser_profiles {
+            check(
+                is_known_profile(name),
+                format!(
+                    "BROWSER_PROFILES contains unknown profile {} (expected one of {})",
+                    name,
+                    PROFILES.iter().map(|profile| profile.name).collect::<Vec<_>>().join(", ")
+                ),
+            );
+        }
+        check(
+            HeaderValue::from_str(&deepseek.app_version).is_ok(),
+            format!("X_APP_VERSION is not a valid header value (got {:?})", deepseek.app_version),
+        );
+        check(
+            self.credentials.master_key.is_none() || self.credentials.master_key_file.is_none(),
+            "CREDENTIALS_MASTER_KEY and CREDENTIALS_MASTER_KEY_FILE cannot both be set".to_string(),
+        );
+        check(
+            !self.credentials.path.trim().is_empty(),
+            "CREDENTIALS_STORAGE_PATH must not be empty".to_string(),
+        );
+        check(
+            deepseek.max_retry_count <= 10,
+            format!("MAX_RETRY_COUNT must be between 0 and 10 (got {})", deepseek.max_retry_count),
+        );
+        check(
+            deepseek.retry_delay_ms <= 60_000,
+            format!("RETRY_DELAY_MS must be between 0 and 60000 (got {})", deepseek.retry_delay_ms),
+        );
+        check(
+            deepseek.retry_max_delay_ms >= deepseek.retry_delay_ms && deepseek.retry_max_delay_ms <= 300_000,
+            format!(
+                "RETRY_MAX_DELAY_MS must be between RETRY_DELAY_MS and 300000 (got {})",
+                deepseek.retry_max_delay_ms
+            ),
+        );
+        check(
+            deepseek.access_token_expires >= 60,
+            format!("ACCESS_TOKEN_EXPIRES must be at least 60 seconds (got {})", deepseek.access_token_expires),
+        );
+        check(
+            deepseek.prewarm_sessions <= 16,
+            format!("PREWARM_SESSIONS must be between 0 and 16 (got {})", deepseek.prewarm_sessions),
+        );
+        check(
+            deepseek.prewarm_interval_secs >= 1,
+            "PREWARM_INTERVAL_SECS must be at least 1".to_string(),
+        );
+        check(
+            deepseek.prewarm_session_ttl_secs >= 1,
+            "PREWARM_SESSION_TTL_SECS must be at least 1".to_string(),
+        );
+        check(
+            deepseek.max_upstream_response_bytes >= 64 * 1024,
+            format!(
+                "MAX_UPSTREAM_RESPONSE_BYTES must be at least 65536 (got {})",
+                deepseek.max_upstream_response_bytes
+            ),
+        );
+
+        let cluster = &self.cluster;
+        if let Some(url) = &cluster.redis_url {
+            check(
+                url.starts_with("redis://") || url.starts_with("unix://"),
+                format!("REDIS_URL must start with redis:// or unix://, got {:?}", url),
+            );
+        }
+        check(cluster.leader_lease_secs >= 3, "LEADER_LEASE_SECS must be at least 3".to_string());
+        check(!cluster.instance_id.trim().is_empty(), "INSTANCE_ID must not be empty".to_string());
+
+        let logging = &self.logging;
+        check(
+            logging.rotation != LogRotation::Size || logging.max_bytes >= 1024,
+            "LOG_MAX_BYTES must be at least 1024 when LOG_ROTATION=size".to_string(),
+        );
+
+        let pool = &self.pool;
+        check(
+            pool.cooldown_error_threshold >= 1,
+            "COOLDOWN_ERROR_THRESHOLD must be at least 1".to_string(),
+        );
+        check(pool.cooldown_window_secs >= 1, "COOLDOWN_WINDOW_SECS must be at least 1".to_string());
+        check(pool.probe_interval_secs >= 1, "PROBE_INTERVAL_SECS must be at least 1".to_string());
+        check(
+            pool.account_max_concurrency >= 1,
+            "ACCOUNT_MAX_CONCURRENCY must be at least 1".to_string(),
+        );
+        check(
+            pool.fair_queue_timeout_secs >= 1,
+            "FAIR_QUEUE_TIMEOUT_SECS must be at least 1".to_string(),
+        );
+
+        let http = &self.http;
+        check(
+            http.connect_timeout_secs >= 1,
+            "HTTP_CONNECT_TIMEOUT_SECS must be at least 1".to_string(),
+        );
+        check(
+            http.request_timeout_secs >= http.connect_timeout_secs,
+            format!(
+                "HTTP_REQUEST_TIMEOUT_SECS must not be shorter than HTTP_CONNECT_TIMEOUT_SECS (got {})",
+                http.request_timeout_secs
+            ),
+        );
+        for (host, ip) in &http.dns_overrides {
+            check(
+                ip.parse::<IpAddr>().is_ok(),
+                format!("DNS_OVERRIDES entry {} must map to an IP address (got {})", host, ip),
+            );
+        }
+        if let Some(url) = &http.doh_url {
+            check(
+                url.starts_with("https://"),
+                format!("DNS_DOH_URL must start with https:// (got {})", url),
+            );
+        }
+        if let Some(proxy) = &http.proxy {
+            let scheme = proxy.split_once("://").map(|(scheme, _)| scheme.to_lowercase());
+            check(
+                matches!(scheme.as_deref(), Some("http" | "https" | "socks5" | "socks5h"))
+                    && reqwest::Proxy::all(proxy.as_str()).is_ok(),
+                format!("UPSTREAM_PROXY must be an http://, https://, socks5:// or socks5h:// URL (got {})", proxy),
+            );
+        }
+
+        check(
+            self.metrics.stats_window_secs >= 60,
+            format!("STATS_WINDOW_SECS must be at least 60 (got {})", self.metrics.stats_window_secs),
+        );
+        if let Some(url) = &self.metrics.push_url {
+            check(
+                url.starts_with("http://") || url.starts_with("https://"),
+                format!("METRICS_PUSH_URL must be an http:// or https:// URL (got {})", url),
+            );
+            check(self.metrics.push_interval_secs >= 1, "METRICS_PUSH_INTERVAL_SECS must be at least 1".to_string());
+            check(!self.metrics.push_job.trim().is_empty(), "METRICS_PUSH_JOB must not be empty".to_string());
+        }
+        check(
+            self.models.resolve_alias(&self.models.default_model).starts_with("deepseek"),
+            format!("DEFAULT_MODEL must be a deepseek model or alias (got {})", self.models.default_model),
+        );
+        for (alias, target) in &self.models.aliases {
+            check(
+                target.starts_with("deepseek"),
+                format!("MODEL_ALIASES: {} must map to a deepseek model (got {})", alias, target),
+            );
+        }
+        // 失败请求包含各租户的消息内容，只在管理接口有令牌保护时保留
+        check(
+            self.audit.failed_request_capacity == 0 || !self.access.admin_tokens.is_empty(),
+            "FAILED_REQUEST_CAPACITY requires ADMIN_SECRET or ADMIN_TOKENS".to_string(),
+        );
+        check(self.threads.max_threads >= 1, "THREAD_MAX_COUNT must be at least 1".to_string());
+        check(self.generations.ttl_secs >= 1, "GENERATION_TTL_SECS must be at least 1".to_string());
+        check(
+            self.generations.max_wait_secs <= 300,
+            format!("GENERATION_MAX_WAIT_SECS must be at most 300 (got {})", self.generations.max_wait_secs),
+        );
+        check(self.scripts.max_operations >= 1, "SCRIPT_MAX_OPERATIONS must be at least 1".to_string());
+        if !self.plugins.paths.is_empty() {
+            check(
+                cfg!(feature = "plugins"),
+                "PLUGIN_PATHS requires building with --features plugins".to_string(),
+            );
+            check(self.plugins.fuel >= 1, "PLUGIN_FUEL must be at least 1".to_string());
+            check(
+                self.plugins.max_memory_bytes >= 65536,
+                format!("PLUGIN_MAX_MEMORY_BYTES must be at least 65536 (got {})", self.plugins.max_memory_bytes),
+            );
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_is_valid() {
+        assert!(Config::default().validate().is_empty());
+    }
+
+    #[test]
+    fn test_validate_reports_every_invalid_value() {
+        let mut config = Config::default();
+        config.server.port = 0;
+        config.deepseek.base_url = "chat.deepseek.com".to_string();
+        config.deepseek.max_retry_count = 100;
+
+        let errors = config.validate();
+        assert_eq!(errors.len(), 3);
+        assert!(errors.iter().any(|e| e.starts_with("PORT")));
+        assert!(errors.iter().any(|e| e.starts_with("DEEPSEEK_BASE_URL")));
+        assert!(errors.iter().any(|e| e.starts_with("MAX_RETRY_COUNT")));
+    }
+
+    #[test]
+    fn test_pool_policy_for_account() {
+        let pool = Config::default().pool;
+        let overrides = AccountOverrides {
+            max_concurrency: Some(3),
+            messages_per_hour: Some(0),
+            cooldown_secs: Some(60),
+            ..Default::default()
+        };
+
+        let policy = pool.for_account(&overrides);
+        assert_eq!(policy.account_max_concurrency, 3);
+        assert_eq!(policy.account_messages_per_hour, 0);
+        assert_eq!(policy.cooldown_secs, 60);
+        assert_eq!(policy.cooldown_error_threshold, pool.cooldown_error_threshold);
+        assert_eq!(pool.for_account(&AccountOverrides::default()).account_max_concurrency, 1);
+    }
+
+    #[test]
+    fn test_model_alias_resolution() {
+        let mut models = Config::default().models;
+        models.aliases.insert("gpt-4o".to_string(), "deepseek-r1".to_string());
+
+        assert_eq!(models.resolve_alias("gpt-4o"), "deepseek-r1");
+        assert_eq!(models.resolve_alias("deepseek-search"), "deepseek-search");
+    }
+
+    #[test]
+    fn test_model_account_group_routing() {
+        let mut models = Config::default().models;
+        models.account_groups.insert("deepseek-r1-search".to_string(), "eu-proxy".to_string());
+        models.account_groups.insert("thinking".to_string(), "r1-capable".to_string());
+
+        assert_eq!(models.account_group("deepseek-r1-search", true).as_deref(), Some("eu-proxy"));
+        assert_eq!(models.account_group("deepseek", true).as_deref(), Some("r1-capable"));
+        assert_eq!(models.account_group("deepseek", false), None);
+    }
+
+    #[test]
+    fn test_fault_rates_parsing() {
+        let rates: FaultRates = "timeout=0.5, rate_limit=1".parse().unwrap();
+        assert_eq!(rates, FaultRates { timeout: 0.5, rate_limit: 1.0, ..Default::default() });
+        assert_eq!(rates.to_string().parse::<FaultRates>().unwrap(), rates);
+        assert!("".parse::<FaultRates>().unwrap().is_empty());
+        assert!("timeout=2".parse::<FaultRates>().is_err());
+        assert!("latency=0.1".parse::<FaultRates>().is_err());
     }
 }
@@ -0,0 +1,162 @@
+use crate::cli::{ControlArgs, StatusArgs, StopArgs};
+use crate::config::Config;
+use crate::services::SecretStore;
+use anyhow::{bail, Context};
+use colored::*;
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// 单次请求的超时
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+/// 等待实例退出时的轮询间隔
+const STOP_POLL_MILLIS: u64 = 500;
+
+/// 连接运行中实例的客户端
+struct Instance {
+    client: Client,
+    base_url: String,
+    token: Option<String>, // 管理令牌，可以是外部密钥引用解析后的值
+}
+
+impl Instance {
+    async fn connect(config: &Config, args: &ControlArgs, token: Option<&str>) -> anyhow::Result<Self> {
+        let local = args.url.is_none();
+        let base_url = match &args.url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => local_url(config),
+        };
+        let client = Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            // 本机实例常用自签名证书
+            .danger_accept_invalid_certs(local)
+            .build()?;
+        let token = match token.or_else(|| config.access.admin_tokens.values().next().map(String::as_str)) {
+            Some(token) => Some(SecretStore::new(config.secrets.clone()).resolve(token).await?),
+            None => None,
+        };
+        Ok(Self { client, base_url, token })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, format!("{}{}", self.base_url, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn get(&self, path: &str) -> anyhow::Result<Value> {
+        let response = self
+            .request(reqwest::Method::GET, path)
+            .send()
+            .await
+            .with_context(|| format!("无法连接 {}，实例可能未运行", self.base_url))?;
+        match response.status() {
+            StatusCode::UNAUTHORIZED => bail!("管理令牌无效，请用 --token 指定"),
+            StatusCode::FORBIDDEN => bail!("没有访问 {} 的权限，请用 --token 指定管理令牌", path),
+            // /admin/ready和/status异常时返回503，但响应体仍是完整的报告
+            _ => Ok(response.json().await?),
+        }
+    }
+}
+
+/// 按监听配置推断本机地址，监听所有地址时连接回环地址
+pub(crate) fn local_url(config: &Config) -> String {
+    let scheme = if config.server.is_tls_enabled() { "https" } else { "http" };
+    let host = match config.server.host.as_str() {
+        "" | "0.0.0.0" => "127.0.0.1".to_string(),
+        "::" | "[::]" => "[::1]".to_string(),
+        host if host.contains(':') && !host.starts_with('[') => format!("[{}]", host),
+        host => host.to_string(),
+    };
+    format!("{}://{}:{}", scheme, host, config.server.port)
+}
+
+/// 打印实例的就绪检查和上游状态，就绪时返回true
+pub async fn status(args: &StatusArgs) -> anyhow::Result<bool> {
+    let config = Config::load()?;
+    let instance = Instance::connect(&config, &args.control, args.token.as_deref()).await?;
+    let ready = instance.get("/admin/ready").await?;
+    let upstream = instance.get("/status").await?;
+
+    let text = |value: &Value, name: &str| value.get(name).and_then(Value::as_str).unwrap_or("unknown").to_string();
+    let healthy = text(&ready, "status") == "ready";
+    let status = text(&ready, "status");
+    println!(
+        "{} {} ({})",
+        instance.base_url,
+        if healthy { status.bright_green().bold() } else { status.bright_red().bold() },
+        text(&ready, "instance_id"),
+    );
+    if ready.get("leader").and_then(Value::as_bool).unwrap_or(false) {
+        println!("领导者: 是，执行独占的后台任务");
+    }
+    for check in ready.get("checks").and_then(Value::as_array).into_iter().flatten() {
+        let label = if check.get("ok").and_then(Value::as_bool).unwrap_or(false) {
+            "[PASS]".bright_green().bold()
+        } else {
+            "[FAIL]".bright_red().bold()
+        };
+        println!(
+            "{} {}: {} ({} ms)",
+            label,
+            text(check, "name"),
+            text(check, "detail"),
+            check.get("latency_ms").and_then(Value::as_u64).unwrap_or(0),
+        );
+    }
+    println!("上游状态: {}", text(&upstream, "state"));
+    Ok(healthy)
+}
+
+/// 请求实例优雅退出，wait大于0时等待实例停止监听
+pub async fn stop(args: &StopArgs) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let instance = Instance::connect(&config, &args.control, args.token.as_deref()).await?;
+    let response = instance
+        .request(reqwest::Method::POST, "/admin/shutdown")
+        .send()
+        .await
+        .with_context(|| format!("无法连接 {}，实例可能未运行", instance.base_url))?;
+    match response.status() {
+        status if status.is_success() => println!("{} 已请求 {} 停止", "[OK]".bright_green().bold(), instance.base_url),
+        StatusCode::UNAUTHORIZED => bail!("管理令牌无效，请用 --token 指定"),
+        StatusCode::FORBIDDEN => bail!("实例未配置管理令牌，无法通过管理接口停止，请使用SIGTERM"),
+        status => bail!("停止请求失败: HTTP {}", status),
+    }
+
+    if args.wait == 0 {
+        return Ok(());
+    }
+    // 实例排空处理中的请求后才停止监听
+    let deadline = Instant::now() + Duration::from_secs(args.wait);
+    while Instant::now() < deadline {
+        if instance.client.get(format!("{}/", instance.base_url)).send().await.is_err() {
+            println!("{} 实例已退出", "[OK]".bright_green().bold());
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(STOP_POLL_MILLIS)).await;
+    }
+    bail!("实例在 {} 秒内未退出，可能仍有未结束的请求", args.wait)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_url() {
+        let mut config = Config::default();
+        config.server.port = 3000;
+        config.server.host = "0.0.0.0".to_string();
+        assert_eq!(local_url(&config), "http://127.0.0.1:3000");
+        config.server.host = "::".to_string();
+        assert_eq!(local_url(&config), "http://[::1]:3000");
+        config.server.host = "fe80::1".to_string();
+        assert_eq!(local_url(&config), "http://[fe80::1]:3000");
+        config.server.host = "localhost".to_string();
+        assert_eq!(local_url(&config), "http://localhost:3000");
+    }
+}
@@ -1,9 +1,10 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use serde_json::json;
+use std::time::Duration;
 use thiserror::Error;
 
 pub type ApiResult<T> = Result<T, ApiError>;
@@ -12,6 +13,9 @@ pub type AppResult<T> = Result<T, AppError>; // 添加别名
 // 添加AppError别名
 pub use ApiError as AppError;
 
+/// 503没有更具体的等待时间时建议客户端等待的秒数
+const DEFAULT_RETRY_AFTER_SECS: u64 = 5;
+
 #[derive(Error, Debug)]
 pub enum ApiError {
     #[error("HTTP request failed: {0}")]
@@ -40,6 +44,12 @@ pub enum ApiError {
     
     #[error("Service unavailable: {0}")]
     ServiceUnavailable(String),
+
+    #[error("Service unavailable: {message}")]
+    Unavailable { message: String, retry_after_secs: u64 }, // 已知何时恢复（账号冷却、速率预算）
+
+    #[error("Service unavailable: {0}")]
+    Banned(String), // 上游明确提示账号或IP被封禁，检测到后自动停用账号
     
     #[error("Internal server error: {0}")]
     InternalError(String),
@@ -54,6 +64,9 @@ pub enum ApiError {
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
     
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Not found: {0}")]
     NotFound(String),
     
@@ -62,37 +75,225 @@ pub enum ApiError {
     
     #[error("Internal error: {0}")]
     Internal(String),
+
+    #[error("Too many requests: {message}")]
+    RateLimited { message: String, retry_after_secs: u64, model: Option<String> }, // model为被上游限流的模型
+}
+
+impl ApiError {
+    /// 是否像是账号被限流或封禁（上游429/403、限流或封禁提示），按错误类型和状态码判断，
+    /// 不匹配错误文本，否则内容过滤等本地拒绝也会被当作封禁
+    pub fn is_ban_like(&self) -> bool {
+        match self {
+            ApiError::RateLimited { .. } | ApiError::Banned(_) => true,
+            ApiError::HttpRequest(e) => e.status().is_some_and(|status| matches!(status.as_u16(), 403 | 429)),
+            ApiError::DeepSeekApiError { code, .. } => matches!(code, 403 | 429),
+            _ => false,
+        }
+    }
+
+    /// 是否由客户端请求本身导致（参数错误、权限不足、内容被过滤等），这类错误不计入账号的错误
+    pub fn is_client_error(&self) -> bool {
+        matches!(
+            self,
+            ApiError::InvalidRequest(_)
+                | ApiError::BadRequest(_)
+                | ApiError::Unauthorized(_)
+                | ApiError::Forbidden(_)
+                | ApiError::NotFound(_)
+        )
+    }
+}
+
+impl ApiError {
+    /// 是否是账号或IP地址被封禁（不包括限流），检测到后自动停用账号
+    pub fn is_banned(&self) -> bool {
+        matches!(self, ApiError::Banned(_))
+    }
+
+    /// 上游错误是否值得重试；认证失败、封禁、限流和请求本身的问题重试也不会成功
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            // 同一账号被限流时立即重试只会继续被拒绝，直接返回429并让账号冷却
+            ApiError::RateLimited { .. } | ApiError::Banned(_) => false,
+            ApiError::Unavailable { .. } => true,
+            _ if self.is_ban_like() => false,
+            ApiError::HttpRequest(e) => e
+                .status()
+                .is_none_or(|status| status.is_server_error()),
+            ApiError::Timeout(_)
+            | ApiError::ServiceUnavailable(_)
+            | ApiError::ExternalApi(_)
+            | ApiError::ChallengeError(_)
+            | ApiError::IoError(_) => true,
+            _ => false,
+        }
+    }
+
+    /// 复制错误，供合并请求的多个调用方共享；底层库错误转为同等语义的文本错误
+    pub fn duplicate(&self) -> ApiError {
+        match self {
+            ApiError::HttpRequest(e) => ApiError::ExternalApi(e.to_string()),
+            ApiError::JsonError(e) => ApiError::InternalError(e.to_string()),
+            ApiError::IoError(e) => ApiError::InternalError(e.to_string()),
+            ApiError::ConfigError(m) => ApiError::ConfigError(m.clone()),
+            ApiError::TokenError(m) => ApiError::TokenError(m.clone()),
+            ApiError::ChallengeError(m) => ApiError::ChallengeError(m.clone()),
+            ApiError::DeepSeekApiError { code, message } => ApiError::DeepSeekApiError {
+                code: *code,
+                message: message.clone(),
+            },
+            ApiError::InvalidRequest(m) => ApiError::InvalidRequest(m.clone()),
+            ApiError::ServiceUnavailable(m) => ApiError::ServiceUnavailable(m.clone()),
+            ApiError::Banned(m) => ApiError::Banned(m.clone()),
+            ApiError::Unavailable { message, retry_after_secs } => ApiError::Unavailable {
+                message: message.clone(),
+                retry_after_secs: *retry_after_secs,
+            },
+            ApiError::InternalError(m) => ApiError::InternalError(m.clone()),
+            ApiError::Timeout(m) => ApiError::Timeout(m.clone()),
+            ApiError::ExternalApi(m) => ApiError::ExternalApi(m.clone()),
+            ApiError::Unauthorized(m) => ApiError::Unauthorized(m.clone()),
+            ApiError::Forbidden(m) => ApiError::Forbidden(m.clone()),
+            ApiError::NotFound(m) => ApiError::NotFound(m.clone()),
+            ApiError::BadRequest(m) => ApiError::BadRequest(m.clone()),
+            ApiError::Internal(m) => ApiError::Internal(m.clone()),
+            ApiError::RateLimited { message, retry_after_secs, model } => ApiError::RateLimited {
+                message: message.clone(),
+                retry_after_secs: *retry_after_secs,
+                model: model.clone(),
+            },
+        }
+    }
+
+    /// 为限流错误标注受影响的模型
+    pub fn with_model(self, model: &str) -> ApiError {
+        match self {
+            ApiError::RateLimited { message, retry_after_secs, model: None } => ApiError::RateLimited {
+                message,
+                retry_after_secs,
+                model: Some(model.to_string()),
+            },
+            e => e,
+        }
+    }
+
+    /// 上游要求的重试等待时间
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ApiError::RateLimited { retry_after_secs, .. } | ApiError::Unavailable { retry_after_secs, .. } => {
+                Some(Duration::from_secs(*retry_after_secs))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ApiError {
+    /// 错误对应的HTTP状态码
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::HttpRequest(_) => StatusCode::BAD_GATEWAY,
+            ApiError::JsonError(_) => StatusCode::BAD_REQUEST,
+            ApiError::IoError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::ConfigError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::TokenError(_) => StatusCode::UNAUTHORIZED,
+            ApiError::ChallengeError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::DeepSeekApiError { .. } => StatusCode::BAD_REQUEST,
+            ApiError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Banned(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Timeout(_) => StatusCode::REQUEST_TIMEOUT,
+            ApiError::ExternalApi(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    /// OpenAI格式的错误体 `{"error": {...}}`，HTTP响应和流中的错误事件共用
+    pub fn to_json(&self) -> serde_json::Value {
+        let (error_type, model) = match self {
+            ApiError::RateLimited { model, .. } => ("rate_limit_error", model.clone()),
+            _ => ("api_error", None),
+        };
+
+        let mut error = json!({
+            "message": self.to_string(),
+            "type": error_type,
+            "param": null,
+            "code": self.status_code().as_u16()
+        });
+        if let Some(model) = model {
+            error["model"] = json!(model);
+        }
+        json!({ "error": error })
+    }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            ApiError::HttpRequest(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
-            ApiError::JsonError(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            ApiError::IoError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            ApiError::ConfigError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            ApiError::TokenError(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
-            ApiError::ChallengeError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            ApiError::DeepSeekApiError { .. } => (StatusCode::BAD_REQUEST, self.to_string()),
-            ApiError::InvalidRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            ApiError::ServiceUnavailable(_) => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
-            ApiError::InternalError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            ApiError::Timeout(_) => (StatusCode::REQUEST_TIMEOUT, self.to_string()),
-            ApiError::ExternalApi(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
-            ApiError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
-            ApiError::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            ApiError::BadRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            ApiError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+        // 429和503都带上Retry-After，便于客户端按时退避
+        let retry_after = match &self {
+            ApiError::RateLimited { retry_after_secs, .. } | ApiError::Unavailable { retry_after_secs, .. } => {
+                Some((*retry_after_secs).max(1))
+            }
+            ApiError::ServiceUnavailable(_) | ApiError::Banned(_) => Some(DEFAULT_RETRY_AFTER_SECS),
+            _ => None,
         };
 
-        let body = Json(json!({
-            "error": {
-                "message": error_message,
-                "type": "api_error",
-                "code": status.as_u16()
-            }
-        }));
+        let status = self.status_code();
+        let body = Json(self.to_json());
+
+        match retry_after {
+            Some(secs) => (status, [(header::RETRY_AFTER, secs.to_string())], body).into_response(),
+            None => (status, body).into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_confirmed_bans_disable_accounts() {
+        assert!(ApiError::Banned("账号或IP地址被封禁: 账号已被封禁".to_string()).is_banned());
+        // 创建会话的一般失败和限流不停用账号
+        assert!(!ApiError::ServiceUnavailable("创建会话失败: code=1 msg=".to_string()).is_banned());
+        let rate_limited = ApiError::RateLimited { message: "封禁".to_string(), retry_after_secs: 1, model: None };
+        assert!(!rate_limited.is_banned());
+        assert!(!ApiError::Banned(String::new()).is_retryable());
+        assert_eq!(ApiError::Banned(String::new()).status_code(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_ban_like_classified_by_variant() {
+        assert!(ApiError::RateLimited { message: String::new(), retry_after_secs: 1, model: None }.is_ban_like());
+        assert!(ApiError::DeepSeekApiError { code: 429, message: String::new() }.is_ban_like());
+        // 错误文本中出现403/forbidden不算封禁
+        let filtered = ApiError::Forbidden("输入包含被过滤的内容".to_string());
+        assert!(!filtered.is_ban_like());
+        assert!(filtered.is_client_error());
+        assert!(!ApiError::ExternalApi("upstream returned 403".to_string()).is_ban_like());
+        assert!(!ApiError::InvalidRequest("429".to_string()).is_ban_like());
+        assert!(!ApiError::ServiceUnavailable(String::new()).is_client_error());
+    }
 
-        (status, body).into_response()
+    #[test]
+    fn test_retryable_errors() {
+        assert!(ApiError::Timeout(String::new()).is_retryable());
+        assert!(ApiError::ServiceUnavailable(String::new()).is_retryable());
+        assert!(ApiError::Unavailable { message: String::new(), retry_after_secs: 1 }.is_retryable());
+        // 限流、认证失败和请求本身的问题重试也不会成功
+        assert!(!ApiError::RateLimited { message: String::new(), retry_after_secs: 1, model: None }.is_retryable());
+        assert!(!ApiError::DeepSeekApiError { code: 429, message: String::new() }.is_retryable());
+        assert!(!ApiError::TokenError(String::new()).is_retryable());
+        assert!(!ApiError::InvalidRequest(String::new()).is_retryable());
     }
 }
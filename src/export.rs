@@ -0,0 +1,208 @@
+use crate::cli::{ExportUsageArgs, UsageDimension, UsageFormat};
+use crate::config::Config;
+use chrono::{DateTime, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+/// 审计日志中汇总用量所需的字段
+#[derive(Debug, Deserialize)]
+struct UsageRecord {
+    timestamp: i64,
+    #[serde(default)]
+    api_key_id: Option<String>,
+    model: String,
+    #[serde(default)]
+    prompt_tokens: Option<u32>,
+    #[serde(default)]
+    completion_tokens: Option<u32>,
+    #[serde(default)]
+    outcome: String,
+    #[serde(default)]
+    latency_ms: u64,
+}
+
+/// 汇总维度的取值，未按某个维度汇总时为None
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct GroupKey {
+    day: Option<NaiveDate>,
+    api_key_id: Option<String>,
+    model: Option<String>,
+}
+
+/// 报告中的一行
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+struct UsageRow {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    day: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    requests: u64,
+    errors: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    total_tokens: u64,
+    avg_latency_ms: u64,
+}
+
+/// 读取审计日志并输出汇总报告
+pub fn export_usage(args: &ExportUsageArgs) -> anyhow::Result<()> {
+    let input = match &args.input {
+        Some(path) => path.clone(),
+        None => Config::load()?
+            .audit
+            .path
+            .ok_or_else(|| anyhow::anyhow!("未设置 AUDIT_LOG_PATH，请用 --input 指定审计日志"))?
+            .into(),
+    };
+    let file = File::open(&input).map_err(|e| anyhow::anyhow!("无法读取 {}: {}", input.display(), e))?;
+
+    let mut skipped = 0;
+    let mut groups: BTreeMap<GroupKey, UsageRow> = BTreeMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(record) = serde_json::from_str::<UsageRecord>(&line) else {
+            skipped += 1;
+            continue;
+        };
+        let Some(day) = DateTime::from_timestamp(record.timestamp, 0).map(|at| at.date_naive()) else {
+            skipped += 1;
+            continue;
+        };
+        if args.since.is_some_and(|since| day < since) || args.until.is_some_and(|until| day > until) {
+            continue;
+        }
+        add(&mut groups, &args.by, day, &record);
+    }
+    if skipped > 0 {
+        eprintln!("跳过了 {} 行无法解析的记录", skipped);
+    }
+
+    let rows: Vec<UsageRow> = groups.into_values().collect();
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path).map_err(|e| anyhow::anyhow!("无法写入 {}: {}", path.display(), e))?),
+        None => Box::new(io::stdout().lock()),
+    };
+    match args.format {
+        UsageFormat::Csv => write_csv(&mut output, &args.by, &rows)?,
+        UsageFormat::Json => {
+            serde_json::to_writer_pretty(&mut output, &rows)?;
+            writeln!(output)?;
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// 把一条记录累加到所属分组
+fn add(groups: &mut BTreeMap<GroupKey, UsageRow>, by: &[UsageDimension], day: NaiveDate, record: &UsageRecord) {
+    let key = GroupKey {
+        day: by.contains(&UsageDimension::Day).then_some(day),
+        api_key_id: by
+            .contains(&UsageDimension::Key)
+            .then(|| record.api_key_id.clone().unwrap_or_default()),
+        model: by.contains(&UsageDimension::Model).then(|| record.model.clone()),
+    };
+    let row = groups.entry(key.clone()).or_insert_with(|| UsageRow {
+        day: key.day.map(|day| day.to_string()),
+        api_key_id: key.api_key_id,
+        model: key.model,
+        ..Default::default()
+    });
+
+    // 平均延迟按累计值增量更新
+    let total_latency = row.avg_latency_ms * row.requests + record.latency_ms;
+    row.requests += 1;
+    row.avg_latency_ms = total_latency / row.requests;
+    if record.outcome.starts_with("error") {
+        row.errors += 1;
+    }
+    let (prompt, completion) = (record.prompt_tokens.unwrap_or(0) as u64, record.completion_tokens.unwrap_or(0) as u64);
+    row.prompt_tokens += prompt;
+    row.completion_tokens += completion;
+    row.total_tokens += prompt + completion;
+}
+
+fn write_csv(output: &mut dyn Write, by: &[UsageDimension], rows: &[UsageRow]) -> io::Result<()> {
+    let mut header = Vec::new();
+    for (dimension, name) in [(UsageDimension::Day, "day"), (UsageDimension::Key, "api_key_id"), (UsageDimension::Model, "model")] {
+        if by.contains(&dimension) {
+            header.push(name);
+        }
+    }
+    header.extend(["requests", "errors", "prompt_tokens", "completion_tokens", "total_tokens", "avg_latency_ms"]);
+    writeln!(output, "{}", header.join(","))?;
+
+    for row in rows {
+        let mut fields: Vec<String> = [&row.day, &row.api_key_id, &row.model]
+            .into_iter()
+            .flatten()
+            .map(|value| csv_field(value))
+            .collect();
+        fields.extend(
+            [row.requests, row.errors, row.prompt_tokens, row.completion_tokens, row.total_tokens, row.avg_latency_ms]
+                .map(|value| value.to_string()),
+        );
+        writeln!(output, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+/// 含逗号、引号或换行的字段加引号
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(line: &str) -> UsageRecord {
+        serde_json::from_str(line).unwrap()
+    }
+
+    #[test]
+    fn test_aggregates_by_dimensions() {
+        let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let records = [
+            record(r#"{"timestamp":1704067200,"api_key_id":"a","model":"deepseek","prompt_tokens":10,"completion_tokens":5,"outcome":"success","latency_ms":100}"#),
+            record(r#"{"timestamp":1704067300,"api_key_id":"a","model":"deepseek-r1","outcome":"error: timeout","latency_ms":300}"#),
+            record(r#"{"timestamp":1704067400,"api_key_id":"b","model":"deepseek","prompt_tokens":1,"completion_tokens":1,"outcome":"coalesced","latency_ms":50}"#),
+        ];
+
+        let mut groups = BTreeMap::new();
+        for record in &records {
+            add(&mut groups, &[UsageDimension::Key], day, record);
+        }
+        let rows: Vec<UsageRow> = groups.into_values().collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].api_key_id.as_deref(), Some("a"));
+        assert_eq!((rows[0].requests, rows[0].errors, rows[0].total_tokens, rows[0].avg_latency_ms), (2, 1, 15, 200));
+        assert_eq!(rows[0].model, None);
+
+        let mut output = Vec::new();
+        write_csv(&mut output, &[UsageDimension::Key], &rows).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "api_key_id,requests,errors,prompt_tokens,completion_tokens,total_tokens,avg_latency_ms\n\
+             a,2,1,10,5,15,200\n\
+             b,1,0,1,1,2,50\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_field_quoting() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+}
@@ -0,0 +1,209 @@
+use crate::config::IpRange;
+use crate::error::ApiError;
+use crate::handlers::{limits::EXEMPT_PATHS, AppState};
+use crate::services::SecretStore;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use subtle::ConstantTimeEq;
+
+/// 管理接口路径前缀，其余路径按聊天接口处理
+const ADMIN_PREFIXES: &[&str] = &["/accounts", "/api_keys", "/auth", "/admin", "/credentials"];
+
+/// 除管理接口外同样需要管理令牌的路径
+const ADMIN_AUTH_PATHS: &[&str] = &["/token/check"];
+
+fn is_admin_path(path: &str) -> bool {
+    ADMIN_PREFIXES.iter().any(|prefix| path == *prefix || path.starts_with(&format!("{}/", prefix)))
+}
+
+/// 管理接口要求 `Authorization: Bearer <令牌>`，并按令牌标签记录操作；
+/// 未配置管理令牌时只接受本机直连（不经过代理）的请求
+pub async fn admin_auth(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    let tokens = &state.config.access.admin_tokens;
+    if !(is_admin_path(path) || ADMIN_AUTH_PATHS.contains(&path)) {
+        return next.run(request).await;
+    }
+    if tokens.is_empty() {
+        let peer = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(peer)| peer.ip());
+        if peer.is_some_and(|ip| is_local_request(ip, request.headers())) {
+            return next.run(request).await;
+        }
+        tracing::warn!(client = ?peer, "Rejected remote request to {} without ADMIN_SECRET", path);
+        return ApiError::Forbidden("未配置ADMIN_SECRET时管理接口只允许本机访问".to_string()).into_response();
+    }
+
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    match presented.and_then(|token| match_admin_token(tokens, &state.secrets, token)) {
+        Some(label) => {
+            tracing::info!(admin = %label, "{} {}", request.method(), path);
+            next.run(request).await
+        }
+        None => {
+            tracing::warn!("Rejected unauthenticated request to {}", path);
+            ApiError::Unauthorized("缺少或无效的管理令牌".to_string()).into_response()
+        }
+    }
+}
+
+/// 常量时间比较所有令牌，返回匹配令牌的标签；先哈希避免泄露令牌长度。
+/// 令牌可以是外部密钥引用，按当前解析的值比较，轮换后立即生效
+fn match_admin_token<'a>(tokens: &'a BTreeMap<String, String>, secrets: &SecretStore, presented: &str) -> Option<&'a str> {
+    let presented = Sha256::digest(presented.as_bytes());
+    let mut matched = None;
+    for (label, token) in tokens {
+        if bool::from(Sha256::digest(secrets.get(token).as_bytes()).ct_eq(&presented)) {
+            matched = Some(label.as_str());
+        }
+    }
+    matched
+}
+
+/// 本机直连的请求：回环地址且没有代理转发的头，本机反向代理转发的外部请求不算
+fn is_local_request(peer: IpAddr, headers: &HeaderMap) -> bool {
+    peer.is_loopback() && !headers.contains_key("x-forwarded-for") && !headers.contains_key(header::FORWARDED)
+}
+
+/// 聊天接口上的管理员身份：Authorization已用于API密钥，管理令牌放在 `X-Admin-Token`；未配置管理令牌时没有管理员
+pub(crate) fn is_admin_request(state: &AppState, headers: &HeaderMap) -> bool {
+    let tokens = &state.config.access.admin_tokens;
+    headers
+        .get("x-admin-token")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|token| match_admin_token(tokens, &state.secrets, token.trim()))
+        .inspect(|label| tracing::info!(admin = %label, "Admin debug request"))
+        .is_some()
+}
+
+/// 按来源IP限制聊天接口和管理接口的访问
+pub async fn ip_filter(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    if EXEMPT_PATHS.contains(&path) {
+        return next.run(request).await;
+    }
+
+    let access = &state.config.access;
+    let (allow, deny) = if is_admin_path(path) {
+        (&access.admin_allow, &access.admin_deny)
+    } else {
+        (&access.chat_allow, &access.chat_deny)
+    };
+    if allow.is_empty() && deny.is_empty() {
+        return next.run(request).await;
+    }
+
+    // 无法确定来源地址时拒绝访问
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| client_ip(peer.ip(), request.headers(), &access.trusted_proxies));
+    match client {
+        Some(ip) if is_allowed(&ip, allow, deny) => next.run(request).await,
+        client => {
+            tracing::warn!(client = ?client, "Rejected request to {} by IP filter", path);
+            ApiError::Forbidden("来源IP不允许访问该接口".to_string()).into_response()
+        }
+    }
+}
+
+/// 确定客户端IP：直连地址是可信代理时，从右向左取X-Forwarded-For中第一个不可信的地址
+fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpRange]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|range| range.contains(ip));
+    if !is_trusted(&peer) {
+        return peer;
+    }
+
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect();
+
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted(ip))
+        .or_else(|| forwarded.first())
+        .copied()
+        .unwrap_or(peer)
+}
+
+fn is_allowed(ip: &IpAddr, allow: &[IpRange], deny: &[IpRange]) -> bool {
+    if deny.iter().any(|range| range.contains(ip)) {
+        return false;
+    }
+    allow.is_empty() || allow.iter().any(|range| range.contains(ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(items: &[&str]) -> Vec<IpRange> {
+        items.iter().map(|item| item.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_client_ip_honors_trusted_proxies_only() {
+        let proxies = ranges(&["10.0.0.0/8"]);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "203.0.113.7, 198.51.100.2, 10.0.0.3".parse().unwrap());
+
+        // 最右侧不可信的地址才是客户端，更左侧的可以被伪造
+        let via_proxy = client_ip("10.0.0.1".parse().unwrap(), &headers, &proxies);
+        assert_eq!(via_proxy, "198.51.100.2".parse::<IpAddr>().unwrap());
+        let direct = client_ip("192.0.2.1".parse().unwrap(), &headers, &proxies);
+        assert_eq!(direct, "192.0.2.1".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn test_deny_list_takes_precedence() {
+        let allow = ranges(&["192.168.0.0/16"]);
+        let deny = ranges(&["192.168.1.10"]);
+
+        assert!(is_allowed(&"192.168.2.1".parse().unwrap(), &allow, &deny));
+        assert!(!is_allowed(&"192.168.1.10".parse().unwrap(), &allow, &deny));
+        assert!(!is_allowed(&"8.8.8.8".parse().unwrap(), &allow, &deny));
+        assert!(is_allowed(&"8.8.8.8".parse().unwrap(), &[], &deny));
+    }
+
+    #[test]
+    fn test_admin_token_matching() {
+        let tokens = BTreeMap::from([
+            ("ops".to_string(), "ops-token-0123456789".to_string()),
+            ("ci".to_string(), "ci-token-0123456789".to_string()),
+        ]);
+
+        let secrets = SecretStore::new(Default::default());
+        assert_eq!(match_admin_token(&tokens, &secrets, "ci-token-0123456789"), Some("ci"));
+        assert_eq!(match_admin_token(&tokens, &secrets, "ci-token-012345678"), None);
+        assert!(is_admin_path("/admin/reload"));
+        assert!(!is_admin_path("/authorize"));
+    }
+
+    #[test]
+    fn test_local_request_requires_direct_loopback() {
+        let mut headers = HeaderMap::new();
+        assert!(is_local_request("127.0.0.1".parse().unwrap(), &headers));
+        assert!(is_local_request("::1".parse().unwrap(), &headers));
+        assert!(!is_local_request("192.0.2.1".parse().unwrap(), &headers));
+
+        // 本机反向代理转发的请求来自外部
+        headers.insert("x-forwarded-for", "203.0.113.7".parse().unwrap());
+        assert!(!is_local_request("127.0.0.1".parse().unwrap(), &headers));
+    }
+}
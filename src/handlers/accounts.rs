@@ -0,0 +1,107 @@
+use crate::error::ApiResult;
+use crate::handlers::AppState;
+use crate::services::account_usage::USAGE_WINDOWS;
+use crate::models::{AccountGroupsRequest, AccountInfo, AccountLinkRequest, AccountOverrides};
+use axum::extract::{Json, Path, State};
+use serde_json::{json, Value};
+
+/// 列出所有账号及其统计
+pub async fn list_accounts(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "object": "list",
+        "data": state.api_key_manager.list_accounts()
+    }))
+}
+
+/// 列出账号分组及其成员
+pub async fn list_account_groups(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "object": "list",
+        "data": state.api_key_manager.list_account_groups()
+    }))
+}
+
+/// 各账号在不同时间窗口内的补全数、深度思考配额消耗、错误率和繁忙程度
+pub async fn account_usage(State(state): State<AppState>) -> Json<Value> {
+    let data: Vec<Value> = state
+        .api_key_manager
+        .list_accounts()
+        .into_iter()
+        .map(|account| {
+            json!({
+                "id": account.id,
+                "email": account.email,
+                "enabled": account.enabled,
+                "cooling_down": account.cooling_down,
+                "thinking_quota": account.thinking_quota,
+                "windows": state.account_usage.windows(&account.email),
+            })
+        })
+        .collect();
+    Json(json!({
+        "object": "list",
+        "windows": USAGE_WINDOWS.iter().map(|(name, secs)| json!({"name": name, "secs": secs})).collect::<Vec<_>>(),
+        "data": data
+    }))
+}
+
+/// 获取单个账号
+pub async fn get_account(State(state): State<AppState>, Path(id): Path<String>) -> ApiResult<Json<AccountInfo>> {
+    Ok(Json(state.api_key_manager.get_account(&id)?))
+}
+
+/// 删除账号
+pub async fn delete_account(State(state): State<AppState>, Path(id): Path<String>) -> ApiResult<Json<Value>> {
+    state.api_key_manager.delete_account(&id)?;
+    Ok(Json(json!({
+        "id": id,
+        "object": "account",
+        "deleted": true
+    })))
+}
+
+/// 启用账号
+pub async fn enable_account(State(state): State<AppState>, Path(id): Path<String>) -> ApiResult<Json<AccountInfo>> {
+    Ok(Json(state.api_key_manager.set_account_enabled(&id, true)?))
+}
+
+/// 停用账号
+pub async fn disable_account(State(state): State<AppState>, Path(id): Path<String>) -> ApiResult<Json<AccountInfo>> {
+    Ok(Json(state.api_key_manager.set_account_enabled(&id, false)?))
+}
+
+/// 把账号关联到API密钥
+pub async fn link_account(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<AccountLinkRequest>,
+) -> ApiResult<Json<AccountInfo>> {
+    Ok(Json(state.api_key_manager.link_account(&id, &request.api_key)?))
+}
+
+/// 取消账号与API密钥的关联
+pub async fn unlink_account(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<AccountLinkRequest>,
+) -> ApiResult<Json<AccountInfo>> {
+    Ok(Json(state.api_key_manager.unlink_account(&id, &request.api_key)?))
+}
+
+/// 设置账号所属的分组
+pub async fn set_account_groups(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<AccountGroupsRequest>,
+) -> ApiResult<Json<AccountInfo>> {
+    Ok(Json(state.api_key_manager.set_account_groups(&id, request.groups)?))
+}
+
+/// 设置账号单独的并发、速率和冷却策略
+pub async fn set_account_overrides(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(overrides): Json<AccountOverrides>,
+) -> ApiResult<Json<AccountInfo>> {
+    Ok(Json(state.api_key_manager.set_account_overrides(&id, overrides)?))
+}
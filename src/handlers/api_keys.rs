@@ -6,7 +6,10 @@ use crate::{
     error::{ApiError, ApiResult},
     models::*,
     handlers::AppState,
+    services::request_stats::RequestStatsReport,
+    services::response_cache::CacheStats,
 };
+use serde::Serialize;
 use tracing::{info, warn};
 
 /// 创建API密钥
@@ -16,10 +19,17 @@ pub async fn create_api_key(
 ) -> ApiResult<JsonResponse<CreateApiKeyResponse>> {
     info!("创建API密钥请求: {}", request.name);
 
-    let response = state.api_key_manager.create_api_key(
-        request.name,
-        request.expires_days,
-    ).map_err(|e| ApiError::Internal(e.to_string()))?;
+    if let Some(model) = &request.default_model {
+        if !state.config.models.resolve_alias(&model.to_lowercase()).starts_with("deepseek") {
+            return Err(ApiError::BadRequest(format!("不支持的默认模型: {}", model)));
+        }
+    }
+    if request.overrides.request_timeout_secs == Some(0) {
+        return Err(ApiError::BadRequest("request_timeout_secs必须大于0".to_string()));
+    }
+
+    let response = state.api_key_manager.create_api_key(request)
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
 
     Ok(JsonResponse(response))
 }
@@ -31,10 +41,13 @@ pub async fn add_account(
 ) -> ApiResult<JsonResponse<AddAccountResponse>> {
     info!("为API密钥添加账户: {}", request.email);
 
+    // 密码可以是外部密钥引用
+    let password = state.secrets.resolve(&request.password).await?;
+
     let response = state.api_key_manager.add_account(
         request.api_key,
         request.email,
-        request.password,
+        password,
     ).await.map_err(|e| ApiError::Internal(e.to_string()))?;
 
     Ok(JsonResponse(response))
@@ -118,9 +131,33 @@ pub async fn verify_user_token(
 
     Ok(JsonResponse(TokenCheckResponse {
         live: is_valid,
+        expires_at: None,
     }))
 }
 
+/// 重新加载API密钥存储并重建账号池
+pub async fn reload_storage(
+    State(state): State<AppState>,
+) -> ApiResult<JsonResponse<ReloadResponse>> {
+    info!("重新加载API密钥存储");
+    let response = state.api_key_manager.reload().await?;
+    Ok(JsonResponse(response))
+}
+
+/// 停止接受新连接，等待处理中的请求结束后退出（与SIGTERM相同）。
+/// 即使在本机也必须配置管理令牌，令牌已由admin_auth校验
+pub async fn shutdown(State(state): State<AppState>) -> ApiResult<JsonResponse<serde_json::Value>> {
+    if state.config.access.admin_tokens.is_empty() {
+        return Err(ApiError::Forbidden("停止服务需要配置ADMIN_SECRET或ADMIN_TOKENS".to_string()));
+    }
+    info!("收到管理接口的停止请求");
+    state.shutdown.notify_one();
+    Ok(JsonResponse(serde_json::json!({
+        "success": true,
+        "message": "服务正在停止"
+    })))
+}
+
 /// 清理过期的API密钥
 pub async fn cleanup_expired_keys(
     State(state): State<AppState>,
@@ -150,3 +187,20 @@ pub async fn get_session_pool_stats(
         Err(ApiError::NotFound("API密钥不存在或无统计信息".to_string()))
     }
 }
+
+#[derive(Serialize)]
+pub struct RequestStatsResponse {
+    #[serde(flatten)]
+    requests: RequestStatsReport,
+    response_cache: Option<CacheStats>,
+}
+
+/// 获取按API密钥和模型汇总的延迟、错误率和重试统计
+pub async fn get_request_stats(
+    State(state): State<AppState>,
+) -> JsonResponse<RequestStatsResponse> {
+    JsonResponse(RequestStatsResponse {
+        requests: state.request_stats.report(),
+        response_cache: state.response_cache.is_enabled().then(|| state.response_cache.stats()),
+    })
+}
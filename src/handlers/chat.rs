@@ -1,183 +1,514 @@
+use crate::config::{FaultRates, FilterMode, ServerConfig, ThreadMode};
 use crate::error::{ApiError, ApiResult};
-use crate::handlers::AppState;
-use crate::models::ChatCompletionRequest;
+use crate::handlers::{access, generations, AppState};
+use crate::models::{ApiKeyInfo, ChatCompletionRequest, ChatCompletionResponse, ChatMessage, ChatMessageContent, CompletionOptions, StreamChunk};
+use crate::services::api_key_manager::SessionLease;
+use crate::services::audit_log::AuditRecord;
+use crate::services::content_filter::StreamFilter;
+use crate::services::request_context::{STAGE_POW, STAGE_SESSION, STAGE_TOKEN, STAGE_UPSTREAM};
+use crate::services::session_pool::{AcquireOptions, DeepSeekSession};
+use crate::services::singleflight::{self, Flight};
+use crate::services::stream_throttle::throttle;
+use crate::services::generation_store::GenerationWriter;
+use crate::services::thread_store::{Thread, ThreadStore};
+use crate::services::plugins::Hook;
+use crate::services::{CompletionRecord, FailedRequest, MessageProcessor, RequestContext, ResponseCache, WebhookEvent};
+use crate::utils::{generate_uuid_simple, is_fold_model, is_search_model, is_silent_model, is_thinking_model};
 use axum::{
-    extract::State,
-    http::HeaderMap,
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue},
     response::{sse::Event, Json, Sse, IntoResponse, Response},
 };
-use futures_util::{stream::StreamExt, Stream};
+use futures_util::{future, stream::{self, StreamExt}, Stream};
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::convert::Infallible;
+use parking_lot::Mutex;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{mpsc, OwnedMutexGuard};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::field::Empty;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CompletionParams {
+    transport: Option<String>, // sse（默认）或 poll
+}
 
 /// 聊天补全处理器  
+#[tracing::instrument(
+    name = "chat_completion",
+    skip_all,
+    fields(
+        request_id = Empty, api_key_id = Empty, model = Empty, account = Empty, latency_ms = Empty,
+        token_ms = Empty, pow_ms = Empty, session_ms = Empty, upstream_ms = Empty
+    )
+)]
 pub async fn completions(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(request): Json<ChatCompletionRequest>,
+    Query(params): Query<CompletionParams>,
+    Json(mut request): Json<ChatCompletionRequest>,
 ) -> Result<Response, ApiError> {
     // 验证请求
     if request.messages.is_empty() {
         return Err(ApiError::InvalidRequest("Messages cannot be empty".to_string()));
     }
+    check_message_limits(&request, &state.config.server)?;
+
+    // transport=poll 按流式请求上游，立即返回补全ID，数据块缓存在服务端供客户端轮询读取
+    let poll_caller = match params.transport.as_deref().map(str::trim) {
+        None | Some("") | Some("sse") => None,
+        Some("poll") => Some(generations::generation_caller(&state, &headers)?),
+        Some(other) => return Err(ApiError::BadRequest(format!("transport无效: {}（可选 sse、poll）", other))),
+    };
+    if poll_caller.is_some() {
+        request.stream = Some(true);
+    }
+
+    let api_key = get_api_key_from_header(&headers);
+    if let Some(key) = api_key.as_deref() {
+        if state.api_key_manager.is_api_key_valid(key).unwrap_or(false) && !state.api_key_manager.has_scope(key, "chat") {
+            return Err(ApiError::Forbidden("API密钥没有chat权限".to_string()));
+        }
+    }
+
+    // 钩子脚本最先处理请求，可以使用API密钥信息追加系统提示词或拒绝请求
+    let key_info = api_key
+        .as_deref()
+        .filter(|_| state.scripts.has_pre_request() || state.scripts.has_post_response())
+        .and_then(|key| state.api_key_manager.get_api_key_info(key).ok());
+    state.scripts.pre_request(&mut request, key_info.as_ref())?;
+
+    // 内容过滤在缓存和上游之前进行，API密钥可单独设置处理方式
+    let filter_mode = api_key
+        .as_deref()
+        .and_then(|key| state.api_key_manager.content_filter_mode(key))
+        .unwrap_or(state.content_filter.default_mode());
+    if state.content_filter.is_enabled() {
+        state.content_filter.filter_messages(&mut request.messages, filter_mode)?;
+    }
+
+    // 未指定模型时依次使用API密钥的默认模型和全局默认模型
+    let requested_model = match request.model.as_deref() {
+        Some(model) => model.to_lowercase(),
+        None => api_key
+            .as_deref()
+            .and_then(|key| state.api_key_manager.default_model(key))
+            .unwrap_or_else(|| state.config.models.default_model.clone()),
+    };
+    let model = state.config.models.resolve_alias(&requested_model);
+    if model != requested_model {
+        tracing::debug!("Model alias {} -> {}", requested_model, model);
+    }
+    // WASM插件在内容过滤之后、缓存和提示词拼接之前改写消息
+    state.plugins.before_prompt(&model, &mut request.messages)?;
+
+    // 服务端托管的对话：客户端只发送本轮新增的消息，同一对话的请求依次执行
+    let mut thread = match thread_id(&request, state.config.threads.mode) {
+        Some(thread_id) => Some(begin_thread_turn(&state, &headers, api_key.as_deref(), &mut request, &thread_id).await?),
+        None => None,
+    };
+
+    let started_at = Instant::now();
+    let request_id = generate_uuid_simple();
+    let api_key_id = api_key.as_deref().and_then(|key| state.api_key_manager.api_key_id(key));
+    let stream = request.stream.unwrap_or(false);
+    // X-Debug: upstream 在响应中附带上游原始数据，可能包含未经过滤的内容，只对管理员开放
+    let debug_upstream = headers
+        .get("x-debug")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("upstream"));
+    if debug_upstream && !access::is_admin_request(&state, &headers) {
+        return Err(ApiError::Forbidden("X-Debug需要有效的X-Admin-Token".to_string()));
+    }
+    // X-Chaos: timeout=0.5,disconnect=1 按指定比例为本次请求注入上游故障，只在故障注入模式下对管理员开放
+    let chaos = match headers.get("x-chaos").and_then(|value| value.to_str().ok()) {
+        Some(value) => {
+            if !state.config.deepseek.chaos {
+                return Err(ApiError::BadRequest("X-Chaos需要开启CHAOS_MODE".to_string()));
+            }
+            if !access::is_admin_request(&state, &headers) {
+                return Err(ApiError::Forbidden("X-Chaos需要有效的X-Admin-Token".to_string()));
+            }
+            Some(value.parse::<FaultRates>().map_err(|e| ApiError::BadRequest(format!("X-Chaos无效: {}", e)))?)
+        }
+        None => None,
+    };
+    // API密钥单独设置的运行参数；思考过程展示方式只在请求和模型名都未指定时使用
+    let overrides = api_key.as_deref().map(|key| state.api_key_manager.overrides(key)).unwrap_or_default();
+    let tokens_per_sec = request
+        .stream_tokens_per_sec
+        .or(overrides.stream_tokens_per_sec)
+        .unwrap_or(state.config.deepseek.stream_tokens_per_sec);
+    let mut features = request.features();
+    if features.reasoning_display.is_none() && !is_silent_model(&model) && !is_fold_model(&model) {
+        features.reasoning_display = overrides.reasoning_display;
+    }
+    let options = CompletionOptions {
+        continue_final_message: request.continue_final_message.unwrap_or(true),
+        features,
+        debug_upstream,
+        overrides,
+        chaos,
+    };
+
+    let span = tracing::Span::current();
+    span.record("request_id", request_id.as_str());
+    span.record("model", model.as_str());
+    if let Some(key_id) = &api_key_id {
+        span.record("api_key_id", key_id.as_str());
+    }
+
+    // 审计记录，未启用审计日志时不计算
+    let mut audit = state.audit_log.is_enabled().then(|| {
+        let mut record = AuditRecord::new(&request_id, &model, stream);
+        let (prompt_hash, prompt) = state.audit_log.redact_prompt(&MessageProcessor::prepare_messages(&request.messages, &state.config.prompt, options.continue_final_message));
+        record.api_key_id = api_key_id.clone();
+        record.prompt_hash = prompt_hash;
+        record.prompt = prompt;
+        record
+    });
+
+    // 补全历史保存拼接后的提示词和返回给客户端的回复，缓存命中和合并的请求不重复保存
+    let mut history = state.completion_store.is_enabled().then(|| {
+        let prompt = MessageProcessor::prepare_messages(&request.messages, &state.config.prompt, options.continue_final_message);
+        CompletionRecord::new(&request_id, api_key_id.clone(), &model, stream, prompt)
+    });
+
+    // 缓存和请求合并只用于不带conversation_id和thread_id的非流式请求，按调用方隔离；调试和故障注入请求总是访问上游
+    let coalesce = state.config.cache.singleflight;
+    let request_key = ((state.response_cache.is_enabled() || coalesce) && !stream && request.conversation_id.is_none() && thread.is_none() && !debug_upstream && chaos.is_none())
+        .then(|| cache_caller(&headers, &state, api_key.as_deref()))
+        .flatten()
+        .map(|caller| ResponseCache::cache_key(&model, &request.messages, options, &caller));
+
+    // 相同的非流式请求直接返回缓存，不占用账号
+    let cache_key = request_key.clone().filter(|_| state.response_cache.is_enabled());
+    if let Some(cached) = cache_key.as_deref().and_then(|key| state.response_cache.get(key)) {
+        let latency_ms = started_at.elapsed().as_millis() as u64;
+        span.record("latency_ms", latency_ms);
+        state.request_stats.record(api_key_id.as_deref(), &model, latency_ms, false, 0);
+        if let Some(record) = audit.as_mut() {
+            record.prompt_tokens = cached.usage.as_ref().map(|usage| usage.prompt_tokens);
+            record.completion_tokens = cached.usage.as_ref().map(|usage| usage.completion_tokens);
+        }
+        finish_audit(&state, audit, "cache_hit".to_string(), started_at);
+        tracing::info!("Chat completion served from cache");
+        return Ok(Json(cached).into_response());
+    }
+
+    // 相同请求正在进行时等待并共享其结果
+    let flight = match request_key.as_deref().filter(|_| coalesce).map(|key| state.singleflight.join(key)) {
+        Some(Flight::Follower(receiver)) => {
+            let result = singleflight::wait(receiver).await;
+            let latency_ms = started_at.elapsed().as_millis() as u64;
+            span.record("latency_ms", latency_ms);
+            state.request_stats.record(api_key_id.as_deref(), &model, latency_ms, result.is_err(), 0);
+            let outcome = match &result {
+                Ok(_) => "coalesced".to_string(),
+                Err(e) => format!("error: {}", e),
+            };
+            finish_audit(&state, audit, outcome, started_at);
+            tracing::info!("Chat completion coalesced with an identical in-flight request");
+            return result.map(|response| Json(response).into_response());
+        }
+        Some(Flight::Leader(leader)) => Some(leader),
+        None => None,
+    };
+
+    let requires_thinking = options.features.thinking_enabled.unwrap_or_else(|| is_thinking_model(&model));
+    let acquire_options = AcquireOptions {
+        requires_thinking,
+        user: request.user.clone(),
+        model: Some(model.clone()),
+        // API密钥指定的分组优先，其次按模型和深度思考的路由规则
+        account_group: api_key
+            .as_deref()
+            .and_then(|key| state.api_key_manager.account_group(key))
+            .or_else(|| state.config.models.account_group(&model, requires_thinking)),
+        group_tokens: None,
+    };
 
     // 获取用户token和会话
-    let (conversation_id, session) = if let Some(api_key) = get_api_key_from_header(&headers) {
+    let (conversation_id, session, lease) = if let Some(api_key) = api_key {
         // 使用API密钥和会话池
-        let (conv_id, session) = state.api_key_manager.acquire_session(&api_key, request.conversation_id.clone()).await
-            .map_err(|e| ApiError::TokenError(format!("Failed to acquire session: {}", e)))?;
-        (Some(conv_id), Some(session))
+        let acquired = state.api_key_manager.acquire_session(&api_key, request.conversation_id.clone(), &acquire_options).await
+            .map_err(|e| match e {
+                // 账号容量类错误原样返回，便于客户端区分
+                ApiError::ServiceUnavailable(_) | ApiError::Unavailable { .. } | ApiError::Banned(_) => e,
+                ApiError::RateLimited { .. } => e.with_model(&model),
+                e => ApiError::TokenError(format!("Failed to acquire session: {}", e)),
+            });
+        match acquired {
+            Ok((lease, session)) => (Some(lease.conversation_id().to_string()), Some(session), Some(lease)),
+            Err(e) => {
+                let latency_ms = started_at.elapsed().as_millis() as u64;
+                state.request_stats.record(api_key_id.as_deref(), &model, latency_ms, true, 0);
+                finish_audit(&state, audit, format!("error: {}", e), started_at);
+                if let Some(leader) = flight {
+                    leader.complete(&Err(e.duplicate()));
+                }
+                return Err(e);
+            }
+        }
     } else {
         // 兼容模式：直接使用userToken
         let _user_token = get_authorization_and_token(&headers, &state)?;
-        (request.conversation_id.clone(), None)
+        (request.conversation_id.clone(), None, None)
     };
 
+    if let Some(session) = &session {
+        span.record("account", session.account_email.as_str());
+        if let Some(record) = audit.as_mut() {
+            record.account = Some(session.account_email.clone());
+        }
+    }
+
     let user_token = session.as_ref()
         .map(|s| s.user_token.clone())
         .unwrap_or_else(|| get_authorization_and_token(&headers, &state).unwrap_or_default());
 
-    let model = request.model.as_deref().unwrap_or("deepseek").to_lowercase();
-    let stream = request.stream.unwrap_or(false);
+    // 托管对话的上游会话不在本次分配的账号上时，在新会话中重新发送完整上下文
+    let account = session.as_ref().map(|session| session.account_email.clone());
+    let mut upstream_conversation = conversation_id.clone();
+    if let Some(turn) = thread.as_mut() {
+        if turn.continued && turn.thread.continuation(account.as_deref()).is_none() {
+            tracing::debug!("Thread session moved to another account, resending full context");
+            request.messages = turn.thread.context(&turn.new_messages);
+            turn.continued = false;
+            if let Some(record) = history.as_mut() {
+                record.prompt = MessageProcessor::prepare_messages(&request.messages, &state.config.prompt, options.continue_final_message);
+            }
+        }
+        if !turn.continued {
+            upstream_conversation = None;
+        }
+    }
 
-    let result = if stream {
+    let ctx = RequestContext::new();
+    let mut result = if stream {
         // 流式响应
-        let stream = state
+        state
             .client
-            .create_completion_stream(&model, &request.messages, &user_token, conversation_id.as_deref())
-            .await?;
-
-        let sse_stream = create_sse_stream(stream);
-        Ok(Sse::new(sse_stream).into_response())
+            .create_completion_stream(&model, &request.messages, &user_token, upstream_conversation.as_deref(), options, &ctx)
+            .await
+            .map_err(|e| e.with_model(&model))
+            .map(|stream| {
+                let stream = script_stream(plugin_stream(filter_stream(stream, &state, filter_mode), &state), &state, key_info);
+                let stream = thread_stream(history_stream(stream, &state, history), thread, account);
+                let stream = lease_stream(stream, lease);
+                match poll_caller {
+                    Some(caller) => {
+                        let generation = state.generations.create(&caller, &model);
+                        let body = json!({
+                            "id": generation.id(),
+                            "object": "chat.completion.generation",
+                            "created": generation.created(),
+                            "model": model,
+                            "status": "in_progress"
+                        });
+                        poll_stream(stream, generation);
+                        Json(body).into_response()
+                    }
+                    None => Sse::new(create_sse_stream(throttle(stream, tokens_per_sec))).into_response(),
+                }
+            })
     } else {
         // 非流式响应
-        let response = state
+        let result = state
             .client
-            .create_completion(&model, &request.messages, &user_token, conversation_id.as_deref())
-            .await?;
+            .create_completion(&model, &request.messages, &user_token, upstream_conversation.as_deref(), options, &ctx)
+            .await
+            .map_err(|e| e.with_model(&model))
+            .and_then(|mut response| {
+                if state.content_filter.is_enabled() {
+                    state.content_filter.filter_response(&mut response, filter_mode)?;
+                }
+                state.plugins.transform_response(&mut response)?;
+                state.scripts.post_response(&mut response, key_info.as_ref())?;
+                Ok(response)
+            });
+        if let Some(leader) = flight {
+            leader.complete(&result);
+        }
+        result.map(|response| {
+            if let (Some(record), Some(usage)) = (audit.as_mut(), &response.usage) {
+                record.prompt_tokens = Some(usage.prompt_tokens);
+                record.completion_tokens = Some(usage.completion_tokens);
+            }
+            if let Some(mut record) = history {
+                record.set_response(&response);
+                let store = state.completion_store.clone();
+                tokio::spawn(async move { store.record(record).await });
+            }
+            if let Some(turn) = thread {
+                turn.commit(reply_text(&response), &response.id, account);
+            }
+            if let Some(key) = cache_key {
+                state.response_cache.put(key, response.clone());
+            }
+            Json(response).into_response()
+        })
+    };
 
-        Ok(Json(response).into_response())
+    // 记录账号的请求数和错误，错误同时用于自动冷却，封禁提示直接停用账号
+    if let Some(session) = &session {
+        state.api_key_manager.record_account_request(&session.user_token, result.as_ref().err());
+        let busy_ms = started_at.elapsed().as_millis() as u64;
+        state.account_usage.record(&session.account_email, busy_ms, requires_thinking, result.is_err());
+        if let Some(e) = result.as_ref().err().filter(|e| e.is_banned()) {
+            disable_banned_account(&state, session, &model, e);
+        }
+    }
+
+    // 非流式请求的会话租约在返回时释放，流式请求的租约随响应流释放
+    // 流式请求的耗时为响应开始返回前的时间
+    let latency_ms = started_at.elapsed().as_millis() as u64;
+    span.record("latency_ms", latency_ms);
+    for (field, stage) in [("token_ms", STAGE_TOKEN), ("pow_ms", STAGE_POW), ("session_ms", STAGE_SESSION), ("upstream_ms", STAGE_UPSTREAM)] {
+        if let Some(ms) = ctx.stage_ms(stage) {
+            span.record(field, ms);
+        }
+    }
+    // 流式响应的头在补全完成前已确定，只为非流式响应附加各阶段耗时
+    if let (false, Ok(response)) = (stream, &mut result) {
+        if let Ok(value) = HeaderValue::from_str(&ctx.server_timing(started_at.elapsed())) {
+            response.headers_mut().insert("server-timing", value);
+        }
+    }
+    state.request_stats.record(api_key_id.as_deref(), &model, latency_ms, result.is_err(), ctx.retries());
+    match &result {
+        Ok(_) => tracing::info!(stream, "Chat completion finished"),
+        Err(e) => tracing::warn!(stream, error = %e, "Chat completion failed"),
+    }
+
+    let outcome = match &result {
+        Ok(_) => "success".to_string(),
+        Err(e) => format!("error: {}", e),
     };
+    finish_audit(&state, audit, outcome, started_at);
 
-    // 释放会话
-    if let Some(conv_id) = conversation_id {
-        state.api_key_manager.release_session(&conv_id);
+    // 保留失败请求供管理员重放
+    if let (Err(e), true) = (&result, state.failed_requests.is_enabled()) {
+        let mut failed = FailedRequest::new(&request_id, &model, request, e.to_string());
+        failed.api_key_id = api_key_id;
+        failed.account = session.map(|session| session.account_email);
+        state.failed_requests.record(failed);
     }
 
     result
 }
 
+/// 停用被封禁的账号，并通过webhook发送带证据的通知
+fn disable_banned_account(state: &AppState, session: &DeepSeekSession, model: &str, error: &ApiError) {
+    let reason = error.to_string();
+    let Some(account) = state.api_key_manager.disable_banned_account(&session.user_token, &reason) else {
+        return;
+    };
+    state.client.forget_access_token(&session.user_token);
+    state.webhook.notify(WebhookEvent::new(
+        "account.disabled",
+        json!({
+            "account": { "id": account.id, "email": account.email },
+            "reason": "banned",
+            "evidence": {
+                "error": reason,
+                "model": model,
+                "conversation_id": session.conversation_id,
+                "api_key_ids": account.api_keys,
+            },
+        }),
+    ));
+}
+
+/// 响应缓存按调用方隔离：API密钥必须有效，兼容模式使用请求中的token
+pub(crate) fn cache_caller(headers: &HeaderMap, state: &AppState, api_key: Option<&str>) -> Option<String> {
+    match api_key {
+        Some(key) => state
+            .api_key_manager
+            .is_api_key_valid(key)
+            .unwrap_or(false)
+            .then(|| key.to_string()),
+        None => get_authorization_and_token(headers, state).ok(),
+    }
+}
+
+/// 写入审计记录
+fn finish_audit(state: &AppState, audit: Option<AuditRecord>, outcome: String, started_at: Instant) {
+    if let Some(mut record) = audit {
+        record.outcome = outcome;
+        record.latency_ms = started_at.elapsed().as_millis() as u64;
+        state.audit_log.record(&record);
+    }
+}
+
+/// 内置模型，能力由模型名中的search/think/r1/silent/fold决定
+const MODEL_IDS: &[&str] = &[
+    "deepseek",
+    "deepseek-search",
+    "deepseek-think",
+    "deepseek-r1",
+    "deepseek-r1-search",
+    "deepseek-think-search",
+    "deepseek-think-silent",
+    "deepseek-r1-silent",
+    "deepseek-search-silent",
+    "deepseek-think-fold",
+    "deepseek-r1-fold",
+];
+
+/// OpenAI格式的模型对象，附带扩展的能力字段；别名的能力取自目标模型
+fn model_object(id: &str, root: &str) -> Value {
+    json!({
+        "id": id,
+        "object": "model",
+        "created": 1234567890,
+        "owned_by": "deepseek",
+        "permission": [],
+        "root": root,
+        "parent": null,
+        "capabilities": {
+            "search": is_search_model(root),
+            "thinking": is_thinking_model(root),
+            "silent_thinking": is_silent_model(root),
+            "folded_thinking": is_fold_model(root)
+        }
+    })
+}
+
 /// 获取模型列表
-pub async fn models() -> Json<Value> {
+pub async fn models(State(state): State<AppState>) -> Json<Value> {
+    let mut data: Vec<Value> = MODEL_IDS.iter().map(|id| model_object(id, id)).collect();
+
+    // 追加配置的模型别名
+    for (alias, target) in &state.config.models.aliases {
+        data.push(model_object(alias, target));
+    }
+
     Json(json!({
         "object": "list",
-        "data": [
-            {
-                "id": "deepseek",
-                "object": "model",
-                "created": 1234567890,
-                "owned_by": "deepseek",
-                "permission": [],
-                "root": "deepseek",
-                "parent": null
-            },
-            {
-                "id": "deepseek-search",
-                "object": "model",
-                "created": 1234567890,
-                "owned_by": "deepseek",
-                "permission": [],
-                "root": "deepseek-search",
-                "parent": null
-            },
-            {
-                "id": "deepseek-think",
-                "object": "model",
-                "created": 1234567890,
-                "owned_by": "deepseek",
-                "permission": [],
-                "root": "deepseek-think",
-                "parent": null
-            },
-            {
-                "id": "deepseek-r1",
-                "object": "model",
-                "created": 1234567890,
-                "owned_by": "deepseek",
-                "permission": [],
-                "root": "deepseek-r1",
-                "parent": null
-            },
-            {
-                "id": "deepseek-r1-search",
-                "object": "model",
-                "created": 1234567890,
-                "owned_by": "deepseek",
-                "permission": [],
-                "root": "deepseek-r1-search",
-                "parent": null
-            },
-            {
-                "id": "deepseek-think-search",
-                "object": "model",
-                "created": 1234567890,
-                "owned_by": "deepseek",
-                "permission": [],
-                "root": "deepseek-think-search",
-                "parent": null
-            },
-            {
-                "id": "deepseek-think-silent",
-                "object": "model",
-                "created": 1234567890,
-                "owned_by": "deepseek",
-                "permission": [],
-                "root": "deepseek-think-silent",
-                "parent": null
-            },
-            {
-                "id": "deepseek-r1-silent",
-                "object": "model",
-                "created": 1234567890,
-                "owned_by": "deepseek",
-                "permission": [],
-                "root": "deepseek-r1-silent",
-                "parent": null
-            },
-            {
-                "id": "deepseek-search-silent",
-                "object": "model",
-                "created": 1234567890,
-                "owned_by": "deepseek",
-                "permission": [],
-                "root": "deepseek-search-silent",
-                "parent": null
-            },
-            {
-                "id": "deepseek-think-fold",
-                "object": "model",
-                "created": 1234567890,
-                "owned_by": "deepseek",
-                "permission": [],
-                "root": "deepseek-think-fold",
-                "parent": null
-            },
-            {
-                "id": "deepseek-r1-fold",
-                "object": "model",
-                "created": 1234567890,
-                "owned_by": "deepseek",
-                "permission": [],
-                "root": "deepseek-r1-fold",
-                "parent": null
-            }
-        ]
+        "data": data
     }))
 }
 
+/// 获取单个模型
+pub async fn retrieve_model(State(state): State<AppState>, Path(model): Path<String>) -> ApiResult<Json<Value>> {
+    if MODEL_IDS.contains(&model.as_str()) {
+        return Ok(Json(model_object(&model, &model)));
+    }
+    state
+        .config
+        .models
+        .aliases
+        .get(&model)
+        .map(|target| Json(model_object(&model, target)))
+        .ok_or_else(|| ApiError::NotFound(format!("The model '{}' does not exist", model)))
+}
+
 /// 从请求头获取API密钥
-fn get_api_key_from_header(headers: &HeaderMap) -> Option<String> {
+pub(super) fn get_api_key_from_header(headers: &HeaderMap) -> Option<String> {
     let auth_header = headers.get("authorization")?;
     let auth_str = auth_header.to_str().ok()?;
     
@@ -189,7 +520,7 @@ fn get_api_key_from_header(headers: &HeaderMap) -> Option<String> {
 }
 
 /// 获取授权头和用户token
-fn get_authorization_and_token(headers: &HeaderMap, state: &AppState) -> ApiResult<String> {
+pub(super) fn get_authorization_and_token(headers: &HeaderMap, state: &AppState) -> ApiResult<String> {
     // 从请求头获取Authorization
     let auth_header = headers
         .get("authorization")
@@ -214,29 +545,290 @@ fn get_authorization_and_token(headers: &HeaderMap, state: &AppState) -> ApiResu
     } else {
         // 优先使用环境变量中的token（兼容模式）
         if let Some(auth) = &state.config.deepseek.authorization {
-            Ok(auth.clone())
+            Ok(state.secrets.get(auth))
         } else {
             Err(ApiError::TokenError("Invalid authorization format".to_string()))
         }
     }
 }
 
-/// 创建SSE流
+/// 流式补全内容过滤，reject模式命中后发送错误事件并结束流
+fn filter_stream(
+    stream: Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>,
+    state: &AppState,
+    mode: FilterMode,
+) -> Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>> {
+    if !state.content_filter.is_enabled() {
+        return stream;
+    }
+
+    let filter = Arc::new(Mutex::new(StreamFilter::new(state.content_filter.clone(), mode)));
+    let remaining = filter.clone();
+    let mut rejected = false;
+    Box::pin(
+        stream
+            .flat_map(move |item| {
+                let items = match item.and_then(|data| filter.lock().push(data)) {
+                    Ok(chunks) => chunks.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                };
+                stream::iter(items)
+            })
+            .chain(stream::iter([()]).filter_map(move |_| future::ready(remaining.lock().finish().map(Ok))))
+            .take_while(move |item| {
+                let keep = !rejected;
+                rejected = matches!(item, Err(ApiError::Forbidden(_)));
+                future::ready(keep)
+            }),
+    )
+}
+
+/// 流式补全的每个数据块经过WASM插件的on_chunk钩子
+fn plugin_stream(
+    stream: Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>,
+    state: &AppState,
+) -> Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>> {
+    if !state.plugins.has_hook(Hook::OnChunk) {
+        return stream;
+    }
+
+    let plugins = state.plugins.clone();
+    Box::pin(stream.map(move |item| item.and_then(|data| plugins.transform_chunk(data))))
+}
+
+/// 流式补全的每个数据块经过钩子脚本的post_response
+fn script_stream(
+    stream: Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>,
+    state: &AppState,
+    key_info: Option<ApiKeyInfo>,
+) -> Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>> {
+    if !state.scripts.has_post_response() {
+        return stream;
+    }
+
+    let scripts = state.scripts.clone();
+    Box::pin(stream.map(move |item| item.and_then(|data| scripts.post_chunk(data, key_info.as_ref()))))
+}
+
+/// 托管对话的一轮请求，持有对话锁直到回复完成
+struct ThreadTurn {
+    thread: OwnedMutexGuard<Thread>,
+    new_messages: Vec<ChatMessage>,
+    continued: bool, // 继续上一轮的上游会话，只发送新增消息
+}
+
+impl ThreadTurn {
+    fn commit(mut self, reply: String, conversation_id: &str, account: Option<String>) {
+        self.thread.commit(self.new_messages, reply, conversation_id, account);
+    }
+}
+
+/// 请求对应的托管对话标识，user模式下未传thread_id时使用user字段
+fn thread_id(request: &ChatCompletionRequest, mode: ThreadMode) -> Option<String> {
+    match mode {
+        ThreadMode::Off => None,
+        ThreadMode::Explicit => request.thread_id.clone(),
+        ThreadMode::User => request.thread_id.clone().or_else(|| request.user.clone()),
+    }
+    .filter(|thread_id| !thread_id.is_empty())
+}
+
+/// 锁定托管对话；有可继续的上游会话时只发送新增消息，否则发送完整上下文
+async fn begin_thread_turn(
+    state: &AppState,
+    headers: &HeaderMap,
+    api_key: Option<&str>,
+    request: &mut ChatCompletionRequest,
+    thread_id: &str,
+) -> ApiResult<ThreadTurn> {
+    if request.conversation_id.is_some() {
+        return Err(ApiError::InvalidRequest("thread_id不能与conversation_id同时使用".to_string()));
+    }
+    let caller = cache_caller(headers, state, api_key)
+        .ok_or_else(|| ApiError::Unauthorized("托管对话需要有效的API密钥或userToken".to_string()))?;
+    let thread = state.threads.lock(&ThreadStore::key(&caller, request.user.as_deref(), thread_id)).await?;
+
+    let new_messages = request.messages.clone();
+    let continued = thread.conversation_id.is_some();
+    if continued {
+        request.conversation_id = thread.conversation_id.clone();
+    } else {
+        request.messages = thread.context(&new_messages);
+    }
+    Ok(ThreadTurn { thread, new_messages, continued })
+}
+
+/// 非流式响应第一个选项的正文
+fn reply_text(response: &ChatCompletionResponse) -> String {
+    match response.choices.first().and_then(|choice| choice.message.as_ref()).map(|message| &message.content) {
+        Some(ChatMessageContent::Text(text)) => text.clone(),
+        _ => String::new(),
+    }
+}
+
+/// 流式补全正常结束后把回复追加到托管对话，出错或客户端断开时对话保持不变
+fn thread_stream(
+    stream: Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>,
+    turn: Option<ThreadTurn>,
+    account: Option<String>,
+) -> Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>> {
+    let Some(turn) = turn else {
+        return stream;
+    };
+
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut stream = stream;
+        let (mut reply, mut conversation_id, mut finished) = (String::new(), String::new(), false);
+        while let Some(item) = stream.next().await {
+            match &item {
+                Ok(data) => {
+                    let payload = data.strip_prefix("data: ").unwrap_or(data).trim_end();
+                    if let Ok(chunk) = serde_json::from_str::<StreamChunk>(payload) {
+                        if !chunk.id.is_empty() {
+                            conversation_id = chunk.id;
+                        }
+                        for choice in chunk.choices.iter().filter(|choice| choice.index == 0) {
+                            reply.push_str(choice.delta.content.as_deref().unwrap_or_default());
+                            finished |= choice.finish_reason.is_some();
+                        }
+                    }
+                }
+                Err(_) => finished = false,
+            }
+            if tx.send(item).await.is_err() {
+                return;
+            }
+        }
+        if finished {
+            turn.commit(reply, &conversation_id, account);
+        }
+    });
+    Box::pin(ReceiverStream::new(rx))
+}
+
+/// 流式补全结束（或客户端断开）时把已发送的内容保存到补全历史
+fn history_stream(
+    stream: Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>,
+    state: &AppState,
+    record: Option<CompletionRecord>,
+) -> Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>> {
+    let Some(mut record) = record else {
+        return stream;
+    };
+
+    let store = state.completion_store.clone();
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut stream = stream;
+        while let Some(item) = stream.next().await {
+            if let Ok(data) = &item {
+                record.append_chunk(data);
+            }
+            if tx.send(item).await.is_err() {
+                break;
+            }
+        }
+        record.finish_stream();
+        store.record(record).await;
+    });
+    Box::pin(ReceiverStream::new(rx))
+}
+
+/// 响应流结束（或客户端断开）前一直持有会话租约，流式请求同样计入账号容量
+fn lease_stream(
+    stream: Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>,
+    lease: Option<SessionLease>,
+) -> Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>> {
+    let Some(lease) = lease else {
+        return stream;
+    };
+    Box::pin(stream.inspect(move |_| {
+        let _ = &lease;
+    }))
+}
+
+/// 轮询方式：在后台读完流式补全，数据块写入服务端缓存；调用方删除补全后停止读取
+fn poll_stream(stream: Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>, generation: GenerationWriter) {
+    tokio::spawn(async move {
+        let mut stream = stream;
+        while let Some(item) = stream.next().await {
+            if generation.is_abandoned() {
+                return;
+            }
+            match item {
+                Ok(data) => {
+                    // [DONE]等非JSON数据不缓存，补全结束由status表示
+                    if let Ok(chunk) = serde_json::from_str::<Value>(sse_payload(&data)) {
+                        generation.push(chunk);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Stream error: {}", e);
+                    generation.finish(Some(e.to_json()));
+                    return;
+                }
+            }
+        }
+        generation.finish(None);
+    });
+}
+
+/// 创建SSE流：上游返回的数据已带 `data: ` 前缀，写入事件前去掉；出错时发送OpenAI格式的错误事件和 `[DONE]` 后结束
 fn create_sse_stream(
     stream: Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>,
 ) -> impl Stream<Item = Result<Event, Infallible>> {
-    stream.map(|result| match result {
-        Ok(data) => Ok(Event::default().data(data)),
-        Err(e) => {
-            tracing::error!("Stream error: {}", e);
-            // 发送错误事件
-            let error_data = json!({
-                "error": {
-                    "message": e.to_string(),
-                    "type": "stream_error"
+    stream
+        .scan(false, |failed, result| {
+            if *failed {
+                return future::ready(None);
+            }
+            *failed = result.is_err();
+            future::ready(Some(result))
+        })
+        .flat_map(|result| {
+            let events = match result {
+                Ok(data) => vec![Event::default().data(sse_payload(&data))],
+                Err(e) => {
+                    tracing::error!("Stream error: {}", e);
+                    vec![Event::default().data(e.to_json().to_string()), Event::default().data("[DONE]")]
                 }
-            });
-            Ok(Event::default().data(format!("data: {}\n\n", error_data)))
+            };
+            stream::iter(events.into_iter().map(Ok))
+        })
+}
+
+/// 去掉数据块的SSE `data: ` 前缀和结尾的空行
+fn sse_payload(data: &str) -> &str {
+    data.strip_prefix("data: ").unwrap_or(data).trim_end_matches('\n')
+}
+
+/// 检查消息条数和单条消息长度
+fn check_message_limits(request: &ChatCompletionRequest, limits: &ServerConfig) -> ApiResult<()> {
+    if request.messages.len() > limits.max_messages {
+        return Err(ApiError::InvalidRequest(format!(
+            "Too many messages: {} (max {})",
+            request.messages.len(),
+            limits.max_messages
+        )));
+    }
+
+    for (index, message) in request.messages.iter().enumerate() {
+        let chars = match &message.content {
+            ChatMessageContent::Text(text) => text.chars().count(),
+            ChatMessageContent::Array(parts) => parts
+                .iter()
+                .filter_map(|part| part.text.as_deref())
+                .map(|text| text.chars().count())
+                .sum(),
+        };
+        if chars > limits.max_message_chars {
+            return Err(ApiError::InvalidRequest(format!(
+                "Message {} is too long: {} characters (max {})",
+                index, chars, limits.max_message_chars
+            )));
         }
-    })
+    }
+
+    Ok(())
 }
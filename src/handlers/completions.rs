@@ -0,0 +1,33 @@
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::AppState;
+use crate::services::completion_store::CompletionQuery;
+use axum::extract::{Json, Path, Query, State};
+use serde_json::{json, Value};
+
+/// 查询补全历史，从新到旧；q按子串搜索提示词、回复和思考过程
+pub async fn list_completions(State(state): State<AppState>, Query(query): Query<CompletionQuery>) -> ApiResult<Json<Value>> {
+    let (records, total) = state.completion_store.list(&query).await?;
+    Ok(Json(json!({
+        "object": "list",
+        "total": total,
+        "data": records
+    })))
+}
+
+/// 查看一条补全历史
+pub async fn get_completion(State(state): State<AppState>, Path(id): Path<String>) -> ApiResult<Json<Value>> {
+    let record = state
+        .completion_store
+        .get(&id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("补全历史不存在: {}", id)))?;
+    Ok(Json(json!(record)))
+}
+
+/// 删除一条补全历史
+pub async fn delete_completion(State(state): State<AppState>, Path(id): Path<String>) -> ApiResult<Json<Value>> {
+    if !state.completion_store.delete(&id).await? {
+        return Err(ApiError::NotFound(format!("补全历史不存在: {}", id)));
+    }
+    Ok(Json(json!({ "success": true, "id": id })))
+}
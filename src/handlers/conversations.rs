@@ -0,0 +1,147 @@
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::chat::{cache_caller, get_api_key_from_header, get_authorization_and_token};
+use crate::handlers::AppState;
+use crate::services::conversation_export::{self, ExportFormat};
+use crate::services::ThreadStore;
+use crate::utils::parse_conversation_id;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Deserialize)]
+pub struct ThreadQuery {
+    user: Option<String>, // 与补全请求中的user字段一致
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>, // json（默认）或 markdown
+}
+
+/// 列出调用方API密钥下的对话
+pub async fn list_conversations(State(state): State<AppState>, headers: HeaderMap) -> ApiResult<Json<Value>> {
+    let api_key = get_api_key_from_header(&headers)
+        .ok_or_else(|| ApiError::Unauthorized("对话列表需要使用API密钥".to_string()))?;
+    let conversations = state.api_key_manager.list_conversations(&api_key)?;
+
+    Ok(Json(json!({
+        "object": "list",
+        "data": conversations
+    })))
+}
+
+/// 导出对话：从上游拉取历史消息，渲染为JSON或Markdown（包括思考过程和引用）
+pub async fn export_conversation(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(conversation_id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> ApiResult<Response> {
+    let format = match query.format.as_deref() {
+        None => ExportFormat::Json,
+        Some(format) => ExportFormat::parse(format)
+            .ok_or_else(|| ApiError::BadRequest(format!("不支持的导出格式: {}", format)))?,
+    };
+
+    let user_token = match get_api_key_from_header(&headers) {
+        Some(api_key) => state.api_key_manager.find_conversation(&api_key, &conversation_id)?
+            .ok_or_else(|| ApiError::NotFound(format!("对话不存在: {}", conversation_id)))?
+            .user_token,
+        None => get_authorization_and_token(&headers, &state)?,
+    };
+    // 新对话在第一次回复前还没有上游会话，也就没有历史
+    let (session_id, _) = parse_conversation_id(&conversation_id)
+        .ok_or_else(|| ApiError::NotFound(format!("对话还没有上游历史: {}", conversation_id)))?;
+
+    let history = state.client.fetch_history(&user_token, &session_id).await?;
+    Ok(match format {
+        ExportFormat::Json => Json(conversation_export::to_json(&conversation_id, &history)).into_response(),
+        ExportFormat::Markdown => (
+            [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+            conversation_export::to_markdown(&history),
+        )
+            .into_response(),
+    })
+}
+
+/// 结束对话：释放会话池中的会话、删除上游DeepSeek会话并移除对话映射
+pub async fn delete_conversation(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(conversation_id): Path<String>,
+) -> ApiResult<Json<Value>> {
+    let (user_token, released) = match get_api_key_from_header(&headers) {
+        Some(api_key) => {
+            let session = state.api_key_manager.end_conversation(&api_key, &conversation_id)?
+                .ok_or_else(|| ApiError::NotFound(format!("对话不存在: {}", conversation_id)))?;
+            (session.user_token, true)
+        }
+        // 直接使用userToken时没有会话池，只删除上游会话
+        None => (get_authorization_and_token(&headers, &state)?, false),
+    };
+
+    // 新对话在第一次回复前还没有上游会话
+    let upstream_deleted = match parse_conversation_id(&conversation_id) {
+        Some((session_id, _)) => {
+            state.client.delete_session(&user_token, &session_id).await?;
+            true
+        }
+        None if released => false,
+        None => return Err(ApiError::BadRequest(format!("无效的对话ID: {}", conversation_id))),
+    };
+
+    tracing::info!(upstream_deleted, "Deleted conversation {}", conversation_id);
+    Ok(Json(json!({
+        "id": conversation_id,
+        "object": "conversation.deleted",
+        "deleted": true,
+        "upstream_deleted": upstream_deleted
+    })))
+}
+
+/// 托管对话的存储键，与补全请求一样按调用方隔离
+fn thread_key(state: &AppState, headers: &HeaderMap, thread_id: &str, user: Option<&str>) -> ApiResult<String> {
+    let caller = cache_caller(headers, state, get_api_key_from_header(headers).as_deref())
+        .ok_or_else(|| ApiError::Unauthorized("托管对话需要有效的API密钥或userToken".to_string()))?;
+    Ok(ThreadStore::key(&caller, user, thread_id))
+}
+
+/// 查看托管对话保存的消息历史
+pub async fn get_thread(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(thread_id): Path<String>,
+    Query(query): Query<ThreadQuery>,
+) -> ApiResult<Json<Value>> {
+    let key = thread_key(&state, &headers, &thread_id, query.user.as_deref())?;
+    let thread = state
+        .threads
+        .get(&key)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("托管对话不存在: {}", thread_id)))?;
+    Ok(Json(json!({
+        "id": thread_id,
+        "object": "thread",
+        "conversation_id": thread.conversation_id,
+        "updated_at": thread.updated_at,
+        "messages": thread.messages
+    })))
+}
+
+/// 删除托管对话，下一次使用同一thread_id时从空历史开始
+pub async fn delete_thread(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(thread_id): Path<String>,
+    Query(query): Query<ThreadQuery>,
+) -> ApiResult<Json<Value>> {
+    let key = thread_key(&state, &headers, &thread_id, query.user.as_deref())?;
+    if !state.threads.remove(&key) {
+        return Err(ApiError::NotFound(format!("托管对话不存在: {}", thread_id)));
+    }
+    Ok(Json(json!({ "id": thread_id, "object": "thread", "deleted": true })))
+}
@@ -0,0 +1,51 @@
+use crate::error::ApiResult;
+use crate::handlers::AppState;
+use crate::models::{AccountInfo, AccountLinkRequest, AddAccountResponse, StoreCredentialRequest};
+use axum::extract::{Json, Path, State};
+use serde_json::{json, Value};
+
+/// 列出凭据库中的凭据（不包含密码）
+pub async fn list_credentials(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "object": "list",
+        "data": state.api_key_manager.list_credentials()
+    }))
+}
+
+/// 保存或更新账号凭据，密码可以是外部密钥引用
+pub async fn store_credential(
+    State(state): State<AppState>,
+    Json(request): Json<StoreCredentialRequest>,
+) -> ApiResult<Json<Value>> {
+    let password = state.secrets.resolve(&request.password).await?;
+    state.api_key_manager.store_credential(&request.email, &password)?;
+    Ok(Json(json!({
+        "email": request.email,
+        "object": "credential",
+        "stored": true
+    })))
+}
+
+/// 删除账号凭据
+pub async fn delete_credential(State(state): State<AppState>, Path(email): Path<String>) -> ApiResult<Json<Value>> {
+    state.api_key_manager.delete_credential(&email)?;
+    Ok(Json(json!({
+        "email": email,
+        "object": "credential",
+        "deleted": true
+    })))
+}
+
+/// 使用保存的凭据登录并关联到API密钥
+pub async fn attach_credential(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    Json(request): Json<AccountLinkRequest>,
+) -> ApiResult<Json<AddAccountResponse>> {
+    Ok(Json(state.api_key_manager.attach_credential(&email, &request.api_key).await?))
+}
+
+/// 使用保存的凭据重新登录账号
+pub async fn login_credential(State(state): State<AppState>, Path(email): Path<String>) -> ApiResult<Json<AccountInfo>> {
+    Ok(Json(state.api_key_manager.login_with_credential(&email).await?))
+}
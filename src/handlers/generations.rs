@@ -0,0 +1,52 @@
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::chat::{cache_caller, get_api_key_from_header};
+use crate::handlers::AppState;
+use axum::{
+    extract::{Json, Path, Query, State},
+    http::HeaderMap,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+#[derive(Debug, Deserialize)]
+pub struct GenerationQuery {
+    #[serde(default)]
+    cursor: usize,     // 上一次读取返回的cursor，首次读取为0
+    wait: Option<u64>, // 没有新数据时最多等待的秒数，默认使用GENERATION_MAX_WAIT_SECS
+}
+
+/// 轮询方式的补全与发起请求时一样按调用方隔离
+pub(crate) fn generation_caller(state: &AppState, headers: &HeaderMap) -> ApiResult<String> {
+    cache_caller(headers, state, get_api_key_from_header(headers).as_deref())
+        .ok_or_else(|| ApiError::Unauthorized("轮询方式需要有效的API密钥或userToken".to_string()))
+}
+
+/// 读取轮询方式补全的增量输出，chunks与SSE方式的数据块格式相同
+pub async fn get_generation(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(query): Query<GenerationQuery>,
+) -> ApiResult<Json<Value>> {
+    let caller = generation_caller(&state, &headers)?;
+    let page = state
+        .generations
+        .poll(&id, &caller, query.cursor, query.wait.map(Duration::from_secs))
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("补全不存在: {}", id)))?;
+    Ok(Json(json!(page)))
+}
+
+/// 删除轮询方式的补全，进行中的补全停止读取上游
+pub async fn delete_generation(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> ApiResult<Json<Value>> {
+    let caller = generation_caller(&state, &headers)?;
+    if !state.generations.remove(&id, &caller) {
+        return Err(ApiError::NotFound(format!("补全不存在: {}", id)));
+    }
+    Ok(Json(json!({ "id": id, "object": "chat.completion.generation", "deleted": true })))
+}
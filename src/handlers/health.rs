@@ -1,5 +1,8 @@
-use axum::{http::StatusCode, response::Json};
+use crate::handlers::AppState;
+use crate::services::health_prober::{ProbeCheck, UpstreamState, UpstreamStatus};
+use axum::{extract::State, http::StatusCode, response::Json};
 use serde_json::{json, Value};
+use std::time::Instant;
 
 /// 根路径处理器
 pub async fn root() -> Json<Value> {
@@ -10,14 +13,97 @@ pub async fn root() -> Json<Value> {
     }))
 }
 
-/// 健康检查
-pub async fn ping() -> (StatusCode, Json<Value>) {
+/// 存活检查：进程能处理请求即返回200，不检查依赖
+pub async fn ping(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "message": "pong",
+        "timestamp": chrono::Utc::now().timestamp(),
+        "status": "ok",
+        "instance_id": state.leader.instance_id(),
+        "leader": state.leader.is_leader(),
+    }))
+}
+
+/// 就绪检查（管理接口）：存储、WASM文件、上游连通性、可用账号和后台任务，任一项失败时为not_ready并返回503
+pub async fn ready(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let checks = readiness_checks(&state);
+    let ready = checks.iter().all(|check| check.ok);
+    let code = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
     (
-        StatusCode::OK,
+        code,
         Json(json!({
-            "message": "pong",
             "timestamp": chrono::Utc::now().timestamp(),
-            "status": "healthy"
+            "status": if ready { "ready" } else { "not_ready" },
+            "instance_id": state.leader.instance_id(),
+            "leader": state.leader.is_leader(),
+            "checks": checks
         }))
     )
 }
+
+/// 汇总各项就绪检查，只读取后台任务缓存的结果，不在请求中访问磁盘或上游
+pub(crate) fn readiness_checks(state: &AppState) -> Vec<ProbeCheck> {
+    let mut checks = state.health_prober.dependencies();
+    if checks.is_empty() {
+        checks.push(pending("dependencies"));
+    }
+
+    // 未启用定期探测时上游连通性由依赖检查任务一并检查
+    if !checks.iter().any(|check| check.name == "upstream") {
+        let status = state.health_prober.status();
+        checks.push(match status.checks.into_iter().find(|check| check.name == "reachability") {
+            Some(reachability) => ProbeCheck { name: "upstream", ..reachability },
+            None => pending("upstream"),
+        });
+    }
+
+    checks.push(timed("accounts", || match state.api_key_manager.live_account_count() {
+        0 => Err("没有参与轮换且未冷却的账号".to_string()),
+        live => Ok(format!("{} 个账号可用", live)),
+    }));
+
+    checks.push(timed("background_tasks", || {
+        let tasks = state.task_monitor.report();
+        let stalled: Vec<&str> = tasks.iter().filter(|task| !task.running).map(|task| task.name).collect();
+        if stalled.is_empty() {
+            Ok(format!("{} 个任务运行中", tasks.len()))
+        } else {
+            Err(format!("任务未按时运行: {}", stalled.join(", ")))
+        }
+    }));
+    checks
+}
+
+/// 后台尚未完成第一次检查
+fn pending(name: &'static str) -> ProbeCheck {
+    ProbeCheck {
+        name,
+        ok: false,
+        latency_ms: 0,
+        detail: "尚未检查".to_string(),
+    }
+}
+
+fn timed(name: &'static str, run: impl FnOnce() -> Result<String, String>) -> ProbeCheck {
+    let started = Instant::now();
+    let (ok, detail) = match run() {
+        Ok(detail) => (true, detail),
+        Err(detail) => (false, detail),
+    };
+    ProbeCheck {
+        name,
+        ok,
+        latency_ms: started.elapsed().as_millis() as u64,
+        detail,
+    }
+}
+
+/// 上游状态，DeepSeek或本服务异常时返回503
+pub async fn status(State(state): State<AppState>) -> (StatusCode, Json<UpstreamStatus>) {
+    let status = state.health_prober.status();
+    let code = match status.state {
+        UpstreamState::Ok | UpstreamState::Unknown => StatusCode::OK,
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (code, Json(status))
+}
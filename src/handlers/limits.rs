@@ -0,0 +1,86 @@
+use crate::error::ApiError;
+use crate::handlers::{chat, AppState};
+use crate::utils::unix_timestamp_ms;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+
+/// 不受并发上限和IP限制影响的路径，保证监控探针在过载时仍可访问
+pub(crate) const EXEMPT_PATHS: &[&str] = &["/", "/ping", "/status"];
+
+/// 全局并发上限：超出时立即返回429，而不是在tokio中无限排队
+pub async fn load_shed(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(in_flight) = state.in_flight.clone() else {
+        return next.run(request).await;
+    };
+    if EXEMPT_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let permit = match in_flight.try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => {
+            tracing::warn!("In-flight limit reached, shedding {}", request.uri().path());
+            return ApiError::RateLimited {
+                message: "服务器繁忙，请稍后重试".to_string(),
+                retry_after_secs: state.config.server.load_shed_retry_after_secs,
+                model: None,
+            }
+            .into_response();
+        }
+    };
+
+    // 许可随响应体一起释放，流式响应在传输结束前持续占用
+    let (parts, body) = next.run(request).await.into_parts();
+    let body = Body::from_stream(body.into_data_stream().map(move |chunk| {
+        let _ = &permit;
+        chunk
+    }));
+    Response::from_parts(parts, body)
+}
+
+/// API密钥的每分钟请求数限制：/v1接口的响应都带x-ratelimit-*头（与OpenAI一致），超出时返回429
+pub async fn request_limits(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if !request.uri().path().starts_with("/v1/") {
+        return next.run(request).await;
+    }
+    let Some(api_key) = chat::get_api_key_from_header(request.headers())
+        .filter(|key| state.api_key_manager.is_api_key_valid(key).unwrap_or(false))
+    else {
+        return next.run(request).await;
+    };
+    let limit = state
+        .api_key_manager
+        .requests_per_minute(&api_key)
+        .unwrap_or(state.config.server.api_key_requests_per_minute);
+    if limit == 0 {
+        return next.run(request).await;
+    }
+
+    let key_id = state.api_key_manager.api_key_id(&api_key).unwrap_or(api_key);
+    let quota = state.request_limiter.acquire(&key_id, limit, unix_timestamp_ms()).await;
+    let mut response = if quota.allowed {
+        next.run(request).await
+    } else {
+        tracing::warn!(api_key_id = %key_id, "Request limit of {}/min reached", limit);
+        ApiError::RateLimited {
+            message: format!("API密钥超过每分钟 {} 次请求的限制", limit),
+            retry_after_secs: quota.retry_after_secs,
+            model: None,
+        }
+        .into_response()
+    };
+
+    let headers = response.headers_mut();
+    headers.insert("x-ratelimit-limit-requests", HeaderValue::from(quota.limit));
+    headers.insert("x-ratelimit-remaining-requests", HeaderValue::from(quota.remaining));
+    if let Ok(reset) = HeaderValue::from_str(&format!("{}s", quota.reset_secs)) {
+        headers.insert("x-ratelimit-reset-requests", reset);
+    }
+    response
+}
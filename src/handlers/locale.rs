@@ -0,0 +1,105 @@
+use crate::config::ErrorLocale;
+use crate::handlers::AppState;
+use crate::services::error_catalog;
+use axum::{
+    body::{to_bytes, Body, HttpBody},
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+
+/// 翻译的错误响应体上限，超过或长度未知时原样返回
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+/// 按配置或Accept-Language把JSON错误响应的消息翻译为中文或英文
+pub async fn localize_errors(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let locale = match state.config.server.error_locale {
+        ErrorLocale::Auto => request
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(error_catalog::negotiate),
+        locale => Some(locale),
+    };
+
+    let response = next.run(request).await;
+    match locale {
+        Some(locale) => translate_response(response, locale).await,
+        None => response,
+    }
+}
+
+/// 翻译JSON错误响应中的error.message
+async fn translate_response(response: Response, locale: ErrorLocale) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !(response.status().is_client_error() || response.status().is_server_error()) || !is_json {
+        return response;
+    }
+
+    // 读取前按长度判断，过大的响应体不被截断
+    let fits = response.body().size_hint().upper().is_some_and(|len| len <= MAX_ERROR_BODY_BYTES as u64);
+    if !fits {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = to_bytes(body, MAX_ERROR_BODY_BYTES).await else {
+        // 读取响应体出错，原长度已不可信
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    };
+    let Ok(mut error) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    let Some(Value::String(message)) = error.pointer_mut("/error/message") else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+
+    *message = error_catalog::translate(message, locale);
+    let body = serde_json::to_vec(&error).unwrap_or_else(|_| bytes.to_vec());
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    fn error_response(message: &str) -> Response {
+        let body = serde_json::json!({ "error": { "message": message } }).to_string();
+        Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn body_of(response: Response) -> Value {
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_translates_error_message() {
+        let response = translate_response(error_response("无效的API密钥"), ErrorLocale::En).await;
+        assert_eq!(body_of(response).await["error"]["message"], "Invalid API key");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_error_passes_through() {
+        let message = format!("无效的API密钥{}", "x".repeat(MAX_ERROR_BODY_BYTES));
+        let response = translate_response(error_response(&message), ErrorLocale::En).await;
+        let length: usize = response.headers()[header::CONTENT_LENGTH].to_str().unwrap().parse().unwrap();
+
+        let body = body_of(response).await;
+        assert_eq!(body["error"]["message"], message.as_str());
+        assert_eq!(body.to_string().len(), length);
+    }
+}
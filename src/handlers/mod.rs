@@ -1,18 +1,53 @@
 pub mod chat;
 pub mod health;
 pub mod token;
+pub mod conversations;
 pub mod api_keys;
+pub mod accounts;
+pub mod credentials;
+pub mod limits;
+pub mod access;
+pub mod locale;
+pub mod trace;
+pub mod replay;
+pub mod wire_log;
+pub mod completions;
+pub mod generations;
 
 use crate::config::Config;
-use crate::error::ApiResult;
-use crate::services::{DeepSeekClient, ApiKeyManager, LoginService};
+use crate::error::{ApiError, ApiResult};
+use crate::models::ChatCompletionResponse;
+use crate::services::rate_limiter::RequestLimiter;
+use crate::services::{BrowserProfiles, CookieStore, DeepSeekClient, ApiKeyManager, LoginService, QuotaCache, SecretStore, AuditLog, RequestStats, HealthProber, ResponseCache, Singleflight, CredentialVault, ContentFilter, WebhookNotifier, AccountUsage, TaskMonitor, LeaderElection, MetricsPusher, FailedRequests, PluginHost, ScriptHooks, CompletionStore, ThreadStore, GenerationStore};
+use crate::services::metrics_push::MetricsSnapshot;
+use crate::services::{cassette, chaos, mock_upstream};
 use axum::{
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    http::HeaderValue,
+    middleware,
+    routing::{delete, get, post},
     Router,
 };
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Notify, Semaphore};
 use tower::ServiceBuilder;
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
+use tower_http::{
+    cors::{AllowHeaders, AllowMethods, Any, CorsLayer},
+    trace::TraceLayer,
+};
+
+/// 检查新账号是否等待预热的间隔（秒）
+const WARMUP_POLL_SECS: u64 = 2;
+
+/// 补全历史的清理间隔（秒）
+const HISTORY_PRUNE_SECS: u64 = 3600;
+
+/// 检查存储目录和WASM文件的间隔
+const DEPENDENCY_CHECK_SECS: u64 = 30;
+
+/// 后台任务中单个账号的上游调用超时，避免一个账号卡住整轮任务
+const ACCOUNT_CALL_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Clone)]
 pub struct AppState {
@@ -20,39 +55,155 @@ pub struct AppState {
     pub config: Config,
     pub api_key_manager: Arc<ApiKeyManager>,
     pub login_service: Arc<LoginService>,
+    pub secrets: Arc<SecretStore>,
+    pub audit_log: Arc<AuditLog>,
+    pub request_stats: Arc<RequestStats>,
+    pub account_usage: Arc<AccountUsage>,
+    pub task_monitor: Arc<TaskMonitor>, // 后台任务心跳，供健康检查使用
+    pub leader: Arc<LeaderElection>,    // 多实例部署时只有领导者执行独占的后台任务
+    pub health_prober: Arc<HealthProber>,
+    pub in_flight: Option<Arc<Semaphore>>, // 全局并发上限，None表示不限制
+    pub response_cache: Arc<ResponseCache>,
+    pub singleflight: Arc<Singleflight<ChatCompletionResponse>>,
+    pub content_filter: Arc<ContentFilter>,
+    pub plugins: Arc<PluginHost>, // WASM插件钩子
+    pub scripts: Arc<ScriptHooks>, // Rhai钩子脚本
+    pub completion_store: Arc<CompletionStore>, // 补全历史，未配置时不保存
+    pub threads: Arc<ThreadStore>, // 服务端托管的对话
+    pub generations: Arc<GenerationStore>, // 轮询方式的补全
+    pub webhook: Arc<WebhookNotifier>,
+    pub request_limiter: Arc<RequestLimiter>,
+    pub shutdown: Arc<Notify>, // POST /admin/shutdown触发优雅退出
+    pub failed_requests: Arc<FailedRequests>, // 最近失败的补全请求，供管理员重放
 }
 
-pub async fn create_router(config: Config) -> ApiResult<Router> {
-    let client = Arc::new(DeepSeekClient::new(config.clone()));
-    let api_key_manager = Arc::new(ApiKeyManager::new());
-    let login_service = Arc::new(LoginService::new());
+pub async fn create_router(mut config: Config, shutdown: Arc<Notify>) -> ApiResult<Router> {
+    // 快照测试固定随机标识
+    #[cfg(any(test, feature = "deterministic-ids"))]
+    if config.identifier_seed != 0 {
+        tracing::warn!("IDENTIFIER_SEED is set, generated identifiers are predictable");
+        crate::utils::seed_identifiers(config.identifier_seed);
+    }
+    // 模拟模式下所有上游请求都发往内置的模拟上游
+    if config.deepseek.mock {
+        config.deepseek.base_url = mock_upstream::spawn().await.map_err(|e| ApiError::InternalError(format!("启动模拟上游失败: {}", e)))?;
+        config.deepseek.mirrors.clear();
+        config.deepseek.app_version_refresh_secs = 0;
+    }
+    // 录制或回放上游流量时经过本地的cassette服务
+    if let Some(base_url) = cassette::spawn(&config).await.map_err(|e| ApiError::InternalError(format!("启动上游录制/回放失败: {}", e)))? {
+        config.deepseek.base_url = base_url;
+        config.deepseek.mirrors.clear();
+        config.deepseek.app_version_refresh_secs = 0;
+    }
+    // 故障注入代理在最外层，录制的是未被注入故障的上游响应
+    if let Some(base_url) = chaos::spawn(&config).await.map_err(|e| ApiError::InternalError(format!("启动故障注入代理失败: {}", e)))? {
+        config.deepseek.base_url = base_url;
+        config.deepseek.mirrors.clear();
+        config.deepseek.app_version_refresh_secs = 0;
+    }
+
+    let quota_cache = Arc::new(QuotaCache::new(config.deepseek.quota_cache_ttl_secs));
+    let profiles = Arc::new(BrowserProfiles::from_config(&config.deepseek));
+    let credentials = Arc::new(CredentialVault::open(&config.credentials)?);
+    let cookies = Arc::new(CookieStore::open(&config.storage.cookies_path).with_vault(credentials.clone()));
+    let plugins = Arc::new(PluginHost::load(&config.plugins)?);
+    let client = Arc::new(
+        DeepSeekClient::new(config.clone(), quota_cache.clone(), profiles.clone(), cookies.clone()).with_plugins(plugins.clone()),
+    );
+    let api_key_manager = Arc::new(ApiKeyManager::new(
+        &config,
+        quota_cache,
+        credentials,
+        profiles.clone(),
+        cookies.clone(),
+    ));
+    let login_service = Arc::new(LoginService::new(&config, profiles.clone(), cookies));
+
+    // 启动时解析外部密钥引用，解析失败直接退出
+    let secrets = Arc::new(SecretStore::new(config.secrets.clone()));
+    if let Some(auth) = &config.deepseek.authorization {
+        secrets.resolve(auth).await?;
+    }
+    if let Some(token) = &config.metrics.status_canary_token {
+        secrets.resolve(token).await?;
+    }
+    for (label, token) in &config.access.admin_tokens {
+        if secrets.resolve(token).await?.len() < 16 {
+            return Err(ApiError::InternalError(format!("管理令牌{:?}解析后不足16个字符", label)));
+        }
+    }
     
     let state = AppState {
         client,
         config: config.clone(),
         api_key_manager,
         login_service,
+        secrets,
+        audit_log: Arc::new(AuditLog::new(&config.audit)),
+        request_stats: Arc::new(RequestStats::new(config.metrics.stats_window_secs)),
+        account_usage: Arc::new(AccountUsage::new()),
+        task_monitor: Arc::new(TaskMonitor::new()),
+        leader: Arc::new(LeaderElection::new(&config.cluster)?),
+        health_prober: Arc::new(HealthProber::new(&config.deepseek.base_url, &config.http)),
+        in_flight: (config.server.max_in_flight_requests > 0)
+            .then(|| Arc::new(Semaphore::new(config.server.max_in_flight_requests))),
+        response_cache: Arc::new(ResponseCache::new(
+            config.cache.response_ttl_secs,
+            config.cache.response_capacity,
+        )),
+        singleflight: Arc::new(Singleflight::new()),
+        content_filter: Arc::new(ContentFilter::load(&config.content_filter)?),
+        plugins,
+        scripts: Arc::new(ScriptHooks::load(&config.scripts)?),
+        completion_store: Arc::new(CompletionStore::open(&config.history)?),
+        threads: Arc::new(ThreadStore::new(&config.threads)),
+        generations: Arc::new(GenerationStore::new(&config.generations)),
+        webhook: Arc::new(WebhookNotifier::new(&config.webhook)),
+        request_limiter: Arc::new(RequestLimiter::from_config(&config.cluster)?),
+        shutdown,
+        failed_requests: Arc::new(FailedRequests::new(config.audit.failed_request_capacity, config.audit.failed_request_max_chars)),
     };
 
-    let cors = CorsLayer::new()
-        .allow_origin(tower_http::cors::Any)
-        .allow_methods(tower_http::cors::Any)
-        .allow_headers(tower_http::cors::Any);
+    if config.access.admin_tokens.is_empty() {
+        tracing::warn!("ADMIN_SECRET is not set, management endpoints only accept local requests");
+    }
+
+    // 启动时验证账号，失效的token不放入账号池
+    if config.server.validate_accounts_on_start && std::path::Path::new(&config.storage.api_keys_path).exists() {
+        state.api_key_manager.reload().await?;
+    }
 
-    let app = Router::new()
+    spawn_background_tasks(&state);
+
+    let public_cors = cors_layer(&config.server.cors_origins, config.server.cors_allow_credentials);
+    let admin_cors = cors_layer(&config.server.admin_cors_origins, config.server.cors_allow_credentials);
+
+    let public_routes = Router::new()
         // 健康检查
         .route("/", get(health::root))
         .route("/ping", get(health::ping))
+        .route("/status", get(health::status))
         
         // 聊天API - OpenAI兼容
         .route("/v1/chat/completions", post(chat::completions))
         
+        // 对话管理
+        .route("/v1/conversations", get(conversations::list_conversations))
+        .route("/v1/conversations/:id", delete(conversations::delete_conversation))
+        .route("/v1/conversations/:id/export", get(conversations::export_conversation))
+        .route("/v1/threads/:id", get(conversations::get_thread).delete(conversations::delete_thread))
+        .route("/v1/generations/:id", get(generations::get_generation).delete(generations::delete_generation))
+
         // Token检查
         .route("/token/check", post(token::check))
         
         // 模型列表 - OpenAI兼容
         .route("/v1/models", get(chat::models))
-        
+        .route("/v1/models/:model", get(chat::retrieve_model))
+        .layer(public_cors);
+
+    let admin_routes = Router::new()
         // API密钥管理
         .route("/api_keys/create", post(api_keys::create_api_key))
         .route("/api_keys/add_account", post(api_keys::add_account))
@@ -60,18 +211,397 @@ pub async fn create_router(config: Config) -> ApiResult<Router> {
         .route("/api_keys/list", get(api_keys::list_api_keys))
         .route("/api_keys/deactivate", post(api_keys::deactivate_api_key))
         .route("/api_keys/cleanup", post(api_keys::cleanup_expired_keys))
-        .route("/api_keys/stats", post(api_keys::get_session_pool_stats))
+        .route("/api_keys/stats", post(api_keys::get_session_pool_stats).get(api_keys::get_request_stats))
         
+        // 账号管理
+        .route("/accounts", get(accounts::list_accounts))
+        .route("/accounts/groups", get(accounts::list_account_groups))
+        .route("/accounts/usage", get(accounts::account_usage))
+        .route("/accounts/:id", get(accounts::get_account).delete(accounts::delete_account))
+        .route("/accounts/:id/enable", post(accounts::enable_account))
+        .route("/accounts/:id/disable", post(accounts::disable_account))
+        .route("/accounts/:id/link", post(accounts::link_account))
+        .route("/accounts/:id/unlink", post(accounts::unlink_account))
+        .route("/accounts/:id/groups", post(accounts::set_account_groups))
+        .route("/accounts/:id/overrides", post(accounts::set_account_overrides))
+
+        // 凭据库
+        .route("/credentials", get(credentials::list_credentials).post(credentials::store_credential))
+        .route("/credentials/:email", delete(credentials::delete_credential))
+        .route("/credentials/:email/attach", post(credentials::attach_credential))
+        .route("/credentials/:email/login", post(credentials::login_credential))
+
         // 登录和Token验证（调试用）
         .route("/auth/login", post(api_keys::login_for_token))
         .route("/auth/verify", post(api_keys::verify_user_token))
-        
+
+        // 运维
+        .route("/admin/reload", post(api_keys::reload_storage))
+        .route("/admin/shutdown", post(api_keys::shutdown))
+        .route("/admin/ready", get(health::ready))
+        .route("/admin/replay", get(replay::list_failed_requests))
+        .route("/admin/replay/:id", post(replay::replay_request))
+        .route("/admin/wire_log", get(wire_log::wire_log_status).post(wire_log::set_wire_log))
+        .route("/admin/completions", get(completions::list_completions))
+        .route("/admin/completions/:id", get(completions::get_completion).delete(completions::delete_completion))
+        .layer(admin_cors);
+
+    let app = public_routes
+        .merge(admin_routes)
         .layer(
             ServiceBuilder::new()
-                .layer(TraceLayer::new_for_http())
-                .layer(cors)
+                .layer(TraceLayer::new_for_http().make_span_with(trace::request_span))
+                .layer(middleware::from_fn(trace::propagate_trace))
+                .layer(middleware::from_fn_with_state(state.clone(), locale::localize_errors))
+                .layer(middleware::from_fn_with_state(state.clone(), access::ip_filter))
+                .layer(middleware::from_fn_with_state(state.clone(), access::admin_auth))
+                .layer(middleware::from_fn_with_state(state.clone(), limits::load_shed))
+                .layer(middleware::from_fn_with_state(state.clone(), limits::request_limits))
+                .layer(DefaultBodyLimit::max(config.server.max_request_body_bytes))
         )
         .with_state(state);
 
     Ok(app)
 }
+
+/// 按配置的来源构建CORS层：为空时不允许跨域，`*` 允许任意来源
+fn cors_layer(origins: &[String], allow_credentials: bool) -> CorsLayer {
+    if origins.is_empty() {
+        return CorsLayer::new();
+    }
+    if origins.iter().any(|origin| origin == "*") {
+        return CorsLayer::new()
+            .allow_origin(Any)
+            .allow_methods(Any)
+            .allow_headers(Any);
+    }
+
+    let origins: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|origin| HeaderValue::from_str(origin).ok())
+        .collect();
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(AllowMethods::mirror_request())
+        .allow_headers(AllowHeaders::mirror_request())
+        .allow_credentials(allow_credentials)
+}
+
+/// 启动后台任务
+fn spawn_background_tasks(state: &AppState) {
+    // 多实例共享Redis时定期续约领导者租约
+    if let Some(renew_interval) = state.leader.renew_interval() {
+        let tasks = state.task_monitor.clone();
+        let leader = state.leader.clone();
+        let interval_secs = renew_interval.as_secs().max(1);
+
+        tasks.register("leader_election", interval_secs);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(renew_interval);
+            loop {
+                interval.tick().await;
+                tasks.beat("leader_election");
+                leader.renew().await;
+            }
+        });
+    }
+
+    // 空闲时为账号预热会话
+    if state.config.deepseek.prewarm_sessions > 0 {
+        let tasks = state.task_monitor.clone();
+        let client = state.client.clone();
+        let api_key_manager = state.api_key_manager.clone();
+        let interval_secs = state.config.deepseek.prewarm_interval_secs.max(1);
+
+        tasks.register("session_prewarm", interval_secs);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                tasks.beat("session_prewarm");
+                for token in api_key_manager.idle_user_tokens() {
+                    match tokio::time::timeout(ACCOUNT_CALL_TIMEOUT, client.prewarm_sessions(&token)).await {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => tracing::warn!("Session prewarm failed: {}", e),
+                        Err(_) => tracing::warn!("Session prewarm timed out"),
+                    }
+                }
+            }
+        });
+    }
+
+    // 定期查询各账号的功能配额，深度思考请求直接使用缓存
+    if state.config.deepseek.quota_poll_interval_secs > 0 {
+        let tasks = state.task_monitor.clone();
+        let leader = state.leader.clone();
+        let client = state.client.clone();
+        let api_key_manager = state.api_key_manager.clone();
+        let interval_secs = state.config.deepseek.quota_poll_interval_secs;
+
+        tasks.register("quota_poll", interval_secs);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                tasks.beat("quota_poll");
+                if !leader.is_leader() {
+                    continue;
+                }
+                for token in api_key_manager.rotation_user_tokens() {
+                    match tokio::time::timeout(ACCOUNT_CALL_TIMEOUT, client.fetch_thinking_quota(&token)).await {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => tracing::warn!("Quota poll failed: {}", e),
+                        Err(_) => tracing::warn!("Quota poll timed out"),
+                    }
+                }
+            }
+        });
+    }
+
+    // 新添加的账号完成预热后再加入轮换
+    if state.config.pool.warmup_new_accounts {
+        let tasks = state.task_monitor.clone();
+        let client = state.client.clone();
+        let api_key_manager = state.api_key_manager.clone();
+
+        tasks.register("account_warmup", WARMUP_POLL_SECS);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(WARMUP_POLL_SECS));
+            loop {
+                interval.tick().await;
+                tasks.beat("account_warmup");
+                for token in api_key_manager.accounts_pending_warmup() {
+                    let client = client.clone();
+                    let api_key_manager = api_key_manager.clone();
+                    tokio::spawn(async move {
+                        let result = client.warm_up_account(&token).await;
+                        api_key_manager.finish_warmup(&token, result);
+                    });
+                }
+            }
+        });
+    }
+
+    // 定期从DeepSeek网页探测X-App-Version，DeepSeek升级后自动跟进
+    if state.config.deepseek.app_version_refresh_secs > 0 {
+        let tasks = state.task_monitor.clone();
+        let client = state.client.clone();
+        let interval_secs = state.config.deepseek.app_version_refresh_secs;
+
+        tasks.register("app_version_refresh", interval_secs);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                tasks.beat("app_version_refresh");
+                if let Err(e) = client.refresh_app_version().await {
+                    tracing::warn!("X-App-Version detection failed, keeping the current version: {}", e);
+                }
+            }
+        });
+    }
+
+    // 配置了备用镜像时定期测量延迟，选择最快的入口
+    if state.client.has_mirrors() {
+        let tasks = state.task_monitor.clone();
+        let client = state.client.clone();
+        let interval_secs = state.config.deepseek.mirror_probe_interval_secs;
+
+        tasks.register("mirror_probe", interval_secs);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                tasks.beat("mirror_probe");
+                client.probe_mirrors().await;
+            }
+        });
+    }
+
+    // 冷却结束的账号重新探测后再放回轮换
+    {
+        let tasks = state.task_monitor.clone();
+        let leader = state.leader.clone();
+        let client = state.client.clone();
+        let api_key_manager = state.api_key_manager.clone();
+        let interval_secs = state.config.pool.probe_interval_secs.max(1);
+
+        tasks.register("account_probe", interval_secs);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                tasks.beat("account_probe");
+                if !leader.is_leader() {
+                    continue;
+                }
+                for token in api_key_manager.accounts_due_for_probe() {
+                    if matches!(tokio::time::timeout(ACCOUNT_CALL_TIMEOUT, client.probe_account(&token)).await, Ok(Ok(_))) {
+                        api_key_manager.finish_probe(&token, true);
+                        continue;
+                    }
+                    // 探测失败时尝试用凭据库中保存的密码重新登录
+                    match tokio::time::timeout(ACCOUNT_CALL_TIMEOUT, api_key_manager.relogin(&token)).await {
+                        Ok(Ok(Some(new_token))) => {
+                            let healthy = matches!(
+                                tokio::time::timeout(ACCOUNT_CALL_TIMEOUT, client.probe_account(&new_token)).await,
+                                Ok(Ok(_))
+                            );
+                            api_key_manager.finish_probe(&new_token, healthy);
+                        }
+                        Ok(Ok(None)) => api_key_manager.finish_probe(&token, false),
+                        Ok(Err(e)) => {
+                            tracing::warn!("账号重新登录失败: {}", e);
+                            api_key_manager.finish_probe(&token, false);
+                        }
+                        Err(_) => {
+                            tracing::warn!("账号重新登录超时");
+                            api_key_manager.finish_probe(&token, false);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    // 定期清理过期的API密钥
+    if state.config.cluster.key_cleanup_interval_secs > 0 {
+        let tasks = state.task_monitor.clone();
+        let leader = state.leader.clone();
+        let api_key_manager = state.api_key_manager.clone();
+        let interval_secs = state.config.cluster.key_cleanup_interval_secs;
+
+        tasks.register("key_cleanup", interval_secs);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                tasks.beat("key_cleanup");
+                if !leader.is_leader() {
+                    continue;
+                }
+                match api_key_manager.cleanup_expired_keys().await {
+                    Ok(0) => {}
+                    Ok(cleaned) => tracing::info!("Cleaned up {} expired API keys", cleaned),
+                    Err(e) => tracing::warn!("Expired key cleanup failed: {}", e),
+                }
+            }
+        });
+    }
+
+    // 按保留策略清理补全历史，数据库在本机，每个实例各自清理
+    if state.completion_store.is_enabled() {
+        let tasks = state.task_monitor.clone();
+        let store = state.completion_store.clone();
+
+        tasks.register("history_prune", HISTORY_PRUNE_SECS);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(HISTORY_PRUNE_SECS));
+            loop {
+                interval.tick().await;
+                tasks.beat("history_prune");
+                if let Err(e) = store.prune().await {
+                    tracing::warn!("History prune failed: {}", e);
+                }
+            }
+        });
+    }
+
+    // 定期检查本机依赖，就绪检查只读取缓存的结果
+    {
+        let tasks = state.task_monitor.clone();
+        let prober = state.health_prober.clone();
+        let storage_path = state.config.storage.api_keys_path.clone();
+        let wasm_path = state.config.deepseek.wasm_path.clone();
+        let probe_upstream = state.config.metrics.status_probe_interval_secs == 0;
+
+        tasks.register("dependency_check", DEPENDENCY_CHECK_SECS);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(DEPENDENCY_CHECK_SECS));
+            loop {
+                interval.tick().await;
+                tasks.beat("dependency_check");
+                prober.check_dependencies(&storage_path, &wasm_path, probe_upstream).await;
+            }
+        });
+    }
+
+    // 定期探测上游，区分本服务故障和DeepSeek故障
+    if state.config.metrics.status_probe_interval_secs > 0 {
+        let tasks = state.task_monitor.clone();
+        let client = state.client.clone();
+        let prober = state.health_prober.clone();
+        let secrets = state.secrets.clone();
+        let canary_token = state.config.metrics.status_canary_token.clone();
+        let interval_secs = state.config.metrics.status_probe_interval_secs;
+
+        tasks.register("status_probe", interval_secs);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                tasks.beat("status_probe");
+                let canary_token = canary_token.as_deref().map(|token| secrets.get(token));
+                prober.probe(&client, canary_token.as_deref()).await;
+            }
+        });
+    }
+
+    // 无法被抓取时定期把指标推送到Pushgateway，每个实例推送到自己的分组
+    if let Some(pusher) = MetricsPusher::new(&state.config.metrics, state.leader.instance_id()) {
+        let tasks = state.task_monitor.clone();
+        let stats = state.request_stats.clone();
+        let api_key_manager = state.api_key_manager.clone();
+        let leader = state.leader.clone();
+        let interval_secs = state.config.metrics.push_interval_secs.max(1);
+
+        tasks.register("metrics_push", interval_secs);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                tasks.beat("metrics_push");
+                let snapshot = MetricsSnapshot {
+                    requests: stats.report(),
+                    live_accounts: api_key_manager.live_account_count(),
+                    leader: leader.is_leader(),
+                };
+                pusher.push(&snapshot).await;
+            }
+        });
+    }
+
+    // 定期重新读取外部密钥，支持轮换
+    if state.config.secrets.refresh_secs > 0 {
+        let tasks = state.task_monitor.clone();
+        let secrets = state.secrets.clone();
+        let interval_secs = state.config.secrets.refresh_secs;
+
+        tasks.register("secrets_refresh", interval_secs);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                tasks.beat("secrets_refresh");
+                let rotated = secrets.refresh().await;
+                if rotated > 0 {
+                    tracing::info!("Rotated {} external secrets", rotated);
+                }
+            }
+        });
+    }
+}
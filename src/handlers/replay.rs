@@ -0,0 +1,64 @@
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::AppState;
+use crate::models::CompletionOptions;
+use crate::services::RequestContext;
+use crate::utils::unix_timestamp;
+use axum::extract::{Json, Path, State};
+use serde_json::{json, Value};
+use std::time::Instant;
+use tracing::Instrument;
+
+/// 列出最近失败的补全请求，从新到旧
+pub async fn list_failed_requests(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "object": "list",
+        "capacity": state.config.audit.failed_request_capacity,
+        "data": state.failed_requests.list()
+    }))
+}
+
+/// 以非流式方式重新执行一个失败请求，响应附带上游原始SSE行和各阶段耗时
+pub async fn replay_request(State(state): State<AppState>, Path(id): Path<String>) -> ApiResult<Json<Value>> {
+    let failed = state
+        .failed_requests
+        .get(&id)
+        .ok_or_else(|| ApiError::NotFound(format!("失败请求不存在或已被淘汰: {}", id)))?;
+    // 原账号不在轮换中时使用其他账号，不占用会话池中的会话
+    let token = state
+        .api_key_manager
+        .rotation_user_token(failed.account.as_deref())
+        .ok_or_else(|| ApiError::ServiceUnavailable("没有可用于重放的账号".to_string()))?;
+    let account = state.api_key_manager.account_email(&token);
+
+    let options = CompletionOptions {
+        continue_final_message: failed.request.continue_final_message.unwrap_or(true),
+        features: failed.request.features(),
+        debug_upstream: true,
+        overrides: Default::default(),
+        chaos: None,
+    };
+    let ctx = RequestContext::new();
+    let started_at = Instant::now();
+    let span = tracing::info_span!("replay", request_id = %failed.id, model = %failed.model, account = ?account);
+    let result = state
+        .client
+        .create_completion(&failed.model, &failed.request.messages, &token, None, options, &ctx)
+        .instrument(span.clone())
+        .await;
+    span.in_scope(|| match &result {
+        Ok(_) => tracing::info!("Replayed failed request succeeded"),
+        Err(e) => tracing::warn!(error = %e, original_error = %failed.error, "Replayed failed request failed again"),
+    });
+
+    Ok(Json(json!({
+        "id": failed.id,
+        "replayed_at": unix_timestamp(),
+        "account": account,
+        "original_error": failed.error,
+        "success": result.is_ok(),
+        "error": result.as_ref().err().map(ToString::to_string),
+        "retries": ctx.retries(),
+        "server_timing": ctx.server_timing(started_at.elapsed()),
+        "response": result.ok(),
+    })))
+}
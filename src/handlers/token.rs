@@ -1,16 +1,59 @@
 use crate::error::ApiError;
 use crate::handlers::AppState;
-use crate::models::{TokenCheckRequest, TokenCheckResponse};
-use axum::{extract::State, response::Json};
+use crate::models::{BatchTokenCheckResponse, TokenCheckRequest, TokenCheckResponse, TokenCheckResult};
+use crate::utils::split_tokens;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Json, Response},
+};
+use futures_util::{stream, StreamExt};
 
-/// 检查token状态
+/// 单次批量检查的token上限
+const MAX_BATCH_TOKENS: usize = 100;
+
+/// 批量检查时同时进行的上游请求数
+const CHECK_CONCURRENCY: usize = 8;
+
+/// 检查token状态；`tokens` 数组或逗号分隔的 `token` 按批量检查返回每个token的结果
 pub async fn check(
     State(state): State<AppState>,
     Json(request): Json<TokenCheckRequest>,
-) -> Result<Json<TokenCheckResponse>, ApiError> {
-    tracing::info!("Checking token status");
+) -> Result<Response, ApiError> {
+    let mut tokens = split_tokens(&request.token);
+    tokens.extend(request.tokens.iter().flat_map(|token| split_tokens(token)));
+
+    if tokens.is_empty() {
+        return Err(ApiError::BadRequest("缺少token参数".to_string()));
+    }
+    if tokens.len() > MAX_BATCH_TOKENS {
+        return Err(ApiError::BadRequest(format!("一次最多检查 {} 个token", MAX_BATCH_TOKENS)));
+    }
+
+    // 单个token保持原来的响应格式
+    if request.tokens.is_empty() && tokens.len() == 1 {
+        tracing::info!("Checking token status");
+        let (live, expires_at) = state.client.check_token(&tokens[0]).await?;
+        return Ok(Json(TokenCheckResponse { live, expires_at }).into_response());
+    }
 
-    let live = state.client.check_token_status(&request.token).await?;
+    tracing::info!("Checking status of {} tokens", tokens.len());
+    let results: Vec<TokenCheckResult> = stream::iter(tokens)
+        .map(|token| {
+            let client = state.client.clone();
+            async move {
+                let (live, expires_at) = client.check_token(&token).await.unwrap_or((false, None));
+                TokenCheckResult { token, live, expires_at }
+            }
+        })
+        .buffered(CHECK_CONCURRENCY)
+        .collect()
+        .await;
 
-    Ok(Json(TokenCheckResponse { live }))
+    let live_count = results.iter().filter(|result| result.live).count();
+    Ok(Json(BatchTokenCheckResponse {
+        dead_count: results.len() - live_count,
+        live_count,
+        results,
+    })
+    .into_response())
 }
@@ -0,0 +1,43 @@
+use crate::services::TraceContext;
+use axum::{
+    body::Body,
+    extract::Request,
+    http::Request as HttpRequest,
+    middleware::Next,
+    response::Response,
+};
+use tracing::{field::Empty, Span};
+
+/// 请求span，预留W3C追踪字段，由propagate_trace填写
+pub fn request_span(request: &HttpRequest<Body>) -> Span {
+    tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        trace_id = Empty,
+        parent_span_id = Empty,
+        span_id = Empty,
+    )
+}
+
+/// 接受调用方的traceparent/tracestate，记录到请求span，并在处理期间传递给发往DeepSeek的请求
+pub async fn propagate_trace(request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    let trace = headers
+        .get("traceparent")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|traceparent| {
+            let tracestate = headers.get("tracestate").and_then(|value| value.to_str().ok());
+            TraceContext::parse(traceparent, tracestate)
+        });
+    let Some(trace) = trace else {
+        return next.run(request).await;
+    };
+
+    let span = Span::current();
+    span.record("trace_id", trace.trace_id.as_str());
+    span.record("parent_span_id", trace.parent_id.as_str());
+    span.record("span_id", trace.span_id.as_str());
+    trace.scope(next.run(request)).await
+}
@@ -0,0 +1,27 @@
+use crate::handlers::AppState;
+use axum::extract::{Json, State};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Deserialize)]
+pub struct WireLogRequest {
+    pub enabled: bool,
+}
+
+/// 查看上游线路日志是否开启
+pub async fn wire_log_status(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({ "enabled": state.client.wire_log().is_enabled() }))
+}
+
+/// 在运行时开启或关闭上游线路日志，重启后恢复为 UPSTREAM_WIRE_LOG 的设置
+pub async fn set_wire_log(State(state): State<AppState>, Json(request): Json<WireLogRequest>) -> Json<Value> {
+    let wire_log = state.client.wire_log();
+    let previous = wire_log.is_enabled();
+    wire_log.set_enabled(request.enabled);
+    tracing::info!("Upstream wire logging {} (was {})", request.enabled, previous);
+    Json(json!({
+        "success": true,
+        "enabled": request.enabled,
+        "previous": previous
+    }))
+}
@@ -0,0 +1,153 @@
+use crate::cli::ImportAccountsArgs;
+use crate::config::Config;
+use crate::services::{ApiKeyManager, BrowserProfiles, CookieStore, CredentialVault, QuotaCache, SecretStore};
+use colored::*;
+use futures_util::{stream, StreamExt};
+use serde_json::Value;
+use std::fs;
+use std::sync::Arc;
+
+/// 账号文件中的一行
+#[derive(Debug, PartialEq, Eq)]
+enum ImportEntry {
+    Credentials { email: String, password: String },
+    Token(String),
+}
+
+impl ImportEntry {
+    /// 报告中显示的账号标识，不输出密码和完整token
+    fn label(&self) -> String {
+        match self {
+            ImportEntry::Credentials { email, .. } => email.clone(),
+            ImportEntry::Token(token) => format!("token:{}", token.chars().take(8).collect::<String>()),
+        }
+    }
+}
+
+/// 解析一行：空行、`#` 注释和表头返回None；`{` 开头按JSON解析，否则按 `email,password` 或单独的token解析
+fn parse_line(line: &str) -> Result<Option<ImportEntry>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    if line.starts_with('{') {
+        let value: Value = serde_json::from_str(line).map_err(|e| format!("JSON格式无效: {}", e))?;
+        let field = |name: &str| value.get(name).and_then(Value::as_str).map(str::trim).filter(|v| !v.is_empty());
+        return match (field("email"), field("password"), field("token").or_else(|| field("user_token"))) {
+            (Some(email), Some(password), _) => Ok(Some(ImportEntry::Credentials {
+                email: email.to_string(),
+                password: password.to_string(),
+            })),
+            (_, _, Some(token)) => Ok(Some(ImportEntry::Token(token.to_string()))),
+            _ => Err("缺少email/password或token字段".to_string()),
+        };
+    }
+
+    // 密码中可能含有逗号，只按第一个逗号拆分
+    match line.split_once(',') {
+        Some((email, _)) if email.trim().eq_ignore_ascii_case("email") => Ok(None),
+        Some((email, password)) if !email.trim().is_empty() && !password.trim().is_empty() => Ok(Some(ImportEntry::Credentials {
+            email: email.trim().to_string(),
+            password: password.trim().to_string(),
+        })),
+        Some(_) => Err("email和password不能为空".to_string()),
+        None if line.eq_ignore_ascii_case("token") => Ok(None),
+        None => Ok(Some(ImportEntry::Token(line.to_string()))),
+    }
+}
+
+/// 批量导入账号并打印报告，全部成功时返回true
+pub async fn import_accounts(args: &ImportAccountsArgs) -> anyhow::Result<bool> {
+    let content = fs::read_to_string(&args.file)
+        .map_err(|e| anyhow::anyhow!("无法读取 {}: {}", args.file.display(), e))?;
+
+    let mut failures = 0;
+    let mut entries = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        match parse_line(line) {
+            Ok(Some(entry)) => entries.push((index + 1, entry)),
+            Ok(None) => {}
+            Err(e) => {
+                failures += 1;
+                println!("{} 第{}行: {}", "[FAIL]".bright_red().bold(), index + 1, e);
+            }
+        }
+    }
+
+    let config = Config::load()?;
+    let credentials = Arc::new(CredentialVault::open(&config.credentials)?);
+    let manager = Arc::new(ApiKeyManager::new(
+        &config,
+        Arc::new(QuotaCache::new(config.deepseek.quota_cache_ttl_secs)),
+        credentials.clone(),
+        Arc::new(BrowserProfiles::from_config(&config.deepseek)),
+        Arc::new(CookieStore::open(&config.storage.cookies_path).with_vault(credentials)),
+    ));
+    if !manager.is_api_key_valid(&args.api_key)? {
+        anyhow::bail!("无效的API密钥");
+    }
+    let secrets = SecretStore::new(config.secrets.clone());
+
+    println!("从 {} 导入 {} 个账号，并发数 {}", args.file.display(), entries.len(), args.concurrency);
+    let mut succeeded = 0;
+    let mut results = stream::iter(entries)
+        .map(|(line, entry)| {
+            let (manager, secrets) = (manager.clone(), &secrets);
+            async move {
+                let result = match &entry {
+                    // 密码和token可以是外部密钥引用
+                    ImportEntry::Credentials { email, password } => match secrets.resolve(password).await {
+                        Ok(password) => manager.add_account(args.api_key.clone(), email.clone(), password).await,
+                        Err(e) => Err(e),
+                    },
+                    ImportEntry::Token(token) => match secrets.resolve(token).await {
+                        Ok(token) => manager.add_account_token(&args.api_key, &token).await,
+                        Err(e) => Err(e),
+                    },
+                };
+                (line, entry.label(), result)
+            }
+        })
+        .buffer_unordered(args.concurrency as usize);
+
+    while let Some((line, label, result)) = results.next().await {
+        match result {
+            Ok(response) => {
+                succeeded += 1;
+                println!("{} 第{}行 {}: {}", "[OK]".bright_green().bold(), line, label, response.message);
+            }
+            Err(e) => {
+                failures += 1;
+                println!("{} 第{}行 {}: {}", "[FAIL]".bright_red().bold(), line, label, e);
+            }
+        }
+    }
+
+    let summary = format!("导入完成：成功 {} 个，失败 {} 个", succeeded, failures);
+    if failures == 0 {
+        println!("{}", summary.bright_green().bold());
+    } else {
+        println!("{}", summary.bright_red().bold());
+    }
+    Ok(failures == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_import_lines() {
+        assert_eq!(parse_line("email,password"), Ok(None));
+        assert_eq!(parse_line("  # comment"), Ok(None));
+        assert_eq!(
+            parse_line("a@example.com, pa,ss "),
+            Ok(Some(ImportEntry::Credentials { email: "a@example.com".to_string(), password: "pa,ss".to_string() }))
+        );
+        assert_eq!(parse_line("abcdef123456"), Ok(Some(ImportEntry::Token("abcdef123456".to_string()))));
+        assert_eq!(parse_line(r#"{"token": "abcdef123456"}"#), Ok(Some(ImportEntry::Token("abcdef123456".to_string()))));
+        assert!(parse_line(r#"{"email": "a@example.com"}"#).is_err());
+        assert!(parse_line("a@example.com,").is_err());
+    }
+}
@@ -0,0 +1,21 @@
+//! DeepSeek Free API服务，二进制入口见main.rs；集成测试可以用 [`TestServer`] 启动完整的服务
+
+pub mod bench;
+pub mod check;
+pub mod cli;
+pub mod compat;
+pub mod config;
+pub mod control;
+pub mod error;
+pub mod export;
+pub mod handlers;
+pub mod import;
+pub mod logging;
+pub mod models;
+pub mod services;
+pub mod systemd;
+pub mod testing;
+pub mod tls;
+pub mod utils;
+
+pub use testing::TestServer;
@@ -0,0 +1,201 @@
+use crate::cli::LogFormat;
+use crate::config::{LogRotation, LoggingConfig};
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::{filter_fn, EnvFilter, FilterExt, Targets};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::{Layer, Registry};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// 访问日志：TraceLayer输出的请求完成事件，以及提供method/uri上下文的请求span
+const ACCESS_TARGET: &str = "tower_http::trace";
+const REQUEST_SPAN_TARGET: &str = "deepseek_free_api::handlers::trace";
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// 初始化日志：控制台、应用日志文件和访问日志文件各自独立输出；返回的guard需要保持到程序退出，否则缓冲的日志会丢失
+pub fn init(log_level: Option<&str>, format: LogFormat, config: &LoggingConfig) -> Result<Vec<WorkerGuard>> {
+    let mut layers: Vec<BoxedLayer> = Vec::new();
+    let mut guards = Vec::new();
+
+    if config.console || config.file_path.is_none() {
+        layers.push(fmt_layer(io::stdout, format, true).with_filter(env_filter(log_level)?).boxed());
+    }
+
+    if let Some(path) = &config.file_path {
+        let (writer, guard) = file_writer(path, config)?;
+        guards.push(guard);
+        let layer = fmt_layer(writer, format, false);
+        if config.access_log_path.is_some() {
+            let not_access = filter_fn(|metadata| !metadata.target().starts_with(ACCESS_TARGET));
+            layers.push(layer.with_filter(env_filter(log_level)?.and(not_access)).boxed());
+        } else {
+            layers.push(layer.with_filter(env_filter(log_level)?).boxed());
+        }
+    }
+
+    if let Some(path) = &config.access_log_path {
+        let (writer, guard) = file_writer(path, config)?;
+        guards.push(guard);
+        let targets = Targets::new()
+            .with_target(ACCESS_TARGET, LevelFilter::DEBUG)
+            .with_target(REQUEST_SPAN_TARGET, LevelFilter::DEBUG);
+        layers.push(fmt_layer(writer, format, false).with_filter(targets).boxed());
+    }
+
+    tracing_subscriber::registry().with(layers).init();
+    Ok(guards)
+}
+
+fn env_filter(log_level: Option<&str>) -> Result<EnvFilter> {
+    Ok(match log_level {
+        Some(level) => EnvFilter::try_new(level)?,
+        None => EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| "deepseek_free_api=debug,tower_http=debug".into()),
+    })
+}
+
+fn fmt_layer<W>(writer: W, format: LogFormat, ansi: bool) -> BoxedLayer
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    }
+}
+
+/// 按配置的轮转方式打开日志文件，写入在后台线程进行
+fn file_writer(path: &str, config: &LoggingConfig) -> Result<(NonBlocking, WorkerGuard)> {
+    let path = Path::new(path);
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("无效的日志文件路径 {}", path.display()))?;
+
+    let rotation = match config.rotation {
+        LogRotation::Size => {
+            let writer = SizeRollingWriter::open(path, config.max_bytes, config.max_files)
+                .with_context(|| format!("无法打开日志文件 {}", path.display()))?;
+            return Ok(tracing_appender::non_blocking(writer));
+        }
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+
+    let mut builder = RollingFileAppender::builder().rotation(rotation).filename_prefix(file_name);
+    if config.max_files > 0 {
+        builder = builder.max_log_files(config.max_files);
+    }
+    let appender = builder
+        .build(dir)
+        .with_context(|| format!("无法打开日志文件 {}", path.display()))?;
+    Ok(tracing_appender::non_blocking(appender))
+}
+
+/// 按大小轮转的日志文件：超过max_bytes时当前文件改名为 `.1`，已有的历史文件依次后移
+struct SizeRollingWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    written: u64,
+}
+
+impl SizeRollingWriter {
+    fn open(path: &Path, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            max_files,
+            file,
+            written,
+        })
+    }
+
+    fn backup(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let keep = if self.max_files == 0 { usize::MAX } else { self.max_files };
+        let mut last = 0;
+        while self.backup(last + 1).exists() {
+            last += 1;
+        }
+        for index in (1..=last).rev() {
+            if index >= keep {
+                fs::remove_file(self.backup(index))?;
+            } else {
+                fs::rename(self.backup(index), self.backup(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.backup(1))?;
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRollingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_rotation_keeps_max_files() {
+        let dir = std::env::temp_dir().join(format!("deepseek-log-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("app.log");
+
+        let mut writer = SizeRollingWriter::open(&path, 10, 2).unwrap();
+        for line in ["first-line\n", "second-line\n", "third-line\n", "fourth-line\n"] {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth-line\n");
+        assert_eq!(fs::read_to_string(writer.backup(1)).unwrap(), "third-line\n");
+        assert_eq!(fs::read_to_string(writer.backup(2)).unwrap(), "second-line\n");
+        assert!(!writer.backup(3).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
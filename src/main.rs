@@ -1,54 +1,205 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use axum_server::tls_rustls::RustlsConfig;
+use clap::Parser;
 use colored::*;
-use std::env;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
-mod config;
-mod error;
-mod handlers;
-mod models;
-mod services;
-mod utils;
-
-use config::Config;
-use handlers::create_router;
+use deepseek_free_api::cli::{Cli, Command, ServeArgs};
+use deepseek_free_api::config::Config;
+use deepseek_free_api::handlers::create_router;
+use deepseek_free_api::tls::ClientCertAcceptor;
+use deepseek_free_api::{bench, check, compat, control, export, import, logging, systemd, tls};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // 初始化日志
-    init_logging()?;
-    
-    // 加载配置
-    dotenv::dotenv().ok();
-    let config = Config::load()?;
-    
+    let mut cli = Cli::parse();
+
+    // 加载配置文件
+    match &cli.config {
+        Some(path) => {
+            dotenv::from_path(path)?;
+        }
+        None => {
+            dotenv::dotenv().ok();
+        }
+    }
+
+    // 初始化日志；配置有误时先按默认方式输出，具体错误由后续加载配置时报告
+    let logging = Config::load().map(|config| config.logging).unwrap_or_else(|_| Config::default().logging);
+    let _log_guards = logging::init(cli.log_level.as_deref(), cli.log_format()?, &logging)?;
+
+    match cli.command() {
+        Command::Serve(args) => serve(Config::load()?, args).await,
+        Command::CheckConfig(args) => {
+            if !check::check_config(&args).await {
+                bail!("配置检查未通过");
+            }
+            Ok(())
+        }
+        Command::ImportAccounts(args) => {
+            if !import::import_accounts(&args).await? {
+                bail!("部分账号导入失败");
+            }
+            Ok(())
+        }
+        Command::ExportUsage(args) => export::export_usage(&args),
+        Command::Status(args) => {
+            if !control::status(&args).await? {
+                bail!("实例状态异常");
+            }
+            Ok(())
+        }
+        Command::Stop(args) => control::stop(&args).await,
+        Command::BenchServe(args) => {
+            if !bench::bench_serve(&args).await? {
+                bail!("部分请求失败");
+            }
+            Ok(())
+        }
+        Command::CompatCheck(args) => {
+            if !compat::compat_check(&args).await? {
+                bail!("兼容性检查未通过");
+            }
+            Ok(())
+        }
+    }
+}
+
+/// 启动HTTP服务
+async fn serve(mut config: Config, args: ServeArgs) -> Result<()> {
+    if let Some(host) = args.host {
+        config.server.host = host;
+    }
+    if let Some(port) = args.port {
+        config.server.port = port;
+    }
+    if let Some(pid_file) = args.pid_file {
+        config.server.pid_file = Some(pid_file);
+    }
+    // systemd等待账号验证完成后才认为服务就绪
+    if systemd::is_supervised() {
+        config.server.validate_accounts_on_start = true;
+    }
+
     println!("{}", "DeepSeek Free API Server (Rust Version)".bright_green().bold());
     println!("Version: {}", env!("CARGO_PKG_VERSION"));
     println!("Environment: {}", config.environment);
     println!("Server binding to: {}:{}", config.server.host, config.server.port);
     
     // 创建路由
-    let app = create_router(config.clone()).await?;
+    let shutdown = Arc::new(tokio::sync::Notify::new());
+    let app = create_router(config.clone(), shutdown.clone()).await?;
     
     // 启动服务器
     let addr = format!("{}:{}", config.server.host, config.server.port);
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    
-    println!("{}", format!("Server started on http://{}", addr).bright_green().bold());
-    
-    axum::serve(listener, app).await?;
+    let listener = bind(&addr, config.server.reuse_port).await?;
+    let drain_timeout = (config.server.drain_timeout_secs > 0)
+        .then(|| Duration::from_secs(config.server.drain_timeout_secs));
+
+    let _pid_file = config.server.pid_file.as_deref().map(systemd::PidFile::create).transpose()?;
+    // 监听套接字和TLS配置都就绪后才通知systemd并开始看门狗心跳，证书加载失败时启动失败而不是先报告就绪
+    let ready = || {
+        systemd::notify(&format!("READY=1\nSTATUS=Listening on {}", addr));
+        systemd::spawn_watchdog();
+    };
+
+    if config.server.is_tls_enabled() {
+        let tls_config = RustlsConfig::from_config(Arc::new(tls::build_server_config(&config.server)?));
+        tls::spawn_reload(config.server.clone(), tls_config.clone());
+
+        ready();
+        println!("{}", format!("Server started on https://{}", addr).bright_green().bold());
+
+        let acceptor = ClientCertAcceptor::new(tls_config, config.server.tls_client_identities.clone());
+        let handle = axum_server::Handle::new();
+        let handle_shutdown = handle.clone();
+        tokio::spawn(async move {
+            shutdown_signal(&shutdown).await;
+            handle_shutdown.graceful_shutdown(drain_timeout);
+        });
+        axum_server::from_tcp(listener.into_std()?)
+            .handle(handle)
+            .acceptor(acceptor)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await?;
+    } else {
+        ready();
+        println!("{}", format!("Server started on http://{}", addr).bright_green().bold());
+
+        let (draining, drain_started) = tokio::sync::oneshot::channel();
+        let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(async move {
+                shutdown_signal(&shutdown).await;
+                let _ = draining.send(());
+            });
+        // 超过排空时间后直接退出，未结束的流随进程关闭
+        tokio::select! {
+            result = server => result?,
+            _ = async {
+                let _ = drain_started.await;
+                match drain_timeout {
+                    Some(timeout) => tokio::time::sleep(timeout).await,
+                    None => std::future::pending().await,
+                }
+            } => tracing::warn!("Drain timeout reached, closing remaining connections"),
+        }
+    }
     
     Ok(())
 }
 
-fn init_logging() -> Result<()> {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "deepseek_free_api=debug,tower_http=debug".into())
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-    
-    Ok(())
+/// 获取监听套接字：优先使用systemd套接字激活传入的套接字，否则按配置绑定，reuse_port时允许新旧进程同时监听
+async fn bind(addr: &str, reuse_port: bool) -> Result<tokio::net::TcpListener> {
+    if let Some(listener) = systemd::inherited_listener() {
+        tracing::info!("Using listener inherited from systemd socket activation");
+        listener.set_nonblocking(true)?;
+        return Ok(tokio::net::TcpListener::from_std(listener)?);
+    }
+    if !reuse_port {
+        return Ok(tokio::net::TcpListener::bind(addr).await?);
+    }
+
+    let addr = tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("无法解析监听地址 {}", addr))?;
+    let socket = if addr.is_ipv4() {
+        tokio::net::TcpSocket::new_v4()?
+    } else {
+        tokio::net::TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    #[cfg(unix)]
+    socket.set_reuseport(true)?;
+    socket.bind(addr)?;
+    Ok(socket.listen(1024)?)
+}
+
+/// 等待Ctrl-C、SIGTERM或管理接口的停止请求，收到后停止接受新连接并通知systemd
+async fn shutdown_signal(requested: &tokio::sync::Notify) {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+        _ = requested.notified() => {},
+    }
+    tracing::info!("Shutdown signal received, draining connections");
+    systemd::notify("STOPPING=1");
 }
@@ -1,3 +1,5 @@
+use crate::config::{FaultRates, FilterMode};
+use crate::utils::{is_fold_model, is_search_model, is_silent_model, is_thinking_model};
 use serde::{Deserialize, Serialize};
 
 // OpenAI兼容的聊天请求结构
@@ -7,18 +9,134 @@ pub struct ChatCompletionRequest {
     pub messages: Vec<ChatMessage>,
     pub stream: Option<bool>,
     pub conversation_id: Option<String>,
+    pub thread_id: Option<String>, // 服务端托管的对话，只需发送本轮新增的消息
     pub temperature: Option<f32>,
     pub max_tokens: Option<u32>,
     pub top_p: Option<f32>,
     pub frequency_penalty: Option<f32>,
     pub presence_penalty: Option<f32>,
     pub stop: Option<Vec<String>>,
+    pub user: Option<String>, // 终端用户标识，用于粘性路由
+    pub continue_final_message: Option<bool>, // 最后一条为assistant消息时是否作为预填充续写，默认true
+    pub stream_tokens_per_sec: Option<u32>, // 本次流式响应的发送速率（token/秒），覆盖API密钥和全局设置
+    #[serde(flatten)]
+    pub features: DeepSeekFeatures, // search_enabled / thinking_enabled / reasoning_display，覆盖模型名推断
+    pub deepseek: Option<DeepSeekFeatures>, // OpenAI SDK的 extra_body={"deepseek": {...}} 展开后的形式
+    pub extra_body: Option<ExtraBody>,
+}
+
+impl ChatCompletionRequest {
+    /// 合并请求中的功能开关，顶层字段优先，其次是deepseek对象和extra_body.deepseek
+    pub fn features(&self) -> DeepSeekFeatures {
+        let extra = self.extra_body.as_ref().and_then(|extra| extra.deepseek).unwrap_or_default();
+        self.features.or(self.deepseek.unwrap_or_default()).or(extra)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtraBody {
+    pub deepseek: Option<DeepSeekFeatures>,
+}
+
+/// 思考过程的展示方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningDisplay {
+    Inline, // 用[思考开始]/[思考结束]标记放在正文前
+    Hidden, // 不输出思考过程
+    Fold,   // 放在<details>折叠块中
+}
+
+/// 请求中的DeepSeek功能开关，未设置的项按模型名推断
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeepSeekFeatures {
+    pub search_enabled: Option<bool>,
+    pub thinking_enabled: Option<bool>,
+    pub reasoning_display: Option<ReasoningDisplay>,
+}
+
+impl DeepSeekFeatures {
+    /// 逐项合并，已设置的项优先
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            search_enabled: self.search_enabled.or(other.search_enabled),
+            thinking_enabled: self.thinking_enabled.or(other.thinking_enabled),
+            reasoning_display: self.reasoning_display.or(other.reasoning_display),
+        }
+    }
+
+    /// 确定最终使用的功能：显式设置的项优先，其余按模型名和提示词中的关键字推断
+    pub fn resolve(&self, model: &str, prompt: &str) -> ModelFeatures {
+        let reasoning_display = if is_silent_model(model) {
+            ReasoningDisplay::Hidden
+        } else if is_fold_model(model) {
+            ReasoningDisplay::Fold
+        } else {
+            ReasoningDisplay::Inline
+        };
+
+        ModelFeatures {
+            search: self.search_enabled.unwrap_or_else(|| is_search_model(model) || prompt.contains("联网搜索")),
+            thinking: self.thinking_enabled.unwrap_or_else(|| is_thinking_model(model) || prompt.contains("深度思考")),
+            reasoning_display: self.reasoning_display.unwrap_or(reasoning_display),
+        }
+    }
+}
+
+/// 一次补全实际使用的功能
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelFeatures {
+    pub search: bool,
+    pub thinking: bool,
+    pub reasoning_display: ReasoningDisplay,
+}
+
+/// 单次补全的选项
+#[derive(Debug, Clone, Copy)]
+pub struct CompletionOptions {
+    pub continue_final_message: bool,
+    pub features: DeepSeekFeatures,
+    pub debug_upstream: bool, // 在响应中附带上游原始SSE行（X-Debug: upstream）
+    pub overrides: ApiKeyOverrides, // API密钥单独设置的运行参数
+    pub chaos: Option<FaultRates>,  // 本次请求注入的故障比例（X-Chaos），只在故障注入模式下有效
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: String,
-    pub content: ChatMessageContent,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub content: ChatMessageContent, // 带tool_calls的assistant消息content可以为null
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>, // 发言者名称，tool/function消息为工具名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>, // tool消息对应的工具调用ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>, // assistant消息发起的工具调用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>, // 思考过程，与官方API一致单独返回
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default)]
+    pub call_type: Option<String>,
+    pub function: ToolCallFunction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallFunction {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: String,
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +146,12 @@ pub enum ChatMessageContent {
     Array(Vec<ContentPart>),
 }
 
+impl Default for ChatMessageContent {
+    fn default() -> Self {
+        ChatMessageContent::Text(String::new())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContentPart {
     #[serde(rename = "type")]
@@ -51,6 +175,8 @@ pub struct ChatCompletionResponse {
     pub model: String,
     pub choices: Vec<ChatChoice>,
     pub usage: Option<ChatUsage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x_upstream: Option<Vec<String>>, // 调试模式下的上游原始SSE行
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,6 +223,46 @@ pub struct ChatSession {
     pub character_id: Option<String>,
 }
 
+/// 上游会话的历史消息
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatHistory {
+    #[serde(default)]
+    pub chat_session: Option<ChatSessionInfo>,
+    #[serde(default)]
+    pub chat_messages: Vec<HistoryMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSessionInfo {
+    pub id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryMessage {
+    pub message_id: u64,
+    #[serde(default)]
+    pub role: String, // USER / ASSISTANT
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub thinking_content: Option<String>,
+    #[serde(default)]
+    pub search_results: Option<Vec<HistorySearchResult>>,
+    #[serde(default)]
+    pub inserted_at: Option<f64>, // unix秒
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySearchResult {
+    pub url: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub cite_index: Option<u32>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChallengeRequest {
     pub target_path: String,
@@ -137,6 +303,18 @@ pub struct CompletionRequest {
     pub thinking_enabled: bool,
 }
 
+/// 上传到DeepSeek的文件，status为SUCCESS后才能在对话中引用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadedFile {
+    pub id: String,
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchFilesResponse {
+    pub files: Vec<UploadedFile>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ThinkingQuota {
     pub quota: u32,
@@ -151,12 +329,31 @@ pub struct FeatureQuota {
 // Token状态检查
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenCheckRequest {
-    pub token: String,
+    #[serde(default)]
+    pub token: String, // 单个token，或逗号分隔的多个token
+    #[serde(default)]
+    pub tokens: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenCheckResponse {
     pub live: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>, // 缓存的访问令牌过期时间（unix秒）
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenCheckResult {
+    pub token: String,
+    pub live: bool,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchTokenCheckResponse {
+    pub results: Vec<TokenCheckResult>, // 与请求中的顺序一致
+    pub live_count: usize,
+    pub dead_count: usize,
 }
 
 // 登录相关
@@ -211,12 +408,66 @@ pub struct ApiKey {
     pub expires_at: Option<u64>,
     pub usage_count: u64,
     pub is_active: bool,
+    #[serde(default = "default_api_key_weight")]
+    pub weight: u32, // 公平调度权重，账号容量紧张时按权重分配
+    #[serde(default)]
+    pub priority: ApiKeyPriority,
+    #[serde(default)]
+    pub default_model: Option<String>, // 请求未指定model时使用，可带search/think等后缀
+    #[serde(default)]
+    pub scopes: Vec<String>, // 允许的权限范围，为空表示不限制
+    #[serde(default)]
+    pub content_filter: Option<FilterMode>, // 内容过滤方式，None表示使用全局设置
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>, // 每分钟请求数上限，None表示使用全局设置，0表示不限制
+    #[serde(default)]
+    pub account_group: Option<String>, // 只使用该分组内的账号，优先于按模型的路由规则
+    #[serde(default, flatten)]
+    pub overrides: ApiKeyOverrides,
+}
+
+/// API密钥单独设置的运行参数，未设置的项使用全局配置
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKeyOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>, // 上游请求失败时的最大重试次数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_timeout_secs: Option<u64>, // 单次补全请求的超时时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_display: Option<ReasoningDisplay>, // 请求和模型名都未指定时的思考过程展示方式
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_coalesce_chars: Option<usize>, // 流式响应合并到该字符数再发送，0表示逐块发送
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_tokens_per_sec: Option<u32>, // 流式响应的发送速率（token/秒），0表示不限速
+}
+
+fn default_api_key_weight() -> u32 {
+    1
+}
+
+/// API密钥的请求优先级（QoS等级）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateApiKeyRequest {
     pub name: String,
     pub expires_days: Option<u32>, // 过期天数，None表示永不过期
+    pub weight: Option<u32>,
+    pub priority: Option<ApiKeyPriority>,
+    pub default_model: Option<String>,
+    pub scopes: Option<Vec<String>>, // 例如 ["chat"]，未设置表示不限制
+    pub content_filter: Option<FilterMode>,
+    pub requests_per_minute: Option<u32>,
+    pub account_group: Option<String>,
+    #[serde(flatten)]
+    pub overrides: ApiKeyOverrides,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -241,6 +492,27 @@ pub struct AddAccountResponse {
     pub accounts_count: usize,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationInfo {
+    pub id: String,
+    pub object: String,
+    pub model: Option<String>,
+    pub account: String,       // 承载对话的账号
+    pub state: String,         // idle / active / reserved / expired
+    pub message_count: usize,
+    pub created_at: u64,
+    pub last_activity: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadResponse {
+    pub api_keys: usize,
+    pub accounts: usize,            // 重建后账号池中的账号数
+    pub invalid_accounts: usize,    // 验证失效、未放入账号池的token数
+    pub unverified_accounts: usize, // 验证请求失败、按有效处理的token数
+    pub removed_accounts: usize,    // 从账号池移除的账号数
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiKeyInfo {
     pub id: String,
@@ -250,6 +522,114 @@ pub struct ApiKeyInfo {
     pub created_at: u64,
     pub expires_at: Option<u64>,
     pub is_active: bool,
+    pub weight: u32,
+    pub priority: ApiKeyPriority,
+    pub default_model: Option<String>,
+    pub scopes: Vec<String>,
+    pub content_filter: Option<FilterMode>,
+    pub requests_per_minute: Option<u32>,
+    pub account_group: Option<String>,
+    #[serde(flatten)]
+    pub overrides: ApiKeyOverrides,
+}
+
+/// DeepSeek账号，独立于API密钥保存，可以关联到多个API密钥
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub id: String,
+    pub email: String,
+    pub user_token: String,
+    pub created_at: u64,
+    #[serde(default)]
+    pub last_login_at: Option<u64>, // 最近一次登录（添加或重新登录）的时间
+    #[serde(default = "default_account_enabled")]
+    pub enabled: bool, // 停用的账号不参与轮换
+    #[serde(default)]
+    pub disabled_reason: Option<String>, // 自动停用的原因（如检测到封禁），手动启用或停用时清除
+    #[serde(default)]
+    pub groups: Vec<String>, // 所属的账号分组，供路由规则选择
+    #[serde(default, flatten)]
+    pub overrides: AccountOverrides, // 账号单独设置的并发、速率和冷却策略
+    #[serde(default)]
+    pub requests_served: u64,
+    #[serde(default)]
+    pub errors: u64,
+}
+
+/// 账号单独设置的账号池策略，未设置的项使用全局配置；整体替换
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>, // 同时处理的请求数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub messages_per_hour: Option<u32>, // 每小时最多发送的消息数，0表示不限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_burst: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_error_threshold: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_window_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_secs: Option<u64>,
+}
+
+fn default_account_enabled() -> bool {
+    true
+}
+
+/// 账号管理接口返回的账号信息，不包含userToken
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountInfo {
+    pub id: String,
+    pub email: String,
+    pub enabled: bool,
+    pub disabled_reason: Option<String>,
+    pub warming_up: bool, // 新账号预热中，完成后才加入轮换
+    pub cooling_down: bool,
+    pub groups: Vec<String>,
+    #[serde(flatten)]
+    pub overrides: AccountOverrides,
+    pub created_at: u64,
+    pub last_login_at: Option<u64>,
+    pub requests_served: u64,
+    pub errors: u64,
+    pub thinking_quota: Option<u32>, // 缓存的剩余深度思考配额（使用时乐观扣减），缓存过期时为None
+    pub quota_checked_at: Option<u64>, // 最近一次从上游查询配额的时间
+    pub api_keys: Vec<String>,       // 关联的API密钥ID
+}
+
+/// 账号关联或取消关联API密钥
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountLinkRequest {
+    pub api_key: String,
+}
+
+/// 凭据库中的一条凭据，不包含密码
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialInfo {
+    pub email: String,
+    pub updated_at: u64,
+    pub account_id: Option<String>, // 已登记的账号，尚未登录过时为None
+}
+
+/// 保存账号凭据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreCredentialRequest {
+    pub email: String,
+    pub password: String,
+}
+
+/// 设置账号所属的分组（整体替换）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountGroupsRequest {
+    pub groups: Vec<String>,
+}
+
+/// 账号分组及其成员
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountGroupInfo {
+    pub name: String,
+    pub accounts: Vec<String>, // 分组内的账号ID
 }
 
 // 流式响应数据
@@ -260,6 +640,8 @@ pub struct StreamChunk {
     pub created: u64,
     pub model: String,
     pub choices: Vec<StreamChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x_upstream: Option<Vec<String>>, // 调试模式下自上一个数据块以来的上游原始SSE行
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -303,12 +685,19 @@ impl Default for ChatCompletionRequest {
             messages: vec![],
             stream: Some(false),
             conversation_id: None,
+            thread_id: None,
             temperature: None,
             max_tokens: None,
             top_p: None,
             frequency_penalty: None,
             presence_penalty: None,
             stop: None,
+            user: None,
+            continue_final_message: None,
+            stream_tokens_per_sec: None,
+            features: DeepSeekFeatures::default(),
+            deepseek: None,
+            extra_body: None,
         }
     }
 }
@@ -0,0 +1,120 @@
+use crate::utils::unix_timestamp;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// 统计的时间窗口：(名称, 秒数)，样本保留到最长的窗口
+pub const USAGE_WINDOWS: &[(&str, u64)] = &[("5m", 300), ("1h", 3600), ("24h", 86_400)];
+
+/// 每个账号最多保留的样本数
+const MAX_SAMPLES: usize = 20_000;
+
+#[derive(Debug, Clone, Copy)]
+struct UsageSample {
+    at: u64,
+    busy_ms: u64,
+    thinking: bool,
+    error: bool,
+}
+
+/// 单个账号在一个时间窗口内的用量
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UsageWindow {
+    pub completions: usize,
+    pub errors: usize,
+    pub error_rate: f64,
+    pub thinking_completions: usize, // 成功的深度思考补全，即消耗的深度思考配额
+    pub busy_secs: f64,              // 账号处理请求的累计时间
+    pub busyness: f64,               // busy_secs占窗口时长的比例，接近1表示账号几乎一直在处理请求
+}
+
+/// 按账号记录补全请求，用于发现接近上限的账号
+pub struct AccountUsage {
+    samples: RwLock<HashMap<String, VecDeque<UsageSample>>>, // account_email -> 样本
+}
+
+impl AccountUsage {
+    pub fn new() -> Self {
+        Self {
+            samples: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// 记录账号处理的一次补全，busy_ms为占用账号的时间
+    pub fn record(&self, account_email: &str, busy_ms: u64, thinking: bool, error: bool) {
+        self.record_at(account_email, unix_timestamp(), busy_ms, thinking, error);
+    }
+
+    fn record_at(&self, account_email: &str, at: u64, busy_ms: u64, thinking: bool, error: bool) {
+        let retention = USAGE_WINDOWS.iter().map(|(_, secs)| *secs).max().unwrap_or(0);
+        let mut samples = self.samples.write();
+        let window = samples.entry(account_email.to_string()).or_default();
+        let cutoff = at.saturating_sub(retention);
+        while window.front().is_some_and(|s| s.at < cutoff) || window.len() >= MAX_SAMPLES {
+            window.pop_front();
+        }
+        window.push_back(UsageSample { at, busy_ms, thinking, error });
+    }
+
+    /// 账号在各时间窗口内的用量，没有记录的窗口各项为0
+    pub fn windows(&self, account_email: &str) -> BTreeMap<&'static str, UsageWindow> {
+        self.windows_at(account_email, unix_timestamp())
+    }
+
+    fn windows_at(&self, account_email: &str, now: u64) -> BTreeMap<&'static str, UsageWindow> {
+        let samples = self.samples.read();
+        let window = samples.get(account_email);
+        USAGE_WINDOWS
+            .iter()
+            .map(|(name, secs)| {
+                let cutoff = now.saturating_sub(*secs);
+                let recent: Vec<UsageSample> = window
+                    .map(|window| window.iter().filter(|s| s.at >= cutoff).copied().collect())
+                    .unwrap_or_default();
+                (*name, summarize(&recent, *secs))
+            })
+            .collect()
+    }
+}
+
+impl Default for AccountUsage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn summarize(samples: &[UsageSample], window_secs: u64) -> UsageWindow {
+    let errors = samples.iter().filter(|s| s.error).count();
+    let busy_secs = samples.iter().map(|s| s.busy_ms).sum::<u64>() as f64 / 1000.0;
+    UsageWindow {
+        completions: samples.len(),
+        errors,
+        error_rate: if samples.is_empty() { 0.0 } else { errors as f64 / samples.len() as f64 },
+        thinking_completions: samples.iter().filter(|s| s.thinking && !s.error).count(),
+        busy_secs,
+        busyness: (busy_secs / window_secs as f64).min(1.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_windows() {
+        let usage = AccountUsage::new();
+        let now = 1_700_000_000;
+        usage.record_at("a@example.com", now - 60, 30_000, true, false);
+        usage.record_at("a@example.com", now - 120, 3_000, true, true);
+        usage.record_at("a@example.com", now - 1800, 6_000, false, false);
+
+        let windows = usage.windows_at("a@example.com", now);
+        let recent = &windows["5m"];
+        assert_eq!(recent.completions, 2);
+        assert_eq!(recent.errors, 1);
+        assert_eq!(recent.thinking_completions, 1);
+        assert_eq!(recent.busyness, 33.0 / 300.0);
+        assert_eq!(windows["1h"].completions, 3);
+        assert_eq!(usage.windows_at("other@example.com", now)["24h"].completions, 0);
+    }
+}
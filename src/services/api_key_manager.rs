@@ -1,12 +1,20 @@
+use crate::config::{Config, FilterMode};
 use crate::error::{AppError, AppResult};
 use crate::models::*;
+use crate::services::browser_profile::BrowserProfiles;
+use crate::services::cookie_store::CookieStore;
+use crate::services::credential_vault::CredentialVault;
 use crate::services::login_service::LoginService;
-use crate::services::session_pool::SessionPoolManager;
-use std::collections::HashMap;
+use crate::services::fair_scheduler::{FairPermit, FairScheduler};
+use crate::services::quota_cache::QuotaCache;
+use crate::services::session_pool::{AcquireOptions, SessionPoolManager};
+use crate::services::signed_key::{ApiKeySigner, SignedKeyClaims};
+use crate::utils::{generate_secret_token, generate_uuid, random_index, unix_timestamp};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use parking_lot::RwLock;
-use std::time::{SystemTime, UNIX_EPOCH};
-use uuid::Uuid;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use futures::future::join_all;
 use tracing::{info, warn, error, debug};
 use serde_json;
 use std::fs;
@@ -15,23 +23,70 @@ use std::path::Path;
 pub struct ApiKeyManager {
     api_keys: Arc<RwLock<HashMap<String, ApiKey>>>,
     user_tokens: Arc<RwLock<HashMap<String, Vec<String>>>>, // api_key -> user_tokens
+    accounts: RwLock<HashMap<String, Account>>, // account_id -> 账号
+    quota_cache: Arc<QuotaCache>,
+    warmup_new_accounts: bool,
+    warming_up: RwLock<HashMap<String, bool>>, // 等待预热的新账号: user_token -> 预热是否已开始
     login_service: Arc<LoginService>,
+    credentials: Arc<CredentialVault>,
+    profiles: Arc<BrowserProfiles>,
     session_pool: Arc<SessionPoolManager>,
+    scheduler: Arc<FairScheduler>,
+    signer: Option<ApiKeySigner>, // 配置签名密钥时签发自描述密钥
+    revoked: RwLock<HashSet<String>>, // 已停用的签名密钥ID
     storage_path: String,
 }
 
+/// 会话租约：持有会话和公平调度许可，释放（drop）时归还两者。
+/// 流式响应把租约移入响应流，流结束或客户端断开前一直计入账号容量；请求被取消时同样会释放
+pub struct SessionLease {
+    conversation_id: String,
+    session_pool: Arc<SessionPoolManager>,
+    _permit: FairPermit,
+}
+
+impl SessionLease {
+    pub fn conversation_id(&self) -> &str {
+        &self.conversation_id
+    }
+}
+
+impl Drop for SessionLease {
+    fn drop(&mut self) {
+        self.session_pool.release_session(&self.conversation_id);
+    }
+}
+
 impl ApiKeyManager {
-    pub fn new() -> Self {
-        let login_service = Arc::new(LoginService::new());
-        let session_pool = Arc::new(SessionPoolManager::new());
-        let storage_path = std::env::var("API_KEYS_STORAGE_PATH")
-            .unwrap_or_else(|_| "./data/api_keys.json".to_string());
+    pub fn new(
+        config: &Config,
+        quota_cache: Arc<QuotaCache>,
+        credentials: Arc<CredentialVault>,
+        profiles: Arc<BrowserProfiles>,
+        cookies: Arc<CookieStore>,
+    ) -> Self {
+        let login_service = Arc::new(LoginService::new(config, profiles.clone(), cookies));
+        let scheduler = Arc::new(FairScheduler::new(
+            Duration::from_secs(config.pool.fair_queue_timeout_secs),
+            config.pool.preempt_low_priority,
+        ));
+        let session_pool = Arc::new(SessionPoolManager::new(config.pool.clone(), quota_cache.clone()));
+        let storage_path = config.storage.api_keys_path.clone();
 
         let manager = Self {
             api_keys: Arc::new(RwLock::new(HashMap::new())),
             user_tokens: Arc::new(RwLock::new(HashMap::new())),
+            accounts: RwLock::new(HashMap::new()),
+            quota_cache,
+            warmup_new_accounts: config.pool.warmup_new_accounts,
+            warming_up: RwLock::new(HashMap::new()),
             login_service,
+            credentials,
+            profiles,
             session_pool,
+            scheduler,
+            signer: config.access.api_key_signing_secret.as_deref().map(ApiKeySigner::new),
+            revoked: RwLock::new(HashSet::new()),
             storage_path,
         };
 
@@ -44,8 +99,9 @@ impl ApiKeyManager {
     }
 
     /// 创建新的API密钥
-    pub fn create_api_key(&self, name: String, expires_days: Option<u32>) -> AppResult<CreateApiKeyResponse> {
-        let api_key = format!("dsk-{}", Uuid::new_v4().simple().to_string());
+    pub fn create_api_key(&self, request: CreateApiKeyRequest) -> AppResult<CreateApiKeyResponse> {
+        let CreateApiKeyRequest { name, expires_days, weight, priority, default_model, scopes, content_filter, requests_per_minute, account_group, overrides } = request;
+        let scopes: Vec<String> = scopes.unwrap_or_default().iter().map(|scope| scope.to_lowercase()).collect();
         let created_at = SystemTime::now().duration_since(UNIX_EPOCH)
             .map_err(|e| AppError::Internal(format!("获取时间戳失败: {}", e)))?
             .as_secs();
@@ -54,8 +110,18 @@ impl ApiKeyManager {
             created_at + (days as u64 * 24 * 60 * 60)
         });
 
+        let id = generate_uuid();
+        let api_key = match &self.signer {
+            Some(signer) => signer.issue(&SignedKeyClaims {
+                kid: id.clone(),
+                scopes: scopes.clone(),
+                exp: expires_at,
+            }),
+            None => format!("dsk-{}", generate_secret_token()),
+        };
+
         let key_info = ApiKey {
-            id: Uuid::new_v4().to_string(),
+            id,
             key: api_key.clone(),
             name: name.clone(),
             user_tokens: Vec::new(),
@@ -63,6 +129,14 @@ impl ApiKeyManager {
             expires_at,
             usage_count: 0,
             is_active: true,
+            weight: weight.unwrap_or(1).max(1),
+            priority: priority.unwrap_or_default(),
+            default_model: default_model.map(|model| model.to_lowercase()),
+            scopes,
+            content_filter,
+            requests_per_minute,
+            account_group: account_group.map(|group| group.trim().to_lowercase()).filter(|group| !group.is_empty()),
+            overrides,
         };
 
         // 存储API密钥
@@ -107,21 +181,12 @@ impl ApiKeyManager {
             return Err(AppError::ExternalApi("获取的userToken无效".to_string()));
         }
 
-        // 添加到token列表
-        let accounts_count = {
-            let mut tokens = self.user_tokens.write();
-            let token_list = tokens.entry(api_key.clone()).or_insert_with(Vec::new);
-            
-            // 避免重复添加相同的token
-            if !token_list.contains(&user_token) {
-                token_list.push(user_token.clone());
-            }
-            
-            token_list.len()
-        };
+        let accounts_count = self.attach_account(&api_key, &email, &user_token);
 
-        // 添加到会话池
-        self.session_pool.add_account(api_key.clone(), email.clone(), user_token.clone());
+        // 保存凭据，token失效后用于自动重新登录
+        if let Err(e) = self.credentials.store(&email, &password) {
+            warn!("保存账号凭据失败: {}", e);
+        }
 
         // 保存到存储
         if let Err(e) = self.save_to_storage() {
@@ -137,6 +202,59 @@ impl ApiKeyManager {
         })
     }
 
+    /// 直接添加已有的userToken（没有密码），验证有效后关联到API密钥
+    pub async fn add_account_token(&self, api_key: &str, user_token: &str) -> AppResult<AddAccountResponse> {
+        if !self.is_api_key_valid(api_key)? {
+            return Err(AppError::Unauthorized("无效的API密钥".to_string()));
+        }
+        if !self.login_service.verify_token(user_token).await? {
+            return Err(AppError::ExternalApi("userToken无效".to_string()));
+        }
+
+        // 已登记的token沿用原账号的邮箱
+        let email = self.account_email(user_token);
+        let accounts_count = self.attach_account(api_key, &email, user_token);
+        if let Err(e) = self.save_to_storage() {
+            warn!("保存账户信息失败: {}", e);
+        }
+
+        info!("成功为API密钥添加token账户 {}，当前共有 {} 个账户", email, accounts_count);
+        Ok(AddAccountResponse {
+            success: true,
+            message: format!("成功添加账户 {}", email),
+            accounts_count,
+        })
+    }
+
+    /// 登记账号并关联到API密钥，返回该密钥下的账号数
+    fn attach_account(&self, api_key: &str, email: &str, user_token: &str) -> usize {
+        let is_new = !self.accounts.read().values().any(|account| account.email == email || account.user_token == user_token);
+        self.register_account(email, user_token);
+        if is_new && self.warmup_new_accounts {
+            self.warming_up.write().insert(user_token.to_string(), false);
+        }
+
+        // 添加到token列表
+        let accounts_count = {
+            let mut tokens = self.user_tokens.write();
+            let token_list = tokens.entry(api_key.to_string()).or_default();
+            
+            // 避免重复添加相同的token
+            if !token_list.iter().any(|token| token == user_token) {
+                token_list.push(user_token.to_string());
+            }
+            
+            token_list.len()
+        };
+
+        // 添加到会话池，停用的账号只关联不参与轮换，新账号预热完成后再加入
+        if self.in_rotation(user_token) {
+            self.session_pool.add_account(api_key.to_string(), email.to_string(), user_token.to_string(), &self.account_overrides(user_token));
+            self.refresh_capacity();
+        }
+        accounts_count
+    }
+
     /// 获取API密钥的可用userToken
     pub fn get_user_token(&self, api_key: &str) -> AppResult<String> {
         if !self.is_api_key_valid(api_key)? {
@@ -147,12 +265,13 @@ impl ApiKeyManager {
         let token_list = tokens.get(api_key)
             .ok_or_else(|| AppError::NotFound("未找到关联的账户".to_string()))?;
 
+        let token_list: Vec<&String> = token_list.iter().filter(|token| self.in_rotation(token)).collect();
         if token_list.is_empty() {
             return Err(AppError::NotFound("该API密钥下没有可用的账户".to_string()));
         }
 
         // 简单的轮询策略，可以后续扩展为更复杂的负载均衡
-        let index = rand::random::<usize>() % token_list.len();
+        let index = random_index(token_list.len());
         let user_token = token_list[index].clone();
 
         // 记录使用次数
@@ -161,27 +280,203 @@ impl ApiKeyManager {
         Ok(user_token)
     }
 
-    /// 获取会话（新方法，支持上下文保持）
+    /// 获取会话（新方法，支持上下文保持），返回的租约释放时归还会话和调度槽位
     pub async fn acquire_session(
         &self, 
         api_key: &str, 
-        conversation_id: Option<String>
-    ) -> AppResult<(String, crate::services::session_pool::DeepSeekSession)> {
+        conversation_id: Option<String>,
+        options: &AcquireOptions,
+    ) -> AppResult<(SessionLease, crate::services::session_pool::DeepSeekSession)> {
         if !self.is_api_key_valid(api_key)? {
             return Err(AppError::Unauthorized("无效的API密钥".to_string()));
         }
 
-        let (conv_id, session) = self.session_pool.acquire_session(api_key, conversation_id).await?;
+        // 在密钥所在的账号池内按优先级和权重公平排队，避免单个密钥占满账号
+        let (weight, priority) = self.api_keys.read().get(api_key)
+            .map(|k| (k.weight, k.priority))
+            .unwrap_or((1, ApiKeyPriority::Normal));
+        let (pool, capacity) = self.session_pool.scheduling_pool(api_key);
+        let permit = self.scheduler.acquire(&pool, api_key, weight, priority, capacity).await?;
+
+        // 路由规则选择了账号分组时，只在分组内启用的账号中选择
+        let mut options = options.clone();
+        options.group_tokens = options.account_group.as_deref().map(|group| self.group_tokens(group));
+
+        let (conv_id, session) = self.session_pool.acquire_session(api_key, conversation_id, &options).await?;
+        let lease = SessionLease {
+            conversation_id: conv_id,
+            session_pool: self.session_pool.clone(),
+            _permit: permit,
+        };
         
         // 记录使用次数
         self.increment_usage(api_key);
         
-        Ok((conv_id, session))
+        Ok((lease, session))
+    }
+
+    /// 列出API密钥下的对话
+    pub fn list_conversations(&self, api_key: &str) -> AppResult<Vec<ConversationInfo>> {
+        if !self.is_api_key_valid(api_key)? {
+            return Err(AppError::Unauthorized("无效的API密钥".to_string()));
+        }
+
+        Ok(self.session_pool.list_conversations(api_key)
+            .into_iter()
+            .filter_map(|session| {
+                Some(ConversationInfo {
+                    id: session.conversation_id?,
+                    object: "conversation".to_string(),
+                    model: session.model,
+                    account: session.account_email,
+                    state: format!("{:?}", session.state).to_lowercase(),
+                    message_count: session.messages_count,
+                    created_at: session.created_at,
+                    last_activity: session.last_used,
+                })
+            })
+            .collect())
+    }
+
+    /// 查找对话所在的会话
+    pub fn find_conversation(&self, api_key: &str, conversation_id: &str) -> AppResult<Option<crate::services::session_pool::DeepSeekSession>> {
+        if !self.is_api_key_valid(api_key)? {
+            return Err(AppError::Unauthorized("无效的API密钥".to_string()));
+        }
+        Ok(self.session_pool.find_conversation(api_key, conversation_id))
+    }
+
+    /// 结束对话并移除会话映射，返回对话所在的会话
+    pub fn end_conversation(&self, api_key: &str, conversation_id: &str) -> AppResult<Option<crate::services::session_pool::DeepSeekSession>> {
+        if !self.is_api_key_valid(api_key)? {
+            return Err(AppError::Unauthorized("无效的API密钥".to_string()));
+        }
+
+        Ok(self.session_pool.end_conversation(api_key, conversation_id))
+    }
+
+    /// 记录账号处理的请求，上游错误同时用于自动冷却；客户端请求本身导致的错误不计入账号
+    pub fn record_account_request(&self, user_token: &str, error: Option<&AppError>) {
+        let error = error.filter(|error| !error.is_client_error());
+        if let Some(account) = self.accounts.write().values_mut().find(|account| account.user_token == user_token) {
+            account.requests_served += 1;
+            account.errors += u64::from(error.is_some());
+        }
+        if let Some(error) = error {
+            self.session_pool.record_account_error(user_token, error);
+        }
+    }
+
+    /// 冷却结束、等待探测的账号
+    pub fn accounts_due_for_probe(&self) -> Vec<String> {
+        self.session_pool.accounts_due_for_probe()
     }
 
-    /// 释放会话
-    pub fn release_session(&self, conversation_id: &str) {
-        self.session_pool.release_session(conversation_id);
+    /// 记录账号探测结果
+    pub fn finish_probe(&self, user_token: &str, healthy: bool) {
+        self.session_pool.finish_probe(user_token, healthy);
+    }
+
+    /// 使用凭据库中保存的密码重新登录失效的账号，返回新token；没有保存凭据时返回None
+    pub async fn relogin(&self, user_token: &str) -> AppResult<Option<String>> {
+        let Some(email) = self.session_pool.account_email(user_token) else {
+            return Ok(None);
+        };
+        let Some(password) = self.credentials.password(&email) else {
+            return Ok(None);
+        };
+
+        let new_token = self.login_service.login(&email, &password).await?;
+        self.replace_account_token(user_token, &new_token);
+
+        if let Err(e) = self.save_to_storage() {
+            warn!("保存账户信息失败: {}", e);
+        }
+
+        info!("账号 {} 已使用保存的凭据重新登录", email);
+        Ok(Some(new_token))
+    }
+
+    /// 账号重新登录后，在API密钥关联、账号池和账号记录中把旧token替换为新token
+    fn replace_account_token(&self, old_token: &str, new_token: &str) {
+        for token in self.user_tokens.write().values_mut().flatten() {
+            if token == old_token {
+                *token = new_token.to_string();
+            }
+        }
+        self.session_pool.replace_user_token(old_token, new_token);
+        if let Some(account) = self.accounts.write().values_mut().find(|account| account.user_token == old_token) {
+            account.user_token = new_token.to_string();
+            account.last_login_at = Some(unix_timestamp());
+        }
+    }
+
+    /// 列出凭据库中的凭据及对应的账号
+    pub fn list_credentials(&self) -> Vec<CredentialInfo> {
+        let accounts = self.accounts.read();
+        self.credentials.list()
+            .into_iter()
+            .map(|(email, updated_at)| CredentialInfo {
+                account_id: accounts.values().find(|account| account.email == email).map(|account| account.id.clone()),
+                email,
+                updated_at,
+            })
+            .collect()
+    }
+
+    /// 保存账号凭据，之后可以直接从凭据库关联到API密钥或重新登录
+    pub fn store_credential(&self, email: &str, password: &str) -> AppResult<()> {
+        if !self.credentials.is_enabled() {
+            return Err(AppError::BadRequest("未配置凭据库主密钥".to_string()));
+        }
+        self.credentials.store(email, password)?;
+        info!("已保存账号 {} 的凭据", email);
+        Ok(())
+    }
+
+    /// 删除账号凭据，已登记的账号保留
+    pub fn delete_credential(&self, email: &str) -> AppResult<()> {
+        self.credential_password(email)?;
+        self.credentials.remove(email)?;
+        info!("已删除账号 {} 的凭据", email);
+        Ok(())
+    }
+
+    /// 使用凭据库中的凭据登录并关联到API密钥
+    pub async fn attach_credential(&self, email: &str, api_key: &str) -> AppResult<AddAccountResponse> {
+        let password = self.credential_password(email)?;
+        self.add_account(api_key.to_string(), email.to_string(), password).await
+    }
+
+    /// 使用凭据库中的凭据重新登录账号，替换已失效的token；账号尚未登记时登记为新账号
+    pub async fn login_with_credential(&self, email: &str) -> AppResult<AccountInfo> {
+        let password = self.credential_password(email)?;
+        let new_token = self.login_service.login(email, &password).await?;
+
+        let old_token = self.accounts.read()
+            .values()
+            .find(|account| account.email == email)
+            .map(|account| account.user_token.clone());
+        match old_token {
+            Some(old_token) => self.replace_account_token(&old_token, &new_token),
+            None => self.register_account(email, &new_token),
+        }
+        if let Err(e) = self.save_to_storage() {
+            warn!("保存账户信息失败: {}", e);
+        }
+
+        info!("账号 {} 已使用凭据库中的凭据重新登录", email);
+        let account = self.accounts.read()
+            .values()
+            .find(|account| account.email == email)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound("账号不存在".to_string()))?;
+        Ok(self.account_info(&account))
+    }
+
+    fn credential_password(&self, email: &str) -> AppResult<String> {
+        self.credentials.password(email)
+            .ok_or_else(|| AppError::NotFound("凭据不存在".to_string()))
     }
 
     /// 获取会话池统计信息
@@ -189,8 +484,66 @@ impl ApiKeyManager {
         self.session_pool.get_api_key_stats(api_key)
     }
 
+    /// 获取当前空闲的账号token（去重）
+    pub fn idle_user_tokens(&self) -> Vec<String> {
+        let tokens = self.user_tokens.read();
+        let mut idle: Vec<String> = Vec::new();
+
+        for token in tokens.values().flatten() {
+            if !idle.contains(token) && self.in_rotation(token) && !self.session_pool.is_token_busy(token) {
+                idle.push(token.clone());
+            }
+        }
+
+        idle
+    }
+
+    /// 参与轮换的账号token（去重）
+    pub fn rotation_user_tokens(&self) -> Vec<String> {
+        let tokens = self.user_tokens.read();
+        let mut rotation: Vec<String> = Vec::new();
+
+        for token in tokens.values().flatten() {
+            if !rotation.contains(token) && self.in_rotation(token) {
+                rotation.push(token.clone());
+            }
+        }
+
+        rotation
+    }
+
+    /// 选择一个参与轮换的账号token，指定的账号仍在轮换中时优先使用
+    pub fn rotation_user_token(&self, preferred_email: Option<&str>) -> Option<String> {
+        let rotation = self.rotation_user_tokens();
+        preferred_email
+            .and_then(|email| {
+                rotation
+                    .iter()
+                    .find(|token| self.account_email(token) == email)
+                    .cloned()
+            })
+            .or_else(|| rotation.into_iter().next())
+    }
+
+    /// 参与轮换且不在冷却中的账号数
+    pub fn live_account_count(&self) -> usize {
+        self.rotation_user_tokens()
+            .iter()
+            .filter(|token| !self.session_pool.is_token_cooling_down(token))
+            .count()
+    }
+
     /// 检查API密钥是否有效
     pub fn is_api_key_valid(&self, api_key: &str) -> AppResult<bool> {
+        // 签名密钥只校验签名、过期时间和停用列表，不查询存储
+        if let Some(signer) = self.signer.as_ref().filter(|_| ApiKeySigner::is_signed(api_key)) {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            return Ok(signer
+                .verify(api_key, now)
+                .map(|claims| !self.revoked.read().contains(&claims.kid))
+                .unwrap_or(false));
+        }
+
         let keys = self.api_keys.read();
         
         if let Some(key_info) = keys.get(api_key) {
@@ -215,6 +568,16 @@ impl ApiKeyManager {
         }
     }
 
+    /// 检查API密钥是否拥有指定权限范围，未限制范围的密钥拥有全部权限
+    pub fn has_scope(&self, api_key: &str, scope: &str) -> bool {
+        let signed = self.signer.as_ref().filter(|_| ApiKeySigner::is_signed(api_key));
+        let scopes = match signed {
+            Some(signer) => signer.verify(api_key, 0).map(|claims| claims.scopes),
+            None => self.api_keys.read().get(api_key).map(|key| key.scopes.clone()),
+        };
+        scopes.is_some_and(|scopes| scopes.is_empty() || scopes.iter().any(|s| s == scope))
+    }
+
     /// 获取API密钥信息
     pub fn get_api_key_info(&self, api_key: &str) -> AppResult<ApiKeyInfo> {
         let keys = self.api_keys.read();
@@ -234,6 +597,14 @@ impl ApiKeyManager {
             created_at: key_info.created_at,
             expires_at: key_info.expires_at,
             is_active: key_info.is_active,
+            weight: key_info.weight,
+            priority: key_info.priority,
+            default_model: key_info.default_model.clone(),
+            scopes: key_info.scopes.clone(),
+            content_filter: key_info.content_filter,
+            requests_per_minute: key_info.requests_per_minute,
+            account_group: key_info.account_group.clone(),
+            overrides: key_info.overrides,
         })
     }
 
@@ -255,16 +626,52 @@ impl ApiKeyManager {
                 created_at: key_info.created_at,
                 expires_at: key_info.expires_at,
                 is_active: key_info.is_active,
+                weight: key_info.weight,
+                priority: key_info.priority,
+                default_model: key_info.default_model.clone(),
+                scopes: key_info.scopes.clone(),
+                content_filter: key_info.content_filter,
+                requests_per_minute: key_info.requests_per_minute,
+                account_group: key_info.account_group.clone(),
+                overrides: key_info.overrides,
             }
         }).collect()
     }
 
+    /// 获取API密钥的ID（用于日志，避免输出密钥本身）
+    pub fn api_key_id(&self, api_key: &str) -> Option<String> {
+        self.api_keys.read().get(api_key).map(|key| key.id.clone())
+    }
+
+    /// 获取API密钥的默认模型
+    pub fn default_model(&self, api_key: &str) -> Option<String> {
+        self.api_keys.read().get(api_key)?.default_model.clone()
+    }
+
+    /// 获取API密钥单独设置的每分钟请求数上限
+    pub fn requests_per_minute(&self, api_key: &str) -> Option<u32> {
+        self.api_keys.read().get(api_key)?.requests_per_minute
+    }
+
+    /// 获取API密钥单独设置的运行参数
+    pub fn overrides(&self, api_key: &str) -> ApiKeyOverrides {
+        self.api_keys.read().get(api_key).map(|key| key.overrides).unwrap_or_default()
+    }
+
+    /// 获取API密钥的内容过滤方式
+    pub fn content_filter_mode(&self, api_key: &str) -> Option<FilterMode> {
+        self.api_keys.read().get(api_key)?.content_filter
+    }
+
     /// 停用API密钥
     pub fn deactivate_api_key(&self, api_key: &str) -> AppResult<()> {
-        let mut keys = self.api_keys.write();
-        if let Some(key_info) = keys.get_mut(api_key) {
+        let key_id = self.api_keys.write().get_mut(api_key).map(|key_info| {
             key_info.is_active = false;
-            
+            key_info.id.clone()
+        });
+        if let Some(key_id) = key_id {
+            self.revoked.write().insert(key_id);
+
             if let Err(e) = self.save_to_storage() {
                 warn!("保存API密钥状态失败: {}", e);
             }
@@ -276,6 +683,351 @@ impl ApiKeyManager {
         }
     }
 
+    /// 登记登录成功的账号：同一邮箱或token沿用原账号并更新token
+    fn register_account(&self, email: &str, user_token: &str) {
+        let now = unix_timestamp();
+        let mut accounts = self.accounts.write();
+        if let Some(account) = accounts.values_mut().find(|account| account.email == email || account.user_token == user_token) {
+            account.email = email.to_string();
+            account.user_token = user_token.to_string();
+            account.last_login_at = Some(now);
+            return;
+        }
+
+        let id = generate_uuid();
+        accounts.insert(id.clone(), Account {
+            id,
+            email: email.to_string(),
+            user_token: user_token.to_string(),
+            created_at: now,
+            last_login_at: Some(now),
+            enabled: true,
+            disabled_reason: None,
+            groups: Vec::new(),
+            overrides: Default::default(),
+            requests_served: 0,
+            errors: 0,
+        });
+    }
+
+    /// 为存储中还没有账号记录的token（旧版本存储）补充账号
+    fn adopt_unregistered_tokens(&self) {
+        let tokens = self.user_tokens.read();
+        let mut accounts = self.accounts.write();
+        for token in tokens.values().flatten() {
+            if accounts.values().any(|account| account.user_token == *token) {
+                continue;
+            }
+            let email = self.session_pool.account_email(token).unwrap_or_else(|| placeholder_email(token));
+            let id = generate_uuid();
+            accounts.insert(id.clone(), Account {
+                id,
+                email,
+                user_token: token.clone(),
+                created_at: unix_timestamp(),
+                last_login_at: None,
+                enabled: true,
+                disabled_reason: None,
+                groups: Vec::new(),
+                overrides: Default::default(),
+                requests_served: 0,
+                errors: 0,
+            });
+        }
+    }
+
+    /// 账号是否启用，没有账号记录的token视为启用
+    fn is_account_enabled(&self, user_token: &str) -> bool {
+        self.accounts.read()
+            .values()
+            .find(|account| account.user_token == user_token)
+            .is_none_or(|account| account.enabled)
+    }
+
+    /// 账号是否参与轮换：已启用且不在预热中
+    fn in_rotation(&self, user_token: &str) -> bool {
+        self.is_account_enabled(user_token) && !self.warming_up.read().contains_key(user_token)
+    }
+
+    /// 把账号加入所有关联API密钥的账号池
+    fn add_to_rotation(&self, account: &Account) {
+        let linked: Vec<String> = self.user_tokens.read()
+            .iter()
+            .filter(|(_, tokens)| tokens.contains(&account.user_token))
+            .map(|(api_key, _)| api_key.clone())
+            .collect();
+        for api_key in linked {
+            self.session_pool.add_account(api_key, account.email.clone(), account.user_token.clone(), &account.overrides);
+        }
+        self.refresh_capacity();
+    }
+
+    /// 账号增删或并发上限变化后更新公平调度的容量，容量增加时立即放行排队中的请求
+    fn refresh_capacity(&self) {
+        self.scheduler.set_capacities(&self.session_pool.scheduling_pools());
+    }
+
+    /// 等待预热且还没有开始预热的账号，返回后标记为已开始
+    pub fn accounts_pending_warmup(&self) -> Vec<String> {
+        let mut warming_up = self.warming_up.write();
+        warming_up
+            .iter_mut()
+            .filter(|(_, started)| !**started)
+            .map(|(token, started)| {
+                *started = true;
+                token.clone()
+            })
+            .collect()
+    }
+
+    /// 预热结束后把账号加入轮换；预热失败不影响使用，token在添加时已验证过
+    pub fn finish_warmup(&self, user_token: &str, result: AppResult<()>) {
+        self.warming_up.write().remove(user_token);
+        let Some(account) = self.accounts.read().values().find(|account| account.user_token == user_token).cloned() else {
+            return;
+        };
+        match result {
+            Ok(()) => info!("账号 {} 预热完成，加入轮换", account.email),
+            Err(e) => warn!("账号 {} 预热失败，直接加入轮换: {}", account.email, e),
+        }
+        if account.enabled {
+            self.add_to_rotation(&account);
+        }
+    }
+
+    fn account_info(&self, account: &Account) -> AccountInfo {
+        let keys = self.api_keys.read();
+        let mut api_keys: Vec<String> = self.user_tokens.read()
+            .iter()
+            .filter(|(_, tokens)| tokens.contains(&account.user_token))
+            .filter_map(|(api_key, _)| keys.get(api_key).map(|key| key.id.clone()))
+            .collect();
+        api_keys.sort();
+
+        AccountInfo {
+            id: account.id.clone(),
+            email: account.email.clone(),
+            enabled: account.enabled,
+            disabled_reason: account.disabled_reason.clone(),
+            warming_up: self.warming_up.read().contains_key(&account.user_token),
+            cooling_down: self.session_pool.is_token_cooling_down(&account.user_token),
+            groups: account.groups.clone(),
+            overrides: account.overrides,
+            created_at: account.created_at,
+            last_login_at: account.last_login_at,
+            requests_served: account.requests_served,
+            errors: account.errors,
+            thinking_quota: self.quota_cache.thinking_remaining(&account.user_token),
+            quota_checked_at: self.quota_cache.fetched_at(&account.user_token),
+            api_keys,
+        }
+    }
+
+    fn find_account(&self, account_id: &str) -> AppResult<Account> {
+        self.accounts.read()
+            .get(account_id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound("账号不存在".to_string()))
+    }
+
+    /// 分组内启用的账号的userToken
+    fn group_tokens(&self, group: &str) -> HashSet<String> {
+        self.accounts.read()
+            .values()
+            .filter(|account| account.enabled && account.groups.iter().any(|name| name == group))
+            .map(|account| account.user_token.clone())
+            .collect()
+    }
+
+    /// 设置账号所属的分组，分组名不区分大小写
+    pub fn set_account_groups(&self, account_id: &str, groups: Vec<String>) -> AppResult<AccountInfo> {
+        let mut groups: Vec<String> = groups.iter()
+            .map(|group| group.trim().to_lowercase())
+            .filter(|group| !group.is_empty())
+            .collect();
+        groups.sort();
+        groups.dedup();
+
+        let account = {
+            let mut accounts = self.accounts.write();
+            let account = accounts.get_mut(account_id)
+                .ok_or_else(|| AppError::NotFound("账号不存在".to_string()))?;
+            account.groups = groups;
+            account.clone()
+        };
+
+        if let Err(e) = self.save_to_storage() {
+            warn!("保存账户信息失败: {}", e);
+        }
+        info!("账号 {} 的分组设置为 {:?}", account.email, account.groups);
+        Ok(self.account_info(&account))
+    }
+
+    /// 设置账号单独的账号池策略（整体替换），立即对账号池生效
+    pub fn set_account_overrides(&self, account_id: &str, overrides: AccountOverrides) -> AppResult<AccountInfo> {
+        if overrides.max_concurrency == Some(0) {
+            return Err(AppError::BadRequest("max_concurrency必须至少为1".to_string()));
+        }
+        if overrides.cooldown_error_threshold == Some(0) {
+            return Err(AppError::BadRequest("cooldown_error_threshold必须至少为1".to_string()));
+        }
+
+        let account = {
+            let mut accounts = self.accounts.write();
+            let account = accounts.get_mut(account_id)
+                .ok_or_else(|| AppError::NotFound("账号不存在".to_string()))?;
+            account.overrides = overrides;
+            account.clone()
+        };
+        self.session_pool.set_account_overrides(&account.user_token, &account.overrides);
+        self.refresh_capacity();
+
+        if let Err(e) = self.save_to_storage() {
+            warn!("保存账户信息失败: {}", e);
+        }
+        info!("账号 {} 的策略设置为 {:?}", account.email, account.overrides);
+        Ok(self.account_info(&account))
+    }
+
+    /// 列出所有账号分组及其成员
+    pub fn list_account_groups(&self) -> Vec<AccountGroupInfo> {
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for account in self.accounts.read().values() {
+            for group in &account.groups {
+                groups.entry(group.clone()).or_default().push(account.id.clone());
+            }
+        }
+        groups.into_iter()
+            .map(|(name, mut accounts)| {
+                accounts.sort();
+                AccountGroupInfo { name, accounts }
+            })
+            .collect()
+    }
+
+    /// 获取API密钥指定的账号分组
+    pub fn account_group(&self, api_key: &str) -> Option<String> {
+        self.api_keys.read().get(api_key)?.account_group.clone()
+    }
+
+    /// 列出所有账号
+    pub fn list_accounts(&self) -> Vec<AccountInfo> {
+        let mut accounts: Vec<Account> = self.accounts.read().values().cloned().collect();
+        accounts.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.email.cmp(&b.email)));
+        accounts.iter().map(|account| self.account_info(account)).collect()
+    }
+
+    /// 获取账号信息
+    pub fn get_account(&self, account_id: &str) -> AppResult<AccountInfo> {
+        Ok(self.account_info(&self.find_account(account_id)?))
+    }
+
+    /// 删除账号：取消与所有API密钥的关联，移出账号池并删除保存的凭据
+    pub fn delete_account(&self, account_id: &str) -> AppResult<()> {
+        let account = self.accounts.write()
+            .remove(account_id)
+            .ok_or_else(|| AppError::NotFound("账号不存在".to_string()))?;
+
+        for tokens in self.user_tokens.write().values_mut() {
+            tokens.retain(|token| *token != account.user_token);
+        }
+        self.session_pool.remove_account(None, &account.user_token);
+        self.refresh_capacity();
+        if let Err(e) = self.credentials.remove(&account.email) {
+            warn!("删除账号凭据失败: {}", e);
+        }
+
+        if let Err(e) = self.save_to_storage() {
+            warn!("保存账户信息失败: {}", e);
+        }
+        info!("账号已删除: {}", account.email);
+        Ok(())
+    }
+
+    /// 启用或停用账号，停用的账号保留关联但不参与轮换
+    pub fn set_account_enabled(&self, account_id: &str, enabled: bool) -> AppResult<AccountInfo> {
+        let account = {
+            let mut accounts = self.accounts.write();
+            let account = accounts.get_mut(account_id)
+                .ok_or_else(|| AppError::NotFound("账号不存在".to_string()))?;
+            account.enabled = enabled;
+            account.disabled_reason = None;
+            account.clone()
+        };
+
+        if !enabled {
+            self.session_pool.remove_account(None, &account.user_token);
+            self.refresh_capacity();
+        } else if self.in_rotation(&account.user_token) {
+            self.add_to_rotation(&account);
+        }
+
+        if let Err(e) = self.save_to_storage() {
+            warn!("保存账户信息失败: {}", e);
+        }
+        info!("账号 {} 已{}", account.email, if enabled { "启用" } else { "停用" });
+        Ok(self.account_info(&account))
+    }
+
+    /// 上游返回封禁提示时自动停用账号，返回被停用的账号；账号已停用或不在账号库中时返回None
+    pub fn disable_banned_account(&self, user_token: &str, reason: &str) -> Option<AccountInfo> {
+        let account = {
+            let mut accounts = self.accounts.write();
+            let account = accounts.values_mut().find(|account| account.user_token == user_token && account.enabled)?;
+            account.enabled = false;
+            account.disabled_reason = Some(reason.to_string());
+            account.clone()
+        };
+        self.session_pool.remove_account(None, user_token);
+        self.refresh_capacity();
+
+        if let Err(e) = self.save_to_storage() {
+            warn!("保存账户信息失败: {}", e);
+        }
+        warn!("账号 {} 疑似被封禁，已自动停用: {}", account.email, reason);
+        Some(self.account_info(&account))
+    }
+
+    /// 把账号关联到API密钥
+    pub fn link_account(&self, account_id: &str, api_key: &str) -> AppResult<AccountInfo> {
+        let account = self.find_account(account_id)?;
+        if !self.api_keys.read().contains_key(api_key) {
+            return Err(AppError::NotFound("API密钥不存在".to_string()));
+        }
+
+        {
+            let mut tokens = self.user_tokens.write();
+            let token_list = tokens.entry(api_key.to_string()).or_default();
+            if !token_list.contains(&account.user_token) {
+                token_list.push(account.user_token.clone());
+            }
+        }
+        if self.in_rotation(&account.user_token) {
+            self.session_pool.add_account(api_key.to_string(), account.email.clone(), account.user_token.clone(), &account.overrides);
+            self.refresh_capacity();
+        }
+
+        if let Err(e) = self.save_to_storage() {
+            warn!("保存账户信息失败: {}", e);
+        }
+        Ok(self.account_info(&account))
+    }
+
+    /// 取消账号与API密钥的关联，账号本身保留
+    pub fn unlink_account(&self, account_id: &str, api_key: &str) -> AppResult<AccountInfo> {
+        let account = self.find_account(account_id)?;
+        if let Some(tokens) = self.user_tokens.write().get_mut(api_key) {
+            tokens.retain(|token| *token != account.user_token);
+        }
+        self.session_pool.remove_account(Some(api_key), &account.user_token);
+        self.refresh_capacity();
+
+        if let Err(e) = self.save_to_storage() {
+            warn!("保存账户信息失败: {}", e);
+        }
+        Ok(self.account_info(&account))
+    }
+
     /// 增加使用次数
     fn increment_usage(&self, api_key: &str) {
         let mut keys = self.api_keys.write();
@@ -286,6 +1038,9 @@ impl ApiKeyManager {
 
     /// 保存到存储
     fn save_to_storage(&self) -> AppResult<()> {
+        if self.storage_path.is_empty() {
+            return Ok(());
+        }
         // 创建目录（如果不存在）
         if let Some(parent) = Path::new(&self.storage_path).parent() {
             fs::create_dir_all(parent)
@@ -294,10 +1049,12 @@ impl ApiKeyManager {
 
         let keys = self.api_keys.read();
         let tokens = self.user_tokens.read();
+        let accounts = self.accounts.read();
 
         let storage_data = serde_json::json!({
             "api_keys": *keys,
             "user_tokens": *tokens,
+            "accounts": *accounts,
             "saved_at": SystemTime::now().duration_since(UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs()
@@ -310,9 +1067,84 @@ impl ApiKeyManager {
         Ok(())
     }
 
+    /// 重新读取存储文件（外部修改或恢复备份后），验证其中的userToken并重建账号池
+    pub async fn reload(&self) -> AppResult<ReloadResponse> {
+        if !Path::new(&self.storage_path).exists() {
+            return Err(AppError::NotFound(format!("存储文件不存在: {}", self.storage_path)));
+        }
+        self.load_from_storage()?;
+
+        let user_tokens = self.user_tokens.read().clone();
+        let mut unique: Vec<&String> = user_tokens.values().flatten().filter(|token| self.is_account_enabled(token)).collect();
+        unique.sort();
+        unique.dedup();
+
+        // 明确失效的token不放入账号池；验证请求本身失败时（如上游不可达）保留，避免一次网络故障清空账号池
+        let results = join_all(unique.iter().map(|token| self.login_service.verify_token(token))).await;
+        let mut invalid = HashSet::new();
+        let mut unverified_accounts = 0;
+        for (token, result) in unique.iter().zip(results) {
+            match result {
+                Ok(true) => {}
+                Ok(false) => {
+                    invalid.insert((*token).clone());
+                }
+                Err(e) => {
+                    warn!("重新加载时验证账号失败，按有效处理: {}", e);
+                    unverified_accounts += 1;
+                }
+            }
+        }
+
+        let accounts: HashMap<String, Vec<(String, String, AccountOverrides)>> = user_tokens
+            .iter()
+            .map(|(api_key, tokens)| {
+                let list = tokens
+                    .iter()
+                    .filter(|token| !invalid.contains(*token) && self.in_rotation(token))
+                    .map(|token| (self.account_email(token), token.clone(), self.account_overrides(token)))
+                    .collect();
+                (api_key.clone(), list)
+            })
+            .collect();
+        let removed_accounts = self.session_pool.rebuild(accounts);
+        self.refresh_capacity();
+
+        let response = ReloadResponse {
+            api_keys: self.api_keys.read().len(),
+            accounts: self.session_pool.total_accounts(),
+            invalid_accounts: invalid.len(),
+            unverified_accounts,
+            removed_accounts,
+        };
+        info!(
+            "重新加载存储完成: {} 个API密钥，{} 个账号，{} 个失效，移除 {} 个",
+            response.api_keys, response.accounts, response.invalid_accounts, response.removed_accounts
+        );
+        Ok(response)
+    }
+
+    /// 账号记录中的邮箱，没有账号记录时按token生成占位邮箱
+    pub fn account_email(&self, user_token: &str) -> String {
+        self.accounts.read()
+            .values()
+            .find(|account| account.user_token == user_token)
+            .map(|account| account.email.clone())
+            .unwrap_or_else(|| placeholder_email(user_token))
+    }
+
+    /// 账号单独设置的账号池策略，未登记的账号使用全局配置
+    fn account_overrides(&self, user_token: &str) -> AccountOverrides {
+        self.accounts.read()
+            .values()
+            .find(|account| account.user_token == user_token)
+            .map(|account| account.overrides)
+            .unwrap_or_default()
+    }
+
     /// 从存储加载
     fn load_from_storage(&self) -> AppResult<()> {
-        if !Path::new(&self.storage_path).exists() {
+        if self.storage_path.is_empty() || !Path::new(&self.storage_path).exists() {
             debug!("存储文件不存在，跳过加载: {}", self.storage_path);
             return Ok(());
         }
@@ -322,15 +1154,35 @@ impl ApiKeyManager {
 
         let storage_data: serde_json::Value = serde_json::from_str(&content)?;
 
-        if let Some(api_keys_data) = storage_data.get("api_keys") {
-            if let Ok(api_keys) = serde_json::from_value::<HashMap<String, ApiKey>>(api_keys_data.clone()) {
-                *self.api_keys.write() = api_keys;
-            }
-        }
+        // 两部分都解析成功后再替换，重新加载时不会只生效一半
+        let api_keys = storage_data.get("api_keys")
+            .map(|data| serde_json::from_value::<HashMap<String, ApiKey>>(data.clone()))
+            .transpose()?;
+        let user_tokens = storage_data.get("user_tokens")
+            .map(|data| serde_json::from_value::<HashMap<String, Vec<String>>>(data.clone()))
+            .transpose()?;
+        let accounts = storage_data.get("accounts")
+            .map(|data| serde_json::from_value::<HashMap<String, Account>>(data.clone()))
+            .transpose()?;
 
-        if let Some(user_tokens_data) = storage_data.get("user_tokens") {
-            if let Ok(user_tokens) = serde_json::from_value::<HashMap<String, Vec<String>>>(user_tokens_data.clone()) {
-                *self.user_tokens.write() = user_tokens;
+        if let Some(api_keys) = api_keys {
+            *self.revoked.write() = api_keys.values()
+                .filter(|key| !key.is_active)
+                .map(|key| key.id.clone())
+                .collect();
+            *self.api_keys.write() = api_keys;
+        }
+        if let Some(user_tokens) = user_tokens {
+            *self.user_tokens.write() = user_tokens;
+        }
+        if let Some(accounts) = accounts {
+            *self.accounts.write() = accounts;
+        }
+        self.adopt_unregistered_tokens();
+        // 重启后不再登录，按存储的邮箱恢复token使用的浏览器指纹；没有邮箱的账号仍按token选择
+        for account in self.accounts.read().values() {
+            if account.email != placeholder_email(&account.user_token) {
+                self.profiles.link(&account.email, &account.user_token);
             }
         }
 
@@ -377,8 +1229,108 @@ impl ApiKeyManager {
     }
 }
 
+/// 只有userToken的账号使用的占位邮箱
+fn placeholder_email(user_token: &str) -> String {
+    format!("token:{}", user_token.chars().take(8).collect::<String>())
+}
+
 impl Default for ApiKeyManager {
     fn default() -> Self {
-        Self::new()
+        let config = Config::default();
+        Self::new(
+            &config,
+            Arc::new(QuotaCache::new(config.deepseek.quota_cache_ttl_secs)),
+            Arc::new(CredentialVault::disabled()),
+            Arc::new(BrowserProfiles::from_config(&config.deepseek)),
+            Arc::new(CookieStore::open("")),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "token-a";
+
+    /// 只保存在内存中的管理器，一个API密钥关联一个账号
+    fn manager(configure: impl FnOnce(&mut Config)) -> (ApiKeyManager, String) {
+        let mut config = Config::default();
+        config.storage.api_keys_path = String::new();
+        configure(&mut config);
+        let manager = ApiKeyManager::new(
+            &config,
+            Arc::new(QuotaCache::new(60)),
+            Arc::new(CredentialVault::disabled()),
+            Arc::new(BrowserProfiles::from_config(&config.deepseek)),
+            Arc::new(CookieStore::open("")),
+        );
+        let request = serde_json::from_value(serde_json::json!({ "name": "test" })).unwrap();
+        let api_key = manager.create_api_key(request).unwrap().api_key;
+        manager.attach_account(&api_key, "a@example.com", TOKEN);
+        (manager, api_key)
+    }
+
+    #[tokio::test]
+    async fn test_session_lease_returns_session_and_permit_on_drop() {
+        let (manager, api_key) = manager(|config| config.pool.fair_queue_timeout_secs = 1);
+        let (lease, session) = manager.acquire_session(&api_key, None, &AcquireOptions::default()).await.unwrap();
+        assert_eq!(session.user_token, TOKEN);
+        assert!(manager.session_pool.is_token_busy(TOKEN));
+
+        // 唯一的调度槽位被占用时排队超时
+        assert!(manager.acquire_session(&api_key, None, &AcquireOptions::default()).await.is_err());
+
+        drop(lease);
+        assert!(!manager.session_pool.is_token_busy(TOKEN));
+        assert!(manager.acquire_session(&api_key, None, &AcquireOptions::default()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_account_max_concurrency_admits_concurrent_leases() {
+        let (manager, api_key) = manager(|config| {
+            config.pool.fair_queue_timeout_secs = 1;
+            config.pool.account_max_concurrency = 2;
+        });
+        let (_first, _) = manager.acquire_session(&api_key, None, &AcquireOptions::default()).await.unwrap();
+        let (_second, session) = manager.acquire_session(&api_key, None, &AcquireOptions::default()).await.unwrap();
+        assert_eq!(session.user_token, TOKEN);
+        assert!(manager.session_pool.is_token_busy(TOKEN));
+    }
+
+    #[tokio::test]
+    async fn test_keys_on_disjoint_accounts_do_not_queue_together() {
+        let (manager, busy_key) = manager(|config| config.pool.fair_queue_timeout_secs = 1);
+        let request = serde_json::from_value(serde_json::json!({ "name": "other" })).unwrap();
+        let other_key = manager.create_api_key(request).unwrap().api_key;
+        manager.attach_account(&other_key, "b@example.com", "token-b");
+        let manager = Arc::new(manager);
+
+        // 第一个密钥占满自己的账号后继续排队
+        let (_lease, _) = manager.acquire_session(&busy_key, None, &AcquireOptions::default()).await.unwrap();
+        let queued = {
+            let manager = manager.clone();
+            tokio::spawn(async move { manager.acquire_session(&busy_key, None, &AcquireOptions::default()).await.map(|_| ()) })
+        };
+        tokio::task::yield_now().await;
+
+        // 使用其他账号的密钥不在它后面排队
+        let (_, session) = manager.acquire_session(&other_key, None, &AcquireOptions::default()).await.unwrap();
+        assert_eq!(session.user_token, "token-b");
+        assert!(queued.await.unwrap().is_err());
+    }
+
+    #[test]
+    fn test_client_errors_do_not_cool_down_accounts() {
+        let (manager, _) = manager(|config| {
+            config.pool.cooldown_error_threshold = 1;
+            config.pool.cooldown_secs = 0;
+        });
+        manager.record_account_request(TOKEN, Some(&AppError::BadRequest("输入包含被过滤的内容".to_string())));
+        manager.record_account_request(TOKEN, Some(&AppError::InvalidRequest("无法解析图片".to_string())));
+        assert!(manager.accounts_due_for_probe().is_empty());
+
+        manager.record_account_request(TOKEN, Some(&AppError::ExternalApi("upstream error".to_string())));
+        assert_eq!(manager.accounts_due_for_probe(), vec![TOKEN.to_string()]);
     }
 }
@@ -0,0 +1,144 @@
+use crate::config::{AuditConfig, AuditRedaction};
+use crate::utils::unix_timestamp;
+use parking_lot::Mutex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// 一条补全审计记录
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRecord {
+    pub timestamp: u64,
+    pub request_id: String,
+    pub api_key_id: Option<String>,
+    pub model: String,
+    pub stream: bool,
+    pub prompt_hash: String,
+    pub prompt: Option<String>, // 按脱敏策略截断或省略
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+    pub account: Option<String>,
+    pub outcome: String,
+    pub latency_ms: u64,
+}
+
+/// 补全审计日志（JSON Lines文件，默认关闭）
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+    redaction: AuditRedaction,
+    prompt_max_chars: usize,
+}
+
+impl AuditLog {
+    pub fn new(config: &AuditConfig) -> Self {
+        let file = config.path.as_deref().and_then(|path| match open_append(path) {
+            Ok(file) => {
+                info!("审计日志已启用: {}", path);
+                Some(Mutex::new(file))
+            }
+            Err(e) => {
+                warn!("打开审计日志失败 {}: {}", path, e);
+                None
+            }
+        });
+
+        Self {
+            file,
+            redaction: config.redaction,
+            prompt_max_chars: config.prompt_max_chars,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// 按脱敏策略处理提示词，返回(哈希, 记录的内容)
+    pub fn redact_prompt(&self, prompt: &str) -> (String, Option<String>) {
+        let hash = hex::encode(Sha256::digest(prompt.as_bytes()));
+        let content = match self.redaction {
+            AuditRedaction::Hash => None,
+            AuditRedaction::Truncate => Some(prompt.chars().take(self.prompt_max_chars).collect()),
+            AuditRedaction::None => Some(prompt.to_string()),
+        };
+        (hash, content)
+    }
+
+    /// 追加一条记录
+    pub fn record(&self, record: &AuditRecord) {
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        let line = match serde_json::to_string(record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("序列化审计记录失败: {}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = writeln!(file.lock(), "{}", line) {
+            warn!("写入审计日志失败: {}", e);
+        }
+    }
+}
+
+impl AuditRecord {
+    pub fn new(request_id: &str, model: &str, stream: bool) -> Self {
+        Self {
+            timestamp: unix_timestamp(),
+            request_id: request_id.to_string(),
+            api_key_id: None,
+            model: model.to_string(),
+            stream,
+            prompt_hash: String::new(),
+            prompt: None,
+            prompt_tokens: None,
+            completion_tokens: None,
+            account: None,
+            outcome: String::new(),
+            latency_ms: 0,
+        }
+    }
+}
+
+fn open_append(path: &str) -> std::io::Result<File> {
+    if let Some(parent) = Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audit_log(redaction: AuditRedaction) -> AuditLog {
+        AuditLog::new(&AuditConfig {
+            path: None,
+            redaction,
+            prompt_max_chars: 5,
+            failed_request_capacity: 0,
+            failed_request_max_chars: 0,
+        })
+    }
+
+    #[test]
+    fn test_redaction_modes() {
+        let (hash, content) = audit_log(AuditRedaction::Hash).redact_prompt("hello world");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(content, None);
+
+        let (_, content) = audit_log(AuditRedaction::Truncate).redact_prompt("hello world");
+        assert_eq!(content.as_deref(), Some("hello"));
+
+        let (_, content) = audit_log(AuditRedaction::None).redact_prompt("hello world");
+        assert_eq!(content.as_deref(), Some("hello world"));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::config::DeepSeekConfig;
+use parking_lot::RwLock;
+use reqwest::header::{HeaderMap, HeaderValue};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// 浏览器指纹：UA与客户端提示必须互相匹配
+#[derive(Debug)]
+pub struct BrowserProfile {
+    pub name: &'static str,
+    pub user_agent: &'static str,
+    pub sec_ch_ua: Option<&'static str>, // Firefox不发送客户端提示
+    pub platform: &'static str,
+    pub accept_language: &'static str, // 与浏览器默认的语言列表格式一致
+}
+
+/// 内置的浏览器指纹
+pub const PROFILES: &[BrowserProfile] = &[
+    BrowserProfile {
+        name: "chrome-macos",
+        user_agent: "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/134.0.0.0 Safari/537.36",
+        sec_ch_ua: Some(r#""Chromium";v="134", "Not:A-Brand";v="24", "Google Chrome";v="134""#),
+        platform: r#""macOS""#,
+        accept_language: "zh-CN,zh;q=0.9,en;q=0.8",
+    },
+    BrowserProfile {
+        name: "chrome-windows",
+        user_agent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/134.0.0.0 Safari/537.36",
+        sec_ch_ua: Some(r#""Chromium";v="134", "Not:A-Brand";v="24", "Google Chrome";v="134""#),
+        platform: r#""Windows""#,
+        accept_language: "zh-CN,zh;q=0.9,en;q=0.8",
+    },
+    BrowserProfile {
+        name: "edge-windows",
+        user_agent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36 Edg/131.0.0.0",
+        sec_ch_ua: Some(r#""Microsoft Edge";v="131", "Chromium";v="131", "Not_A Brand";v="24""#),
+        platform: r#""Windows""#,
+        accept_language: "zh-CN,zh;q=0.9,en;q=0.8,en-GB;q=0.7,en-US;q=0.6",
+    },
+    BrowserProfile {
+        name: "chrome-linux",
+        user_agent: "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/134.0.0.0 Safari/537.36",
+        sec_ch_ua: Some(r#""Chromium";v="134", "Not:A-Brand";v="24", "Google Chrome";v="134""#),
+        platform: r#""Linux""#,
+        accept_language: "zh-CN,zh;q=0.9,en;q=0.8",
+    },
+    BrowserProfile {
+        name: "firefox-windows",
+        user_agent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:136.0) Gecko/20100101 Firefox/136.0",
+        sec_ch_ua: None,
+        platform: r#""Windows""#,
+        accept_language: "zh-CN,zh;q=0.8,zh-TW;q=0.7,zh-HK;q=0.5,en-US;q=0.3,en;q=0.2",
+    },
+];
+
+/// 网页客户端的语言、平台和版本，所有指纹共用
+const CLIENT_LOCALE: &str = "zh_CN";
+const CLIENT_PLATFORM: &str = "web";
+const CLIENT_VERSION: &str = "1.3.0-auto-resume";
+
+/// 是否为内置的浏览器指纹名称
+pub fn is_known_profile(name: &str) -> bool {
+    PROFILES.iter().any(|profile| profile.name == name)
+}
+
+/// 按账号分配浏览器指纹，同一账号的所有请求保持一致
+#[derive(Debug)]
+pub struct BrowserProfiles {
+    enabled: Vec<&'static BrowserProfile>,
+    app_version: RwLock<String>,             // 启动后可被自动探测到的版本替换
+    accounts: RwLock<HashMap<String, String>>, // userToken -> 邮箱，登录和之后的请求按邮箱选择同一指纹
+}
+
+impl BrowserProfiles {
+    pub fn new(names: &[String], app_version: &str) -> Self {
+        let mut enabled: Vec<_> = PROFILES
+            .iter()
+            .filter(|profile| names.iter().any(|name| name == profile.name))
+            .collect();
+        if enabled.is_empty() {
+            enabled.push(&PROFILES[0]);
+        }
+
+        Self {
+            enabled,
+            app_version: RwLock::new(app_version.to_string()),
+            accounts: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_config(config: &DeepSeekConfig) -> Self {
+        Self::new(&config.browser_profiles, &config.app_version)
+    }
+
+    pub fn app_version(&self) -> String {
+        self.app_version.read().clone()
+    }
+
+    /// 更新X-App-Version，返回是否发生变化
+    pub fn set_app_version(&self, version: &str) -> bool {
+        let mut current = self.app_version.write();
+        if *current == version {
+            return false;
+        }
+        *current = version.to_string();
+        true
+    }
+
+    /// 让userToken沿用邮箱的指纹，重新登录换了token也不变
+    pub fn link(&self, email: &str, token: &str) {
+        self.accounts.write().insert(token.to_string(), email.to_string());
+    }
+
+    /// 按账号标识的哈希选择指纹，不随重启变化；已关联邮箱的token按邮箱选择
+    pub fn for_account(&self, account: &str) -> &'static BrowserProfile {
+        let accounts = self.accounts.read();
+        let account = accounts.get(account).map_or(account, String::as_str);
+        let digest = Sha256::digest(account.as_bytes());
+        let index = u64::from_be_bytes(digest[..8].try_into().unwrap()) % self.enabled.len() as u64;
+        self.enabled[index as usize]
+    }
+
+    /// 写入账号对应的UA、客户端提示、语言和X-Client-*/X-App-Version，保证同一账号的请求头互相一致
+    pub fn apply(&self, account: &str, headers: &mut HeaderMap) {
+        let profile = self.for_account(account);
+        headers.insert("User-Agent", HeaderValue::from_static(profile.user_agent));
+        headers.insert("Accept-Language", HeaderValue::from_static(profile.accept_language));
+        headers.insert("X-Client-Locale", HeaderValue::from_static(CLIENT_LOCALE));
+        headers.insert("X-Client-Platform", HeaderValue::from_static(CLIENT_PLATFORM));
+        headers.insert("X-Client-Version", HeaderValue::from_static(CLIENT_VERSION));
+        if let Some(sec_ch_ua) = profile.sec_ch_ua {
+            headers.insert("Sec-Ch-Ua", HeaderValue::from_static(sec_ch_ua));
+            headers.insert("Sec-Ch-Ua-Mobile", HeaderValue::from_static("?0"));
+            headers.insert("Sec-Ch-Ua-Platform", HeaderValue::from_static(profile.platform));
+        }
+        if let Ok(version) = self.app_version.read().parse() {
+            headers.insert("X-App-Version", version);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_is_stable_per_account() {
+        let names: Vec<String> = PROFILES.iter().map(|profile| profile.name.to_string()).collect();
+        let profiles = BrowserProfiles::new(&names, "20241129.1");
+        let first = profiles.for_account("token-a").name;
+        assert!((0..10).all(|_| profiles.for_account("token-a").name == first));
+
+        let mut headers = HeaderMap::new();
+        BrowserProfiles::new(&["firefox-windows".to_string()], "20241129.1").apply("token-a", &mut headers);
+        assert!(headers["User-Agent"].to_str().unwrap().contains("Firefox"));
+        assert!(headers["Accept-Language"].to_str().unwrap().starts_with("zh-CN,zh;q=0.8"));
+        assert!(!headers.contains_key("Sec-Ch-Ua"));
+    }
+
+    #[test]
+    fn test_linked_token_uses_email_profile() {
+        let names: Vec<String> = PROFILES.iter().map(|profile| profile.name.to_string()).collect();
+        let profiles = BrowserProfiles::new(&names, "20241129.1");
+        let email = "user@example.com";
+        let token = (0..)
+            .map(|i| format!("token-{}", i))
+            .find(|token| profiles.for_account(token).name != profiles.for_account(email).name)
+            .unwrap();
+
+        profiles.link(email, &token);
+        let (mut login, mut chat) = (HeaderMap::new(), HeaderMap::new());
+        profiles.apply(email, &mut login);
+        profiles.apply(&token, &mut chat);
+        assert_eq!(login, chat);
+    }
+}
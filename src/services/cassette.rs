@@ -0,0 +1,366 @@
+use crate::config::{CassetteMode, Config};
+use crate::utils::unix_timestamp;
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Request, State},
+    http::{header, request::Parts, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use parking_lot::Mutex;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// 录制时单个请求体的上限
+pub(super) const MAX_REQUEST_BYTES: usize = 32 * 1024 * 1024;
+
+/// 写入文件前替换为占位符的字段，出现在请求体和响应体的任意层级
+const REDACTED_FIELDS: &[&str] = &["password", "token", "email", "mobile", "device_id", "access_token", "refresh_token"];
+
+/// 不转发给上游的请求头：由reqwest重新生成，或会让上游压缩响应导致录制的内容不可读
+const HOP_HEADERS: &[HeaderName] = &[header::HOST, header::CONTENT_LENGTH, header::ACCEPT_ENCODING, header::CONNECTION];
+
+/// 一次上游请求和响应，cassette文件中每行一条
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub path: String,
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(default)]
+    pub request_body: Option<String>,
+    pub status: u16,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    pub response_body: String, // SSE响应保留原始的data行
+    pub recorded_at: u64,
+}
+
+/// 按配置启动录制或回放服务，返回客户端应使用的上游地址；未开启时返回None
+pub async fn spawn(config: &Config) -> io::Result<Option<String>> {
+    let Some(path) = config.deepseek.cassette_path.as_deref() else {
+        return Ok(None);
+    };
+    let app = match config.deepseek.cassette_mode {
+        CassetteMode::Off => return Ok(None),
+        CassetteMode::Record => {
+            let client = config
+                .http
+                .build_client()
+                .map_err(io::Error::other)?;
+            let recorder = Recorder {
+                client,
+                target: config.deepseek.base_url.trim_end_matches('/').to_string(),
+                file: Mutex::new(create(path)?),
+            };
+            tracing::warn!("Recording upstream traffic to {}", path);
+            Router::new().fallback(record).with_state(Arc::new(recorder))
+        }
+        CassetteMode::Replay => {
+            let replayer = Replayer::load(path)?;
+            tracing::warn!("Replaying upstream traffic from {} ({} interactions)", path, replayer.len());
+            Router::new().fallback(replay).with_state(Arc::new(replayer))
+        }
+    };
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base_url = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("Upstream cassette server stopped: {}", e);
+        }
+    });
+    Ok(Some(base_url))
+}
+
+fn create(path: &str) -> io::Result<File> {
+    if let Some(parent) = Path::new(path).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    File::create(path)
+}
+
+/// 转发请求到上游并记录
+struct Recorder {
+    client: Client,
+    target: String,
+    file: Mutex<File>,
+}
+
+async fn record(State(recorder): State<Arc<Recorder>>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, MAX_REQUEST_BYTES).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response(),
+    };
+    let upstream = match forward(&recorder.client, &recorder.target, &parts, body.clone()).await {
+        Ok(upstream) => upstream,
+        Err(response) => return response,
+    };
+
+    let request_type = parts.headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    let content_type = upstream.content_type().map(str::to_string);
+    let interaction = Interaction {
+        method: parts.method.to_string(),
+        path: parts.uri.path().to_string(),
+        query: parts.uri.query().map(str::to_string),
+        request_body: (!body.is_empty()).then(|| sanitize_body(request_type, &body)),
+        status: upstream.status.as_u16(),
+        response_body: sanitize_body(content_type.as_deref(), &upstream.body),
+        content_type,
+        recorded_at: unix_timestamp(),
+    };
+    match serde_json::to_string(&interaction) {
+        Ok(line) => {
+            if let Err(e) = writeln!(recorder.file.lock(), "{}", line) {
+                tracing::warn!("Failed to write cassette: {}", e);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to serialize interaction: {}", e),
+    }
+
+    // 客户端收到未脱敏的原始响应
+    upstream.into_response(Body::from)
+}
+
+/// 完整读取的上游响应
+pub(super) struct Forwarded {
+    pub status: StatusCode,
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub body: Bytes,
+}
+
+impl Forwarded {
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(name, _)| name == header::CONTENT_TYPE)
+            .and_then(|(_, value)| value.to_str().ok())
+    }
+
+    /// 以原状态码和响应头返回，响应体可以由调用方改写
+    pub fn into_response(self, body: impl FnOnce(Bytes) -> Body) -> Response {
+        let mut response = Response::new(body(self.body));
+        *response.status_mut() = self.status;
+        for (name, value) in self.headers {
+            response.headers_mut().append(name, value);
+        }
+        response
+    }
+}
+
+/// 把请求原样转发到上游并读取完整响应，失败时返回502
+pub(super) async fn forward(client: &Client, target: &str, parts: &Parts, body: Bytes) -> Result<Forwarded, Response> {
+    let path_and_query = parts.uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+    // reqwest与axum使用不同版本的http类型，按名称和字节转换
+    let method = reqwest::Method::from_bytes(parts.method.as_str().as_bytes()).unwrap_or(reqwest::Method::GET);
+    let mut request = client.request(method, format!("{}{}", target, path_and_query));
+    for (name, value) in parts.headers.iter() {
+        if !HOP_HEADERS.contains(name) {
+            request = request.header(name.as_str(), value.as_bytes());
+        }
+    }
+    let upstream = request
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()).into_response())?;
+
+    let status = StatusCode::from_u16(upstream.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut headers = Vec::new();
+    for (name, value) in upstream.headers() {
+        let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_str().as_bytes()), HeaderValue::from_bytes(value.as_bytes())) else {
+            continue;
+        };
+        if name != header::CONTENT_LENGTH && name != header::TRANSFER_ENCODING {
+            headers.push((name, value));
+        }
+    }
+    let body = upstream
+        .bytes()
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()).into_response())?;
+    Ok(Forwarded { status, headers, body })
+}
+
+/// 按 (method, path) 依次返回录制的响应，同一接口的录制用完后重复最后一条
+struct Replayer {
+    interactions: Mutex<HashMap<(String, String), VecDeque<Interaction>>>,
+    last: Mutex<HashMap<(String, String), Interaction>>,
+}
+
+impl Replayer {
+    fn load(path: &str) -> io::Result<Self> {
+        let mut interactions: HashMap<(String, String), VecDeque<Interaction>> = HashMap::new();
+        for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let interaction: Interaction = serde_json::from_str(&line).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{} line {}: {}", path, index + 1, e))
+            })?;
+            interactions
+                .entry((interaction.method.clone(), interaction.path.clone()))
+                .or_default()
+                .push_back(interaction);
+        }
+        Ok(Self {
+            interactions: Mutex::new(interactions),
+            last: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.interactions.lock().values().map(VecDeque::len).sum()
+    }
+
+    fn next(&self, method: &Method, path: &str) -> Option<Interaction> {
+        let key = (method.to_string(), path.to_string());
+        let next = self.interactions.lock().get_mut(&key).and_then(VecDeque::pop_front);
+        match next {
+            Some(interaction) => {
+                self.last.lock().insert(key, interaction.clone());
+                Some(interaction)
+            }
+            None => self.last.lock().get(&key).cloned(),
+        }
+    }
+}
+
+async fn replay(State(replayer): State<Arc<Replayer>>, request: Request) -> Response {
+    let Some(interaction) = replayer.next(request.method(), request.uri().path()) else {
+        tracing::warn!("No recorded interaction for {} {}", request.method(), request.uri().path());
+        return (StatusCode::NOT_FOUND, "no recorded interaction").into_response();
+    };
+
+    let mut response = Response::new(Body::from(interaction.response_body));
+    *response.status_mut() = StatusCode::from_u16(interaction.status).unwrap_or(StatusCode::OK);
+    if let Some(content_type) = interaction.content_type.and_then(|value| value.parse().ok()) {
+        response.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    response
+}
+
+/// JSON请求体和响应体中的敏感字段替换为占位符，文件上传等二进制内容只记录大小
+pub(super) fn sanitize_body(content_type: Option<&str>, body: &[u8]) -> String {
+    if content_type.is_some_and(|content_type| content_type.starts_with("multipart/")) {
+        return format!("<{} bytes multipart>", body.len());
+    }
+    let Ok(text) = std::str::from_utf8(body) else {
+        return format!("<{} bytes binary>", body.len());
+    };
+    match serde_json::from_str::<Value>(text) {
+        Ok(mut value) => {
+            redact(&mut value);
+            value.to_string()
+        }
+        Err(_) => text.to_string(),
+    }
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if REDACTED_FIELDS.contains(&key.as_str()) && !value.is_null() {
+                    *value = Value::String("[REDACTED]".to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ChatMessage, ChatMessageContent, CompletionOptions};
+    use crate::services::{mock_upstream, BrowserProfiles, CookieStore, DeepSeekClient, QuotaCache, RequestContext};
+    use crate::utils::generate_uuid_simple;
+
+    fn client(base_url: &str) -> DeepSeekClient {
+        let mut config = Config::default();
+        config.deepseek.base_url = base_url.to_string();
+        config.deepseek.mirrors.clear();
+        let cookies = std::env::temp_dir().join(format!("cassette-cookies-{}.json", generate_uuid_simple()));
+        DeepSeekClient::new(
+            config.clone(),
+            Arc::new(QuotaCache::new(config.deepseek.quota_cache_ttl_secs)),
+            Arc::new(BrowserProfiles::from_config(&config.deepseek)),
+            Arc::new(CookieStore::open(cookies.to_str().unwrap())),
+        )
+    }
+
+    async fn complete(base_url: &str, text: &str) -> String {
+        let messages = [ChatMessage {
+            role: "user".to_string(),
+            content: ChatMessageContent::Text(text.to_string()),
+            name: None,
+            tool_call_id: None,
+            tool_calls: None,
+            reasoning_content: None,
+        }];
+        let options = CompletionOptions {
+            continue_final_message: false,
+            features: Default::default(),
+            debug_upstream: false,
+            overrides: Default::default(),
+            chaos: None,
+        };
+        let response = client(base_url)
+            .create_completion("deepseek", &messages, "mock-secret-token", None, options, &RequestContext::new())
+            .await
+            .unwrap();
+        match &response.choices[0].message.as_ref().unwrap().content {
+            ChatMessageContent::Text(text) => text.clone(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_sanitize_body() {
+        let body = br#"{"email":"a@b.c","password":"p","biz_data":{"token":"t","id":"1"},"prompt":"hi"}"#;
+        let sanitized: Value = serde_json::from_str(&sanitize_body(Some("application/json"), body)).unwrap();
+        assert_eq!(sanitized["email"], "[REDACTED]");
+        assert_eq!(sanitized["password"], "[REDACTED]");
+        assert_eq!(sanitized["biz_data"]["token"], "[REDACTED]");
+        assert_eq!(sanitized["biz_data"]["id"], "1");
+        assert_eq!(sanitized["prompt"], "hi");
+        assert_eq!(sanitize_body(Some("multipart/form-data; boundary=x"), b"abc"), "<3 bytes multipart>");
+        assert_eq!(sanitize_body(Some("text/event-stream"), b"data: {}\n\n"), "data: {}\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let path = std::env::temp_dir().join(format!("cassette-{}.jsonl", generate_uuid_simple()));
+        let mut config = Config::default();
+        config.deepseek.base_url = mock_upstream::spawn().await.unwrap();
+        config.deepseek.cassette_path = Some(path.to_str().unwrap().to_string());
+
+        config.deepseek.cassette_mode = CassetteMode::Record;
+        let recorder = spawn(&config).await.unwrap().unwrap();
+        let recorded = complete(&recorder, "record this").await;
+        assert!(recorded.contains("record this"));
+
+        let cassette = fs::read_to_string(&path).unwrap();
+        assert!(cassette.contains("/api/v0/chat/completion"));
+        assert!(!cassette.contains("mock-secret-token"));
+
+        // 回放时不再访问上游，提示词不同也返回录制的回复
+        config.deepseek.base_url = "http://127.0.0.1:9".to_string();
+        config.deepseek.cassette_mode = CassetteMode::Replay;
+        let replayer = spawn(&config).await.unwrap().unwrap();
+        assert_eq!(complete(&replayer, "something else").await, recorded);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +1,10 @@
 use crate::error::ApiResult;
 use crate::models::{Challenge, ChallengeAnswer};
+use crate::utils::unix_timestamp_ms;
 use base64::{engine::general_purpose, Engine as _};
+use parking_lot::RwLock;
 use serde_json;
+use std::collections::HashMap;
 
 /// 挑战求解器
 pub struct ChallengeSolver {
@@ -41,3 +44,117 @@ impl ChallengeSolver {
         Ok(base64_answer)
     }
 }
+
+/// 已解出的挑战答案
+#[derive(Debug, Clone)]
+struct CachedAnswer {
+    answer: String,
+    valid_until_ms: u64,
+}
+
+/// 挑战答案缓存：在挑战有效期内按 (token, target_path) 复用已解出的答案
+pub struct ChallengeCache {
+    answers: RwLock<HashMap<(String, String), CachedAnswer>>,
+    reuse_window_ms: u64,
+    safety_margin_ms: u64,
+}
+
+impl ChallengeCache {
+    pub fn new(reuse_window_secs: u64) -> Self {
+        Self {
+            answers: RwLock::new(HashMap::new()),
+            reuse_window_ms: reuse_window_secs * 1000,
+            safety_margin_ms: 5000,
+        }
+    }
+
+    /// 获取仍在有效期内的答案
+    pub fn get(&self, token: &str, target_path: &str) -> Option<String> {
+        if self.reuse_window_ms == 0 {
+            return None;
+        }
+
+        let now = unix_timestamp_ms();
+        let key = (token.to_string(), target_path.to_string());
+        let answers = self.answers.read();
+        answers
+            .get(&key)
+            .filter(|cached| now < cached.valid_until_ms)
+            .map(|cached| cached.answer.clone())
+    }
+
+    /// 缓存答案，有效期取挑战过期时间与复用窗口中较早者
+    pub fn put(&self, token: &str, target_path: &str, challenge: &Challenge, answer: String) {
+        if self.reuse_window_ms == 0 {
+            return;
+        }
+
+        let now = unix_timestamp_ms();
+        let valid_until_ms = challenge
+            .expire_at
+            .saturating_sub(self.safety_margin_ms)
+            .min(now + self.reuse_window_ms);
+        if valid_until_ms <= now {
+            return;
+        }
+
+        let mut answers = self.answers.write();
+        answers.retain(|_, cached| now < cached.valid_until_ms);
+        answers.insert(
+            (token.to_string(), target_path.to_string()),
+            CachedAnswer { answer, valid_until_ms },
+        );
+    }
+
+    /// 使答案失效（例如上游拒绝了该答案）
+    pub fn invalidate(&self, token: &str, target_path: &str) {
+        let mut answers = self.answers.write();
+        answers.remove(&(token.to_string(), target_path.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn challenge(expires_in_ms: u64) -> Challenge {
+        Challenge {
+            algorithm: "DeepSeekHashV1".to_string(),
+            challenge: "challenge".to_string(),
+            salt: "salt".to_string(),
+            difficulty: 1,
+            expire_at: unix_timestamp_ms() + expires_in_ms,
+            signature: "signature".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_answers_reused_per_token_and_path() {
+        let cache = ChallengeCache::new(60);
+        cache.put("token-a", "/api/v0/chat/completion", &challenge(120_000), "answer".to_string());
+        assert_eq!(cache.get("token-a", "/api/v0/chat/completion").as_deref(), Some("answer"));
+        // 其他账号和其他接口不能复用
+        assert!(cache.get("token-b", "/api/v0/chat/completion").is_none());
+        assert!(cache.get("token-a", "/api/v0/file/upload_file").is_none());
+
+        cache.invalidate("token-a", "/api/v0/chat/completion");
+        assert!(cache.get("token-a", "/api/v0/chat/completion").is_none());
+    }
+
+    #[test]
+    fn test_answers_not_cached_near_expiry_or_when_disabled() {
+        // 挑战在安全余量内过期时不缓存
+        let cache = ChallengeCache::new(60);
+        cache.put("token", "/path", &challenge(3_000), "answer".to_string());
+        assert!(cache.get("token", "/path").is_none());
+
+        // 有效期取挑战过期时间减去安全余量，早于复用窗口
+        cache.put("token", "/path", &challenge(10_000), "answer".to_string());
+        let valid_until_ms = cache.answers.read()[&("token".to_string(), "/path".to_string())].valid_until_ms;
+        assert!(valid_until_ms <= unix_timestamp_ms() + 5_000);
+
+        let disabled = ChallengeCache::new(0);
+        disabled.put("token", "/path", &challenge(120_000), "answer".to_string());
+        assert!(disabled.get("token", "/path").is_none());
+    }
+}
@@ -0,0 +1,214 @@
+use crate::config::{Config, FaultRates};
+use crate::services::cassette::{forward, MAX_REQUEST_BYTES};
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use parking_lot::Mutex;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use reqwest::Client;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 单次请求的故障比例，由客户端在X-Chaos中指定时转发给故障注入代理
+pub const CHAOS_HEADER: &str = "x-chaos";
+
+/// 插入SSE响应中的损坏行
+const MALFORMED_LINE: &[u8] = b"data: {\"v\": \"\xe6\x96\n\n";
+
+/// 在上游前面按比例注入故障的代理
+struct ChaosProxy {
+    client: Client,
+    target: String,
+    faults: FaultRates,
+    rng: Mutex<StdRng>, // 固定种子时同样顺序的请求得到同样的故障
+    hang: Duration,     // 模拟超时时保持连接不响应的时间，超过客户端的请求超时
+}
+
+/// 按配置启动故障注入代理，返回客户端应使用的上游地址；未开启时返回None
+pub async fn spawn(config: &Config) -> io::Result<Option<String>> {
+    if !config.deepseek.chaos {
+        return Ok(None);
+    }
+    let rng = match config.deepseek.chaos_seed {
+        0 => StdRng::from_entropy(),
+        seed => StdRng::seed_from_u64(seed),
+    };
+    let proxy = ChaosProxy {
+        client: config.http.build_client().map_err(io::Error::other)?,
+        target: config.deepseek.base_url.trim_end_matches('/').to_string(),
+        faults: config.deepseek.chaos_faults,
+        rng: Mutex::new(rng),
+        hang: Duration::from_secs(config.http.request_timeout_secs + 1),
+    };
+    tracing::warn!("Chaos mode enabled, injecting upstream faults ({})", proxy.faults);
+
+    let app = Router::new().fallback(inject).with_state(Arc::new(proxy));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base_url = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("Chaos proxy stopped: {}", e);
+        }
+    });
+    Ok(Some(base_url))
+}
+
+impl ChaosProxy {
+    fn roll(&self, rate: f64) -> bool {
+        rate > 0.0 && self.rng.lock().gen_bool(rate)
+    }
+}
+
+async fn inject(State(proxy): State<Arc<ChaosProxy>>, request: Request) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let path = parts.uri.path().to_string();
+    // 请求头中的比例只作用于本次请求，不转发给上游
+    let faults = parts
+        .headers
+        .remove(CHAOS_HEADER)
+        .and_then(|value| value.to_str().ok()?.parse::<FaultRates>().ok())
+        .unwrap_or(proxy.faults);
+
+    if proxy.roll(faults.rate_limit) {
+        tracing::info!("Chaos: 429 for {}", path);
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, "1")],
+            axum::Json(serde_json::json!({"code": 429, "msg": "Too Many Requests (chaos)"})),
+        )
+            .into_response();
+    }
+    if proxy.roll(faults.timeout) {
+        tracing::info!("Chaos: timeout for {}", path);
+        tokio::time::sleep(proxy.hang).await;
+        return StatusCode::GATEWAY_TIMEOUT.into_response();
+    }
+
+    let body = match to_bytes(body, MAX_REQUEST_BYTES).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response(),
+    };
+    let upstream = match forward(&proxy.client, &proxy.target, &parts, body).await {
+        Ok(upstream) => upstream,
+        Err(response) => return response,
+    };
+    if !upstream.content_type().is_some_and(|content_type| content_type.contains("text/event-stream")) {
+        return upstream.into_response(Body::from);
+    }
+
+    let malformed = proxy.roll(faults.malformed);
+    let disconnect = proxy.roll(faults.disconnect);
+    if !malformed && !disconnect {
+        return upstream.into_response(Body::from);
+    }
+    let mut chunks: Vec<Bytes> = split_lines(&upstream.body);
+    if malformed {
+        let at = proxy.rng.lock().gen_range(0..=chunks.len());
+        tracing::info!("Chaos: malformed SSE line {} for {}", at, path);
+        chunks.insert(at, Bytes::from_static(MALFORMED_LINE));
+    }
+    let cut = disconnect.then(|| proxy.rng.lock().gen_range(0..chunks.len().max(1)));
+    if let Some(cut) = cut {
+        tracing::info!("Chaos: disconnect after {} lines for {}", cut, path);
+        chunks.truncate(cut);
+    }
+
+    // 流以错误结束时连接被直接断开，客户端读到不完整的响应体
+    let mut items: Vec<io::Result<Bytes>> = chunks.into_iter().map(Ok).collect();
+    if cut.is_some() {
+        items.push(Err(io::Error::new(io::ErrorKind::ConnectionReset, "chaos disconnect")));
+    }
+    upstream.into_response(|_| Body::from_stream(futures::stream::iter(items)))
+}
+
+/// 按行切分响应体，保留换行符
+fn split_lines(body: &Bytes) -> Vec<Bytes> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (index, byte) in body.iter().enumerate() {
+        if *byte == b'\n' {
+            lines.push(body.slice(start..=index));
+            start = index + 1;
+        }
+    }
+    if start < body.len() {
+        lines.push(body.slice(start..));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ApiError;
+    use crate::models::{ChatCompletionResponse, ChatMessage, ChatMessageContent, CompletionOptions};
+    use crate::services::{mock_upstream, BrowserProfiles, CookieStore, DeepSeekClient, QuotaCache, RequestContext};
+    use crate::utils::generate_uuid_simple;
+
+    async fn complete(faults: &str, chaos: Option<&str>) -> Result<ChatCompletionResponse, ApiError> {
+        let mut config = Config::default();
+        config.deepseek.base_url = mock_upstream::spawn().await.unwrap();
+        config.deepseek.mirrors.clear();
+        config.deepseek.max_retry_count = 0;
+        config.deepseek.chaos = true;
+        config.deepseek.chaos_faults = faults.parse().unwrap();
+        config.deepseek.chaos_seed = 7;
+        config.deepseek.base_url = spawn(&config).await.unwrap().unwrap();
+
+        let cookies = std::env::temp_dir().join(format!("chaos-cookies-{}.json", generate_uuid_simple()));
+        let client = DeepSeekClient::new(
+            config.clone(),
+            Arc::new(QuotaCache::new(config.deepseek.quota_cache_ttl_secs)),
+            Arc::new(BrowserProfiles::from_config(&config.deepseek)),
+            Arc::new(CookieStore::open(cookies.to_str().unwrap())),
+        );
+        let messages = [ChatMessage {
+            role: "user".to_string(),
+            content: ChatMessageContent::Text("hello".to_string()),
+            name: None,
+            tool_call_id: None,
+            tool_calls: None,
+            reasoning_content: None,
+        }];
+        let options = CompletionOptions {
+            continue_final_message: false,
+            features: Default::default(),
+            debug_upstream: false,
+            overrides: Default::default(),
+            chaos: chaos.map(|chaos| chaos.parse().unwrap()),
+        };
+        client
+            .create_completion("deepseek", &messages, "mock-token", None, options, &RequestContext::new())
+            .await
+    }
+
+    #[test]
+    fn test_split_lines() {
+        let lines = split_lines(&Bytes::from_static(b"data: 1\n\ndata: 2"));
+        assert_eq!(lines, vec![Bytes::from_static(b"data: 1\n"), Bytes::from_static(b"\n"), Bytes::from_static(b"data: 2")]);
+    }
+
+    #[tokio::test]
+    async fn test_injects_configured_faults() {
+        assert!(complete("", None).await.is_ok());
+        assert!(complete("rate_limit=1", None).await.is_err());
+        assert!(complete("disconnect=1", None).await.is_err());
+        // 损坏的SSE行被跳过，回复不受影响
+        let response = complete("malformed=1", None).await.unwrap();
+        match &response.choices[0].message.as_ref().unwrap().content {
+            ChatMessageContent::Text(text) => assert!(text.contains("hello")),
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_header_overrides_configured_faults() {
+        assert!(matches!(complete("", Some("rate_limit=1")).await, Err(ApiError::RateLimited { .. })));
+        assert!(complete("", Some("disconnect=1")).await.is_err());
+    }
+}
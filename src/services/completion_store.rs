@@ -0,0 +1,391 @@
+use crate::config::HistoryConfig;
+use crate::error::{ApiError, ApiResult};
+use crate::models::{ChatCompletionResponse, ChatMessageContent, StreamChunk};
+use crate::utils::{estimate_tokens, unix_timestamp};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+/// 单次查询最多返回的记录数
+const MAX_LIST_LIMIT: usize = 500;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS completions (
+    id TEXT PRIMARY KEY,
+    created_at INTEGER NOT NULL,
+    finished_at INTEGER NOT NULL,
+    api_key_id TEXT,
+    model TEXT NOT NULL,
+    stream INTEGER NOT NULL,
+    prompt TEXT NOT NULL,
+    response TEXT NOT NULL,
+    reasoning TEXT,
+    finish_reason TEXT,
+    prompt_tokens INTEGER,
+    completion_tokens INTEGER
+);
+CREATE INDEX IF NOT EXISTS completions_created_at ON completions (created_at);
+CREATE INDEX IF NOT EXISTS completions_api_key_id ON completions (api_key_id, created_at);
+";
+
+/// 一次成功补全的提示词和回复
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompletionRecord {
+    pub id: String, // 请求ID，与审计日志一致
+    pub created_at: u64,
+    pub finished_at: u64,
+    pub api_key_id: Option<String>,
+    pub model: String,
+    pub stream: bool,
+    pub prompt: String, // 拼接后发往上游的提示词
+    pub response: String,
+    pub reasoning: Option<String>,
+    pub finish_reason: Option<String>,
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+}
+
+impl CompletionRecord {
+    /// 开始一次补全的记录，回复在完成后填入
+    pub fn new(id: &str, api_key_id: Option<String>, model: &str, stream: bool, prompt: String) -> Self {
+        let now = unix_timestamp();
+        Self {
+            id: id.to_string(),
+            created_at: now,
+            finished_at: now,
+            api_key_id,
+            model: model.to_string(),
+            stream,
+            prompt,
+            response: String::new(),
+            reasoning: None,
+            finish_reason: None,
+            prompt_tokens: None,
+            completion_tokens: None,
+        }
+    }
+
+    /// 填入非流式响应的回复和token数
+    pub fn set_response(&mut self, response: &ChatCompletionResponse) {
+        if let Some(choice) = response.choices.first() {
+            if let Some(message) = &choice.message {
+                if let ChatMessageContent::Text(text) = &message.content {
+                    self.response = text.clone();
+                }
+                self.reasoning = message.reasoning_content.clone();
+            }
+            self.finish_reason = choice.finish_reason.clone();
+        }
+        self.prompt_tokens = response.usage.as_ref().map(|usage| usage.prompt_tokens);
+        self.completion_tokens = response.usage.as_ref().map(|usage| usage.completion_tokens);
+        self.finished_at = unix_timestamp();
+    }
+
+    /// 追加流式响应一个数据块（带SSE的`data: `前缀）中的正文和思考过程
+    pub fn append_chunk(&mut self, data: &str) {
+        let payload = data.strip_prefix("data: ").unwrap_or(data).trim_end();
+        let Ok(chunk) = serde_json::from_str::<StreamChunk>(payload) else {
+            return;
+        };
+        for choice in chunk.choices.iter().filter(|choice| choice.index == 0) {
+            if let Some(content) = &choice.delta.content {
+                self.response.push_str(content);
+            }
+            if let Some(reasoning) = &choice.delta.reasoning_content {
+                self.reasoning.get_or_insert_with(String::new).push_str(reasoning);
+            }
+            if choice.finish_reason.is_some() {
+                self.finish_reason = choice.finish_reason.clone();
+            }
+        }
+    }
+
+    /// 流式响应结束，上游不返回token数，按内容估算
+    pub fn finish_stream(&mut self) {
+        let reasoning = self.reasoning.as_deref().unwrap_or_default();
+        self.prompt_tokens = Some(estimate_tokens(&self.prompt) as u32);
+        self.completion_tokens = Some((estimate_tokens(&self.response) + estimate_tokens(reasoning)) as u32);
+        self.finished_at = unix_timestamp();
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            created_at: row.get("created_at")?,
+            finished_at: row.get("finished_at")?,
+            api_key_id: row.get("api_key_id")?,
+            model: row.get("model")?,
+            stream: row.get("stream")?,
+            prompt: row.get("prompt")?,
+            response: row.get("response")?,
+            reasoning: row.get("reasoning")?,
+            finish_reason: row.get("finish_reason")?,
+            prompt_tokens: row.get("prompt_tokens")?,
+            completion_tokens: row.get("completion_tokens")?,
+        })
+    }
+}
+
+/// GET /admin/completions 的查询参数，q按子串匹配提示词、回复和思考过程（不区分大小写）
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CompletionQuery {
+    pub q: Option<String>,
+    pub api_key_id: Option<String>,
+    pub model: Option<String>,
+    pub since: Option<u64>, // 包含，UNIX时间戳（秒）
+    pub until: Option<u64>, // 不包含
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// 补全历史（SQLite，默认关闭），按保留天数和记录数上限定期清理。
+/// SQLite调用都在阻塞线程池中执行，不占用处理请求的异步线程
+pub struct CompletionStore {
+    conn: Option<Arc<Mutex<Connection>>>,
+    retention_days: u64,
+    max_records: u64,
+}
+
+impl CompletionStore {
+    pub fn open(config: &HistoryConfig) -> ApiResult<Self> {
+        let conn = match &config.path {
+            Some(path) => {
+                let error = |e: rusqlite::Error| ApiError::ConfigError(format!("打开补全历史 {} 失败: {}", path, e));
+                let conn = Connection::open(path).map_err(error)?;
+                conn.pragma_update(None, "journal_mode", "WAL").map_err(error)?;
+                conn.execute_batch(SCHEMA).map_err(error)?;
+                prune(&conn, config.retention_days, config.max_records)?;
+                tracing::info!("补全历史已启用: {}", path);
+                Some(Arc::new(Mutex::new(conn)))
+            }
+            None => None,
+        };
+        Ok(Self {
+            conn,
+            retention_days: config.retention_days,
+            max_records: config.max_records,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.conn.is_some()
+    }
+
+    /// 在阻塞线程池中使用数据库连接
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Connection) -> ApiResult<T> + Send + 'static,
+    ) -> ApiResult<T> {
+        let conn = self
+            .conn
+            .clone()
+            .ok_or_else(|| ApiError::NotFound("补全历史未启用（HISTORY_DB_PATH）".to_string()))?;
+        tokio::task::spawn_blocking(move || f(&conn.lock()))
+            .await
+            .map_err(|e| ApiError::InternalError(format!("补全历史任务失败: {}", e)))?
+    }
+
+    /// 保存一条记录，失败只记录日志，不影响请求
+    pub async fn record(&self, record: CompletionRecord) {
+        if !self.is_enabled() {
+            return;
+        }
+        let result = self
+            .with_conn(move |conn| {
+                conn.execute(
+                    "INSERT OR REPLACE INTO completions
+                        (id, created_at, finished_at, api_key_id, model, stream, prompt, response, reasoning, finish_reason, prompt_tokens, completion_tokens)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                    params![
+                        record.id,
+                        record.created_at,
+                        record.finished_at,
+                        record.api_key_id,
+                        record.model,
+                        record.stream,
+                        record.prompt,
+                        record.response,
+                        record.reasoning,
+                        record.finish_reason,
+                        record.prompt_tokens,
+                        record.completion_tokens,
+                    ],
+                )
+                .map_err(sqlite_error)
+            })
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("保存补全历史失败: {}", e);
+        }
+    }
+
+    pub async fn get(&self, id: &str) -> ApiResult<Option<CompletionRecord>> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            conn.query_row("SELECT * FROM completions WHERE id = ?1", [id], CompletionRecord::from_row)
+                .optional()
+                .map_err(sqlite_error)
+        })
+        .await
+    }
+
+    /// 按条件查询，从新到旧，返回(本页记录, 符合条件的总数)
+    pub async fn list(&self, query: &CompletionQuery) -> ApiResult<(Vec<CompletionRecord>, u64)> {
+        let query = query.clone();
+        self.with_conn(move |conn| list(conn, &query)).await
+    }
+
+    pub async fn delete(&self, id: &str) -> ApiResult<bool> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let deleted = conn.execute("DELETE FROM completions WHERE id = ?1", [id]).map_err(sqlite_error)?;
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    /// 删除超过保留天数的记录，再按记录数上限删除最旧的记录，返回删除的条数
+    pub async fn prune(&self) -> ApiResult<usize> {
+        if !self.is_enabled() {
+            return Ok(0);
+        }
+        let (retention_days, max_records) = (self.retention_days, self.max_records);
+        self.with_conn(move |conn| prune(conn, retention_days, max_records)).await
+    }
+}
+
+fn list(conn: &Connection, query: &CompletionQuery) -> ApiResult<(Vec<CompletionRecord>, u64)> {
+    let mut conditions = Vec::new();
+    let mut values: Vec<rusqlite::types::Value> = Vec::new();
+    if let Some(q) = query.q.as_deref().filter(|q| !q.is_empty()) {
+        let pattern = format!("%{}%", q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        conditions.push("(prompt LIKE ? ESCAPE '\\' OR response LIKE ? ESCAPE '\\' OR reasoning LIKE ? ESCAPE '\\')");
+        values.extend(std::iter::repeat_n(pattern.into(), 3));
+    }
+    if let Some(api_key_id) = &query.api_key_id {
+        conditions.push("api_key_id = ?");
+        values.push(api_key_id.clone().into());
+    }
+    if let Some(model) = &query.model {
+        conditions.push("model = ?");
+        values.push(model.clone().into());
+    }
+    if let Some(since) = query.since {
+        conditions.push("created_at >= ?");
+        values.push((since as i64).into());
+    }
+    if let Some(until) = query.until {
+        conditions.push("created_at < ?");
+        values.push((until as i64).into());
+    }
+    let filter = if conditions.is_empty() { String::new() } else { format!("WHERE {}", conditions.join(" AND ")) };
+
+    let total: u64 = conn
+        .query_row(&format!("SELECT COUNT(*) FROM completions {}", filter), params_from_iter(&values), |row| row.get(0))
+        .map_err(sqlite_error)?;
+    let limit = query.limit.unwrap_or(50).min(MAX_LIST_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    let mut statement = conn
+        .prepare(&format!("SELECT * FROM completions {} ORDER BY created_at DESC, rowid DESC LIMIT {} OFFSET {}", filter, limit, offset))
+        .map_err(sqlite_error)?;
+    let records = statement
+        .query_map(params_from_iter(&values), CompletionRecord::from_row)
+        .and_then(Iterator::collect)
+        .map_err(sqlite_error)?;
+    Ok((records, total))
+}
+
+fn prune(conn: &Connection, retention_days: u64, max_records: u64) -> ApiResult<usize> {
+    let mut deleted = 0;
+    if retention_days > 0 {
+        let cutoff = unix_timestamp().saturating_sub(retention_days * 86400);
+        deleted += conn.execute("DELETE FROM completions WHERE created_at < ?1", [cutoff]).map_err(sqlite_error)?;
+    }
+    if max_records > 0 {
+        deleted += conn
+            .execute(
+                "DELETE FROM completions WHERE rowid NOT IN
+                    (SELECT rowid FROM completions ORDER BY created_at DESC, rowid DESC LIMIT ?1)",
+                [max_records],
+            )
+            .map_err(sqlite_error)?;
+    }
+    if deleted > 0 {
+        tracing::info!("已清理 {} 条过期的补全历史", deleted);
+    }
+    Ok(deleted)
+}
+
+fn sqlite_error(e: rusqlite::Error) -> ApiError {
+    ApiError::InternalError(format!("补全历史查询失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, created_at: u64, api_key_id: &str, response: &str) -> CompletionRecord {
+        CompletionRecord {
+            id: id.to_string(),
+            created_at,
+            finished_at: created_at + 1,
+            api_key_id: Some(api_key_id.to_string()),
+            model: "deepseek".to_string(),
+            stream: false,
+            prompt: format!("<｜User｜>question {}", id),
+            response: response.to_string(),
+            reasoning: None,
+            finish_reason: Some("stop".to_string()),
+            prompt_tokens: Some(3),
+            completion_tokens: Some(5),
+        }
+    }
+
+    fn store(retention_days: u64, max_records: u64) -> CompletionStore {
+        CompletionStore::open(&HistoryConfig { path: Some(":memory:".to_string()), retention_days, max_records }).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_list_and_search() {
+        let store = store(0, 0);
+        let now = unix_timestamp();
+        store.record(record("a", now - 30, "k1", "Paris is the capital")).await;
+        store.record(record("b", now - 20, "k2", "100% sure")).await;
+        store.record(record("c", now - 10, "k1", "Berlin")).await;
+
+        let (records, total) = store.list(&CompletionQuery::default()).await.unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(records.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), ["c", "b", "a"]);
+
+        let search = |query: CompletionQuery| {
+            let conn = store.conn.as_ref().unwrap().lock();
+            list(&conn, &query).unwrap().0.into_iter().map(|r| r.id).collect::<Vec<_>>()
+        };
+        assert_eq!(search(CompletionQuery { q: Some("paris".to_string()), ..Default::default() }), ["a"]);
+        assert_eq!(search(CompletionQuery { q: Some("100%".to_string()), ..Default::default() }), ["b"]);
+        assert_eq!(search(CompletionQuery { api_key_id: Some("k1".to_string()), limit: Some(1), ..Default::default() }), ["c"]);
+        assert_eq!(search(CompletionQuery { since: Some(now - 25), until: Some(now - 15), ..Default::default() }), ["b"]);
+
+        assert_eq!(store.get("a").await.unwrap(), Some(record("a", now - 30, "k1", "Paris is the capital")));
+        assert!(store.delete("a").await.unwrap());
+        assert_eq!(store.get("a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_prune() {
+        let store = store(1, 2);
+        let now = unix_timestamp();
+        store.record(record("old", now - 2 * 86400, "k1", "x")).await;
+        store.record(record("a", now - 30, "k1", "x")).await;
+        store.record(record("b", now - 20, "k1", "x")).await;
+        store.record(record("c", now - 10, "k1", "x")).await;
+
+        assert_eq!(store.prune().await.unwrap(), 2);
+        let (records, _) = store.list(&CompletionQuery::default()).await.unwrap();
+        assert_eq!(records.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), ["c", "b"]);
+
+        let disabled = CompletionStore::open(&HistoryConfig { path: None, retention_days: 0, max_records: 0 }).unwrap();
+        assert!(disabled.list(&CompletionQuery::default()).await.is_err());
+    }
+}
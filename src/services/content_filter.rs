@@ -0,0 +1,286 @@
+use crate::config::{ContentFilterConfig, FilterMode};
+use crate::error::{ApiError, ApiResult};
+use crate::models::{ChatCompletionResponse, ChatMessage, ChatMessageContent};
+use regex::{Regex, RegexBuilder};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Arc;
+
+/// 正则规则无法确定匹配长度，流式过滤时按这个字符数保留结尾
+const REGEX_HOLDBACK_CHARS: usize = 64;
+
+/// 提示词和补全内容的关键词/正则过滤
+pub struct ContentFilter {
+    patterns: Vec<Regex>,
+    default_mode: FilterMode,
+    holdback: usize, // 最长规则的字符数，流式过滤时保留的结尾长度
+}
+
+impl ContentFilter {
+    /// 加载规则文件：每行一个关键词（不区分大小写），`re:` 开头的行为正则，`#` 开头为注释
+    pub fn load(config: &ContentFilterConfig) -> ApiResult<Self> {
+        let mut patterns = Vec::new();
+        let mut holdback = 0;
+        if let Some(path) = &config.rules_path {
+            let content = fs::read_to_string(path)
+                .map_err(|e| ApiError::ConfigError(format!("无法读取内容过滤规则 {}: {}", path, e)))?;
+            for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+                let pattern = match line.strip_prefix("re:") {
+                    Some(regex) => {
+                        holdback = holdback.max(REGEX_HOLDBACK_CHARS);
+                        regex.to_string()
+                    }
+                    None => {
+                        holdback = holdback.max(line.chars().count());
+                        regex::escape(line)
+                    }
+                };
+                let regex = RegexBuilder::new(&pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| ApiError::ConfigError(format!("内容过滤规则 {:?} 无效: {}", line, e)))?;
+                patterns.push(regex);
+            }
+        }
+
+        Ok(Self {
+            patterns,
+            default_mode: config.mode,
+            holdback,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.patterns.is_empty()
+    }
+
+    pub fn default_mode(&self) -> FilterMode {
+        self.default_mode
+    }
+
+    /// 检查文本：未命中返回None，redact模式返回替换后的文本，reject模式返回错误
+    pub fn apply(&self, text: &str, mode: FilterMode, direction: &str) -> ApiResult<Option<String>> {
+        let matched: Vec<&Regex> = self.patterns.iter().filter(|regex| regex.is_match(text)).collect();
+        if matched.is_empty() {
+            return Ok(None);
+        }
+
+        tracing::warn!(mode = ?mode, rules = matched.len(), "Content filter matched {}", direction);
+        match mode {
+            FilterMode::Reject => Err(ApiError::Forbidden(format!("{}包含被过滤的内容", direction))),
+            FilterMode::Redact => Ok(Some(
+                matched.iter().fold(text.to_string(), |text, regex| regex.replace_all(&text, "***").into_owned()),
+            )),
+            FilterMode::Log => Ok(None),
+        }
+    }
+
+    /// 过滤请求中的消息内容
+    pub fn filter_messages(&self, messages: &mut [ChatMessage], mode: FilterMode) -> ApiResult<()> {
+        for message in messages {
+            match &mut message.content {
+                ChatMessageContent::Text(text) => self.replace(text, mode, "提示词")?,
+                ChatMessageContent::Array(parts) => {
+                    for text in parts.iter_mut().filter_map(|part| part.text.as_mut()) {
+                        self.replace(text, mode, "提示词")?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 过滤非流式补全结果
+    pub fn filter_response(&self, response: &mut ChatCompletionResponse, mode: FilterMode) -> ApiResult<()> {
+        for message in response.choices.iter_mut().filter_map(|choice| choice.message.as_mut()) {
+            if let ChatMessageContent::Text(text) = &mut message.content {
+                self.replace(text, mode, "补全内容")?;
+            }
+            if let Some(reasoning) = message.reasoning_content.as_mut() {
+                self.replace(reasoning, mode, "补全内容")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn replace(&self, text: &mut String, mode: FilterMode, direction: &str) -> ApiResult<()> {
+        if let Some(redacted) = self.apply(text, mode, direction)? {
+            *text = redacted;
+        }
+        Ok(())
+    }
+}
+
+/// 流式补全的过滤状态。每个choice的每个字段保留可能是规则开头的结尾部分，与下一个数据块拼接后再检查，
+/// 避免被拆到两个数据块中的关键词漏过；保留的内容在带finish_reason的数据块、[DONE]或流结束时输出
+pub struct StreamFilter {
+    filter: Arc<ContentFilter>,
+    mode: FilterMode,
+    pending: BTreeMap<(u64, &'static str), String>, // (choice下标, 字段) -> 已检查但尚未发送的结尾
+    template: Option<Value>,                        // 最近一个数据块，用于构造输出保留内容的数据块
+}
+
+impl StreamFilter {
+    pub fn new(filter: Arc<ContentFilter>, mode: FilterMode) -> Self {
+        Self {
+            filter,
+            mode,
+            pending: BTreeMap::new(),
+            template: None,
+        }
+    }
+
+    /// 过滤一个数据块（可带SSE的`data: `前缀），返回可以发送的数据块；无法解析的数据（如[DONE]）原样返回
+    pub fn push(&mut self, data: String) -> ApiResult<Vec<String>> {
+        let payload = data.strip_prefix("data: ").unwrap_or(&data).trim_end();
+        let Ok(mut chunk) = serde_json::from_str::<Value>(payload) else {
+            return Ok(self.finish().into_iter().chain([data]).collect());
+        };
+        let Some(choices) = chunk.get_mut("choices").and_then(Value::as_array_mut) else {
+            return Ok(vec![data]);
+        };
+
+        for (position, choice) in choices.iter_mut().enumerate() {
+            let index = choice.get("index").and_then(Value::as_u64).unwrap_or(position as u64);
+            let finished = choice.get("finish_reason").is_some_and(|reason| !reason.is_null());
+            let Some(delta) = choice.get_mut("delta").and_then(Value::as_object_mut) else {
+                continue;
+            };
+            for field in ["content", "reasoning_content"] {
+                let text = delta.get(field).and_then(Value::as_str);
+                if text.is_none() && !(finished && self.pending.contains_key(&(index, field))) {
+                    continue;
+                }
+                let mut buffer = self.pending.remove(&(index, field)).unwrap_or_default();
+                buffer.push_str(text.unwrap_or_default());
+                if let Some(redacted) = self.filter.apply(&buffer, self.mode, "补全内容")? {
+                    buffer = redacted;
+                }
+                if !finished {
+                    let tail = buffer.split_off(self.holdback_start(&buffer));
+                    if !tail.is_empty() {
+                        self.pending.insert((index, field), tail);
+                    }
+                }
+                delta.insert(field.to_string(), Value::String(buffer));
+            }
+        }
+
+        self.template = Some(chunk.clone());
+        let prefix = if data.starts_with("data: ") { "data: " } else { "" };
+        let suffix = &data[data.trim_end().len()..];
+        Ok(vec![format!("{}{}{}", prefix, chunk, suffix)])
+    }
+
+    /// 输出所有保留的内容，没有保留内容时返回None
+    pub fn finish(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let mut chunk = self.template.clone()?;
+        let mut choices: BTreeMap<u64, serde_json::Map<String, Value>> = BTreeMap::new();
+        for ((index, field), text) in std::mem::take(&mut self.pending) {
+            choices.entry(index).or_default().insert(field.to_string(), Value::String(text));
+        }
+        chunk["choices"] = choices
+            .into_iter()
+            .map(|(index, delta)| json!({ "index": index, "delta": delta, "finish_reason": null }))
+            .collect();
+        if let Some(object) = chunk.as_object_mut() {
+            object.remove("x_upstream");
+        }
+        Some(format!("data: {}\n\n", chunk))
+    }
+
+    /// 保留部分的起始位置：最长规则的字符数减一，更长的匹配在本次检查中已经完整出现
+    fn holdback_start(&self, text: &str) -> usize {
+        match self.filter.holdback.saturating_sub(1) {
+            0 => text.len(),
+            keep => text.char_indices().rev().nth(keep - 1).map_or(0, |(i, _)| i),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(rules: &str) -> ContentFilter {
+        let path = std::env::temp_dir().join(format!("content-filter-{}.txt", uuid::Uuid::new_v4()));
+        fs::write(&path, rules).unwrap();
+        let filter = ContentFilter::load(&ContentFilterConfig {
+            rules_path: Some(path.to_string_lossy().to_string()),
+            mode: FilterMode::Reject,
+        })
+        .unwrap();
+        let _ = fs::remove_file(&path);
+        filter
+    }
+
+    #[test]
+    fn test_filter_modes() {
+        let filter = filter("# comment\nSecret Project\nre:\\b\\d{3}-\\d{4}\\b\n");
+
+        assert_eq!(filter.apply("hello", FilterMode::Reject, "提示词").unwrap(), None);
+        assert!(filter.apply("about the secret project", FilterMode::Reject, "提示词").is_err());
+        assert_eq!(
+            filter.apply("call 555-1234 re: SECRET PROJECT", FilterMode::Redact, "提示词").unwrap().as_deref(),
+            Some("call *** re: ***")
+        );
+        assert_eq!(filter.apply("secret project", FilterMode::Log, "提示词").unwrap(), None);
+    }
+
+    fn content_chunk(content: &str, finish_reason: Option<&str>) -> String {
+        format!(
+            "data: {}\n\n",
+            json!({"id": "c", "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": finish_reason}]})
+        )
+    }
+
+    fn streamed_content(chunks: &[String]) -> String {
+        chunks
+            .iter()
+            .filter_map(|chunk| serde_json::from_str::<Value>(chunk.strip_prefix("data: ")?.trim_end()).ok())
+            .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str().map(str::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_stream_chunk() {
+        let filter = Arc::new(filter("forbidden\n"));
+        let mut stream = StreamFilter::new(filter.clone(), FilterMode::Redact);
+        let framed = stream.push(content_chunk("a forbidden word", None)).unwrap();
+        assert!(framed[0].starts_with("data: {") && framed[0].ends_with("}\n\n"));
+        let mut output = framed;
+        output.extend(stream.push("data: [DONE]\n\n".to_string()).unwrap());
+        assert_eq!(output.last().unwrap(), "data: [DONE]\n\n");
+        assert_eq!(streamed_content(&output), "a *** word");
+
+        let mut stream = StreamFilter::new(filter, FilterMode::Reject);
+        assert_eq!(stream.push("[DONE]".to_string()).unwrap(), vec!["[DONE]".to_string()]);
+    }
+
+    #[test]
+    fn test_filter_stream_keyword_split_across_chunks() {
+        let filter = Arc::new(filter("forbidden\n"));
+        let mut stream = StreamFilter::new(filter.clone(), FilterMode::Redact);
+        let mut output = Vec::new();
+        for part in ["a forb", "id", "den word, ", "fine"] {
+            output.extend(stream.push(content_chunk(part, None)).unwrap());
+        }
+        output.extend(stream.push(content_chunk("", Some("stop"))).unwrap());
+        assert_eq!(streamed_content(&output), "a *** word, fine");
+        assert_eq!(stream.finish(), None);
+
+        // 没有finish_reason和[DONE]时由流结束输出保留的内容
+        let mut stream = StreamFilter::new(filter.clone(), FilterMode::Redact);
+        let mut output = stream.push(content_chunk("tail", None)).unwrap();
+        output.extend(stream.finish());
+        assert_eq!(streamed_content(&output), "tail");
+
+        let mut stream = StreamFilter::new(filter, FilterMode::Reject);
+        assert!(stream.push(content_chunk("forbi", None)).is_ok());
+        assert!(stream.push(content_chunk("dden", None)).is_err());
+    }
+}
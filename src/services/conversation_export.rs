@@ -0,0 +1,144 @@
+use crate::models::{ChatHistory, HistoryMessage};
+use regex::Regex;
+use serde_json::{json, Value};
+
+/// 对话导出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Markdown,
+}
+
+impl ExportFormat {
+    pub fn parse(format: &str) -> Option<Self> {
+        match format.trim().to_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "markdown" | "md" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// 上游角色名转为OpenAI风格
+fn role_name(message: &HistoryMessage) -> String {
+    message.role.to_lowercase()
+}
+
+/// 正文中的 `[citation:N]` 转为 `[N]`，与引用列表对应
+fn render_citations(content: &str) -> String {
+    let citation_regex = Regex::new(r"\[citation:(\d+)\]").unwrap();
+    citation_regex.replace_all(content, "[$1]").to_string()
+}
+
+/// 消息引用的搜索结果：(编号, 标题, 链接)，只保留正文中引用过的条目
+fn citations(message: &HistoryMessage) -> Vec<(u32, &str, &str)> {
+    let mut cited: Vec<_> = message
+        .search_results
+        .iter()
+        .flatten()
+        .filter_map(|result| {
+            let index = result.cite_index?;
+            message
+                .content
+                .contains(&format!("[citation:{}]", index))
+                .then_some((index, result.title.as_str(), result.url.as_str()))
+        })
+        .collect();
+    cited.sort_by_key(|(index, _, _)| *index);
+    cited.dedup_by_key(|(index, _, _)| *index);
+    cited
+}
+
+/// 导出为JSON：每条消息包含角色、正文、思考过程和引用
+pub fn to_json(conversation_id: &str, history: &ChatHistory) -> Value {
+    let messages: Vec<Value> = history
+        .chat_messages
+        .iter()
+        .map(|message| {
+            json!({
+                "role": role_name(message),
+                "content": render_citations(&message.content),
+                "reasoning_content": message.thinking_content.as_deref().filter(|text| !text.is_empty()),
+                "citations": citations(message)
+                    .into_iter()
+                    .map(|(index, title, url)| json!({"index": index, "title": title, "url": url}))
+                    .collect::<Vec<_>>(),
+                "created_at": message.inserted_at.map(|time| time as u64),
+            })
+        })
+        .collect();
+
+    json!({
+        "id": conversation_id,
+        "object": "conversation.export",
+        "title": history.chat_session.as_ref().and_then(|session| session.title.as_deref()),
+        "messages": messages
+    })
+}
+
+/// 导出为Markdown：思考过程放在折叠块中，引用列在每条回复之后
+pub fn to_markdown(history: &ChatHistory) -> String {
+    let title = history
+        .chat_session
+        .as_ref()
+        .and_then(|session| session.title.as_deref())
+        .filter(|title| !title.is_empty())
+        .unwrap_or("DeepSeek对话");
+    let mut output = format!("# {}\n", title);
+
+    for message in &history.chat_messages {
+        let heading = if role_name(message) == "user" { "用户" } else { "助手" };
+        output.push_str(&format!("\n## {}\n\n", heading));
+
+        if let Some(thinking) = message.thinking_content.as_deref().filter(|text| !text.trim().is_empty()) {
+            output.push_str(&format!("<details><summary>思考过程</summary>\n\n{}\n\n</details>\n\n", thinking.trim()));
+        }
+        output.push_str(render_citations(&message.content).trim());
+        output.push('\n');
+
+        let cited = citations(message);
+        if !cited.is_empty() {
+            output.push_str("\n引用：\n");
+            for (index, title, url) in cited {
+                output.push_str(&format!("{}. [{}]({})\n", index, title, url));
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_includes_reasoning_and_citations() {
+        let history: ChatHistory = serde_json::from_value(json!({
+            "chat_session": {"id": "s", "title": "天气"},
+            "chat_messages": [
+                {"message_id": 1, "role": "USER", "content": "今天天气？"},
+                {
+                    "message_id": 2,
+                    "role": "ASSISTANT",
+                    "content": "晴天[citation:2]",
+                    "thinking_content": "查一下",
+                    "search_results": [
+                        {"url": "https://a.example", "title": "A", "cite_index": 1},
+                        {"url": "https://b.example", "title": "B", "cite_index": 2}
+                    ]
+                }
+            ]
+        }))
+        .unwrap();
+
+        let markdown = to_markdown(&history);
+        assert!(markdown.starts_with("# 天气\n"));
+        assert!(markdown.contains("<details><summary>思考过程</summary>\n\n查一下"));
+        assert!(markdown.contains("晴天[2]\n\n引用：\n2. [B](https://b.example)\n"));
+        assert!(!markdown.contains("a.example"));
+
+        let exported = to_json("s@2", &history);
+        assert_eq!(exported["messages"][1]["citations"][0]["url"], "https://b.example");
+        assert_eq!(exported["messages"][0]["reasoning_content"], Value::Null);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::services::credential_vault::CredentialVault;
+use crate::utils::{fill_random, generate_random_string, generate_uuid_simple, unix_timestamp_ms};
+use base64::{engine::general_purpose, Engine as _};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// 超过该时间没有请求视为一次新的访问（与百度统计的会话超时一致）
+const VISIT_TIMEOUT_MS: u64 = 30 * 60 * 1000;
+
+/// Hm_lvt最多保留的访问时间数
+const MAX_VISITS: usize = 4;
+
+/// 单个账号的设备身份（登录用的device_id和浏览器cookie），生成一次后长期沿用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CookieIdentity {
+    #[serde(default = "generate_device_id")]
+    device_id: String,      // 登录时上报的设备ID
+    waf_session_id: String,
+    waf_session_time: u64,  // 毫秒
+    hm_site_id: String,
+    visits: Vec<u64>,       // 最近几次访问的开始时间（秒）
+    frid: String,           // 设备标识，不会变化
+    fr_session_id: String,  // 每次访问重新生成
+    fr_pageview_id: String, // 每次访问重新生成
+    #[serde(skip)]
+    last_seen_ms: u64,
+}
+
+impl CookieIdentity {
+    fn new(now_ms: u64) -> Self {
+        Self {
+            device_id: generate_device_id(),
+            waf_session_id: generate_random_string(18, "hex"),
+            waf_session_time: now_ms,
+            hm_site_id: generate_uuid_simple(),
+            visits: vec![now_ms / 1000],
+            frid: generate_uuid_simple(),
+            fr_session_id: generate_uuid_simple(),
+            fr_pageview_id: generate_uuid_simple(),
+            last_seen_ms: now_ms,
+        }
+    }
+
+    /// 记录一次请求，距上次请求超过访问超时时开始新的访问，返回身份是否需要保存
+    fn touch(&mut self, now_ms: u64) -> bool {
+        let last_seen = self.last_seen_ms.max(self.visits.last().map_or(0, |visit| visit * 1000));
+        self.last_seen_ms = now_ms;
+        if now_ms.saturating_sub(last_seen) <= VISIT_TIMEOUT_MS {
+            return false;
+        }
+
+        self.waf_session_id = generate_random_string(18, "hex");
+        self.waf_session_time = now_ms;
+        self.visits.push(now_ms / 1000);
+        if self.visits.len() > MAX_VISITS {
+            self.visits.remove(0);
+        }
+        self.fr_session_id = generate_uuid_simple();
+        self.fr_pageview_id = generate_uuid_simple();
+        true
+    }
+
+    fn header(&self) -> String {
+        let visits = self.visits.iter().map(u64::to_string).collect::<Vec<_>>().join(",");
+        format!(
+            "intercom-HWWAFSESTIME={}; HWWAFSESID={}; Hm_lvt_{}={}; Hm_lpvt_{}={}; _frid={}; _fr_ssid={}; _fr_pvid={}",
+            self.waf_session_time,
+            self.waf_session_id,
+            self.hm_site_id,
+            visits,
+            self.hm_site_id,
+            self.last_seen_ms / 1000,
+            self.frid,
+            self.fr_session_id,
+            self.fr_pageview_id
+        )
+    }
+}
+
+/// 生成网页客户端格式的设备ID
+fn generate_device_id() -> String {
+    let mut bytes = [0u8; 48];
+    fill_random(&mut bytes);
+    general_purpose::STANDARD.encode(bytes)
+}
+
+/// 账号标识（userToken或登录邮箱）的哈希，文件中不保存明文
+fn identity_key(account: &str) -> String {
+    hex::encode(Sha256::digest(account.as_bytes()))
+}
+
+/// 按账号持久化的设备身份，文件中只保存userToken或邮箱的哈希
+pub struct CookieStore {
+    path: Option<String>,
+    identities: Mutex<HashMap<String, CookieIdentity>>,
+    vault: Option<Arc<CredentialVault>>, // 配置了凭据库主密钥时加密保存在凭据库中，不再写明文文件
+}
+
+impl CookieStore {
+    /// 路径为空时只保存在内存中
+    pub fn open(path: &str) -> Self {
+        let path = Some(path.to_string()).filter(|path| !path.trim().is_empty());
+        let identities = path
+            .as_deref()
+            .filter(|path| Path::new(path).exists())
+            .map(|path| {
+                fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|content| serde_json::from_str(&content).map_err(|e| e.to_string()))
+                    .unwrap_or_else(|e| {
+                        warn!("加载cookie存储失败，重新生成: {}", e);
+                        HashMap::new()
+                    })
+            })
+            .unwrap_or_default();
+
+        Self {
+            path,
+            identities: Mutex::new(identities),
+            vault: None,
+        }
+    }
+
+    /// 凭据库已解锁时改为加密保存在凭据库中；凭据库中还没有cookie时迁移明文文件的内容并删除明文文件
+    pub fn with_vault(mut self, vault: Arc<CredentialVault>) -> Self {
+        if !vault.is_enabled() {
+            return self;
+        }
+        match vault.cookies().map(serde_json::from_value::<HashMap<String, CookieIdentity>>) {
+            Some(Ok(identities)) => *self.identities.get_mut() = identities,
+            Some(Err(e)) => warn!("凭据库中的cookie数据无效，使用明文文件: {}", e),
+            None => {}
+        }
+        self.vault = Some(vault);
+        self.save_or_warn(&self.identities.lock());
+        if let Some(path) = self.path.as_deref().filter(|path| Path::new(path).exists()) {
+            match fs::remove_file(path) {
+                Ok(()) => info!("cookie存储已迁移到凭据库，删除明文文件 {}", path),
+                Err(e) => warn!("删除明文cookie文件失败 {}: {}", path, e),
+            }
+        }
+        self
+    }
+
+    /// 返回账号的设备ID，没有时生成
+    pub fn device_id(&self, account: &str) -> String {
+        let mut identities = self.identities.lock();
+        if let Some(identity) = identities.get(&identity_key(account)) {
+            return identity.device_id.clone();
+        }
+        let identity = CookieIdentity::new(unix_timestamp_ms());
+        let device_id = identity.device_id.clone();
+        identities.insert(identity_key(account), identity);
+        self.save_or_warn(&identities);
+        device_id
+    }
+
+    /// 登录成功后让userToken沿用登录时（按邮箱）的设备身份，重新登录得到新token时也保持同一设备
+    pub fn link(&self, email: &str, token: &str) {
+        let mut identities = self.identities.lock();
+        if let Some(identity) = identities.get(&identity_key(email)).cloned() {
+            identities.insert(identity_key(token), identity);
+            self.save_or_warn(&identities);
+        }
+    }
+
+    /// 返回账号当前的Cookie请求头，并推进访问时间
+    pub fn cookie_for(&self, account: &str) -> String {
+        let key = identity_key(account);
+        let now_ms = unix_timestamp_ms();
+
+        let mut identities = self.identities.lock();
+        let mut changed = false;
+        let identity = identities.entry(key).or_insert_with(|| {
+            changed = true;
+            CookieIdentity::new(now_ms)
+        });
+        changed |= identity.touch(now_ms);
+        let header = identity.header();
+
+        if changed {
+            self.save_or_warn(&identities);
+        }
+        header
+    }
+
+    fn save_or_warn(&self, identities: &HashMap<String, CookieIdentity>) {
+        let result = match &self.vault {
+            Some(vault) => serde_json::to_value(identities)
+                .map_err(Into::into)
+                .and_then(|cookies| vault.store_cookies(cookies))
+                .map_err(|e| e.to_string()),
+            None => self.save(identities).map_err(|e| e.to_string()),
+        };
+        if let Err(e) = result {
+            warn!("保存cookie存储失败: {}", e);
+        }
+    }
+
+    fn save(&self, identities: &HashMap<String, CookieIdentity>) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = format!("{}.tmp", path);
+        fs::write(&temp_path, serde_json::to_string_pretty(identities)?)?;
+        fs::rename(&temp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_evolves_across_visits() {
+        let start = 1_700_000_000_000;
+        let mut identity = CookieIdentity::new(start);
+        let frid = identity.frid.clone();
+        let session = identity.fr_session_id.clone();
+
+        assert!(!identity.touch(start + 60_000));
+        assert_eq!(identity.fr_session_id, session);
+        assert!(identity.header().contains(&format!("Hm_lpvt_{}={}", identity.hm_site_id, (start + 60_000) / 1000)));
+
+        assert!(identity.touch(start + 2 * VISIT_TIMEOUT_MS));
+        assert_ne!(identity.fr_session_id, session);
+        assert_eq!(identity.frid, frid);
+        assert_eq!(identity.visits.len(), 2);
+    }
+
+    #[test]
+    fn test_login_identity_is_linked_to_token() {
+        let store = CookieStore::open("");
+        let device_id = store.device_id("user@example.com");
+        let login_cookie = store.cookie_for("user@example.com");
+        store.link("user@example.com", "token-1");
+
+        assert_eq!(store.device_id("token-1"), device_id);
+        let frid = |cookie: &str| cookie.split("_frid=").nth(1).unwrap().split(';').next().unwrap().to_string();
+        assert_eq!(frid(&store.cookie_for("token-1")), frid(&login_cookie));
+    }
+
+    #[test]
+    fn test_identities_move_into_unlocked_vault() {
+        let dir = std::env::temp_dir();
+        let cookies = dir.join(format!("cookies-{}.json", uuid::Uuid::new_v4()));
+        let vault_config = crate::config::CredentialsConfig {
+            path: dir.join(format!("credentials-{}.enc", uuid::Uuid::new_v4())).to_string_lossy().to_string(),
+            master_key: Some("correct horse battery staple".to_string()),
+            master_key_file: None,
+        };
+
+        let device_id = CookieStore::open(cookies.to_str().unwrap()).device_id("user@example.com");
+        assert!(cookies.exists());
+
+        let vault = Arc::new(CredentialVault::open(&vault_config).unwrap());
+        let store = CookieStore::open(cookies.to_str().unwrap()).with_vault(vault);
+        assert!(!cookies.exists());
+        assert_eq!(store.device_id("user@example.com"), device_id);
+
+        // 重启后从凭据库读取，明文文件不再生成
+        let vault = Arc::new(CredentialVault::open(&vault_config).unwrap());
+        let store = CookieStore::open(cookies.to_str().unwrap()).with_vault(vault);
+        assert_eq!(store.device_id("user@example.com"), device_id);
+        store.cookie_for("token-1");
+        assert!(!cookies.exists());
+
+        let _ = fs::remove_file(&vault_config.path);
+    }
+}
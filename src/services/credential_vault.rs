@@ -0,0 +1,276 @@
+use crate::config::CredentialsConfig;
+use crate::error::{AppError, AppResult};
+use crate::utils::unix_timestamp;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::Argon2;
+use base64::{engine::general_purpose, Engine as _};
+use parking_lot::{Mutex, RwLock};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+/// 保存的账号凭据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredCredential {
+    pub email: String,
+    pub password: String,
+    pub updated_at: u64,
+}
+
+/// 解密后的凭据库内容；版本1的文件只有账号凭据
+#[derive(Default, Serialize, Deserialize)]
+struct VaultContents {
+    accounts: BTreeMap<String, StoredCredential>, // email -> 凭据
+    #[serde(default)]
+    cookies: Option<Value>, // 配置主密钥后CookieStore的设备身份和cookie保存在这里
+}
+
+/// 当前的凭据文件版本
+const VAULT_VERSION: u32 = 2;
+
+/// AES-GCM的nonce长度（字节）
+const NONCE_LEN: usize = 12;
+
+/// 凭据文件格式：Argon2id从主密钥派生AES-256-GCM密钥，每次保存使用新的nonce
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// 加密的账号凭据库，与api_keys.json分开存放，用于token失效后自动重新登录
+pub struct CredentialVault {
+    path: String,
+    cipher: Option<(Aes256Gcm, Vec<u8>)>, // (密钥, salt)，None表示未配置主密钥
+    accounts: RwLock<BTreeMap<String, StoredCredential>>, // email -> 凭据
+    cookies: RwLock<Option<Value>>,
+    save_lock: Mutex<()>, // 保存依次进行，避免并发写同一个临时文件
+}
+
+impl CredentialVault {
+    /// 未配置主密钥时不保存任何凭据
+    pub fn disabled() -> Self {
+        Self {
+            path: String::new(),
+            cipher: None,
+            accounts: RwLock::new(BTreeMap::new()),
+            cookies: RwLock::new(None),
+            save_lock: Mutex::new(()),
+        }
+    }
+
+    /// 使用主密钥解锁凭据库，文件不存在时创建新的凭据库
+    pub fn open(config: &CredentialsConfig) -> AppResult<Self> {
+        let master_key = match (&config.master_key, &config.master_key_file) {
+            (Some(key), _) => key.clone(),
+            (None, Some(file)) => fs::read_to_string(file)
+                .map_err(|e| AppError::ConfigError(format!("无法读取主密钥文件 {}: {}", file, e)))?
+                .trim()
+                .to_string(),
+            (None, None) => return Ok(Self::disabled()),
+        };
+        if master_key.is_empty() {
+            return Err(AppError::ConfigError("凭据库主密钥不能为空".to_string()));
+        }
+
+        let (salt, contents) = if Path::new(&config.path).exists() {
+            let content = fs::read_to_string(&config.path)?;
+            let file: VaultFile = serde_json::from_str(&content)?;
+            let salt = decode(&file.salt)?;
+            let nonce = decode(&file.nonce)?;
+            if nonce.len() != NONCE_LEN {
+                return Err(AppError::ConfigError(format!("凭据文件格式错误: nonce长度应为{}字节", NONCE_LEN)));
+            }
+            let cipher = derive_cipher(master_key.as_bytes(), &salt)?;
+            let plaintext = cipher
+                .decrypt(Nonce::from_slice(&nonce), decode(&file.ciphertext)?.as_ref())
+                .map_err(|_| AppError::ConfigError("无法解锁凭据库：主密钥错误或文件已损坏".to_string()))?;
+            let contents = match file.version {
+                1 => VaultContents {
+                    accounts: serde_json::from_slice(&plaintext)?,
+                    cookies: None,
+                },
+                _ => serde_json::from_slice(&plaintext)?,
+            };
+            (salt, contents)
+        } else {
+            let mut salt = vec![0u8; 16];
+            rand::thread_rng().fill_bytes(&mut salt);
+            (salt, VaultContents::default())
+        };
+
+        let cipher = derive_cipher(master_key.as_bytes(), &salt)?;
+        let vault = Self {
+            path: config.path.clone(),
+            cipher: Some((cipher, salt)),
+            accounts: RwLock::new(contents.accounts),
+            cookies: RwLock::new(contents.cookies),
+            save_lock: Mutex::new(()),
+        };
+        info!("凭据库已解锁: {} ({} 个账号)", vault.path, vault.accounts.read().len());
+        Ok(vault)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// 保存或更新账号密码
+    pub fn store(&self, email: &str, password: &str) -> AppResult<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        self.accounts.write().insert(
+            email.to_string(),
+            StoredCredential {
+                email: email.to_string(),
+                password: password.to_string(),
+                updated_at: unix_timestamp(),
+            },
+        );
+        self.save()
+    }
+
+    /// 删除账号凭据
+    pub fn remove(&self, email: &str) -> AppResult<()> {
+        if self.accounts.write().remove(email).is_none() {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    /// 列出保存了凭据的邮箱和更新时间，不返回密码
+    pub fn list(&self) -> Vec<(String, u64)> {
+        self.accounts
+            .read()
+            .values()
+            .map(|credential| (credential.email.clone(), credential.updated_at))
+            .collect()
+    }
+
+    /// 获取账号密码
+    pub fn password(&self, email: &str) -> Option<String> {
+        self.accounts.read().get(email).map(|credential| credential.password.clone())
+    }
+
+    /// 保存的cookie数据，没有时返回None
+    pub fn cookies(&self) -> Option<Value> {
+        self.cookies.read().clone()
+    }
+
+    /// 加密保存cookie数据
+    pub fn store_cookies(&self, cookies: Value) -> AppResult<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        *self.cookies.write() = Some(cookies);
+        self.save()
+    }
+
+    /// 加密后写入文件，先写临时文件再重命名，避免中途失败损坏凭据库
+    fn save(&self) -> AppResult<()> {
+        let Some((cipher, salt)) = &self.cipher else {
+            return Ok(());
+        };
+
+        let _guard = self.save_lock.lock();
+        let plaintext = serde_json::to_vec(&serde_json::json!({
+            "accounts": &*self.accounts.read(),
+            "cookies": &*self.cookies.read(),
+        }))?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| AppError::Internal("加密凭据失败".to_string()))?;
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            salt: general_purpose::STANDARD.encode(salt),
+            nonce: general_purpose::STANDARD.encode(nonce),
+            ciphertext: general_purpose::STANDARD.encode(ciphertext),
+        };
+
+        if let Some(parent) = Path::new(&self.path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = format!("{}.tmp", self.path);
+        fs::write(&temp_path, serde_json::to_string_pretty(&file)?)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&temp_path, fs::Permissions::from_mode(0o600))?;
+        }
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+fn derive_cipher(master_key: &[u8], salt: &[u8]) -> AppResult<Aes256Gcm> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(master_key, salt, &mut key)
+        .map_err(|e| AppError::Internal(format!("派生凭据库密钥失败: {}", e)))?;
+    Ok(Aes256Gcm::new(&key.into()))
+}
+
+fn decode(value: &str) -> AppResult<Vec<u8>> {
+    general_purpose::STANDARD
+        .decode(value)
+        .map_err(|e| AppError::ConfigError(format!("凭据文件格式错误: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_round_trip_and_wrong_key() {
+        let path = std::env::temp_dir().join(format!("credentials-{}.enc", uuid::Uuid::new_v4()));
+        let mut config = CredentialsConfig {
+            path: path.to_string_lossy().to_string(),
+            master_key: Some("correct horse battery staple".to_string()),
+            master_key_file: None,
+        };
+
+        let vault = CredentialVault::open(&config).unwrap();
+        vault.store("user@example.com", "hunter2").unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("hunter2"));
+
+        vault.store_cookies(serde_json::json!({"device": "secret-cookie"})).unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("secret-cookie"));
+
+        let reopened = CredentialVault::open(&config).unwrap();
+        assert_eq!(reopened.password("user@example.com").as_deref(), Some("hunter2"));
+        assert_eq!(reopened.cookies(), Some(serde_json::json!({"device": "secret-cookie"})));
+
+        config.master_key = Some("wrong key".to_string());
+        assert!(CredentialVault::open(&config).is_err());
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_truncated_nonce_is_an_error() {
+        let path = std::env::temp_dir().join(format!("credentials-{}.enc", uuid::Uuid::new_v4()));
+        let config = CredentialsConfig {
+            path: path.to_string_lossy().to_string(),
+            master_key: Some("correct horse battery staple".to_string()),
+            master_key_file: None,
+        };
+        CredentialVault::open(&config).unwrap().store("user@example.com", "hunter2").unwrap();
+
+        let mut file: VaultFile = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        file.nonce = general_purpose::STANDARD.encode([0u8; 4]);
+        fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(matches!(CredentialVault::open(&config), Err(AppError::ConfigError(_))));
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1,37 +1,80 @@
 use crate::config::Config;
 use crate::error::{ApiError, ApiResult};
 use crate::models::*;
-use crate::services::{ChallengeSolver, MessageProcessor, TokenManager};
+use crate::services::chaos::CHAOS_HEADER;
+use crate::services::request_context::{STAGE_POW, STAGE_SESSION, STAGE_TOKEN, STAGE_UPSTREAM};
+use crate::services::{
+    BrowserProfiles, ChallengeCache, ChallengeSolver, CookieStore, MessageProcessor, MirrorSelector, Pacer,
+    QuotaCache, RequestContext, RetryPolicy, SessionPrewarmer, StreamProcessor, TokenManager, TraceContext, WireLog,
+};
+use crate::services::plugins::PluginHost;
 use crate::utils::{
-    generate_cookie, is_search_model, is_thinking_model,
-    parse_conversation_id, unix_timestamp,
+    extract_app_version, extract_script_paths, generate_uuid_simple, parse_conversation_id, parse_data_uri, unix_timestamp,
 };
-use futures_util::Stream;
+use futures_util::{Stream, StreamExt};
+use rand::Rng;
 use reqwest::Client;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 
+/// 上传文件后等待解析完成的最大轮询次数（每秒一次）
+const FILE_PARSE_POLL_ATTEMPTS: usize = 30;
+
+/// 探测X-App-Version时最多检查的脚本数
+const MAX_VERSION_SCRIPTS: usize = 5;
+
+/// 新账号预热时各步骤之间的停顿（秒），模拟真人浏览和输入
+const WARMUP_PAUSE_SECS: std::ops::RangeInclusive<u64> = 2..=6;
+
 /// DeepSeek客户端
 pub struct DeepSeekClient {
     client: Client,
     config: Config,
-    token_manager: TokenManager,
-    challenge_solver: ChallengeSolver,
+    token_manager: Arc<TokenManager>,
+    challenge_solver: Arc<ChallengeSolver>,
     message_processor: MessageProcessor,
+    session_prewarmer: Arc<SessionPrewarmer>,
+    challenge_cache: Arc<ChallengeCache>,
+    quota_cache: Arc<QuotaCache>,
+    retry_policy: RetryPolicy,
+    profiles: Arc<BrowserProfiles>,
+    pacer: Arc<Pacer>,
+    cookies: Arc<CookieStore>,
+    mirrors: Arc<MirrorSelector>,
+    wire_log: Arc<WireLog>,
+    plugins: Arc<PluginHost>,
 }
 
 impl DeepSeekClient {
-    pub fn new(config: Config) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(120))
-            .build()
-            .unwrap();
-
-        let token_manager = TokenManager::new(client.clone(), config.deepseek.access_token_expires);
-        let challenge_solver = ChallengeSolver::new(config.deepseek.wasm_path.clone());
+    pub fn new(
+        config: Config,
+        quota_cache: Arc<QuotaCache>,
+        profiles: Arc<BrowserProfiles>,
+        cookies: Arc<CookieStore>,
+    ) -> Self {
+        let client = config.http.build_client().expect("failed to build HTTP client");
+
+        let mirrors = Arc::new(MirrorSelector::from_config(&config.deepseek));
+        let token_manager = Arc::new(TokenManager::new(
+            client.clone(),
+            config.deepseek.access_token_expires,
+            profiles.clone(),
+            cookies.clone(),
+            mirrors.clone(),
+        ));
+        let challenge_solver = Arc::new(ChallengeSolver::new(config.deepseek.wasm_path.clone()));
         let message_processor = MessageProcessor;
+        let session_prewarmer = Arc::new(SessionPrewarmer::new(
+            config.deepseek.prewarm_sessions,
+            config.deepseek.prewarm_session_ttl_secs,
+        ));
+        let challenge_cache = Arc::new(ChallengeCache::new(config.deepseek.challenge_reuse_secs));
+        let retry_policy = RetryPolicy::from_config(&config.deepseek);
+        let pacer = Arc::new(Pacer::from_config(&config.deepseek));
+        let wire_log = Arc::new(WireLog::new(config.deepseek.wire_log));
 
         Self {
             client,
@@ -39,9 +82,25 @@ impl DeepSeekClient {
             token_manager,
             challenge_solver,
             message_processor,
+            session_prewarmer,
+            challenge_cache,
+            quota_cache,
+            retry_policy,
+            profiles,
+            pacer,
+            cookies,
+            mirrors,
+            wire_log,
+            plugins: Arc::new(PluginHost::default()),
         }
     }
 
+    /// 使用WASM插件的before_upstream钩子改写发往上游的提示词
+    pub fn with_plugins(mut self, plugins: Arc<PluginHost>) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
     /// 创建聊天完成
     pub async fn create_completion(
         &self,
@@ -49,25 +108,15 @@ impl DeepSeekClient {
         messages: &[ChatMessage],
         token: &str,
         conversation_id: Option<&str>,
+        options: CompletionOptions,
+        ctx: &RequestContext,
     ) -> ApiResult<ChatCompletionResponse> {
-        let mut retry_count = 0;
-        let max_retries = self.config.deepseek.max_retry_count;
-
-        loop {
-            match self
-                .try_create_completion(model, messages, token, conversation_id)
-                .await
-            {
-                Ok(response) => return Ok(response),
-                Err(e) if retry_count < max_retries => {
-                    tracing::warn!("Completion failed, retrying: {}", e);
-                    retry_count += 1;
-                    tokio::time::sleep(Duration::from_millis(self.config.deepseek.retry_delay_ms))
-                        .await;
-                }
-                Err(e) => return Err(e),
-            }
-        }
+        self.retry_policy
+            .with_max_retries(options.overrides.max_retries)
+            .run(ctx, "Completion", || {
+                self.try_create_completion(model, messages, token, conversation_id, options, ctx)
+            })
+            .await
     }
 
     /// 尝试创建聊天完成
@@ -77,6 +126,8 @@ impl DeepSeekClient {
         messages: &[ChatMessage],
         token: &str,
         conversation_id: Option<&str>,
+        options: CompletionOptions,
+        ctx: &RequestContext,
     ) -> ApiResult<ChatCompletionResponse> {
         tracing::info!("Creating completion for model: {}", model);
 
@@ -87,56 +138,71 @@ impl DeepSeekClient {
             (None, None)
         };
 
-        // 消息预处理
-        let prompt = MessageProcessor::prepare_messages(messages);
+        // 消息预处理，内联图片上传后通过ref_file_ids引用
+        let mut prompt = MessageProcessor::prepare_messages(messages, &self.config.prompt, options.continue_final_message);
+        self.plugins.before_upstream(model, &mut prompt)?;
+        let ref_file_ids = self.upload_inline_images(token, messages).await?;
         
-        // 检查模型类型
-        let is_search = is_search_model(model) || prompt.contains("联网搜索");
-        let is_thinking = is_thinking_model(model) || prompt.contains("深度思考");
-
-        // 检查深度思考配额
-        if is_thinking {
-            let quota = self.get_thinking_quota(token).await?;
-            if quota <= 0 {
+        // 请求中的功能开关优先，其余按模型类型推断
+        let features = options.features.resolve(model, &prompt);
+
+        // 检查深度思考配额，优先使用后台轮询缓存的配额
+        if features.thinking {
+            let quota = match self.quota_cache.thinking_remaining(token) {
+                Some(quota) => quota,
+                None => self.fetch_thinking_quota(token).await?,
+            };
+            if quota == 0 {
                 return Err(ApiError::ServiceUnavailable("深度思考配额不足".to_string()));
             }
         }
 
         // 获取POW挑战并解决
-        let challenge_response = self.get_challenge(token, "/api/v0/chat/completion").await?;
-        let challenge_answer = self
-            .challenge_solver
-            .solve_challenge(&challenge_response.challenge, "/api/v0/chat/completion")
-            .await?;
+        let challenge_answer = ctx.timed(STAGE_POW, self.solve_pow(token, "/api/v0/chat/completion")).await?;
 
-        // 创建会话
+        // 创建会话（优先领取预热会话）
         let session_id = if let Some(id) = ref_session_id {
             id
+        } else if let Some(id) = self.session_prewarmer.claim(token) {
+            id
         } else {
-            self.create_session(token).await?
+            ctx.timed(STAGE_SESSION, self.create_session(token)).await?
         };
 
         // 发送完成请求
-        let access_token = self.token_manager.acquire_token(token).await?;
+        let access_token = ctx.timed(STAGE_TOKEN, self.token_manager.acquire_token(token)).await?;
         let completion_request = CompletionRequest {
             chat_session_id: session_id.clone(),
             parent_message_id: ref_parent_msg_id,
             prompt,
-            ref_file_ids: vec![],
-            search_enabled: is_search,
-            thinking_enabled: is_thinking,
+            ref_file_ids,
+            search_enabled: features.search,
+            thinking_enabled: features.thinking,
         };
 
-        let mut headers = self.create_headers(&access_token);
+        let mut headers = self.create_headers(token, &access_token);
         headers.insert("X-Ds-Pow-Response", challenge_answer.parse().unwrap());
+        // 交给故障注入代理，不会发往DeepSeek
+        if let Some(chaos) = options.chaos.filter(|_| self.config.deepseek.chaos) {
+            headers.insert(CHAOS_HEADER, chaos.to_string().parse().unwrap());
+        }
 
-        let response = self
+        self.pacer.pace(token).await;
+        let base_url = self.mirrors.current();
+        let mut request = self
             .client
-            .post(&format!("{}/api/v0/chat/completion", self.config.deepseek.base_url))
+            .post(format!("{}/api/v0/chat/completion", base_url))
             .headers(headers)
-            .json(&completion_request)
-            .send()
-            .await?;
+            .json(&completion_request);
+        // API密钥单独设置的超时覆盖客户端的默认超时（包括读取响应体）
+        if let Some(secs) = options.overrides.request_timeout_secs {
+            request = request.timeout(Duration::from_secs(secs));
+        }
+        let request = self.wire_log.send(request);
+        // 到收到上游响应头为止的时间
+        let response = ctx.timed(STAGE_UPSTREAM, request)
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?;
 
         // 发送事件以降低封号风险
         let _ = self.send_events(&session_id, token).await;
@@ -146,12 +212,15 @@ impl DeepSeekClient {
             .map(|h| h.contains("text/event-stream"))
             .unwrap_or(false)
         {
+            if features.thinking {
+                self.quota_cache.consume_thinking(token);
+            }
             // 处理流式响应
-            self.process_completion_stream(response, model, &session_id).await
+            self.process_completion_stream(response, model, features, &session_id, options.debug_upstream).await
         } else {
-            Err(ApiError::ServiceUnavailable(
-                "服务暂时不可用，第三方响应错误".to_string(),
-            ))
+            // 答案可能已被上游拒绝，下次重试时重新求解
+            self.challenge_cache.invalidate(token, "/api/v0/chat/completion");
+            Err(completion_error(response).await)
         }
     }
 
@@ -162,25 +231,15 @@ impl DeepSeekClient {
         messages: &[ChatMessage],
         token: &str,
         conversation_id: Option<&str>,
+        options: CompletionOptions,
+        ctx: &RequestContext,
     ) -> ApiResult<Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>> {
-        let mut retry_count = 0;
-        let max_retries = self.config.deepseek.max_retry_count;
-
-        loop {
-            match self
-                .try_create_completion_stream(model, messages, token, conversation_id)
-                .await
-            {
-                Ok(stream) => return Ok(stream),
-                Err(e) if retry_count < max_retries => {
-                    tracing::warn!("Stream creation failed, retrying: {}", e);
-                    retry_count += 1;
-                    tokio::time::sleep(Duration::from_millis(self.config.deepseek.retry_delay_ms))
-                        .await;
-                }
-                Err(e) => return Err(e),
-            }
-        }
+        self.retry_policy
+            .with_max_retries(options.overrides.max_retries)
+            .run(ctx, "Stream creation", || {
+                self.try_create_completion_stream(model, messages, token, conversation_id, options, ctx)
+            })
+            .await
     }
 
     /// 尝试创建流式聊天完成
@@ -190,6 +249,8 @@ impl DeepSeekClient {
         messages: &[ChatMessage],
         token: &str,
         conversation_id: Option<&str>,
+        options: CompletionOptions,
+        ctx: &RequestContext,
     ) -> ApiResult<Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>> {
         tracing::info!("Creating completion stream for model: {}", model);
 
@@ -200,56 +261,71 @@ impl DeepSeekClient {
             (None, None)
         };
 
-        // 消息预处理
-        let prompt = MessageProcessor::prepare_messages(messages);
+        // 消息预处理，内联图片上传后通过ref_file_ids引用
+        let mut prompt = MessageProcessor::prepare_messages(messages, &self.config.prompt, options.continue_final_message);
+        self.plugins.before_upstream(model, &mut prompt)?;
+        let ref_file_ids = self.upload_inline_images(token, messages).await?;
         
-        // 检查模型类型
-        let is_search = is_search_model(model) || prompt.contains("联网搜索");
-        let is_thinking = is_thinking_model(model) || prompt.contains("深度思考");
-
-        // 检查深度思考配额
-        if is_thinking {
-            let quota = self.get_thinking_quota(token).await?;
-            if quota <= 0 {
+        // 请求中的功能开关优先，其余按模型类型推断
+        let features = options.features.resolve(model, &prompt);
+
+        // 检查深度思考配额，优先使用后台轮询缓存的配额
+        if features.thinking {
+            let quota = match self.quota_cache.thinking_remaining(token) {
+                Some(quota) => quota,
+                None => self.fetch_thinking_quota(token).await?,
+            };
+            if quota == 0 {
                 return Err(ApiError::ServiceUnavailable("深度思考配额不足".to_string()));
             }
         }
 
         // 获取POW挑战并解决
-        let challenge_response = self.get_challenge(token, "/api/v0/chat/completion").await?;
-        let challenge_answer = self
-            .challenge_solver
-            .solve_challenge(&challenge_response.challenge, "/api/v0/chat/completion")
-            .await?;
+        let challenge_answer = ctx.timed(STAGE_POW, self.solve_pow(token, "/api/v0/chat/completion")).await?;
 
-        // 创建会话
+        // 创建会话（优先领取预热会话）
         let session_id = if let Some(id) = ref_session_id {
             id
+        } else if let Some(id) = self.session_prewarmer.claim(token) {
+            id
         } else {
-            self.create_session(token).await?
+            ctx.timed(STAGE_SESSION, self.create_session(token)).await?
         };
 
         // 发送完成请求
-        let access_token = self.token_manager.acquire_token(token).await?;
+        let access_token = ctx.timed(STAGE_TOKEN, self.token_manager.acquire_token(token)).await?;
         let completion_request = CompletionRequest {
             chat_session_id: session_id.clone(),
             parent_message_id: ref_parent_msg_id,
             prompt,
-            ref_file_ids: vec![],
-            search_enabled: is_search,
-            thinking_enabled: is_thinking,
+            ref_file_ids,
+            search_enabled: features.search,
+            thinking_enabled: features.thinking,
         };
 
-        let mut headers = self.create_headers(&access_token);
+        let mut headers = self.create_headers(token, &access_token);
         headers.insert("X-Ds-Pow-Response", challenge_answer.parse().unwrap());
+        // 交给故障注入代理，不会发往DeepSeek
+        if let Some(chaos) = options.chaos.filter(|_| self.config.deepseek.chaos) {
+            headers.insert(CHAOS_HEADER, chaos.to_string().parse().unwrap());
+        }
 
-        let response = self
+        self.pacer.pace(token).await;
+        let base_url = self.mirrors.current();
+        let mut request = self
             .client
-            .post(&format!("{}/api/v0/chat/completion", self.config.deepseek.base_url))
+            .post(format!("{}/api/v0/chat/completion", base_url))
             .headers(headers)
-            .json(&completion_request)
-            .send()
-            .await?;
+            .json(&completion_request);
+        // API密钥单独设置的超时覆盖客户端的默认超时（包括读取响应体）
+        if let Some(secs) = options.overrides.request_timeout_secs {
+            request = request.timeout(Duration::from_secs(secs));
+        }
+        let request = self.wire_log.send(request);
+        // 到收到上游响应头为止的时间
+        let response = ctx.timed(STAGE_UPSTREAM, request)
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?;
 
         // 发送事件以降低封号风险
         let session_id_clone = session_id.clone();
@@ -264,13 +340,17 @@ impl DeepSeekClient {
             .map(|h| h.contains("text/event-stream"))
             .unwrap_or(false)
         {
+            if features.thinking {
+                self.quota_cache.consume_thinking(token);
+            }
             // 创建转换流
-            let stream = self.create_transform_stream(response, model, session_id).await?;
+            let coalesce_chars = options.overrides.stream_coalesce_chars.unwrap_or(self.config.deepseek.stream_coalesce_chars);
+            let stream = self.create_transform_stream(response, model, features, session_id, options.debug_upstream, coalesce_chars).await?;
             Ok(stream)
         } else {
-            Err(ApiError::ServiceUnavailable(
-                "服务暂时不可用，第三方响应错误".to_string(),
-            ))
+            // 答案可能已被上游拒绝，下次重试时重新求解
+            self.challenge_cache.invalidate(token, "/api/v0/chat/completion");
+            Err(completion_error(response).await)
         }
     }
 
@@ -279,24 +359,29 @@ impl DeepSeekClient {
         &self,
         response: reqwest::Response,
         model: &str,
+        features: ModelFeatures,
         session_id: &str,
+        debug_upstream: bool,
     ) -> ApiResult<ChatCompletionResponse> {
         let mut content = String::new();
+        // 与官方API一致，内联显示的思考过程放在reasoning_content中
+        let mut processor = StreamProcessor::new(features).with_separate_reasoning();
         let message_id = "1".to_string(); // 简化处理
 
         // 简化流处理
-        let bytes = response.bytes().await?;
+        let bytes = read_body_capped(response, self.config.deepseek.max_upstream_response_bytes).await?;
         let text = String::from_utf8_lossy(&bytes);
         
         // 模拟处理SSE数据
         for line in text.lines() {
+            self.wire_log.log_line(line);
             if line.starts_with("data: ") && !line.contains("[DONE]") {
                 let data_part = &line[6..]; // 移除 "data: " 前缀
                 if let Ok(data) = serde_json::from_str::<DeepSeekStreamData>(data_part) {
                     if let Some(choices) = &data.choices {
                         for choice in choices {
-                            if let Some(delta_content) = &choice.delta.content {
-                                content.push_str(delta_content);
+                            if let Some(text) = choice.delta.content.as_deref().and_then(|delta| processor.push(delta, choice.delta.delta_type.as_deref())) {
+                                content.push_str(&text);
                             }
                         }
                     }
@@ -304,8 +389,12 @@ impl DeepSeekClient {
             }
         }
 
+        if let Some(rest) = processor.finish() {
+            content.push_str(&rest);
+        }
+
         // 构造响应
-        let final_content = MessageProcessor::add_search_references(&content, "");
+        let final_content = MessageProcessor::add_search_references(&content, processor.references());
         let conv_id = format!("{}@{}", session_id, message_id);
 
         Ok(ChatCompletionResponse {
@@ -318,6 +407,10 @@ impl DeepSeekClient {
                 message: Some(ChatMessage {
                     role: "assistant".to_string(),
                     content: ChatMessageContent::Text(final_content),
+                    name: None,
+                    tool_call_id: None,
+                    tool_calls: None,
+                    reasoning_content: processor.take_reasoning(),
                 }),
                 delta: None,
                 finish_reason: Some("stop".to_string()),
@@ -327,6 +420,7 @@ impl DeepSeekClient {
                 completion_tokens: 1,
                 total_tokens: 2,
             }),
+            x_upstream: debug_upstream.then(|| text.lines().filter(|line| !line.is_empty()).map(str::to_string).collect()),
         })
     }
 
@@ -335,7 +429,10 @@ impl DeepSeekClient {
         &self,
         response: reqwest::Response,
         model: &str,
+        features: ModelFeatures,
         session_id: String,
+        debug_upstream: bool,
+        coalesce_chars: usize,
     ) -> ApiResult<Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>> {
         let (tx, rx) = mpsc::channel(100);
         let created = unix_timestamp();
@@ -355,6 +452,7 @@ impl DeepSeekClient {
                 },
                 finish_reason: None,
             }],
+            x_upstream: None,
         };
         
         let initial_data = format!("data: {}\n\n", serde_json::to_string(&initial_chunk)?);
@@ -364,75 +462,55 @@ impl DeepSeekClient {
 
         // 启动后台任务处理流
         let model_clone = model.to_string();
+        let max_bytes = self.config.deepseek.max_upstream_response_bytes;
+        let wire_log = self.wire_log.clone();
         tokio::spawn(async move {
             // 简化流处理
-            let bytes = match response.bytes().await {
+            let bytes = match read_body_capped(response, max_bytes).await {
                 Ok(bytes) => bytes,
                 Err(e) => {
-                    let _ = tx.send(Err(ApiError::HttpRequest(e))).await;
+                    let _ = tx.send(Err(e)).await;
                     return;
                 }
             };
             
             let text = String::from_utf8_lossy(&bytes);
+            let id = format!("{}@1", session_id);
+            let mut processor = StreamProcessor::new(features);
+            // 调试模式下把上游原始行附在随后发出的数据块上
+            let mut upstream = debug_upstream.then(Vec::new);
+            // 累积到coalesce_chars个字符再发出一个数据块
+            let mut buffered = String::new();
             
             // 模拟处理SSE数据
             for line in text.lines() {
+                wire_log.log_line(line);
+                if let Some(lines) = upstream.as_mut().filter(|_| !line.is_empty()) {
+                    lines.push(line.to_string());
+                }
                 if line.starts_with("data: ") && !line.contains("[DONE]") {
                     let data_part = &line[6..]; // 移除 "data: " 前缀
                     if let Ok(data) = serde_json::from_str::<DeepSeekStreamData>(data_part) {
                         if let Some(choices) = &data.choices {
                             for choice in choices {
-                                if let Some(delta_content) = &choice.delta.content {
-                                    let chunk = StreamChunk {
-                                        id: format!("{}@1", session_id),
-                                        object: "chat.completion.chunk".to_string(),
-                                        created,
-                                        model: model_clone.clone(),
-                                        choices: vec![StreamChoice {
-                                            index: 0,
-                                            delta: ChatMessageDelta {
-                                                role: Some("assistant".to_string()),
-                                                content: Some(delta_content.clone()),
-                                                reasoning_content: None,
-                                            },
-                                            finish_reason: None,
-                                        }],
-                                    };
-
-                                    let chunk_data = format!(
-                                        "data: {}\n\n",
-                                        serde_json::to_string(&chunk).unwrap_or_default()
-                                    );
-
-                                    if tx.send(Ok(chunk_data)).await.is_err() {
-                                        return;
+                                let content = choice.delta.content.as_deref().and_then(|delta| processor.push(delta, choice.delta.delta_type.as_deref()));
+                                if let Some(content) = content.filter(|content| !content.is_empty()) {
+                                    buffered.push_str(&content);
+                                    if buffered.chars().count() >= coalesce_chars {
+                                        let chunk_data = stream_chunk(&id, created, &model_clone, std::mem::take(&mut buffered), None, &mut upstream);
+                                        if tx.send(Ok(chunk_data)).await.is_err() {
+                                            return;
+                                        }
                                     }
                                 }
 
                                 if choice.finish_reason.is_some() {
-                                    // 发送结束chunk
-                                    let final_chunk = StreamChunk {
-                                        id: format!("{}@1", session_id),
-                                        object: "chat.completion.chunk".to_string(),
-                                        created,
-                                        model: model_clone.clone(),
-                                        choices: vec![StreamChoice {
-                                            index: 0,
-                                            delta: ChatMessageDelta {
-                                                role: Some("assistant".to_string()),
-                                                content: Some(String::new()),
-                                                reasoning_content: None,
-                                            },
-                                            finish_reason: Some("stop".to_string()),
-                                        }],
-                                    };
-
-                                    let final_data = format!(
-                                        "data: {}\n\n",
-                                        serde_json::to_string(&final_chunk).unwrap_or_default()
-                                    );
-
+                                    // 输出缓冲中剩余的内容后发送结束chunk
+                                    buffered.push_str(&processor.finish().unwrap_or_default());
+                                    if !buffered.is_empty() {
+                                        let _ = tx.send(Ok(stream_chunk(&id, created, &model_clone, buffered, None, &mut upstream))).await;
+                                    }
+                                    let final_data = stream_chunk(&id, created, &model_clone, String::new(), Some("stop"), &mut upstream);
                                     let _ = tx.send(Ok(final_data)).await;
                                     let _ = tx.send(Ok("data: [DONE]\n\n".to_string())).await;
                                     return;
@@ -444,6 +522,13 @@ impl DeepSeekClient {
             }
             
             // 如果没有结束标记，手动发送结束
+            buffered.push_str(&processor.finish().unwrap_or_default());
+            if !buffered.is_empty() {
+                let _ = tx.send(Ok(stream_chunk(&id, created, &model_clone, buffered, None, &mut upstream))).await;
+            }
+            if upstream.as_ref().is_some_and(|lines| !lines.is_empty()) {
+                let _ = tx.send(Ok(stream_chunk(&id, created, &model_clone, String::new(), None, &mut upstream))).await;
+            }
             let _ = tx.send(Ok("data: [DONE]\n\n".to_string())).await;
         });
 
@@ -452,49 +537,167 @@ impl DeepSeekClient {
 
     /// 创建会话
     async fn create_session(&self, token: &str) -> ApiResult<String> {
+        self.pacer.pace(token).await;
         let access_token = self.token_manager.acquire_token(token).await?;
-        let headers = self.create_headers(&access_token);
+        let headers = self.create_headers(token, &access_token);
 
         let session_request = serde_json::json!({
             "character_id": null
         });
 
-        let response = self
+        let base_url = self.mirrors.current();
+        let request = self
             .client
-            .post(&format!("{}/api/v0/chat_session/create", self.config.deepseek.base_url))
+            .post(format!("{}/api/v0/chat_session/create", base_url))
             .headers(headers)
             .json(&session_request)
-            .timeout(Duration::from_secs(15))
-            .send()
-            .await?;
+            .timeout(Duration::from_secs(15));
+        let response = self.wire_log.send(request)
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?;
 
+        if !response.status().is_success() {
+            return Err(upstream_error(&response));
+        }
         let result: DeepSeekResponse<ChatSession> = response.json().await?;
         
         match result.biz_data {
             Some(session) => Ok(session.id),
-            None => Err(ApiError::ServiceUnavailable(
-                "创建会话失败，可能是账号或IP地址被封禁".to_string(),
-            )),
+            None if result.msg.as_deref().is_some_and(is_rate_limit_message) => Err(ApiError::RateLimited {
+                message: format!("上游请求过于频繁: {}", result.msg.unwrap_or_default()),
+                retry_after_secs: UPSTREAM_RETRY_AFTER_SECS,
+                model: None,
+            }),
+            None if result.msg.as_deref().is_some_and(is_ban_message) => Err(ApiError::Banned(format!(
+                "账号或IP地址被封禁: {}",
+                result.msg.unwrap_or_default()
+            ))),
+            None => Err(ApiError::ServiceUnavailable(format!(
+                "创建会话失败: code={} msg={}",
+                result.code.unwrap_or_default(),
+                result.msg.unwrap_or_default()
+            ))),
+        }
+    }
+
+    /// 获取上游会话的历史消息
+    pub async fn fetch_history(&self, token: &str, session_id: &str) -> ApiResult<ChatHistory> {
+        let access_token = self.token_manager.acquire_token(token).await?;
+        let headers = self.create_headers(token, &access_token);
+
+        let base_url = self.mirrors.current();
+        let request = self
+            .client
+            .get(format!("{}/api/v0/chat/history_messages", base_url))
+            .query(&[("chat_session_id", session_id)])
+            .headers(headers)
+            .timeout(Duration::from_secs(15));
+        let response = self.wire_log.send(request)
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?;
+
+        if !response.status().is_success() {
+            return Err(upstream_error(&response));
+        }
+        let result: serde_json::Value = response.json().await?;
+        // 业务数据可能在顶层或data中
+        let biz_data = result
+            .get("biz_data")
+            .or_else(|| result.pointer("/data/biz_data"))
+            .filter(|data| !data.is_null())
+            .cloned()
+            .ok_or_else(|| {
+                let message = result.get("msg").and_then(|msg| msg.as_str()).unwrap_or("获取历史消息失败");
+                ApiError::NotFound(format!("{}: {}", message, session_id))
+            })?;
+        Ok(serde_json::from_value(biz_data)?)
+    }
+
+    /// 删除上游会话
+    pub async fn delete_session(&self, token: &str, session_id: &str) -> ApiResult<()> {
+        self.pacer.pace(token).await;
+        let access_token = self.token_manager.acquire_token(token).await?;
+        let headers = self.create_headers(token, &access_token);
+
+        let base_url = self.mirrors.current();
+        let request = self
+            .client
+            .post(format!("{}/api/v0/chat_session/delete", base_url))
+            .headers(headers)
+            .json(&serde_json::json!({ "chat_session_id": session_id }))
+            .timeout(Duration::from_secs(15));
+        let response = self.wire_log.send(request)
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?;
+
+        if !response.status().is_success() {
+            return Err(upstream_error(&response));
+        }
+        let result: DeepSeekResponse<serde_json::Value> = response.json().await?;
+        match result.code {
+            Some(0) | None => Ok(()),
+            Some(code) => Err(ApiError::DeepSeekApiError {
+                code,
+                message: result.msg.unwrap_or_else(|| "删除会话失败".to_string()),
+            }),
+        }
+    }
+
+    /// 为账号补充预热会话，返回新创建的数量
+    pub async fn prewarm_sessions(&self, token: &str) -> ApiResult<usize> {
+        if !self.session_prewarmer.is_enabled() {
+            return Ok(0);
+        }
+
+        let deficit = self.session_prewarmer.deficit(token);
+        for _ in 0..deficit {
+            let session_id = self.create_session(token).await?;
+            self.session_prewarmer.put(token, session_id);
         }
+
+        if deficit > 0 {
+            tracing::debug!("Prewarmed {} sessions", deficit);
+        }
+        Ok(deficit)
+    }
+
+    /// 获取POW答案，有效期内复用已解出的答案
+    async fn solve_pow(&self, token: &str, target_path: &str) -> ApiResult<String> {
+        if let Some(answer) = self.challenge_cache.get(token, target_path) {
+            tracing::debug!("Reusing cached POW answer for {}", target_path);
+            return Ok(answer);
+        }
+
+        let challenge_response = self.get_challenge(token, target_path).await?;
+        let answer = self
+            .challenge_solver
+            .solve_challenge(&challenge_response.challenge, target_path)
+            .await?;
+        self.challenge_cache
+            .put(token, target_path, &challenge_response.challenge, answer.clone());
+
+        Ok(answer)
     }
 
     /// 获取挑战
     async fn get_challenge(&self, token: &str, target_path: &str) -> ApiResult<ChallengeResponse> {
         let access_token = self.token_manager.acquire_token(token).await?;
-        let headers = self.create_headers(&access_token);
+        let headers = self.create_headers(token, &access_token);
 
         let challenge_request = ChallengeRequest {
             target_path: target_path.to_string(),
         };
 
-        let response = self
+        let base_url = self.mirrors.current();
+        let request = self
             .client
-            .post(&format!("{}/api/v0/chat/create_pow_challenge", self.config.deepseek.base_url))
+            .post(format!("{}/api/v0/chat/create_pow_challenge", base_url))
             .headers(headers)
             .json(&challenge_request)
-            .timeout(Duration::from_secs(15))
-            .send()
-            .await?;
+            .timeout(Duration::from_secs(15));
+        let response = self.wire_log.send(request)
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?;
 
         let result: DeepSeekResponse<ChallengeResponse> = response.json().await?;
         
@@ -504,18 +707,178 @@ impl DeepSeekClient {
         }
     }
 
-    /// 获取深度思考配额
-    async fn get_thinking_quota(&self, token: &str) -> ApiResult<u32> {
+    /// 上传消息中以data URI内联的图片，返回文件ID
+    async fn upload_inline_images(&self, token: &str, messages: &[ChatMessage]) -> ApiResult<Vec<String>> {
+        let images: Vec<&str> = messages
+            .iter()
+            .filter_map(|message| match &message.content {
+                ChatMessageContent::Array(parts) => Some(parts),
+                ChatMessageContent::Text(_) => None,
+            })
+            .flatten()
+            .filter_map(|part| part.image_url.as_ref())
+            .map(|image| image.url.as_str())
+            .filter(|url| url.starts_with("data:"))
+            .collect();
+
+        let mut file_ids = Vec::new();
+        for (index, url) in images.into_iter().enumerate() {
+            let (mime, bytes) = parse_data_uri(url)
+                .ok_or_else(|| ApiError::InvalidRequest("无法解析data URI格式的图片".to_string()))?;
+            let extension = mime.rsplit('/').next().unwrap_or("png");
+            let file_name = format!("image_{}.{}", index + 1, extension);
+            file_ids.push(self.upload_file(token, &file_name, &mime, bytes).await?);
+        }
+        Ok(file_ids)
+    }
+
+    /// 上传文件并等待DeepSeek解析完成
+    async fn upload_file(&self, token: &str, file_name: &str, mime: &str, bytes: Vec<u8>) -> ApiResult<String> {
+        let challenge_answer = self.solve_pow(token, "/api/v0/file/upload_file").await?;
         let access_token = self.token_manager.acquire_token(token).await?;
-        let headers = self.create_headers(&access_token);
 
-        let response = self
+        // 手动构造multipart请求体
+        let boundary = format!("----DeepSeekFormBoundary{}", generate_uuid_simple());
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+            boundary, file_name, mime
+        )
+        .into_bytes();
+        body.extend_from_slice(&bytes);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        let mut headers = self.create_headers(token, &access_token);
+        headers.insert("X-Ds-Pow-Response", challenge_answer.parse().unwrap());
+        headers.insert(
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", boundary).parse().unwrap(),
+        );
+
+        self.pacer.pace(token).await;
+        let base_url = self.mirrors.current();
+        let request = self
             .client
-            .get(&format!("{}/api/v0/users/feature_quota", self.config.deepseek.base_url))
+            .post(format!("{}/api/v0/file/upload_file", base_url))
             .headers(headers)
-            .timeout(Duration::from_secs(15))
-            .send()
-            .await?;
+            .body(body);
+        let response = self.wire_log.send(request)
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?;
+        if !response.status().is_success() {
+            return Err(upstream_error(&response));
+        }
+
+        let result: DeepSeekResponse<UploadedFile> = response.json().await?;
+        let mut file = result
+            .biz_data
+            .ok_or_else(|| ApiError::ExternalApi("上传文件失败".to_string()))?;
+
+        for _ in 0..FILE_PARSE_POLL_ATTEMPTS {
+            match file.status.as_str() {
+                "SUCCESS" => {
+                    tracing::debug!("Uploaded inline image {} as {}", file_name, file.id);
+                    return Ok(file.id);
+                }
+                "FAILED" | "CONTENT_EMPTY" | "UNSUPPORTED" => {
+                    return Err(ApiError::InvalidRequest(format!("DeepSeek无法解析图片 {}: {}", file_name, file.status)));
+                }
+                _ => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
+            file = self.fetch_file(token, &file.id).await?;
+        }
+
+        Err(ApiError::Timeout(format!("等待图片 {} 解析超时", file_name)))
+    }
+
+    /// 从DeepSeek网页及其脚本中探测当前的X-App-Version
+    pub async fn detect_app_version(&self) -> ApiResult<String> {
+        let base_url = &self.mirrors.current();
+        let mut headers = reqwest::header::HeaderMap::new();
+        self.profiles.apply(base_url, &mut headers);
+        headers.remove("X-App-Version");
+
+        let html = self.client.get(base_url).headers(headers.clone()).send().await?.text().await?;
+        if let Some(version) = extract_app_version(&html) {
+            return Ok(version);
+        }
+
+        // 版本号通常打包在入口脚本中
+        for path in extract_script_paths(&html).into_iter().take(MAX_VERSION_SCRIPTS) {
+            let script = self
+                .client
+                .get(format!("{}{}", base_url, path))
+                .headers(headers.clone())
+                .send()
+                .await?
+                .text()
+                .await?;
+            if let Some(version) = extract_app_version(&script) {
+                return Ok(version);
+            }
+        }
+
+        Err(ApiError::ExternalApi("未能在DeepSeek网页中找到X-App-Version".to_string()))
+    }
+
+    /// 探测X-App-Version，版本变化时更新所有账号使用的请求头
+    pub async fn refresh_app_version(&self) -> ApiResult<()> {
+        let version = self.detect_app_version().await?;
+        let previous = self.profiles.app_version();
+        if self.profiles.set_app_version(&version) {
+            tracing::info!("X-App-Version updated: {} -> {}", previous, version);
+        }
+        Ok(())
+    }
+
+    pub fn has_mirrors(&self) -> bool {
+        self.mirrors.is_enabled()
+    }
+
+    /// 上游线路日志，管理接口通过它在运行时开关
+    pub fn wire_log(&self) -> &WireLog {
+        &self.wire_log
+    }
+
+    /// 测量各镜像的延迟，之后的请求走最快的可用镜像
+    pub async fn probe_mirrors(&self) {
+        self.mirrors.probe(&self.client).await;
+    }
+
+    /// 查询上传文件的解析状态
+    async fn fetch_file(&self, token: &str, file_id: &str) -> ApiResult<UploadedFile> {
+        let access_token = self.token_manager.acquire_token(token).await?;
+        let base_url = self.mirrors.current();
+        let request = self
+            .client
+            .get(format!("{}/api/v0/file/fetch_files", base_url))
+            .query(&[("file_ids", file_id)])
+            .headers(self.create_headers(token, &access_token))
+            .timeout(Duration::from_secs(15));
+        let response = self.wire_log.send(request)
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?;
+
+        let result: DeepSeekResponse<FetchFilesResponse> = response.json().await?;
+        result
+            .biz_data
+            .and_then(|data| data.files.into_iter().next())
+            .ok_or_else(|| ApiError::ExternalApi(format!("查询文件 {} 状态失败", file_id)))
+    }
+
+    /// 从上游查询深度思考配额并更新缓存，查询失败时返回错误且不更新缓存
+    pub async fn fetch_thinking_quota(&self, token: &str) -> ApiResult<u32> {
+        let access_token = self.token_manager.acquire_token(token).await?;
+        let headers = self.create_headers(token, &access_token);
+
+        let base_url = self.mirrors.current();
+        let request = self
+            .client
+            .get(format!("{}/api/v0/users/feature_quota", base_url))
+            .headers(headers)
+            .timeout(Duration::from_secs(15));
+        let response = self.wire_log.send(request)
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?;
 
         let result: DeepSeekResponse<FeatureQuota> = response.json().await?;
         
@@ -523,12 +886,14 @@ impl DeepSeekClient {
             Some(quota) => {
                 let remaining = quota.thinking.quota.saturating_sub(quota.thinking.used);
                 tracing::info!("Thinking quota: {}/{}", quota.thinking.used, quota.thinking.quota);
+                self.quota_cache.set_thinking_remaining(token, remaining);
                 Ok(remaining)
             }
-            None => {
-                tracing::warn!("Failed to get thinking quota");
-                Ok(0)
-            }
+            // 响应无法解析时不写入缓存，否则一次异常响应会让深度思考在整个缓存有效期内不可用
+            None => Err(ApiError::ExternalApi(format!(
+                "查询深度思考配额失败: {}",
+                result.msg.as_deref().unwrap_or_default()
+            ))),
         }
     }
 
@@ -540,41 +905,88 @@ impl DeepSeekClient {
         Ok(())
     }
 
-    /// 检查token状态
-    pub async fn check_token_status(&self, token: &str) -> ApiResult<bool> {
-        self.token_manager.check_token_status(token).await
+    /// 探测账号是否恢复正常（强制刷新token）
+    pub async fn probe_account(&self, token: &str) -> ApiResult<()> {
+        self.token_manager.remove_token(token);
+        self.token_manager.acquire_token(token).await?;
+        Ok(())
     }
 
-    /// 创建请求头
-    fn create_headers(&self, auth_token: &str) -> reqwest::header::HeaderMap {
+    /// 新账号加入轮换前的预热：像真人第一次使用一样打开聊天页面、查询资料和配额，再以人工节奏发送一条简短消息
+    pub async fn warm_up_account(&self, token: &str) -> ApiResult<()> {
+        let access_token = self.token_manager.acquire_token(token).await?;
+        let base_url = self.mirrors.current();
+
+        let mut page_headers = reqwest::header::HeaderMap::new();
+        self.profiles.apply(token, &mut page_headers);
+        page_headers.insert("Cookie", self.cookies.cookie_for(token).parse().unwrap());
+        let request = self.client.get(format!("{}/", base_url)).headers(page_headers);
+        self.wire_log.send(request)
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?;
+        warmup_pause().await;
+
+        let request = self
+            .client
+            .get(format!("{}/api/v0/users/current", base_url))
+            .headers(self.create_headers(token, &access_token))
+            .timeout(Duration::from_secs(15));
+        self.wire_log.send(request)
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?
+            .error_for_status()?;
+        self.fetch_thinking_quota(token).await?;
+        warmup_pause().await;
+
+        let messages = [ChatMessage {
+            role: "user".to_string(),
+            content: ChatMessageContent::Text("你好".to_string()),
+            name: None,
+            tool_call_id: None,
+            tool_calls: None,
+            reasoning_content: None,
+        }];
+        let options = CompletionOptions {
+            continue_final_message: false,
+            features: Default::default(),
+            debug_upstream: false,
+            overrides: Default::default(),
+            chaos: None,
+        };
+        self.create_completion("deepseek", &messages, token, None, options, &RequestContext::new()).await?;
+        Ok(())
+    }
+
+    /// 丢弃账号缓存的访问令牌（账号停用后不再复用）
+    pub fn forget_access_token(&self, token: &str) {
+        self.token_manager.remove_token(token);
+    }
+
+    /// 检查token状态，有效时附带访问令牌的过期时间
+    pub async fn check_token(&self, token: &str) -> ApiResult<(bool, Option<u64>)> {
+        let live = self.token_manager.check_token_status(token).await?;
+        Ok((live, live.then(|| self.token_manager.expire_time(token)).flatten()))
+    }
+
+    /// 创建请求头，浏览器指纹按账号的userToken分配
+    fn create_headers(&self, token: &str, auth_token: &str) -> reqwest::header::HeaderMap {
         let mut headers = reqwest::header::HeaderMap::new();
         
         headers.insert("Accept", "*/*".parse().unwrap());
         headers.insert("Accept-Encoding", "gzip, deflate, br, zstd".parse().unwrap());
-        headers.insert("Accept-Language", "zh-CN,zh;q=0.9,en;q=0.8".parse().unwrap());
         headers.insert("Origin", self.config.deepseek.base_url.parse().unwrap());
         headers.insert("Pragma", "no-cache".parse().unwrap());
         headers.insert("Priority", "u=1, i".parse().unwrap());
         headers.insert("Referer", format!("{}/", self.config.deepseek.base_url).parse().unwrap());
-        headers.insert(
-            "Sec-Ch-Ua",
-            r#""Chromium";v="134", "Not:A-Brand";v="24", "Google Chrome";v="134""#.parse().unwrap()
-        );
-        headers.insert("Sec-Ch-Ua-Mobile", "?0".parse().unwrap());
-        headers.insert("Sec-Ch-Ua-Platform", r#""macOS""#.parse().unwrap());
         headers.insert("Sec-Fetch-Dest", "empty".parse().unwrap());
         headers.insert("Sec-Fetch-Mode", "cors".parse().unwrap());
         headers.insert("Sec-Fetch-Site", "same-origin".parse().unwrap());
-        headers.insert(
-            "User-Agent",
-            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/134.0.0.0 Safari/537.36".parse().unwrap()
-        );
-        headers.insert("X-App-Version", "20241129.1".parse().unwrap());
-        headers.insert("X-Client-Locale", "zh-CN".parse().unwrap());
-        headers.insert("X-Client-Platform", "web".parse().unwrap());
-        headers.insert("X-Client-Version", "1.0.0-always".parse().unwrap());
-        headers.insert("Cookie", generate_cookie().parse().unwrap());
+        self.profiles.apply(token, &mut headers);
+        headers.insert("Cookie", self.cookies.cookie_for(token).parse().unwrap());
         headers.insert("Authorization", format!("Bearer {}", auth_token).parse().unwrap());
+        if let Some(trace) = TraceContext::current() {
+            trace.apply(&mut headers);
+        }
 
         headers
     }
@@ -585,9 +997,129 @@ impl Clone for DeepSeekClient {
         Self {
             client: self.client.clone(),
             config: self.config.clone(),
-            token_manager: TokenManager::new(self.client.clone(), self.config.deepseek.access_token_expires),
-            challenge_solver: ChallengeSolver::new(self.config.deepseek.wasm_path.clone()),
+            token_manager: self.token_manager.clone(),
+            challenge_solver: self.challenge_solver.clone(),
             message_processor: MessageProcessor,
+            session_prewarmer: self.session_prewarmer.clone(),
+            challenge_cache: self.challenge_cache.clone(),
+            quota_cache: self.quota_cache.clone(),
+            retry_policy: self.retry_policy,
+            profiles: self.profiles.clone(),
+            pacer: self.pacer.clone(),
+            cookies: self.cookies.clone(),
+            mirrors: self.mirrors.clone(),
+            wire_log: self.wire_log.clone(),
+            plugins: self.plugins.clone(),
         }
     }
 }
+
+/// 预热步骤之间随机停顿
+async fn warmup_pause() {
+    let secs = rand::thread_rng().gen_range(WARMUP_PAUSE_SECS);
+    tokio::time::sleep(Duration::from_secs(secs)).await;
+}
+
+/// 构造一个SSE格式的补全数据块，调试模式下带走已缓冲的上游原始行
+fn stream_chunk(id: &str, created: u64, model: &str, content: String, finish_reason: Option<&str>, upstream: &mut Option<Vec<String>>) -> String {
+    let chunk = StreamChunk {
+        id: id.to_string(),
+        object: "chat.completion.chunk".to_string(),
+        created,
+        model: model.to_string(),
+        choices: vec![StreamChoice {
+            index: 0,
+            delta: ChatMessageDelta {
+                role: Some("assistant".to_string()),
+                content: Some(content),
+                reasoning_content: None,
+            },
+            finish_reason: finish_reason.map(str::to_string),
+        }],
+        x_upstream: upstream.as_mut().map(std::mem::take).filter(|lines| !lines.is_empty()),
+    };
+    format!("data: {}\n\n", serde_json::to_string(&chunk).unwrap_or_default())
+}
+
+/// 读取上游响应体，超过上限时中止，避免异常响应占满内存
+async fn read_body_capped(response: reqwest::Response, max_bytes: usize) -> ApiResult<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut chunks = response.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > max_bytes {
+            return Err(ApiError::InternalError(format!(
+                "上游响应超过 {} 字节上限",
+                max_bytes
+            )));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// 上游限流但没有给出Retry-After时建议的等待秒数
+const UPSTREAM_RETRY_AFTER_SECS: u64 = 1;
+
+/// 上游业务消息是否表示请求过于频繁
+fn is_rate_limit_message(message: &str) -> bool {
+    let message = message.to_lowercase();
+    ["too many requests", "rate limit", "频繁", "请求过多", "限流"]
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
+/// 补全接口的非SSE响应：HTTP 429或限流类业务消息转为429，其余按状态码处理
+async fn completion_error(response: reqwest::Response) -> ApiError {
+    let error = upstream_error(&response);
+    if matches!(error, ApiError::RateLimited { .. }) {
+        return error;
+    }
+
+    // 限流时上游也可能返回200和业务错误码，消息在顶层或data中
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+    let messages: Vec<&str> = ["/msg", "/message", "/data/biz_msg", "/data/msg"]
+        .iter()
+        .filter_map(|pointer| body.pointer(pointer).and_then(|value| value.as_str()))
+        .collect();
+    if let Some(message) = messages.iter().find(|message| is_rate_limit_message(message)) {
+        return ApiError::RateLimited {
+            message: format!("上游请求过于频繁: {}", message),
+            retry_after_secs: UPSTREAM_RETRY_AFTER_SECS,
+            model: None,
+        };
+    }
+    match messages.iter().find(|message| is_ban_message(message)) {
+        Some(message) => ApiError::Banned(format!("账号或IP地址被封禁: {}", message)),
+        None => error,
+    }
+}
+
+/// 上游消息是否明确表示账号或IP被封禁；内容审核的"违规"提示不算，否则任何客户端都能触发停用账号
+fn is_ban_message(message: &str) -> bool {
+    let message = message.to_lowercase();
+    ["封禁", "封号", "banned", "suspended"].iter().any(|pattern| message.contains(pattern))
+}
+
+/// 将上游的非SSE响应转换为错误，保留状态码语义以便判断是否重试
+fn upstream_error(response: &reqwest::Response) -> ApiError {
+    let retry_after = response
+        .headers()
+        .get("retry-after")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+    match (response.status().as_u16(), retry_after) {
+        (401, _) => ApiError::TokenError("上游认证失败，userToken可能已失效".to_string()),
+        (429, retry_after_secs) => ApiError::RateLimited {
+            message: "上游请求过于频繁".to_string(),
+            retry_after_secs: retry_after_secs.unwrap_or(UPSTREAM_RETRY_AFTER_SECS),
+            model: None,
+        },
+        // 上游给出了恢复时间时原样转告客户端
+        (503, Some(retry_after_secs)) => ApiError::Unavailable {
+            message: "服务暂时不可用，上游维护中".to_string(),
+            retry_after_secs,
+        },
+        _ => ApiError::ServiceUnavailable("服务暂时不可用，第三方响应错误".to_string()),
+    }
+}
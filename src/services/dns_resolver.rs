@@ -0,0 +1,117 @@
+use hyper_legacy::client::connect::dns::Name;
+use parking_lot::Mutex;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// DoH记录的最短缓存时间，避免TTL很短的记录导致每次连接都查询
+const MIN_CACHE_TTL_SECS: u64 = 30;
+
+/// DNS JSON API（application/dns-json）的响应
+#[derive(Debug, Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    #[serde(rename = "TTL", default)]
+    ttl: u64,
+    data: String,
+}
+
+/// 缓存的解析结果
+struct CachedAddrs {
+    addrs: Vec<IpAddr>,
+    expires_at: Instant,
+}
+
+/// 通过DNS-over-HTTPS解析上游域名，用于本地DNS被污染或屏蔽的环境
+#[derive(Clone)]
+pub struct DohResolver {
+    client: reqwest::Client,
+    url: Arc<str>,
+    cache: Arc<Mutex<HashMap<String, CachedAddrs>>>,
+}
+
+impl DohResolver {
+    /// DoH服务本身用系统DNS解析，建议直接写IP（如 https://1.1.1.1/dns-query）
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .expect("Failed to create DoH client"),
+            url: Arc::from(url),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>, String> {
+        if let Some(cached) = self.cache.lock().get(host) {
+            if Instant::now() < cached.expires_at {
+                return Ok(cached.addrs.clone());
+            }
+        }
+
+        // 优先A记录，没有时再查AAAA
+        for record_type in ["A", "AAAA"] {
+            let (addrs, ttl) = self.query(host, record_type).await?;
+            if !addrs.is_empty() {
+                let expires_at = Instant::now() + Duration::from_secs(ttl.max(MIN_CACHE_TTL_SECS));
+                self.cache.lock().insert(host.to_string(), CachedAddrs { addrs: addrs.clone(), expires_at });
+                return Ok(addrs);
+            }
+        }
+        Err(format!("DoH未返回 {} 的地址", host))
+    }
+
+    async fn query(&self, host: &str, record_type: &str) -> Result<(Vec<IpAddr>, u64), String> {
+        let response: DohResponse = self
+            .client
+            .get(&*self.url)
+            .query(&[("name", host), ("type", record_type)])
+            .header("Accept", "application/dns-json")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("DoH请求失败: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("DoH响应解析失败: {}", e))?;
+
+        if response.status != 0 {
+            return Err(format!("DoH解析 {} 失败，状态码 {}", host, response.status));
+        }
+
+        // 只取A(1)和AAAA(28)记录，CNAME链上的中间记录忽略
+        let records: Vec<_> = response
+            .answer
+            .iter()
+            .filter(|answer| matches!(answer.record_type, 1 | 28))
+            .filter_map(|answer| answer.data.parse::<IpAddr>().ok().map(|ip| (ip, answer.ttl)))
+            .collect();
+        let ttl = records.iter().map(|(_, ttl)| *ttl).min().unwrap_or(0);
+        Ok((records.into_iter().map(|(ip, _)| ip).collect(), ttl))
+    }
+}
+
+impl Resolve for DohResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addrs = resolver.lookup(name.as_str()).await?;
+            tracing::debug!("Resolved {} via DoH: {:?}", name.as_str(), addrs);
+            let addrs: Addrs = Box::new(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
@@ -0,0 +1,184 @@
+use crate::config::ErrorLocale;
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// 错误前缀（ApiError的Display）和常见错误消息的中英文对照，`{}` 为动态部分
+const CATALOG: &[(&str, &str)] = &[
+    // 错误类型前缀
+    ("HTTP请求失败：", "HTTP request failed: "),
+    ("JSON序列化错误：", "JSON serialization error: "),
+    ("IO错误：", "IO error: "),
+    ("配置错误：", "Configuration error: "),
+    ("令牌错误：", "Token error: "),
+    ("挑战计算失败：", "Challenge calculation failed: "),
+    ("DeepSeek接口错误：", "DeepSeek API error: "),
+    ("无效请求：", "Invalid request: "),
+    ("服务不可用：", "Service unavailable: "),
+    ("服务器内部错误：", "Internal server error: "),
+    ("请求超时：", "Timeout error: "),
+    ("外部接口错误：", "External API error: "),
+    ("未授权：", "Unauthorized: "),
+    ("禁止访问：", "Forbidden: "),
+    ("未找到：", "Not found: "),
+    ("请求错误：", "Bad request: "),
+    ("内部错误：", "Internal error: "),
+    ("请求过于频繁：", "Too many requests: "),
+    // 认证和权限
+    ("缺少Authorization请求头", "Authorization header missing"),
+    ("Authorization请求头格式无效", "Invalid authorization header format"),
+    ("Authorization格式无效", "Invalid authorization format"),
+    ("API密钥无效或没有关联账号", "Invalid API key or no accounts associated"),
+    ("无效的API密钥", "Invalid API key"),
+    ("API密钥不存在或无统计信息", "API key not found or has no statistics"),
+    ("API密钥不存在", "API key not found"),
+    ("API密钥没有chat权限", "API key does not have the chat scope"),
+    ("缺少或无效的管理令牌", "Missing or invalid admin token"),
+    ("来源IP不允许访问该接口", "Source IP is not allowed to access this endpoint"),
+    ("X-Debug需要有效的X-Admin-Token", "X-Debug requires a valid X-Admin-Token"),
+    ("对话列表需要使用API密钥", "Listing conversations requires an API key"),
+    ("上游认证失败，userToken可能已失效", "Upstream authentication failed, the userToken may have expired"),
+    // 账号和会话
+    ("获取会话失败：{}", "Failed to acquire session: {}"),
+    ("该API密钥下没有可用的账号", "No accounts available for this API key"),
+    ("未找到合适的账号", "No suitable account found"),
+    ("账号正在处理其他会话", "Account is busy with another session"),
+    ("会话不存在", "Session not found"),
+    ("账号不存在", "Account not found"),
+    ("凭据不存在", "Credential not found"),
+    ("未配置凭据库主密钥", "Credential vault master key is not configured"),
+    ("账号 {} 已达到消息速率上限，{}秒后重试", "Account {} exceeded its message rate budget, retry in {}s"),
+    ("所有账号都在冷却中，请稍后再试", "All accounts are cooling down, please retry later"),
+    ("所有账号都已达到消息速率上限，请稍后再试", "All accounts have reached their message rate limit, please retry later"),
+    ("账号分组 {} 中没有可用的账号", "No accounts available in account group {}"),
+    ("所有账号的深度思考配额均已耗尽", "All accounts have exhausted their deep thinking quota"),
+    ("深度思考配额不足", "Insufficient deep thinking quota"),
+    ("请求被高优先级流量抢占，请稍后重试", "Request was preempted by higher priority traffic, please retry later"),
+    ("账号容量繁忙，排队超时", "Account capacity is busy, timed out while queueing"),
+    ("创建会话失败: {}", "Failed to create a session: {}"),
+    ("账号或IP地址被封禁: {}", "Account or IP address has been banned: {}"),
+    ("对话不存在: {}", "Conversation not found: {}"),
+    ("无效的对话ID: {}", "Invalid conversation ID: {}"),
+    // 上游和请求
+    ("服务器繁忙，请稍后重试", "Server is busy, please retry later"),
+    ("API密钥超过每分钟 {} 次请求的限制", "API key exceeded its limit of {} requests per minute"),
+    ("服务暂时不可用，第三方响应错误", "Service temporarily unavailable, upstream returned an error"),
+    ("服务暂时不可用，上游维护中", "Service temporarily unavailable, upstream is under maintenance"),
+    ("上游请求过于频繁", "Upstream rate limit exceeded"),
+    ("消息不能为空", "Messages cannot be empty"),
+    ("模型 '{}' 不存在", "The model '{}' does not exist"),
+    ("{}包含被过滤的内容", "{} contains filtered content"),
+    ("获取挑战失败", "Failed to get challenge"),
+    ("上传文件失败", "Failed to upload file"),
+    ("无法解析data URI格式的图片", "Unable to parse data URI image"),
+];
+
+/// 每条对照编译为中英文两个匹配模式
+struct Entry {
+    zh: Regex,
+    en: Regex,
+    zh_template: &'static str,
+    en_template: &'static str,
+}
+
+static ENTRIES: LazyLock<Vec<Entry>> = LazyLock::new(|| {
+    CATALOG
+        .iter()
+        .map(|(zh, en)| Entry {
+            zh: template_regex(zh),
+            en: template_regex(en),
+            zh_template: zh,
+            en_template: en,
+        })
+        .collect()
+});
+
+/// 模板中的 `{}` 匹配任意非空文本（末尾的匹配到行尾），其余按字面匹配
+fn template_regex(template: &str) -> Regex {
+    let mut pattern = template.split("{}").map(regex::escape).collect::<Vec<_>>().join("(.+?)");
+    if template.ends_with("{}") {
+        pattern.push('$');
+    }
+    Regex::new(&pattern).expect("invalid error catalog template")
+}
+
+/// 按Accept-Language的首选语言选择错误语言，不是中文或英文时返回None
+pub fn negotiate(accept_language: &str) -> Option<ErrorLocale> {
+    let mut languages: Vec<(f32, &str)> = accept_language
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && quality > 0.0).then_some((quality, tag))
+        })
+        .collect();
+    languages.sort_by(|a, b| b.0.total_cmp(&a.0));
+    languages.into_iter().find_map(|(_, tag)| {
+        let primary = tag.split(['-', '_']).next().unwrap_or_default().to_lowercase();
+        match primary.as_str() {
+            "zh" => Some(ErrorLocale::Zh),
+            "en" => Some(ErrorLocale::En),
+            _ => None,
+        }
+    })
+}
+
+/// 把错误消息中能识别的部分翻译为目标语言，无法识别的部分保持原样
+pub fn translate(message: &str, locale: ErrorLocale) -> String {
+    let mut message = message.to_string();
+    for entry in ENTRIES.iter() {
+        let (source, target) = match locale {
+            ErrorLocale::Zh => (&entry.en, entry.zh_template),
+            ErrorLocale::En => (&entry.zh, entry.en_template),
+            ErrorLocale::Auto => return message,
+        };
+        if source.is_match(&message) {
+            message = source
+                .replace_all(&message, |captures: &regex::Captures| fill_template(target, captures))
+                .into_owned();
+        }
+    }
+    message
+}
+
+/// 依次用捕获的动态部分替换模板中的 `{}`
+fn fill_template(template: &str, captures: &regex::Captures) -> String {
+    let mut values = captures.iter().skip(1).flatten().map(|value| value.as_str());
+    let mut parts = template.split("{}");
+    let mut output = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        output.push_str(values.next().unwrap_or_default());
+        output.push_str(part);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate_prefix_and_message() {
+        assert_eq!(
+            translate("Service unavailable: 深度思考配额不足", ErrorLocale::En),
+            "Service unavailable: Insufficient deep thinking quota"
+        );
+        assert_eq!(
+            translate("Service unavailable: Account demo@example.com exceeded its message rate budget, retry in 3s", ErrorLocale::Zh),
+            "服务不可用：账号 demo@example.com 已达到消息速率上限，3秒后重试"
+        );
+        assert_eq!(translate("Token error: something new", ErrorLocale::Zh), "令牌错误：something new");
+        assert_eq!(translate("Forbidden: 来源IP不允许访问该接口", ErrorLocale::Auto), "Forbidden: 来源IP不允许访问该接口");
+    }
+
+    #[test]
+    fn test_negotiate_accept_language() {
+        assert_eq!(negotiate("en-US,en;q=0.9,zh-CN;q=0.8"), Some(ErrorLocale::En));
+        assert_eq!(negotiate("fr;q=1.0, zh-TW;q=0.5"), Some(ErrorLocale::Zh));
+        assert_eq!(negotiate("zh;q=0.2, en;q=0.9"), Some(ErrorLocale::En));
+        assert_eq!(negotiate("fr, de"), None);
+    }
+}
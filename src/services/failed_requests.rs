@@ -0,0 +1,167 @@
+use crate::models::{ChatCompletionRequest, ChatMessageContent};
+use crate::utils::unix_timestamp;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// 一次失败的补全请求，不包含API密钥、账号token和终端用户标识
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedRequest {
+    pub id: String, // 与日志中的request_id相同
+    pub timestamp: u64,
+    pub model: String,
+    pub api_key_id: Option<String>,
+    pub account: Option<String>, // 原请求使用的账号邮箱，重放时优先使用
+    pub error: String,
+    pub request: ChatCompletionRequest,
+}
+
+impl FailedRequest {
+    pub fn new(id: &str, model: &str, mut request: ChatCompletionRequest, error: String) -> Self {
+        request.model = Some(model.to_string());
+        request.user = None;
+        Self {
+            id: id.to_string(),
+            timestamp: unix_timestamp(),
+            model: model.to_string(),
+            api_key_id: None,
+            account: None,
+            error,
+            request,
+        }
+    }
+}
+
+/// 保留最近N个失败请求的环形缓冲，供管理员重放排查偶发的上游解析错误
+pub struct FailedRequests {
+    entries: Mutex<VecDeque<FailedRequest>>,
+    capacity: usize,          // 0表示不保留
+    max_content_chars: usize, // 列表中每条消息显示的最大字符数，重放使用完整内容
+}
+
+impl FailedRequests {
+    pub fn new(capacity: usize, max_content_chars: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            max_content_chars,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// 记录完整的失败请求供重放，超出容量时丢弃最早的
+    pub fn record(&self, failed: FailedRequest) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut entries = self.entries.lock();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(failed);
+    }
+
+    pub fn get(&self, id: &str) -> Option<FailedRequest> {
+        self.entries.lock().iter().find(|failed| failed.id == id).cloned()
+    }
+
+    /// 按时间从新到旧列出，消息内容按长度截断，内嵌的data URI图片替换为占位符
+    pub fn list(&self) -> Vec<FailedRequest> {
+        self.entries.lock().iter().rev().map(|failed| self.summarize(failed)).collect()
+    }
+
+    fn summarize(&self, failed: &FailedRequest) -> FailedRequest {
+        let mut failed = failed.clone();
+        let truncate = |text: &mut String| {
+            if let Some((end, _)) = text.char_indices().nth(self.max_content_chars) {
+                text.truncate(end);
+            }
+        };
+        for message in &mut failed.request.messages {
+            match &mut message.content {
+                ChatMessageContent::Text(text) => truncate(text),
+                ChatMessageContent::Array(parts) => {
+                    for part in parts {
+                        if let Some(text) = part.text.as_mut() {
+                            truncate(text);
+                        }
+                        // 不在base64中间截断，只保留data URI的媒体类型
+                        if let Some(image) = part.image_url.as_mut() {
+                            if let Some((header, data)) = image.url.strip_prefix("data:").and_then(|url| url.split_once(',')) {
+                                image.url = format!("data:{},<省略{}字节>", header, data.len());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed(id: &str) -> FailedRequest {
+        let request: ChatCompletionRequest = serde_json::from_str(
+            r#"{"model": "alias", "user": "end-user-1", "messages": [{"role": "user", "content": "hi"}]}"#,
+        )
+        .unwrap();
+        FailedRequest::new(id, "deepseek", request, "upstream parse error".to_string())
+    }
+
+    #[test]
+    fn test_keeps_most_recent() {
+        let buffer = FailedRequests::new(2, 100);
+        for id in ["a", "b", "c"] {
+            buffer.record(failed(id));
+        }
+
+        let ids: Vec<String> = buffer.list().into_iter().map(|failed| failed.id).collect();
+        assert_eq!(ids, ["c", "b"]);
+        assert!(buffer.get("a").is_none());
+
+        let entry = buffer.get("b").unwrap();
+        assert_eq!(entry.request.model.as_deref(), Some("deepseek"));
+        assert_eq!(entry.request.user, None);
+    }
+
+    #[test]
+    fn test_truncates_only_listing() {
+        let buffer = FailedRequests::new(1, 3);
+        let request: ChatCompletionRequest = serde_json::from_str(
+            r#"{"messages": [
+                {"role": "user", "content": "机密内容很长"},
+                {"role": "user", "content": [{"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}}]}
+            ]}"#,
+        )
+        .unwrap();
+        buffer.record(FailedRequest::new("a", "deepseek", request, "error".to_string()));
+
+        let listed = &buffer.list()[0].request.messages;
+        assert_eq!(serde_json::to_value(&listed[0].content).unwrap(), "机密内");
+        assert_eq!(
+            serde_json::to_value(&listed[1].content).unwrap()[0]["image_url"]["url"],
+            "data:image/png;base64,<省略12字节>"
+        );
+
+        // 重放使用完整请求
+        let entry = buffer.get("a").unwrap();
+        assert_eq!(serde_json::to_value(&entry.request.messages[0].content).unwrap(), "机密内容很长");
+        assert_eq!(
+            serde_json::to_value(&entry.request.messages[1].content).unwrap()[0]["image_url"]["url"],
+            "data:image/png;base64,iVBORw0KGgo="
+        );
+    }
+
+    #[test]
+    fn test_disabled_keeps_nothing() {
+        let buffer = FailedRequests::new(0, 100);
+        buffer.record(failed("a"));
+        assert!(buffer.list().is_empty());
+    }
+}
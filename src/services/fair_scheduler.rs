@@ -0,0 +1,285 @@
+use crate::error::{AppError, AppResult};
+use crate::models::ApiKeyPriority;
+use parking_lot::Mutex;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// 排队中的请求
+struct Waiter {
+    priority: ApiKeyPriority,
+    finish: f64,
+    seq: u64,
+    tx: oneshot::Sender<FairPermit>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    // BinaryHeap是大顶堆：高优先级优先，同优先级内虚拟完成时间最小者优先
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.finish.total_cmp(&self.finish))
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// 一个账号池的队列
+struct SchedulerState {
+    in_use: usize,
+    capacity: usize, // 最近一次观察到的账号池容量
+    virtual_time: f64,
+    last_finish: HashMap<String, f64>, // api_key -> 上次分配的虚拟完成时间
+    queue: BinaryHeap<Waiter>,
+    seq: u64,
+}
+
+impl SchedulerState {
+    fn new() -> Self {
+        Self {
+            in_use: 0,
+            capacity: 1,
+            virtual_time: 0.0,
+            last_finish: HashMap::new(),
+            queue: BinaryHeap::new(),
+            seq: 0,
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.in_use == 0 && self.queue.is_empty()
+    }
+}
+
+/// 加权公平调度器：账号容量紧张时按API密钥优先级和权重分配会话槽位；
+/// 每个账号池单独排队，一个账号池满载时不影响使用其他账号的API密钥
+pub struct FairScheduler {
+    pools: Mutex<HashMap<String, SchedulerState>>, // 账号池标识 -> 队列
+    queue_timeout: Duration,
+    preempt_low_priority: bool, // 高优先级请求排队时是否直接拒绝排队中的低优先级请求
+}
+
+/// 调度许可，释放时把槽位交给同一账号池的下一个排队者
+pub struct FairPermit {
+    scheduler: Option<Arc<FairScheduler>>, // None表示槽位已转交，释放时不做处理
+    pool: String,
+}
+
+impl FairScheduler {
+    pub fn new(queue_timeout: Duration, preempt_low_priority: bool) -> Self {
+        Self {
+            pools: Mutex::new(HashMap::new()),
+            queue_timeout,
+            preempt_low_priority,
+        }
+    }
+
+    /// 在账号池中获取一个槽位，capacity为该账号池当前的容量
+    pub async fn acquire(
+        self: &Arc<Self>,
+        pool: &str,
+        api_key: &str,
+        weight: u32,
+        priority: ApiKeyPriority,
+        capacity: usize,
+    ) -> AppResult<FairPermit> {
+        let rx = {
+            let mut pools = self.pools.lock();
+            let state = pools.entry(pool.to_string()).or_insert_with(SchedulerState::new);
+            let start = state
+                .last_finish
+                .get(api_key)
+                .copied()
+                .unwrap_or(0.0)
+                .max(state.virtual_time);
+            let finish = start + 1.0 / weight.max(1) as f64;
+            state.last_finish.insert(api_key.to_string(), finish);
+
+            // 超时或被取消的排队者不再占位
+            state.queue.retain(|waiter| !waiter.tx.is_closed());
+            state.capacity = capacity.max(1);
+            if state.in_use < state.capacity && state.queue.is_empty() {
+                state.in_use += 1;
+                state.virtual_time = state.virtual_time.max(start);
+                return Ok(FairPermit { scheduler: Some(self.clone()), pool: pool.to_string() });
+            }
+
+            // 抢占：丢弃排队中的低优先级请求，它们会立即收到拒绝
+            if self.preempt_low_priority && priority == ApiKeyPriority::High {
+                let waiters = std::mem::take(&mut state.queue).into_vec();
+                let (kept, preempted): (Vec<Waiter>, Vec<Waiter>) = waiters
+                    .into_iter()
+                    .partition(|waiter| waiter.priority > ApiKeyPriority::Low);
+                if !preempted.is_empty() {
+                    tracing::info!("Preempted {} queued low-priority requests", preempted.len());
+                }
+                state.queue = kept.into();
+            }
+
+            let (tx, rx) = oneshot::channel();
+            state.seq += 1;
+            let seq = state.seq;
+            state.queue.push(Waiter { priority, finish, seq, tx });
+            rx
+        };
+
+        match tokio::time::timeout(self.queue_timeout, rx).await {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(_)) => Err(AppError::ServiceUnavailable(
+                "请求被高优先级流量抢占，请稍后重试".to_string(),
+            )),
+            Err(_) => Err(AppError::ServiceUnavailable(
+                "账号容量繁忙，排队超时".to_string(),
+            )),
+        }
+    }
+
+    /// 更新各账号池的容量（账号增删或并发上限变化时调用），容量增加时立即放行排队者；
+    /// 不再存在且空闲的账号池被移除
+    pub fn set_capacities(self: &Arc<Self>, capacities: &HashMap<String, usize>) {
+        let mut pools = self.pools.lock();
+        pools.retain(|pool, state| capacities.contains_key(pool) || !state.is_idle());
+        for (pool, state) in pools.iter_mut() {
+            let Some(&capacity) = capacities.get(pool) else {
+                continue;
+            };
+            state.capacity = capacity.max(1);
+            while state.in_use < state.capacity && self.grant_next(pool, state) {
+                state.in_use += 1;
+            }
+        }
+    }
+
+    /// 按虚拟完成时间把许可交给下一个排队者，没有排队者时返回false；
+    /// 排队者在收到许可后被取消时，许可随通道一起释放，槽位不会丢失
+    fn grant_next(self: &Arc<Self>, pool: &str, state: &mut SchedulerState) -> bool {
+        while let Some(waiter) = state.queue.pop() {
+            let finish = waiter.finish;
+            match waiter.tx.send(FairPermit { scheduler: Some(self.clone()), pool: pool.to_string() }) {
+                Ok(()) => {
+                    state.virtual_time = state.virtual_time.max(finish);
+                    return true;
+                }
+                // 排队者已离开，收回许可（持有锁时不能触发释放）
+                Err(mut permit) => {
+                    permit.scheduler = None;
+                }
+            }
+        }
+        false
+    }
+
+    /// 释放槽位，容量未缩减时把槽位直接转交给下一个排队者
+    fn release(self: &Arc<Self>, pool: &str) {
+        let mut pools = self.pools.lock();
+        let Some(state) = pools.get_mut(pool) else {
+            return;
+        };
+        if state.in_use <= state.capacity && self.grant_next(pool, state) {
+            return;
+        }
+        state.in_use = state.in_use.saturating_sub(1);
+    }
+}
+
+impl Drop for FairPermit {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            scheduler.release(&self.pool);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_quiet_key_is_served_before_chatty_key() {
+        let scheduler = Arc::new(FairScheduler::new(Duration::from_secs(5), false));
+        let first = scheduler.acquire("pool", "chatty", 1, ApiKeyPriority::Normal, 1).await.unwrap();
+
+        let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+        for key in ["chatty", "chatty", "quiet"] {
+            let scheduler = scheduler.clone();
+            let order_tx = order_tx.clone();
+            tokio::spawn(async move {
+                let _permit = scheduler.acquire("pool", key, 1, ApiKeyPriority::Normal, 1).await.unwrap();
+                order_tx.send(key).unwrap();
+            });
+            tokio::task::yield_now().await;
+        }
+
+        drop(first);
+        assert_eq!(order_rx.recv().await, Some("quiet"));
+        assert_eq!(order_rx.recv().await, Some("chatty"));
+        assert_eq!(order_rx.recv().await, Some("chatty"));
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_preempts_low_priority() {
+        let scheduler = Arc::new(FairScheduler::new(Duration::from_secs(5), true));
+        let first = scheduler.acquire("pool", "prod", 1, ApiKeyPriority::High, 1).await.unwrap();
+
+        let low = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move { scheduler.acquire("pool", "exp", 1, ApiKeyPriority::Low, 1).await.map(|_| ()) })
+        };
+        tokio::task::yield_now().await;
+
+        let high = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move { scheduler.acquire("pool", "prod", 1, ApiKeyPriority::High, 1).await.map(|_| ()) })
+        };
+        tokio::task::yield_now().await;
+
+        assert!(low.await.unwrap().is_err());
+        drop(first);
+        assert!(high.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_waiters_do_not_block_free_capacity() {
+        let scheduler = Arc::new(FairScheduler::new(Duration::from_millis(10), false));
+        let first = scheduler.acquire("pool", "a", 1, ApiKeyPriority::Normal, 1).await.unwrap();
+        assert!(scheduler.acquire("pool", "b", 1, ApiKeyPriority::Normal, 1).await.is_err());
+
+        // 槽位释放后不因已超时的排队者而重新排队
+        drop(first);
+        let second = scheduler.acquire("pool", "c", 1, ApiKeyPriority::Normal, 1).await.unwrap();
+        assert_eq!(scheduler.pools.lock()["pool"].in_use, 1);
+        drop(second);
+        assert_eq!(scheduler.pools.lock()["pool"].in_use, 0);
+    }
+
+    #[tokio::test]
+    async fn test_capacity_increase_wakes_waiters() {
+        let scheduler = Arc::new(FairScheduler::new(Duration::from_secs(5), false));
+        let _first = scheduler.acquire("pool", "a", 1, ApiKeyPriority::Normal, 1).await.unwrap();
+
+        let waiter = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move { scheduler.acquire("pool", "b", 1, ApiKeyPriority::Normal, 1).await.map(|_| ()) })
+        };
+        tokio::task::yield_now().await;
+
+        // 不等已有许可释放，容量增加后排队者立即获得槽位
+        scheduler.set_capacities(&HashMap::from([("pool".to_string(), 2)]));
+        assert!(waiter.await.unwrap().is_ok());
+    }
+}
@@ -0,0 +1,241 @@
+use crate::config::GenerationConfig;
+use crate::utils::{generate_uuid_simple, unix_timestamp};
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenerationStatus {
+    InProgress,
+    Completed,
+    Failed,
+}
+
+/// 轮询方式的一次补全：服务端缓存的流式数据块
+#[derive(Debug)]
+struct Generation {
+    chunks: Vec<Value>,
+    status: GenerationStatus,
+    error: Option<Value>,
+    updated_at: u64,
+}
+
+struct Entry {
+    id: String,
+    owner: String, // 调用方的哈希，只有发起补全的调用方可以读取
+    model: String,
+    created: u64,
+    state: Mutex<Generation>,
+    notify: Notify,
+}
+
+/// 一次读取的结果：cursor之后的数据块和下一次读取使用的cursor
+#[derive(Debug, Serialize)]
+pub struct GenerationPage {
+    pub id: String,
+    pub object: &'static str,
+    pub model: String,
+    pub created: u64,
+    pub status: GenerationStatus,
+    pub chunks: Vec<Value>,
+    pub cursor: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+}
+
+impl Entry {
+    fn page(&self, cursor: usize) -> GenerationPage {
+        let mut state = self.state.lock();
+        state.updated_at = unix_timestamp();
+        let cursor = cursor.min(state.chunks.len());
+        GenerationPage {
+            id: self.id.clone(),
+            object: "chat.completion.generation",
+            model: self.model.clone(),
+            created: self.created,
+            status: state.status,
+            chunks: state.chunks[cursor..].to_vec(),
+            cursor: state.chunks.len(),
+            error: state.error.clone(),
+        }
+    }
+
+    /// 有cursor之后的新数据或补全已结束
+    fn ready(&self, cursor: usize) -> bool {
+        let state = self.state.lock();
+        state.chunks.len() > cursor || state.status != GenerationStatus::InProgress
+    }
+}
+
+/// 写入一次补全的数据块，由转发上游流的任务持有
+pub struct GenerationWriter {
+    entry: Arc<Entry>,
+}
+
+impl GenerationWriter {
+    pub fn id(&self) -> &str {
+        &self.entry.id
+    }
+
+    pub fn created(&self) -> u64 {
+        self.entry.created
+    }
+
+    pub fn push(&self, chunk: Value) {
+        let mut state = self.entry.state.lock();
+        state.chunks.push(chunk);
+        state.updated_at = unix_timestamp();
+        drop(state);
+        self.entry.notify.notify_waiters();
+    }
+
+    /// 补全已被调用方删除，不再需要继续读取上游
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.entry) == 1
+    }
+
+    /// 补全结束，error为OpenAI格式的错误对象
+    pub fn finish(&self, error: Option<Value>) {
+        let mut state = self.entry.state.lock();
+        state.status = match error {
+            Some(_) => GenerationStatus::Failed,
+            None => GenerationStatus::Completed,
+        };
+        state.error = error;
+        state.updated_at = unix_timestamp();
+        drop(state);
+        self.entry.notify.notify_waiters();
+    }
+}
+
+/// 轮询方式（transport=poll）的补全，在内存中缓存数据块，供无法接收SSE的客户端分段读取。
+/// 结束或最后一次读取后超过保留时间的补全被清除
+pub struct GenerationStore {
+    generations: Mutex<HashMap<String, Arc<Entry>>>,
+    ttl_secs: u64,
+    max_wait: Duration,
+}
+
+impl GenerationStore {
+    pub fn new(config: &GenerationConfig) -> Self {
+        Self {
+            generations: Mutex::new(HashMap::new()),
+            ttl_secs: config.ttl_secs,
+            max_wait: Duration::from_secs(config.max_wait_secs),
+        }
+    }
+
+    fn owner(caller: &str) -> String {
+        hex::encode(&Sha256::digest(caller.as_bytes())[..16])
+    }
+
+    pub fn create(&self, caller: &str, model: &str) -> GenerationWriter {
+        let now = unix_timestamp();
+        let entry = Arc::new(Entry {
+            id: format!("gen-{}", generate_uuid_simple()),
+            owner: Self::owner(caller),
+            model: model.to_string(),
+            created: now,
+            state: Mutex::new(Generation {
+                chunks: Vec::new(),
+                status: GenerationStatus::InProgress,
+                error: None,
+                updated_at: now,
+            }),
+            notify: Notify::new(),
+        });
+        let mut generations = self.generations.lock();
+        self.prune(&mut generations);
+        generations.insert(entry.id.clone(), entry.clone());
+        GenerationWriter { entry }
+    }
+
+    /// 读取cursor之后的数据块；没有新数据时最多等待wait（不超过配置的上限），
+    /// 其他调用方的补全视为不存在
+    pub async fn poll(&self, id: &str, caller: &str, cursor: usize, wait: Option<Duration>) -> Option<GenerationPage> {
+        let entry = self
+            .generations
+            .lock()
+            .get(id)
+            .filter(|entry| entry.owner == Self::owner(caller))
+            .cloned()?;
+        let wait = wait.map_or(self.max_wait, |wait| wait.min(self.max_wait));
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            // 先注册通知再检查，避免检查和等待之间写入的数据块被错过
+            let mut notified = pin!(entry.notify.notified());
+            notified.as_mut().enable();
+            if entry.ready(cursor) || tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return Some(entry.page(cursor));
+            }
+        }
+    }
+
+    pub fn remove(&self, id: &str, caller: &str) -> bool {
+        let mut generations = self.generations.lock();
+        match generations.get(id) {
+            Some(entry) if entry.owner == Self::owner(caller) => generations.remove(id).is_some(),
+            _ => false,
+        }
+    }
+
+    /// 清除已结束且超过保留时间的补全，进行中的补全不清除
+    fn prune(&self, generations: &mut HashMap<String, Arc<Entry>>) {
+        let cutoff = unix_timestamp().saturating_sub(self.ttl_secs);
+        generations.retain(|_, entry| {
+            let state = entry.state.lock();
+            state.status == GenerationStatus::InProgress || state.updated_at >= cutoff
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_generation_poll() {
+        let store = Arc::new(GenerationStore::new(&Config::default().generations));
+        let writer = store.create("dsk-test", "deepseek-chat");
+        let id = writer.id().to_string();
+        assert!(store.poll(&id, "dsk-other", 0, None).await.is_none());
+
+        let page = store.poll(&id, "dsk-test", 0, Some(Duration::ZERO)).await.unwrap();
+        assert_eq!(page.status, GenerationStatus::InProgress);
+        assert!(page.chunks.is_empty());
+
+        // 等待中的读取在新数据块写入后返回
+        let waiting = tokio::spawn({
+            let store = store.clone();
+            let id = id.clone();
+            async move { store.poll(&id, "dsk-test", 0, Some(Duration::from_secs(5))).await }
+        });
+        tokio::task::yield_now().await;
+        writer.push(json!({"choices": [{"delta": {"content": "a"}}]}));
+        let page = waiting.await.unwrap().unwrap();
+        assert_eq!(page.chunks.len(), 1);
+        assert_eq!(page.cursor, 1);
+
+        writer.push(json!({"choices": [{"delta": {"content": "b"}}]}));
+        writer.finish(None);
+        let page = store.poll(&id, "dsk-test", 1, None).await.unwrap();
+        assert_eq!(page.status, GenerationStatus::Completed);
+        assert_eq!(page.chunks, vec![json!({"choices": [{"delta": {"content": "b"}}]})]);
+        assert_eq!(page.cursor, 2);
+
+        assert!(!store.remove(&id, "dsk-other"));
+        assert!(!writer.is_abandoned());
+        assert!(store.remove(&id, "dsk-test"));
+        assert!(writer.is_abandoned());
+        assert!(store.poll(&id, "dsk-test", 0, None).await.is_none());
+    }
+}
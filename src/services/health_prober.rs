@@ -0,0 +1,223 @@
+use crate::config::HttpClientConfig;
+use crate::models::{ChatMessage, ChatMessageContent, CompletionOptions};
+use crate::services::{DeepSeekClient, RequestContext};
+use crate::utils::unix_timestamp;
+use parking_lot::RwLock;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+/// 单项探测结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    pub latency_ms: u64,
+    pub detail: String,
+}
+
+/// 上游整体状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamState {
+    Unknown,             // 尚未完成第一次探测
+    Ok,
+    DeepseekUnreachable, // 无法连接chat.deepseek.com
+    DeepseekDegraded,    // 能连接但登录接口异常
+    ProxyBroken,         // DeepSeek正常但金丝雀补全失败，问题多半在本服务
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamStatus {
+    pub state: UpstreamState,
+    pub checked_at: Option<u64>,
+    pub checks: Vec<ProbeCheck>,
+}
+
+/// 上游健康探测器，同时缓存本机依赖（存储目录、WASM文件）的检查结果
+pub struct HealthProber {
+    client: Client,
+    base_url: String,
+    status: RwLock<UpstreamStatus>,
+    dependencies: RwLock<Vec<ProbeCheck>>, // 后台定期检查的结果，尚未检查时为空
+}
+
+impl HealthProber {
+    pub fn new(base_url: &str, http: &HttpClientConfig) -> Self {
+        let client = http
+            .with_network(Client::builder().timeout(Duration::from_secs(10)))
+            .and_then(|builder| builder.build())
+            .expect("Failed to create HTTP client");
+
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            status: RwLock::new(UpstreamStatus {
+                state: UpstreamState::Unknown,
+                checked_at: None,
+                checks: Vec::new(),
+            }),
+            dependencies: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// 最近一次本机依赖检查的结果
+    pub fn dependencies(&self) -> Vec<ProbeCheck> {
+        self.dependencies.read().clone()
+    }
+
+    /// 在阻塞线程池中检查存储目录可写和WASM文件有效，probe_upstream时同时检查上游连通性（未启用定期探测时）；
+    /// 结果只给出概要，具体错误（含路径）只写入日志
+    pub async fn check_dependencies(&self, storage_path: &str, wasm_path: &str, probe_upstream: bool) {
+        let (storage_path, wasm_path) = (storage_path.to_string(), wasm_path.to_string());
+        let checks = tokio::task::spawn_blocking(move || {
+            let started = Instant::now();
+            let storage = crate::check::check_writable(&storage_path);
+            let storage = check("storage", summarize("storage", storage, "存储目录可写", "存储目录不可写"), started);
+            let started = Instant::now();
+            let wasm = crate::check::check_wasm_file(&wasm_path);
+            let wasm = check("wasm", summarize("wasm", wasm, "WASM文件有效", "WASM文件无效"), started);
+            vec![storage, wasm]
+        })
+        .await;
+        let mut checks = match checks {
+            Ok(checks) => checks,
+            Err(e) => {
+                tracing::warn!("Dependency check task failed: {}", e);
+                return;
+            }
+        };
+        if probe_upstream {
+            checks.push(ProbeCheck { name: "upstream", ..self.check_reachability().await });
+        }
+        *self.dependencies.write() = checks;
+    }
+
+    /// 最近一次探测结果
+    pub fn status(&self) -> UpstreamStatus {
+        self.status.read().clone()
+    }
+
+    /// 检查能否连接DeepSeek
+    pub async fn check_reachability(&self) -> ProbeCheck {
+        let started = Instant::now();
+        let reachability = match self.client.get(&self.base_url).send().await {
+            Ok(response) => (response.status().as_u16() < 500, format!("HTTP {}", response.status())),
+            Err(e) => (false, e.to_string()),
+        };
+        check("reachability", reachability, started)
+    }
+
+    /// 执行一轮探测，canary_token为用于金丝雀补全的账号
+    pub async fn probe(&self, deepseek: &DeepSeekClient, canary_token: Option<&str>) {
+        let mut checks = vec![self.check_reachability().await];
+
+        // 登录接口：空请求返回4xx说明接口在线
+        let started = Instant::now();
+        let login_url = format!("{}/api/v0/users/login", self.base_url);
+        let login = match self.client.post(&login_url).json(&json!({})).send().await {
+            Ok(response) => (response.status().as_u16() < 500, format!("HTTP {}", response.status())),
+            Err(e) => (false, e.to_string()),
+        };
+        checks.push(check("login_endpoint", login, started));
+
+        if let Some(token) = canary_token {
+            let started = Instant::now();
+            let messages = [ChatMessage {
+                role: "user".to_string(),
+                content: ChatMessageContent::Text("ping".to_string()),
+                name: None,
+                tool_call_id: None,
+                tool_calls: None,
+                reasoning_content: None,
+            }];
+            let ctx = RequestContext::new();
+            let options = CompletionOptions {
+                continue_final_message: false,
+                features: Default::default(),
+                debug_upstream: false,
+                overrides: Default::default(),
+                chaos: None,
+            };
+            let canary = match deepseek.create_completion("deepseek", &messages, token, None, options, &ctx).await {
+                Ok(_) => (true, "completion succeeded".to_string()),
+                Err(e) => (false, e.to_string()),
+            };
+            checks.push(check("canary_completion", canary, started));
+        }
+
+        let state = classify(&checks);
+        if state != UpstreamState::Ok {
+            tracing::warn!("Upstream status: {:?}", state);
+        }
+
+        *self.status.write() = UpstreamStatus {
+            state,
+            checked_at: Some(unix_timestamp()),
+            checks,
+        };
+    }
+}
+
+fn summarize(name: &str, result: Result<String, String>, ok: &str, failed: &str) -> (bool, String) {
+    match result {
+        Ok(_) => (true, ok.to_string()),
+        Err(e) => {
+            tracing::warn!("Dependency check {} failed: {}", name, e);
+            (false, failed.to_string())
+        }
+    }
+}
+
+fn check(name: &'static str, (ok, detail): (bool, String), started: Instant) -> ProbeCheck {
+    ProbeCheck {
+        name,
+        ok,
+        latency_ms: started.elapsed().as_millis() as u64,
+        detail,
+    }
+}
+
+/// 根据各项探测结果判断故障位置
+fn classify(checks: &[ProbeCheck]) -> UpstreamState {
+    let passed = |name: &str| checks.iter().find(|c| c.name == name).map(|c| c.ok);
+
+    if passed("reachability") == Some(false) {
+        UpstreamState::DeepseekUnreachable
+    } else if passed("login_endpoint") == Some(false) {
+        UpstreamState::DeepseekDegraded
+    } else if passed("canary_completion") == Some(false) {
+        UpstreamState::ProxyBroken
+    } else {
+        UpstreamState::Ok
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checks(results: &[(&'static str, bool)]) -> Vec<ProbeCheck> {
+        results
+            .iter()
+            .map(|&(name, ok)| ProbeCheck { name, ok, latency_ms: 0, detail: String::new() })
+            .collect()
+    }
+
+    #[test]
+    fn test_classify_distinguishes_proxy_from_upstream() {
+        assert_eq!(
+            classify(&checks(&[("reachability", false), ("login_endpoint", false)])),
+            UpstreamState::DeepseekUnreachable
+        );
+        assert_eq!(
+            classify(&checks(&[("reachability", true), ("login_endpoint", true), ("canary_completion", false)])),
+            UpstreamState::ProxyBroken
+        );
+        assert_eq!(
+            classify(&checks(&[("reachability", true), ("login_endpoint", true)])),
+            UpstreamState::Ok
+        );
+    }
+}
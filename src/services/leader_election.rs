@@ -0,0 +1,165 @@
+use crate::config::ClusterConfig;
+use crate::error::{AppError, AppResult};
+use parking_lot::Mutex;
+use redis::{AsyncConnectionConfig, Script};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// 已持有租约时续约，租约空闲时获取；返回1表示本实例是领导者
+const ACQUIRE_SCRIPT: &str = r"
+local holder = redis.call('GET', KEYS[1])
+if holder == ARGV[1] then
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+    return 1
+end
+if not holder then
+    redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+    return 1
+end
+return 0
+";
+
+/// 基于Redis租约的领导者选举：多个副本中只有持有租约的实例执行会写共享存储的后台任务
+pub struct LeaderElection {
+    client: Option<redis::Client>, // None表示单实例，始终是领导者
+    instance_id: String,
+    key: String,
+    lease: Duration,
+    is_leader: AtomicBool,
+    lease_expires_at: Mutex<Option<Instant>>, // 按本地时钟估计的租约到期时间，过期后不再认为自己是领导者
+}
+
+impl LeaderElection {
+    pub fn new(config: &ClusterConfig) -> AppResult<Self> {
+        let client = config
+            .redis_url
+            .as_deref()
+            .map(redis::Client::open)
+            .transpose()
+            .map_err(|e| AppError::Internal(format!("无效的REDIS_URL: {}", e)))?;
+
+        Ok(Self {
+            is_leader: AtomicBool::new(client.is_none()),
+            client,
+            instance_id: config.instance_id.clone(),
+            key: config.leader_key.clone(),
+            lease: Duration::from_secs(config.leader_lease_secs),
+            lease_expires_at: Mutex::new(None),
+        })
+    }
+
+    /// 本实例当前是否应执行独占的后台任务；续约卡住时租约到期后同样放弃，避免与接管的实例同时执行
+    pub fn is_leader(&self) -> bool {
+        if self.client.is_none() {
+            return true;
+        }
+        self.is_leader.load(Ordering::Relaxed)
+            && self.lease_expires_at.lock().is_some_and(|expires_at| Instant::now() < expires_at)
+    }
+
+    /// 本实例的标识
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// 续约间隔：租约时间的1/3，单实例时为None
+    pub fn renew_interval(&self) -> Option<Duration> {
+        self.client.as_ref().map(|_| self.lease / 3)
+    }
+
+    /// 获取或续约一次租约；Redis不可用时立即放弃领导者身份，宁可暂停任务也不重复执行
+    pub async fn renew(&self) {
+        let Some(client) = &self.client else {
+            return;
+        };
+
+        // 连接和响应的超时合计不超过租约的一半，Redis卡住时在租约到期前得到结果
+        let started_at = Instant::now();
+        let config = AsyncConnectionConfig::new()
+            .set_connection_timeout(self.lease / 4)
+            .set_response_timeout(self.lease / 4);
+        let result: redis::RedisResult<i64> = async {
+            let mut connection = client.get_multiplexed_async_connection_with_config(&config).await?;
+            Script::new(ACQUIRE_SCRIPT)
+                .key(&self.key)
+                .arg(&self.instance_id)
+                .arg(self.lease.as_millis() as u64)
+                .invoke_async(&mut connection)
+                .await
+        }
+        .await;
+
+        let leader = match result {
+            Ok(acquired) => acquired == 1,
+            Err(e) => {
+                warn!("Leader lease renewal failed: {}", e);
+                false
+            }
+        };
+
+        // 租约从发出请求时起算，本地估计不会晚于Redis中的实际到期时间
+        *self.lease_expires_at.lock() = leader.then(|| started_at + self.lease);
+        let was_leader = self.is_leader.swap(leader, Ordering::Relaxed);
+        if leader && !was_leader {
+            info!("Instance {} became leader for background jobs", self.instance_id);
+        } else if !leader && was_leader {
+            warn!("Instance {} lost leadership, pausing background jobs", self.instance_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn test_single_instance_always_leader() {
+        let election = LeaderElection::new(&Config::default().cluster).unwrap();
+        assert!(election.is_leader());
+        assert_eq!(election.renew_interval(), None);
+
+        election.renew().await;
+        assert!(election.is_leader());
+    }
+
+    #[test]
+    fn test_redis_starts_as_follower() {
+        let mut config = Config::default().cluster;
+        config.redis_url = Some("redis://127.0.0.1:6379".to_string());
+        config.leader_lease_secs = 30;
+
+        let election = LeaderElection::new(&config).unwrap();
+        assert!(!election.is_leader());
+        assert_eq!(election.renew_interval(), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_leadership_ends_when_lease_expires() {
+        let mut config = Config::default().cluster;
+        config.redis_url = Some("redis://127.0.0.1:6379".to_string());
+        let election = LeaderElection::new(&config).unwrap();
+
+        election.is_leader.store(true, Ordering::Relaxed);
+        *election.lease_expires_at.lock() = Some(Instant::now() + Duration::from_secs(30));
+        assert!(election.is_leader());
+
+        // 续约一直没有完成，租约到期后即使没有收到错误也放弃领导者身份
+        *election.lease_expires_at.lock() = Some(Instant::now() - Duration::from_millis(1));
+        assert!(!election.is_leader());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_redis_gives_up_leadership() {
+        let mut config = Config::default().cluster;
+        config.redis_url = Some("redis://127.0.0.1:1".to_string());
+        config.leader_lease_secs = 2;
+        let election = LeaderElection::new(&config).unwrap();
+        election.is_leader.store(true, Ordering::Relaxed);
+        *election.lease_expires_at.lock() = Some(Instant::now() + Duration::from_secs(2));
+
+        tokio::time::timeout(Duration::from_secs(1), election.renew()).await.unwrap();
+        assert!(!election.is_leader());
+    }
+}
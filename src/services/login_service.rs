@@ -1,31 +1,31 @@
+use crate::config::Config;
+use crate::services::{BrowserProfiles, CookieStore};
 use crate::error::{AppError, AppResult};
 use crate::models::*;
-use reqwest::{Client, cookie::Jar};
+use reqwest::{Client, cookie::Jar, header::HeaderMap};
 use std::sync::Arc;
 use std::time::{Duration, UNIX_EPOCH, SystemTime};
 use serde_json::{json, Value};
 use std::collections::HashMap;
 use tracing::{info, warn, error, debug};
-use base64::prelude::*;
-use chrono;
 
 pub struct LoginService {
     client: Client,
     base_url: String,
+    profiles: Arc<BrowserProfiles>,
+    cookies: Arc<CookieStore>,
 }
 
 impl LoginService {
-    pub fn new() -> Self {
-        // 创建一个支持cookie的HTTP客户端，使用更真实的浏览器特征
+    pub fn new(config: &Config, profiles: Arc<BrowserProfiles>, cookies: Arc<CookieStore>) -> Self {
+        // 创建一个支持cookie的HTTP客户端，使用更真实的浏览器特征，与其他上游请求使用同一个代理
         let _jar = Arc::new(Jar::default());
-        let client = Client::builder()
+        let builder = Client::builder()
             .cookie_store(true)
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36")
             .timeout(Duration::from_secs(30))
             .default_headers({
                 let mut headers = reqwest::header::HeaderMap::new();
                 headers.insert("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7".parse().unwrap());
-                headers.insert("Accept-Language", "zh-CN,zh;q=0.9,en;q=0.8".parse().unwrap());
                 headers.insert("Accept-Encoding", "gzip".parse().unwrap());
                 headers.insert("Connection", "keep-alive".parse().unwrap());
                 headers.insert("Upgrade-Insecure-Requests", "1".parse().unwrap());
@@ -34,13 +34,18 @@ impl LoginService {
                 headers.insert("Sec-Fetch-Site", "none".parse().unwrap());
                 headers.insert("Sec-Fetch-User", "?1".parse().unwrap());
                 headers
-            })
-            .build()
+            });
+        let client = config
+            .http
+            .with_network(builder)
+            .and_then(|builder| builder.build())
             .expect("Failed to create HTTP client");
 
         Self {
             client,
-            base_url: "https://chat.deepseek.com".to_string(),
+            base_url: config.deepseek.base_url.trim_end_matches('/').to_string(),
+            profiles,
+            cookies,
         }
     }
 
@@ -51,9 +56,8 @@ impl LoginService {
         // 直接尝试登录API，使用精确的浏览器请求头
         let login_url = format!("{}/api/v0/users/login", self.base_url);
         
-        // 生成设备ID
-        let timestamp = chrono::Utc::now().timestamp();
-        let device_id = base64::prelude::BASE64_STANDARD.encode(format!("web_device_{}_{}", timestamp, email.len()));
+        // 设备ID按账号持久化，同一账号每次登录都上报同一台设备
+        let device_id = self.cookies.device_id(email);
         
         let login_payload = json!({
             "area_code": "",
@@ -65,34 +69,29 @@ impl LoginService {
         });
 
         debug!("准备发送登录请求到: {}", login_url);
-        debug!("登录payload: {}", serde_json::to_string_pretty(&login_payload).unwrap_or_default());
+        // 密码可能来自凭据库解密，日志中只保留其余字段
+        let mut logged_payload = login_payload.clone();
+        logged_payload["password"] = json!("***");
+        debug!("登录payload: {}", serde_json::to_string_pretty(&logged_payload).unwrap_or_default());
 
-        // 发送登录请求，完全模拟浏览器
+        // 发送登录请求，完全模拟浏览器；浏览器指纹按邮箱分配，之后的userToken沿用同一指纹
+        let mut fingerprint = HeaderMap::new();
+        self.profiles.apply(email, &mut fingerprint);
         let login_response = self.client
             .post(&login_url)
             .header("Accept", "*/*")
             .header("Accept-Encoding", "gzip, deflate, br, zstd")
-            .header("Accept-Language", "zh-CN,zh;q=0.9,en;q=0.8,en-GB;q=0.7,en-US;q=0.6")
             .header("Cache-Control", "no-cache")
             .header("Content-Type", "application/json")
             .header("Origin", "https://chat.deepseek.com")
             .header("Pragma", "no-cache")
             .header("Priority", "u=1, i")
             .header("Referer", "https://chat.deepseek.com/sign_in")
-            .header("Sec-Ch-Ua", "\"Microsoft Edge\";v=\"131\", \"Chromium\";v=\"131\", \"Not_A Brand\";v=\"24\"")
-            .header("Sec-Ch-Ua-Arch", "\"x86\"")
-            .header("Sec-Ch-Ua-Bitness", "\"64\"")
-            .header("Sec-Ch-Ua-Mobile", "?0")
-            .header("Sec-Ch-Ua-Platform", "\"Windows\"")
-            .header("Sec-Ch-Ua-Platform-Version", "\"19.0.0\"")
             .header("Sec-Fetch-Dest", "empty")
             .header("Sec-Fetch-Mode", "cors")
             .header("Sec-Fetch-Site", "same-origin")
-            .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36 Edg/131.0.0.0")
-            .header("X-App-Version", "20241129.1")
-            .header("X-Client-Locale", "zh_CN")
-            .header("X-Client-Platform", "web")
-            .header("X-Client-Version", "1.3.0-auto-resume")
+            .header("Cookie", self.cookies.cookie_for(email))
+            .headers(fingerprint.clone())
             .json(&login_payload)
             .send()
             .await
@@ -130,7 +129,10 @@ impl LoginService {
         }
 
         // 6. 尝试通过不同方式获取token
-        let user_token = self.extract_user_token(&login_result).await?;
+        let user_token = self.extract_user_token(&login_result, fingerprint).await?;
+        // 之后用userToken发出的请求沿用登录时的设备身份和浏览器指纹
+        self.cookies.link(email, &user_token);
+        self.profiles.link(email, &user_token);
 
         info!("DeepSeek登录成功，获取到userToken: {}...", 
               &user_token[..std::cmp::min(20, user_token.len())]);
@@ -139,7 +141,7 @@ impl LoginService {
     }
 
     /// 从登录响应或后续请求中提取userToken
-    async fn extract_user_token(&self, login_response: &Value) -> AppResult<String> {
+    async fn extract_user_token(&self, login_response: &Value, fingerprint: HeaderMap) -> AppResult<String> {
         // 方法1: 从登录响应中直接获取
         if let Some(token) = login_response.get("data")
             .and_then(|d| d.get("token"))
@@ -156,7 +158,7 @@ impl LoginService {
         // 方法3: 访问用户信息页面获取token
         debug!("尝试从用户信息接口获取token");
         let user_info_url = format!("{}/api/v1/users/current", self.base_url);
-        let user_response = self.client.get(&user_info_url).send().await
+        let user_response = self.client.get(&user_info_url).headers(fingerprint.clone()).send().await
             .map_err(|e| AppError::ExternalApi(format!("获取用户信息失败: {}", e)))?;
 
         if user_response.status().is_success() {
@@ -175,7 +177,7 @@ impl LoginService {
         // 方法4: 尝试访问聊天页面，从页面中提取token
         debug!("尝试从聊天页面获取token");
         let chat_url = format!("{}/", self.base_url);
-        let chat_response = self.client.get(&chat_url).send().await
+        let chat_response = self.client.get(&chat_url).headers(fingerprint).send().await
             .map_err(|e| AppError::ExternalApi(format!("访问聊天页面失败: {}", e)))?;
 
         if chat_response.status().is_success() {
@@ -236,9 +238,12 @@ impl LoginService {
     /// 验证token是否有效
     pub async fn verify_token(&self, token: &str) -> AppResult<bool> {
         let verify_url = format!("{}/api/v1/chat/sessions", self.base_url);
-        
+        let mut fingerprint = HeaderMap::new();
+        self.profiles.apply(token, &mut fingerprint);
+
         let response = self.client
             .get(&verify_url)
+            .headers(fingerprint)
             .header("Authorization", format!("Bearer {}", token))
             .send()
             .await
@@ -265,6 +270,11 @@ impl LoginService {
 
 impl Default for LoginService {
     fn default() -> Self {
-        Self::new()
+        let config = Config::default();
+        Self::new(
+            &config,
+            Arc::new(BrowserProfiles::from_config(&config.deepseek)),
+            Arc::new(CookieStore::open("")),
+        )
     }
 }
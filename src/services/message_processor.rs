@@ -1,34 +1,77 @@
-use crate::models::{ChatMessage, ChatMessageContent};
-use crate::utils::{is_fold_model, is_search_model, is_silent_model, is_thinking_model};
+use crate::config::{MarkdownImageMode, PromptTemplate};
+use crate::models::{ChatMessage, ChatMessageContent, ModelFeatures, ReasoningDisplay};
+use crate::utils::estimate_tokens;
 use regex::Regex;
+use std::sync::LazyLock;
+
+/// markdown图片链接，捕获组1为替代文本
+static MARKDOWN_IMAGE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"!\[([^\]]*)\]\([^)]*\)").unwrap());
+
+/// 搜索结果的引用标记
+static CITATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[citation:\d+\]").unwrap());
 
 /// 消息处理器
 pub struct MessageProcessor;
 
 impl MessageProcessor {
-    /// 预处理聊天消息
-    pub fn prepare_messages(messages: &[ChatMessage]) -> String {
+    /// 预处理聊天消息，continue_final_message为true时最后一条assistant消息作为预填充，由模型接着续写
+    pub fn prepare_messages(messages: &[ChatMessage], template: &PromptTemplate, continue_final_message: bool) -> String {
         if messages.is_empty() {
             return String::new();
         }
 
         // 处理消息内容
-        let processed_messages: Vec<ProcessedMessage> = messages
-            .iter()
-            .map(|message| {
-                let text = Self::extract_text_content(&message.content);
-                ProcessedMessage {
-                    role: message.role.clone(),
-                    text,
-                }
-            })
-            .collect();
+        let processed_messages: Vec<ProcessedMessage> =
+            messages.iter().map(Self::process_message).collect();
 
-        // 合并连续相同角色的消息
+        // 合并连续相同角色的消息，超出token预算时丢弃最早的消息
         let merged_blocks = Self::merge_same_role_messages(processed_messages);
+        let merged_blocks = Self::truncate_history(merged_blocks, template);
 
         // 添加标签并连接结果
-        Self::format_messages_with_tags(&merged_blocks)
+        Self::format_messages_with_tags(&merged_blocks, template, continue_final_message)
+    }
+
+    /// 提取消息文本，工具结果按user消息处理并标注工具名，工具调用附加到assistant消息
+    fn process_message(message: &ChatMessage) -> ProcessedMessage {
+        let text = Self::extract_text_content(&message.content);
+        match message.role.as_str() {
+            "tool" | "function" => {
+                let name = message.name.as_deref().unwrap_or("tool");
+                let header = match &message.tool_call_id {
+                    Some(id) => format!("[工具 {}（调用 {}）返回结果]", name, id),
+                    None => format!("[工具 {} 返回结果]", name),
+                };
+                ProcessedMessage {
+                    role: "user".to_string(),
+                    text: format!("{}\n{}", header, text),
+                }
+            }
+            "assistant" => {
+                let calls = message.tool_calls.iter().flatten().map(|call| {
+                    format!(
+                        "[调用工具 {}（{}）：{}]",
+                        call.function.name, call.id, call.function.arguments
+                    )
+                });
+                let text = std::iter::once(text)
+                    .chain(calls)
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                ProcessedMessage {
+                    role: message.role.clone(),
+                    text,
+                }
+            }
+            _ => ProcessedMessage {
+                role: message.role.clone(),
+                text: match &message.name {
+                    Some(name) => format!("{}: {}", name, text),
+                    None => text,
+                },
+            },
+        }
     }
 
     /// 从内容中提取文本
@@ -74,91 +117,71 @@ impl MessageProcessor {
         merged_blocks
     }
 
-    /// 使用标签格式化消息
-    fn format_messages_with_tags(blocks: &[ProcessedMessage]) -> String {
+    /// 估算token数超出预算时从最早的消息开始丢弃，保留system消息和最近的消息，避免上游静默截断
+    fn truncate_history(mut blocks: Vec<ProcessedMessage>, template: &PromptTemplate) -> Vec<ProcessedMessage> {
+        if template.token_budget == 0 {
+            return blocks;
+        }
+
+        let mut total: usize = blocks.iter().map(|block| estimate_tokens(&block.text)).sum();
+        let mut dropped = 0;
+        while total > template.token_budget {
+            let protected_from = blocks.len().saturating_sub(template.keep_last_messages);
+            let Some(index) = blocks[..protected_from].iter().position(|block| block.role != "system") else {
+                break;
+            };
+            total -= estimate_tokens(&blocks.remove(index).text);
+            dropped += 1;
+        }
+
+        if dropped > 0 {
+            tracing::info!(dropped, estimated_tokens = total, "Truncated conversation history to fit the token budget");
+        }
         blocks
+    }
+
+    /// 使用模板中的角色标记格式化消息
+    fn format_messages_with_tags(blocks: &[ProcessedMessage], template: &PromptTemplate, continue_final_message: bool) -> String {
+        let prompt = blocks
             .iter()
             .enumerate()
             .map(|(index, block)| {
-                match block.role.as_str() {
-                    "assistant" => {
-                        format!("<｜Assistant｜>{}<｜end▁of▁sentence｜>", block.text)
+                let prefix = match block.role.as_str() {
+                    // 预填充的assistant消息不加结束标记
+                    "assistant" if continue_final_message && index == blocks.len() - 1 => {
+                        return format!("{}{}", template.assistant_prefix, block.text);
                     }
-                    "user" | "system" => {
-                        if index > 0 {
-                            format!("<｜User｜>{}", block.text)
-                        } else {
-                            block.text.clone()
-                        }
+                    "assistant" => {
+                        return format!("{}{}{}", template.assistant_prefix, block.text, template.assistant_suffix);
                     }
-                    _ => block.text.clone(),
+                    "system" => template.system_prefix.as_deref().unwrap_or(&template.user_prefix),
+                    "user" => &template.user_prefix,
+                    _ => return block.text.clone(),
+                };
+                if index > 0 || template.prefix_first_message {
+                    format!("{}{}", prefix, block.text)
+                } else {
+                    block.text.clone()
                 }
             })
             .collect::<Vec<_>>()
-            .join("")
-            .replace("![.*]\\(.*\\)", "") // 移除图片链接
-    }
-
-    /// 处理流式响应内容
-    pub fn process_stream_content(
-        content: &str,
-        model: &str,
-        thinking_active: &mut bool,
-        ref_content: &mut String,
-    ) -> Option<String> {
-        let is_thinking = is_thinking_model(model);
-        let is_search = is_search_model(model);
-        let is_silent = is_silent_model(model);
-        let is_fold = is_fold_model(model);
-
-        // 处理搜索结果
-        if is_search && !is_silent {
-            // 搜索结果处理逻辑
-            if content.contains("检索") {
-                ref_content.push_str(content);
-                ref_content.push('\n');
-                return Some(content.to_string());
-            }
-        }
+            .join(&template.separator);
+        Self::replace_markdown_images(&prompt, template.markdown_images)
+    }
 
-        // 处理思考模式
-        if is_thinking {
-            if is_fold {
-                // 折叠模式的思考处理
-                if !*thinking_active && content.contains("[思考") {
-                    *thinking_active = true;
-                    return Some("<details><summary>思考过程</summary><pre>".to_string());
-                } else if *thinking_active && content.contains("[思考结束]") {
-                    *thinking_active = false;
-                    return Some("</pre></details>".to_string());
-                }
-            } else if is_silent {
-                // 静默模式，不输出思考内容
-                if content.contains("[思考") || content.contains("思考过程") {
-                    return None;
-                }
-            } else {
-                // 普通思考模式
-                if !*thinking_active && content.contains("[思考") {
-                    *thinking_active = true;
-                    return Some("[思考开始]\n".to_string());
-                } else if *thinking_active && content.contains("[思考结束]") {
-                    *thinking_active = false;
-                    return Some("\n\n[思考结束]\n".to_string());
-                }
-            }
+    /// 按配置处理markdown图片链接
+    fn replace_markdown_images(text: &str, mode: MarkdownImageMode) -> String {
+        match mode {
+            MarkdownImageMode::Strip => MARKDOWN_IMAGE.replace_all(text, "").to_string(),
+            MarkdownImageMode::Keep => text.to_string(),
+            MarkdownImageMode::Alt => MARKDOWN_IMAGE.replace_all(text, "$1").to_string(),
+            MarkdownImageMode::Placeholder => MARKDOWN_IMAGE.replace_all(text, "[图片]").to_string(),
         }
-
-        // 移除引用标记
-        let cleaned_content = Self::remove_citations(content);
-
-        Some(cleaned_content)
     }
 
     /// 移除引用标记
     fn remove_citations(content: &str) -> String {
-        let citation_regex = Regex::new(r"\[citation:\d+\]").unwrap();
-        citation_regex.replace_all(content, "").to_string()
+        CITATION.replace_all(content, "").to_string()
     }
 
     /// 添加搜索结果引用
@@ -173,6 +196,204 @@ impl MessageProcessor {
     }
 }
 
+/// 可能被数据块截断的标记前缀
+const MARKER_PREFIXES: &[&str] = &["[citation:", "[思考"];
+
+/// 缓冲中的未完成标记超过该长度时视为普通文本输出
+const MAX_PENDING_MARKER_CHARS: usize = 32;
+
+/// 单个流的内容处理器：跨数据块缓冲未完成的引用标记和思考标记，只输出完整处理后的文本
+pub struct StreamProcessor {
+    is_thinking: bool,
+    is_search: bool,
+    is_silent: bool,
+    is_fold: bool,
+    thinking: ThinkingState,
+    ref_content: String,
+    pending: String,
+    pending_kind: Option<bool>, // 缓冲内容的增量类型：Some(true)思考，Some(false)正文，None未知
+    reasoning: Option<String>,  // 单独收集思考过程时的内容，此时正文中不再输出思考过程
+}
+
+impl StreamProcessor {
+    pub fn new(features: ModelFeatures) -> Self {
+        Self {
+            is_thinking: features.thinking,
+            is_search: features.search,
+            is_silent: features.reasoning_display == ReasoningDisplay::Hidden,
+            is_fold: features.reasoning_display == ReasoningDisplay::Fold,
+            thinking: ThinkingState::Answer,
+            ref_content: String::new(),
+            pending: String::new(),
+            pending_kind: None,
+            reasoning: None,
+        }
+    }
+
+    /// 内联显示的思考过程改为单独收集（非流式响应的reasoning_content），静默和折叠模式不受影响
+    pub fn with_separate_reasoning(mut self) -> Self {
+        if !self.is_silent && !self.is_fold {
+            self.reasoning = Some(String::new());
+        }
+        self
+    }
+
+    /// 单独收集的思考过程，没有思考内容时为None
+    pub fn take_reasoning(&mut self) -> Option<String> {
+        self.reasoning.as_mut().map(std::mem::take).filter(|reasoning| !reasoning.is_empty())
+    }
+
+    /// 输入一段增量内容和上游的增量类型（thinking/text），返回可以输出的文本；标记不完整时先缓冲
+    pub fn push(&mut self, delta: &str, delta_type: Option<&str>) -> Option<String> {
+        let kind = match delta_type {
+            Some("thinking") => Some(true),
+            Some("text") => Some(false),
+            _ => None,
+        };
+
+        // 增量类型变化时先输出之前缓冲的内容
+        let mut output = if kind != self.pending_kind { self.flush() } else { None };
+        self.pending_kind = kind;
+
+        self.pending.push_str(delta);
+        let split = Self::incomplete_marker_start(&self.pending).unwrap_or(self.pending.len());
+        if split > 0 {
+            let ready: String = self.pending.drain(..split).collect();
+            if let Some(text) = self.process(&ready, kind) {
+                output.get_or_insert_with(String::new).push_str(&text);
+            }
+        }
+        output
+    }
+
+    /// 输出缓冲中剩余的内容
+    fn flush(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let rest = std::mem::take(&mut self.pending);
+        self.process(&rest, self.pending_kind)
+    }
+
+    /// 流结束时输出剩余内容，并闭合未结束的思考过程
+    pub fn finish(&mut self) -> Option<String> {
+        let mut output = self.flush().unwrap_or_default();
+        self.leave_thinking(&mut output);
+        (!output.is_empty()).then_some(output)
+    }
+
+    /// 搜索模式下收集到的检索结果
+    pub fn references(&self) -> &str {
+        &self.ref_content
+    }
+
+    /// 缓冲末尾可能是被截断的标记时，返回其起始位置
+    fn incomplete_marker_start(text: &str) -> Option<usize> {
+        let start = text.rfind('[')?;
+        let tail = &text[start..];
+        if tail.contains(']') || tail.chars().count() > MAX_PENDING_MARKER_CHARS {
+            return None;
+        }
+
+        let partial = MARKER_PREFIXES.iter().any(|marker| marker.starts_with(tail) || tail.starts_with(marker));
+        partial.then_some(start)
+    }
+
+    fn process(&mut self, content: &str, thinking: Option<bool>) -> Option<String> {
+        // 处理搜索结果
+        if self.is_search && !self.is_silent && content.contains("检索") {
+            self.ref_content.push_str(content);
+            self.ref_content.push('\n');
+            return Some(content.to_string());
+        }
+
+        match thinking {
+            Some(thinking) => self.process_typed(content, thinking),
+            None => self.process_marked(content),
+        }
+    }
+
+    /// 按上游增量类型区分思考内容和正文
+    fn process_typed(&mut self, content: &str, thinking: bool) -> Option<String> {
+        let mut output = String::new();
+        if thinking {
+            self.enter_thinking(&mut output);
+        } else {
+            self.leave_thinking(&mut output);
+        }
+        self.push_text(&mut output, content);
+        (!output.is_empty()).then_some(output)
+    }
+
+    /// 上游未提供增量类型时，按内容中的思考标记切换状态，标记前后的文本照常输出
+    fn process_marked(&mut self, content: &str) -> Option<String> {
+        let mut output = String::new();
+        let mut rest = content;
+        if self.is_thinking {
+            while let Some((before, is_end, after)) = next_thinking_marker(rest) {
+                self.push_text(&mut output, before);
+                if is_end {
+                    self.leave_thinking(&mut output);
+                } else {
+                    self.enter_thinking(&mut output);
+                }
+                rest = after;
+            }
+        }
+        self.push_text(&mut output, rest);
+        (!output.is_empty()).then_some(output)
+    }
+
+    fn enter_thinking(&mut self, output: &mut String) {
+        if self.thinking == ThinkingState::Thinking {
+            return;
+        }
+        self.thinking = ThinkingState::Thinking;
+        if !self.is_silent && self.reasoning.is_none() {
+            output.push_str(if self.is_fold { "<details><summary>思考过程</summary><pre>" } else { "[思考开始]\n" });
+        }
+    }
+
+    fn leave_thinking(&mut self, output: &mut String) {
+        if self.thinking != ThinkingState::Thinking {
+            return;
+        }
+        self.thinking = ThinkingState::Answer;
+        if !self.is_silent && self.reasoning.is_none() {
+            output.push_str(if self.is_fold { "</pre></details>\n\n" } else { "\n\n[思考结束]\n" });
+        }
+    }
+
+    /// 输出文本：静默模式丢弃思考内容，折叠模式对思考内容做HTML转义以保证结构完整，单独收集时写入思考过程
+    fn push_text(&mut self, output: &mut String, text: &str) {
+        let text = MessageProcessor::remove_citations(text);
+        match (self.thinking, self.reasoning.as_mut()) {
+            (ThinkingState::Thinking, Some(reasoning)) => reasoning.push_str(&text),
+            (ThinkingState::Thinking, None) if self.is_silent => {}
+            (ThinkingState::Thinking, None) if self.is_fold => output.push_str(&escape_html(&text)),
+            _ => output.push_str(&text),
+        }
+    }
+}
+
+/// 思考过程的输出状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ThinkingState {
+    Answer,
+    Thinking,
+}
+
+/// 查找下一个思考标记，返回（标记前的文本, 是否为结束标记, 标记后的文本）
+fn next_thinking_marker(text: &str) -> Option<(&str, bool, &str)> {
+    let start = text.find("[思考")?;
+    let end = text[start..].find(']').map(|offset| start + offset + 1).unwrap_or(text.len());
+    Some((&text[..start], text[start..end].contains("结束"), &text[end..]))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
 #[derive(Debug, Clone)]
 struct ProcessedMessage {
     role: String,
@@ -182,7 +403,11 @@ struct ProcessedMessage {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::ContentPart;
+    use crate::models::{ContentPart, DeepSeekFeatures};
+
+    fn processor_for(model: &str) -> StreamProcessor {
+        StreamProcessor::new(DeepSeekFeatures::default().resolve(model, ""))
+    }
 
     #[test]
     fn test_extract_text_content() {
@@ -210,6 +435,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_replace_markdown_images() {
+        let text = "see ![chart](https://example.com/a.png) and ![](b.png)";
+        assert_eq!(MessageProcessor::replace_markdown_images(text, MarkdownImageMode::Strip), "see  and ");
+        assert_eq!(MessageProcessor::replace_markdown_images(text, MarkdownImageMode::Alt), "see chart and ");
+        assert_eq!(MessageProcessor::replace_markdown_images(text, MarkdownImageMode::Placeholder), "see [图片] and [图片]");
+        assert_eq!(MessageProcessor::replace_markdown_images(text, MarkdownImageMode::Keep), text);
+    }
+
     #[test]
     fn test_remove_citations() {
         let content = "This is a test [citation:1] with citations [citation:23].";
@@ -217,21 +451,195 @@ mod tests {
         assert_eq!(cleaned, "This is a test  with citations .");
     }
 
+    #[test]
+    fn test_stream_processor_buffers_split_markers() {
+        let mut processor = processor_for("deepseek");
+        let mut output = String::new();
+        for delta in ["Answer [cit", "ation:1", "2] done [", "not a marker]"] {
+            output.push_str(&processor.push(delta, None).unwrap_or_default());
+        }
+        output.push_str(&processor.finish().unwrap_or_default());
+        assert_eq!(output, "Answer  done [not a marker]");
+
+        let mut thinking = processor_for("deepseek-r1");
+        assert_eq!(thinking.push("[思", None), None);
+        assert_eq!(thinking.push("考开始]", None).as_deref(), Some("[思考开始]\n"));
+    }
+
+    #[test]
+    fn test_stream_processor_uses_delta_type() {
+        let mut processor = processor_for("deepseek-r1");
+        let mut output = String::new();
+        for (delta, kind) in [("Let me think", "thinking"), (" more", "thinking"), ("Answer", "text")] {
+            output.push_str(&processor.push(delta, Some(kind)).unwrap_or_default());
+        }
+        assert_eq!(output, "[思考开始]\nLet me think more\n\n[思考结束]\nAnswer");
+
+        let mut silent = processor_for("deepseek-r1-silent");
+        assert_eq!(silent.push("hidden", Some("thinking")), None);
+        assert_eq!(silent.push("Answer", Some("text")).as_deref(), Some("Answer"));
+
+        // 单独收集思考过程时正文只保留回答，静默模式仍然丢弃
+        let mut separate = processor_for("deepseek-r1").with_separate_reasoning();
+        assert_eq!(separate.push("Let me think", Some("thinking")), None);
+        assert_eq!(separate.push("Answer", Some("text")).as_deref(), Some("Answer"));
+        assert_eq!(separate.finish(), None);
+        assert_eq!(separate.take_reasoning().as_deref(), Some("Let me think"));
+        let mut silent = processor_for("deepseek-r1-silent").with_separate_reasoning();
+        silent.push("hidden", Some("thinking"));
+        assert_eq!(silent.take_reasoning(), None);
+    }
+
+    #[test]
+    fn test_request_features_override_model_name() {
+        let request: crate::models::ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "deepseek-r1-search",
+            "messages": [],
+            "search_enabled": false,
+            "extra_body": {"deepseek": {"search_enabled": true, "reasoning_display": "fold"}}
+        }))
+        .unwrap();
+
+        let features = request.features().resolve("deepseek-r1-search", "");
+        assert!(!features.search);
+        assert!(features.thinking);
+        assert_eq!(features.reasoning_display, ReasoningDisplay::Fold);
+        assert_eq!(DeepSeekFeatures::default().resolve("deepseek-think-silent", "").reasoning_display, ReasoningDisplay::Hidden);
+    }
+
+    #[test]
+    fn test_stream_processor_fold_state_machine() {
+        let collect = |processor: &mut StreamProcessor, deltas: &[&str]| {
+            let mut output: String = deltas.iter().filter_map(|delta| processor.push(delta, None)).collect();
+            output.push_str(&processor.finish().unwrap_or_default());
+            output
+        };
+
+        // 标记跨数据块、与正文在同一数据块中
+        let mut processor = processor_for("deepseek-r1-fold");
+        let output = collect(&mut processor, &["[思", "考开始]a<b", " & c[思考", "结束]Answer"]);
+        assert_eq!(output, "<details><summary>思考过程</summary><pre>a&lt;b &amp; c</pre></details>\n\nAnswer");
+
+        // 流提前结束时闭合折叠块
+        let mut processor = processor_for("deepseek-r1-fold");
+        let output = collect(&mut processor, &["[思考开始]still thinking"]);
+        assert_eq!(output, "<details><summary>思考过程</summary><pre>still thinking</pre></details>\n\n");
+    }
+
     #[test]
     fn test_prepare_messages() {
         let messages = vec![
             ChatMessage {
                 role: "user".to_string(),
                 content: ChatMessageContent::Text("Hello".to_string()),
+                name: None,
+                tool_call_id: None,
+                tool_calls: None,
+                reasoning_content: None,
             },
             ChatMessage {
                 role: "assistant".to_string(),
                 content: ChatMessageContent::Text("Hi there!".to_string()),
+                name: None,
+                tool_call_id: None,
+                tool_calls: None,
+                reasoning_content: None,
             },
         ];
 
-        let result = MessageProcessor::prepare_messages(&messages);
+        let result = MessageProcessor::prepare_messages(&messages, &PromptTemplate::default(), false);
         assert!(result.contains("Hello"));
         assert!(result.contains("<｜Assistant｜>Hi there!<｜end▁of▁sentence｜>"));
     }
+
+    #[test]
+    fn test_truncate_history_keeps_system_and_recent() {
+        let template = PromptTemplate {
+            token_budget: 10,
+            keep_last_messages: 2,
+            ..PromptTemplate::default()
+        };
+        let long = "x".repeat(40); // 约10个token
+        let messages: Vec<ChatMessage> = [
+            ("system", "sys"),
+            ("user", long.as_str()),
+            ("assistant", long.as_str()),
+            ("user", "recent question"),
+            ("assistant", "recent answer"),
+        ]
+        .iter()
+        .map(|(role, text)| ChatMessage {
+            role: role.to_string(),
+            content: ChatMessageContent::Text(text.to_string()),
+            name: None,
+            tool_call_id: None,
+            tool_calls: None,
+            reasoning_content: None,
+        })
+        .collect();
+
+        let result = MessageProcessor::prepare_messages(&messages, &template, false);
+        assert!(result.starts_with("sys"));
+        assert!(!result.contains(&long));
+        assert!(result.contains("recent question") && result.contains("recent answer"));
+    }
+
+    #[test]
+    fn test_prepare_messages_with_tool_roles() {
+        let messages: Vec<ChatMessage> = serde_json::from_value(serde_json::json!([
+            {"role": "user", "name": "alice", "content": "What's the weather?"},
+            {"role": "assistant", "content": null, "tool_calls": [
+                {"id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}}
+            ]},
+            {"role": "tool", "tool_call_id": "call_1", "name": "get_weather", "content": "Sunny"}
+        ]))
+        .unwrap();
+
+        let result = MessageProcessor::prepare_messages(&messages, &PromptTemplate::default(), true);
+        assert!(result.starts_with("alice: What's the weather?"));
+        assert!(result.contains("<｜Assistant｜>[调用工具 get_weather（call_1）：{\"city\":\"Paris\"}]<｜end▁of▁sentence｜>"));
+        assert!(result.ends_with("<｜User｜>[工具 get_weather（调用 call_1）返回结果]\nSunny"));
+    }
+
+    #[test]
+    fn test_prepare_messages_with_custom_template() {
+        let template = PromptTemplate {
+            user_prefix: "User: ".to_string(),
+            assistant_prefix: "Assistant: ".to_string(),
+            assistant_suffix: String::new(),
+            system_prefix: Some("System: ".to_string()),
+            separator: "\n".to_string(),
+            prefix_first_message: true,
+            ..PromptTemplate::default()
+        };
+        let messages: Vec<ChatMessage> = [("system", "Be brief"), ("user", "Hi"), ("assistant", "Hello")]
+            .iter()
+            .map(|(role, text)| ChatMessage {
+                role: role.to_string(),
+                content: ChatMessageContent::Text(text.to_string()),
+                name: None,
+                tool_call_id: None,
+                tool_calls: None,
+                reasoning_content: None,
+            })
+            .collect();
+
+        let result = MessageProcessor::prepare_messages(&messages, &template, false);
+        assert_eq!(result, "System: Be brief\nUser: Hi\nAssistant: Hello");
+    }
+
+    #[test]
+    fn test_prepare_messages_with_assistant_prefill() {
+        let messages: Vec<ChatMessage> = serde_json::from_value(serde_json::json!([
+            {"role": "user", "content": "List three colors"},
+            {"role": "assistant", "content": "1. Red\n2."}
+        ]))
+        .unwrap();
+
+        let template = PromptTemplate::default();
+        let prefill = MessageProcessor::prepare_messages(&messages, &template, true);
+        assert!(prefill.ends_with("<｜Assistant｜>1. Red\n2."));
+        let closed = MessageProcessor::prepare_messages(&messages, &template, false);
+        assert!(closed.ends_with("<｜end▁of▁sentence｜>"));
+    }
 }
@@ -0,0 +1,159 @@
+use crate::config::MetricsConfig;
+use crate::services::request_stats::{RequestStatsReport, StatsSummary};
+use reqwest::{Client, Url};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// 单次推送的超时
+const PUSH_TIMEOUT_SECS: u64 = 10;
+
+/// 一次推送的指标快照
+pub struct MetricsSnapshot {
+    pub requests: RequestStatsReport,
+    pub live_accounts: usize,
+    pub leader: bool,
+}
+
+/// 把指标快照定期推送到Prometheus Pushgateway，适用于无法被抓取的部署（如NAT之后）
+pub struct MetricsPusher {
+    client: Client,
+    url: Url, // 带分组键的完整地址：<PUSHGATEWAY>/metrics/job/<job>/instance/<instance>
+}
+
+impl MetricsPusher {
+    /// 未配置METRICS_PUSH_URL时返回None
+    pub fn new(config: &MetricsConfig, instance_id: &str) -> Option<Self> {
+        let base = config.push_url.as_deref().filter(|url| !url.trim().is_empty())?;
+        let mut url = match Url::parse(base) {
+            Ok(url) => url,
+            Err(e) => {
+                warn!("METRICS_PUSH_URL无效，不推送指标: {}", e);
+                return None;
+            }
+        };
+        // 逐段添加，任务名和实例标识中的特殊字符会被转义
+        url.path_segments_mut()
+            .ok()?
+            .pop_if_empty()
+            .extend(["metrics", "job", &config.push_job, "instance", instance_id]);
+
+        Some(Self {
+            client: Client::builder()
+                .timeout(Duration::from_secs(PUSH_TIMEOUT_SECS))
+                .build()
+                .expect("Failed to create metrics push HTTP client"),
+            url,
+        })
+    }
+
+    /// 以PUT替换本实例分组下的全部指标，失败只记录警告
+    pub async fn push(&self, snapshot: &MetricsSnapshot) {
+        let request = self
+            .client
+            .put(self.url.clone())
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(render(snapshot));
+        match request.send().await {
+            Ok(response) if response.status().is_success() => debug!("Metrics pushed to {}", self.url),
+            Ok(response) => warn!("Metrics push failed: HTTP {}", response.status()),
+            Err(e) => warn!("Metrics push failed: {}", e),
+        }
+    }
+}
+
+/// 指标名后缀、说明和从统计摘要中取值的函数
+type Series = (&'static str, &'static str, fn(&StatsSummary) -> f64);
+
+/// 按Prometheus文本格式输出快照
+fn render(snapshot: &MetricsSnapshot) -> String {
+    let mut out = String::new();
+    gauge(&mut out, "deepseek_live_accounts", "Accounts in rotation and not cooling down", snapshot.live_accounts as f64);
+    gauge(&mut out, "deepseek_leader", "Whether this instance runs the exclusive background jobs", snapshot.leader as u8 as f64);
+    gauge(
+        &mut out,
+        "deepseek_stats_window_seconds",
+        "Length of the rolling window the request metrics cover",
+        snapshot.requests.window_secs as f64,
+    );
+
+    let groups = [("api_key_id", &snapshot.requests.by_api_key), ("model", &snapshot.requests.by_model)];
+    let series: [Series; 5] = [
+        ("requests", "Completions in the window", |s| s.requests as f64),
+        ("errors", "Failed completions in the window", |s| s.errors as f64),
+        ("latency_p50_ms", "Median completion latency in the window", |s| s.p50_latency_ms as f64),
+        ("latency_p95_ms", "95th percentile completion latency in the window", |s| s.p95_latency_ms as f64),
+        ("upstream_retries", "Upstream retries in the window", |s| s.upstream_retries as f64),
+    ];
+    for (label, summaries) in groups {
+        let dimension = label.trim_end_matches("_id");
+        for (suffix, help, value) in series {
+            labelled(&mut out, &format!("deepseek_{}_{}", dimension, suffix), help, label, summaries, value);
+        }
+    }
+    out
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}", name, help, name, name, value);
+}
+
+fn labelled(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    summaries: &BTreeMap<String, StatsSummary>,
+    value: fn(&StatsSummary) -> f64,
+) {
+    if summaries.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+    for (key, summary) in summaries {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, escape_label(key), value(summary));
+    }
+}
+
+/// 转义标签值中的反斜杠、引号和换行
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::services::RequestStats;
+
+    #[test]
+    fn test_push_url_includes_grouping_key() {
+        let mut config = Config::default().metrics;
+        config.push_url = Some("http://pushgateway:9091/".to_string());
+        let pusher = MetricsPusher::new(&config, "api 1").unwrap();
+        assert_eq!(
+            pusher.url.as_str(),
+            "http://pushgateway:9091/metrics/job/deepseek_free_api/instance/api%201"
+        );
+
+        config.push_url = None;
+        assert!(MetricsPusher::new(&config, "api-1").is_none());
+    }
+
+    #[test]
+    fn test_render_text_format() {
+        let stats = RequestStats::new(3600);
+        stats.record(Some("key\"a"), "deepseek", 120, true, 1);
+        let text = render(&MetricsSnapshot {
+            requests: stats.report(),
+            live_accounts: 3,
+            leader: true,
+        });
+
+        assert!(text.contains("# TYPE deepseek_live_accounts gauge\ndeepseek_live_accounts 3\n"));
+        assert!(text.contains("deepseek_leader 1\n"));
+        assert!(text.contains("deepseek_api_key_errors{api_key_id=\"key\\\"a\"} 1\n"));
+        assert!(text.contains("deepseek_model_latency_p95_ms{model=\"deepseek\"} 120\n"));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::config::DeepSeekConfig;
+use crate::utils::unix_timestamp;
+use reqwest::Client;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 探测失败或连接失败的镜像暂停使用的时间
+const MIRROR_FAILOVER_SECS: u64 = 60;
+
+/// 单次延迟探测的超时
+const MIRROR_PROBE_TIMEOUT_SECS: u64 = 5;
+
+/// 一个DeepSeek入口地址
+#[derive(Debug)]
+struct Mirror {
+    base_url: String,
+    latency_ms: AtomicU64,   // 最近一次探测的延迟，未探测时按配置顺序排列
+    failed_until: AtomicU64, // 在此之前不使用（unix秒）
+}
+
+/// 在主地址和备用镜像之间选择最快的可用入口，连接失败时自动切换
+#[derive(Debug)]
+pub struct MirrorSelector {
+    mirrors: Vec<Mirror>,
+}
+
+impl MirrorSelector {
+    pub fn from_config(config: &DeepSeekConfig) -> Self {
+        let mirrors = std::iter::once(&config.base_url)
+            .chain(config.mirrors.iter())
+            .enumerate()
+            .map(|(index, base_url)| Mirror {
+                base_url: base_url.trim_end_matches('/').to_string(),
+                latency_ms: AtomicU64::new(index as u64),
+                failed_until: AtomicU64::new(0),
+            })
+            .collect();
+        Self { mirrors }
+    }
+
+    /// 是否配置了备用镜像
+    pub fn is_enabled(&self) -> bool {
+        self.mirrors.len() > 1
+    }
+
+    /// 当前使用的入口地址：可用镜像中延迟最低的一个，全部不可用时回到主地址
+    pub fn current(&self) -> String {
+        let now = unix_timestamp();
+        self.mirrors
+            .iter()
+            .filter(|mirror| mirror.failed_until.load(Ordering::Relaxed) <= now)
+            .min_by_key(|mirror| mirror.latency_ms.load(Ordering::Relaxed))
+            .unwrap_or(&self.mirrors[0])
+            .base_url
+            .clone()
+    }
+
+    /// 连接失败或超时的请求把对应镜像暂停一段时间，重试时会换用其他镜像
+    pub fn on_send_error(&self, base_url: &str, error: reqwest::Error) -> reqwest::Error {
+        if self.is_enabled() && (error.is_connect() || error.is_timeout()) {
+            if let Some(mirror) = self.mirrors.iter().find(|mirror| mirror.base_url == base_url) {
+                mirror.failed_until.store(unix_timestamp() + MIRROR_FAILOVER_SECS, Ordering::Relaxed);
+                tracing::warn!("DeepSeek mirror {} unreachable, failing over: {}", base_url, error);
+            }
+        }
+        error
+    }
+
+    /// 测量每个镜像的首页响应延迟
+    pub async fn probe(&self, client: &Client) {
+        for mirror in &self.mirrors {
+            let started = Instant::now();
+            let result = client
+                .get(&mirror.base_url)
+                .timeout(Duration::from_secs(MIRROR_PROBE_TIMEOUT_SECS))
+                .send()
+                .await;
+            match result {
+                Ok(_) => {
+                    let latency_ms = started.elapsed().as_millis() as u64;
+                    mirror.latency_ms.store(latency_ms, Ordering::Relaxed);
+                    mirror.failed_until.store(0, Ordering::Relaxed);
+                    tracing::debug!("Mirror {} latency {}ms", mirror.base_url, latency_ms);
+                }
+                Err(e) => {
+                    mirror.failed_until.store(unix_timestamp() + MIRROR_FAILOVER_SECS, Ordering::Relaxed);
+                    tracing::warn!("Mirror {} probe failed: {}", mirror.base_url, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_current_prefers_fastest_available_mirror() {
+        let mut config = Config::default().deepseek;
+        config.mirrors = vec!["https://mirror-a.example".to_string(), "https://mirror-b.example/".to_string()];
+        let selector = MirrorSelector::from_config(&config);
+        assert_eq!(selector.current(), config.base_url);
+
+        selector.mirrors[0].latency_ms.store(300, Ordering::Relaxed);
+        selector.mirrors[1].latency_ms.store(200, Ordering::Relaxed);
+        selector.mirrors[2].latency_ms.store(100, Ordering::Relaxed);
+        assert_eq!(selector.current(), "https://mirror-b.example");
+
+        selector.mirrors[2].failed_until.store(unix_timestamp() + 60, Ordering::Relaxed);
+        assert_eq!(selector.current(), "https://mirror-a.example");
+    }
+}
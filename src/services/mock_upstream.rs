@@ -0,0 +1,355 @@
+use crate::models::{ChatSession, CompletionRequest, HistoryMessage, HistorySearchResult};
+use crate::utils::{generate_uuid, generate_uuid_simple, unix_timestamp, unix_timestamp_ms};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// 每个模拟账号的深度思考配额
+const MOCK_THINKING_QUOTA: u32 = 50;
+
+/// 模拟回复中引用的搜索结果
+const MOCK_SEARCH_RESULTS: &[(&str, &str)] = &[
+    ("Mock search result one", "https://example.com/mock-1"),
+    ("Mock search result two", "https://example.com/mock-2"),
+];
+
+/// 以 `invalid` 开头的userToken被模拟上游视为失效，用于测试账号失效的处理
+const INVALID_TOKEN_PREFIX: &str = "invalid";
+
+/// 内置的模拟DeepSeek上游：提供登录、token刷新、POW挑战、会话和补全接口，不需要真实账号
+#[derive(Default)]
+struct MockUpstream {
+    sessions: Mutex<HashMap<String, Vec<HistoryMessage>>>, // 会话ID -> 历史消息
+}
+
+/// 在本机随机端口启动模拟上游，返回其地址
+pub async fn spawn() -> std::io::Result<String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base_url = format!("http://{}", listener.local_addr()?);
+    let app = router();
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("Mock upstream stopped: {}", e);
+        }
+    });
+    tracing::warn!("DEEPSEEK_MOCK is enabled, upstream requests go to the mock at {}", base_url);
+    Ok(base_url)
+}
+
+fn router() -> Router {
+    Router::new()
+        .route("/", get(|| async { Html("<!DOCTYPE html><html><head><title>DeepSeek (mock)</title></head></html>") }))
+        .route("/api/v0/users/login", post(login))
+        .route("/api/v0/users/current", get(current_user))
+        .route("/api/v0/users/feature_quota", get(feature_quota))
+        .route("/api/v1/chat/sessions", get(list_sessions))
+        .route("/api/v0/chat/create_pow_challenge", post(create_pow_challenge))
+        .route("/api/v0/chat_session/create", post(create_session))
+        .route("/api/v0/chat_session/delete", post(delete_session))
+        .route("/api/v0/chat/history_messages", get(history_messages))
+        .route("/api/v0/chat/completion", post(completion))
+        .route("/api/v0/file/upload_file", post(upload_file))
+        .route("/api/v0/file/fetch_files", get(fetch_files))
+        .with_state(Arc::new(MockUpstream::default()))
+}
+
+/// 上游的业务响应格式
+fn biz(data: Value) -> Json<Value> {
+    Json(json!({ "code": 0, "msg": "", "biz_data": data }))
+}
+
+fn biz_error(code: u32, msg: &str) -> Json<Value> {
+    Json(json!({ "code": code, "msg": msg, "biz_data": null }))
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .filter(|token| !token.is_empty() && !token.starts_with(INVALID_TOKEN_PREFIX))
+}
+
+/// 同一邮箱每次登录得到同一个userToken
+async fn login(Json(body): Json<Value>) -> Response {
+    let field = |name: &str| body.get(name).and_then(Value::as_str).filter(|value| !value.is_empty());
+    let (Some(email), Some(password)) = (field("email"), field("password")) else {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "code": 40001, "message": "缺少邮箱或密码" }))).into_response();
+    };
+    if password == "wrong" {
+        return Json(json!({ "code": 40002, "message": "邮箱或密码错误" })).into_response();
+    }
+
+    let token = format!("mock-{}", &hex::encode(Sha256::digest(email.as_bytes()))[..32]);
+    Json(json!({
+        "code": 0,
+        "data": {
+            "token": token,
+            "user": { "id": format!("user-{}", &token[5..13]), "email": email, "name": email.split('@').next() }
+        }
+    }))
+    .into_response()
+}
+
+async fn current_user(headers: HeaderMap) -> Json<Value> {
+    match bearer(&headers) {
+        Some(token) => biz(json!({ "token": token, "id": "mock-user", "email": "mock@example.com" })),
+        None => biz_error(40003, "Authorization Failed (invalid token)"),
+    }
+}
+
+/// 添加账号时用于验证userToken，只看HTTP状态码
+async fn list_sessions(headers: HeaderMap) -> Response {
+    match bearer(&headers) {
+        Some(_) => biz(json!({ "chat_sessions": [] })).into_response(),
+        None => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+async fn feature_quota(headers: HeaderMap) -> Json<Value> {
+    match bearer(&headers) {
+        Some(_) => biz(json!({ "thinking": { "quota": MOCK_THINKING_QUOTA, "used": 0 } })),
+        None => biz_error(40003, "Authorization Failed (invalid token)"),
+    }
+}
+
+/// 挑战不校验答案，求解器照常计算
+async fn create_pow_challenge(Json(body): Json<Value>) -> Json<Value> {
+    biz(json!({
+        "challenge": {
+            "algorithm": "DeepSeekHashV1",
+            "challenge": generate_uuid_simple(),
+            "salt": generate_uuid_simple()[..20].to_string(),
+            "difficulty": 1000,
+            "expire_at": unix_timestamp_ms() + 300_000,
+            "signature": generate_uuid_simple(),
+            "target_path": body.get("target_path").cloned().unwrap_or(Value::Null),
+        }
+    }))
+}
+
+async fn create_session(State(mock): State<Arc<MockUpstream>>, headers: HeaderMap) -> Json<Value> {
+    if bearer(&headers).is_none() {
+        return biz_error(40003, "Authorization Failed (invalid token)");
+    }
+    let id = generate_uuid();
+    mock.sessions.lock().insert(id.clone(), Vec::new());
+    biz(serde_json::to_value(ChatSession { id, character_id: None }).unwrap_or_default())
+}
+
+async fn delete_session(State(mock): State<Arc<MockUpstream>>, Json(body): Json<Value>) -> Json<Value> {
+    let id = body.get("chat_session_id").and_then(Value::as_str).unwrap_or_default();
+    match mock.sessions.lock().remove(id) {
+        Some(_) => biz(Value::Null),
+        None => biz_error(40400, "会话不存在"),
+    }
+}
+
+async fn history_messages(
+    State(mock): State<Arc<MockUpstream>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Json<Value> {
+    let id = query.get("chat_session_id").map(String::as_str).unwrap_or_default();
+    match mock.sessions.lock().get(id) {
+        Some(messages) => biz(json!({
+            "chat_session": { "id": id, "title": messages.first().map(|m| m.content.chars().take(20).collect::<String>()) },
+            "chat_messages": messages,
+        })),
+        None => biz_error(40400, "会话不存在"),
+    }
+}
+
+/// 按请求的思考和搜索开关返回固定格式的SSE，回复中带上提示词的最后一行便于核对
+async fn completion(
+    State(mock): State<Arc<MockUpstream>>,
+    headers: HeaderMap,
+    Json(request): Json<CompletionRequest>,
+) -> Response {
+    if bearer(&headers).is_none() {
+        return biz_error(40003, "Authorization Failed (invalid token)").into_response();
+    }
+    if !headers.contains_key("x-ds-pow-response") {
+        return (StatusCode::BAD_REQUEST, biz_error(40300, "缺少POW答案")).into_response();
+    }
+
+    let prompt_tail: String = request
+        .prompt
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default()
+        .chars()
+        .take(80)
+        .collect();
+    let thinking = request
+        .thinking_enabled
+        .then(|| format!("The user said \"{}\". This is a mock reasoning trace.", prompt_tail));
+    let search_results = request.search_enabled.then(|| {
+        MOCK_SEARCH_RESULTS
+            .iter()
+            .enumerate()
+            .map(|(index, (title, url))| HistorySearchResult {
+                url: url.to_string(),
+                title: title.to_string(),
+                cite_index: Some(index as u32 + 1),
+            })
+            .collect::<Vec<_>>()
+    });
+    let answer = if request.search_enabled {
+        format!("Mock reply to: {} [citation:1]", prompt_tail)
+    } else {
+        format!("Mock reply to: {}", prompt_tail)
+    };
+
+    let mut events = Vec::new();
+    if let Some(results) = &search_results {
+        let results: Vec<Value> = results.iter().map(|r| json!({ "title": r.title, "url": r.url })).collect();
+        events.push(json!({ "type": "text", "content": format!("已检索到 {} 个网页\n", results.len()), "search_results": results }));
+    }
+    if let Some(thinking) = &thinking {
+        for piece in chunks(thinking) {
+            events.push(json!({ "type": "thinking", "content": piece }));
+        }
+    }
+    for piece in chunks(&answer) {
+        events.push(json!({ "type": "text", "content": piece }));
+    }
+
+    let mut body = String::new();
+    let message_id = {
+        let mut sessions = mock.sessions.lock();
+        let Some(messages) = sessions.get_mut(&request.chat_session_id) else {
+            return biz_error(40400, "会话不存在").into_response();
+        };
+        let now = unix_timestamp() as f64;
+        let user_id = messages.len() as u64 + 1;
+        messages.push(HistoryMessage {
+            message_id: user_id,
+            role: "USER".to_string(),
+            content: request.prompt.clone(),
+            thinking_content: None,
+            search_results: None,
+            inserted_at: Some(now),
+        });
+        messages.push(HistoryMessage {
+            message_id: user_id + 1,
+            role: "ASSISTANT".to_string(),
+            content: answer.clone(),
+            thinking_content: thinking.clone(),
+            search_results,
+            inserted_at: Some(now),
+        });
+        user_id + 1
+    };
+    for delta in events {
+        let data = json!({ "message_id": message_id.to_string(), "choices": [{ "delta": delta, "finish_reason": null }] });
+        body.push_str(&format!("data: {}\n\n", data));
+    }
+    let done = json!({ "message_id": message_id.to_string(), "choices": [{ "delta": { "type": "text", "content": "" }, "finish_reason": "stop" }] });
+    body.push_str(&format!("data: {}\n\ndata: [DONE]\n\n", done));
+
+    ([(header::CONTENT_TYPE, "text/event-stream; charset=utf-8")], body).into_response()
+}
+
+/// 按词切分，模拟上游逐块输出
+fn chunks(text: &str) -> Vec<String> {
+    text.split_inclusive(' ').map(str::to_string).collect()
+}
+
+async fn upload_file(headers: HeaderMap) -> Json<Value> {
+    match bearer(&headers) {
+        Some(_) => biz(json!({ "id": format!("file-{}", generate_uuid_simple()), "status": "SUCCESS" })),
+        None => biz_error(40003, "Authorization Failed (invalid token)"),
+    }
+}
+
+async fn fetch_files(Query(query): Query<HashMap<String, String>>) -> Json<Value> {
+    let id = query.get("file_ids").cloned().unwrap_or_default();
+    biz(json!({ "files": [{ "id": id, "status": "SUCCESS" }] }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::models::{ChatMessage, ChatMessageContent, CompletionOptions};
+    use crate::services::{BrowserProfiles, CookieStore, DeepSeekClient, QuotaCache, RequestContext};
+
+    fn client(base_url: &str) -> DeepSeekClient {
+        let mut config = Config::default();
+        config.deepseek.base_url = base_url.to_string();
+        config.deepseek.mirrors.clear();
+        let cookies = std::env::temp_dir().join(format!("mock-cookies-{}.json", generate_uuid_simple()));
+        DeepSeekClient::new(
+            config.clone(),
+            Arc::new(QuotaCache::new(config.deepseek.quota_cache_ttl_secs)),
+            Arc::new(BrowserProfiles::from_config(&config.deepseek)),
+            Arc::new(CookieStore::open(cookies.to_str().unwrap())),
+        )
+    }
+
+    fn user_message(text: &str) -> ChatMessage {
+        ChatMessage {
+            role: "user".to_string(),
+            content: ChatMessageContent::Text(text.to_string()),
+            name: None,
+            tool_call_id: None,
+            tool_calls: None,
+            reasoning_content: None,
+        }
+    }
+
+    fn options() -> CompletionOptions {
+        CompletionOptions {
+            continue_final_message: false,
+            features: Default::default(),
+            debug_upstream: false,
+            overrides: Default::default(),
+            chaos: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_completion_against_mock() {
+        let base_url = spawn().await.unwrap();
+        let client = client(&base_url);
+        let ctx = RequestContext::new();
+
+        let response = client
+            .create_completion("deepseek", &[user_message("hello mock")], "mock-token", None, options(), &ctx)
+            .await
+            .unwrap();
+        let message = response.choices[0].message.as_ref().unwrap();
+        assert!(matches!(&message.content, ChatMessageContent::Text(text) if text.contains("Mock reply to:") && text.contains("hello mock")));
+
+        let thinking = client
+            .create_completion("deepseek-r1", &[user_message("think")], "mock-token", None, options(), &ctx)
+            .await
+            .unwrap();
+        let reasoning = thinking.choices[0].message.as_ref().unwrap().reasoning_content.clone();
+        assert!(reasoning.is_some_and(|reasoning| reasoning.contains("mock reasoning")));
+
+        let session_id = thinking.id.split('@').next().unwrap();
+        let history = client.fetch_history("mock-token", session_id).await.unwrap();
+        assert_eq!(history.chat_messages.len(), 2);
+        client.delete_session("mock-token", session_id).await.unwrap();
+        assert!(client.fetch_history("mock-token", session_id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_token_rejected() {
+        let base_url = spawn().await.unwrap();
+        let client = client(&base_url);
+        assert!(!client.check_token("invalid-token").await.unwrap().0);
+        assert!(client.check_token("mock-token").await.unwrap().0);
+    }
+}
@@ -5,11 +5,77 @@ pub mod message_processor;
 pub mod login_service;
 pub mod api_key_manager;
 pub mod session_pool;
+pub mod session_prewarmer;
+pub mod quota_cache;
+pub mod rate_limiter;
+pub mod fair_scheduler;
+pub mod secrets;
+pub mod audit_log;
+pub mod request_context;
+pub mod request_stats;
+pub mod health_prober;
+pub mod response_cache;
+pub mod retry_policy;
+pub mod singleflight;
+pub mod credential_vault;
+pub mod signed_key;
+pub mod content_filter;
+pub mod browser_profile;
+pub mod pacer;
+pub mod cookie_store;
+pub mod dns_resolver;
+pub mod mirror_selector;
+pub mod conversation_export;
+pub mod error_catalog;
+pub mod webhook;
+pub mod account_usage;
+pub mod task_monitor;
+pub mod leader_election;
+pub mod metrics_push;
+pub mod mock_upstream;
+pub mod cassette;
+pub mod chaos;
+pub mod failed_requests;
+pub mod wire_log;
+pub mod stream_throttle;
+pub mod plugins;
+pub mod script_hooks;
+pub mod completion_store;
+pub mod thread_store;
+pub mod generation_store;
 
 pub use token_manager::TokenManager;
-pub use challenge_solver::ChallengeSolver;
+pub use challenge_solver::{ChallengeCache, ChallengeSolver};
 pub use deepseek_client::DeepSeekClient;
-pub use message_processor::MessageProcessor;
+pub use message_processor::{MessageProcessor, StreamProcessor};
 pub use login_service::LoginService;
 pub use api_key_manager::ApiKeyManager;
 pub use session_pool::SessionPoolManager;
+pub use session_prewarmer::SessionPrewarmer;
+pub use quota_cache::QuotaCache;
+pub use secrets::SecretStore;
+pub use audit_log::AuditLog;
+pub use request_context::{RequestContext, TraceContext};
+pub use request_stats::RequestStats;
+pub use health_prober::HealthProber;
+pub use response_cache::ResponseCache;
+pub use retry_policy::RetryPolicy;
+pub use singleflight::Singleflight;
+pub use credential_vault::CredentialVault;
+pub use content_filter::ContentFilter;
+pub use browser_profile::BrowserProfiles;
+pub use pacer::Pacer;
+pub use cookie_store::CookieStore;
+pub use mirror_selector::MirrorSelector;
+pub use webhook::{WebhookEvent, WebhookNotifier};
+pub use account_usage::AccountUsage;
+pub use task_monitor::TaskMonitor;
+pub use leader_election::LeaderElection;
+pub use metrics_push::MetricsPusher;
+pub use failed_requests::{FailedRequest, FailedRequests};
+pub use wire_log::WireLog;
+pub use plugins::PluginHost;
+pub use script_hooks::ScriptHooks;
+pub use completion_store::{CompletionRecord, CompletionStore};
+pub use thread_store::ThreadStore;
+pub use generation_store::GenerationStore;
@@ -0,0 +1,71 @@
+use crate::config::DeepSeekConfig;
+use parking_lot::Mutex;
+use rand::Rng;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 同一账号连续上游操作之间的节奏控制：最小间隔加随机抖动，模拟人工操作
+#[derive(Debug)]
+pub struct Pacer {
+    min_gap: Duration,
+    jitter: Duration,
+    next_allowed: Mutex<HashMap<String, Instant>>, // userToken -> 下一次操作最早可开始的时间
+}
+
+impl Pacer {
+    pub fn from_config(config: &DeepSeekConfig) -> Self {
+        Self {
+            min_gap: Duration::from_millis(config.pacing_min_gap_ms),
+            jitter: Duration::from_millis(config.pacing_jitter_ms),
+            next_allowed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.min_gap.is_zero() || !self.jitter.is_zero()
+    }
+
+    /// 预约账号的下一个操作时间并等待到该时间，并发的操作依次排开
+    pub async fn pace(&self, token: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let start = self.reserve(token, Instant::now());
+        tokio::time::sleep_until(start).await;
+    }
+
+    /// 返回本次操作的开始时间，并把下一次操作推迟一个间隔
+    fn reserve(&self, token: &str, now: Instant) -> Instant {
+        let jitter = rand::thread_rng().gen_range(0..=self.jitter.as_millis() as u64);
+        let gap = self.min_gap + Duration::from_millis(jitter);
+
+        let mut next_allowed = self.next_allowed.lock();
+        // 顺带清理已经空闲的账号
+        next_allowed.retain(|_, next| *next > now);
+        let start = next_allowed.get(token).copied().unwrap_or(now).max(now);
+        next_allowed.insert(token.to_string(), start + gap);
+        start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_spaces_out_actions_per_account() {
+        let pacer = Pacer {
+            min_gap: Duration::from_millis(500),
+            jitter: Duration::ZERO,
+            next_allowed: Mutex::new(HashMap::new()),
+        };
+        let now = Instant::now();
+
+        assert_eq!(pacer.reserve("a", now), now);
+        assert_eq!(pacer.reserve("a", now), now + Duration::from_millis(500));
+        assert_eq!(pacer.reserve("b", now), now);
+        assert_eq!(pacer.reserve("a", now + Duration::from_secs(5)), now + Duration::from_secs(5));
+    }
+}
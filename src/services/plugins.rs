@@ -0,0 +1,324 @@
+use crate::config::PluginConfig;
+use crate::error::{ApiError, ApiResult};
+use crate::models::{ChatCompletionResponse, ChatMessage, ChatMessageContent};
+use serde_json::{json, Value};
+
+/// 插件可以导出的钩子，每个钩子接收一个JSON对象，返回的对象合并到输入上传给下一个插件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// 拼接提示词之前：{"model", "messages"}，可改写messages
+    BeforePrompt,
+    /// 发往上游之前：{"model", "prompt"}，可改写拼接好的提示词
+    BeforeUpstream,
+    /// 每段输出（流式数据块或完整的非流式回复）：{"model", "content", "reasoning_content"}
+    OnChunk,
+}
+
+impl Hook {
+    const ALL: [Hook; 3] = [Hook::BeforePrompt, Hook::BeforeUpstream, Hook::OnChunk];
+
+    /// 插件中对应的导出函数名
+    pub fn export_name(self) -> &'static str {
+        match self {
+            Hook::BeforePrompt => "before_prompt",
+            Hook::BeforeUpstream => "before_upstream",
+            Hook::OnChunk => "on_chunk",
+        }
+    }
+}
+
+/// WASM插件宿主，按PLUGIN_PATHS的顺序调用各插件的钩子；未配置插件时所有钩子都不做处理
+#[derive(Default)]
+pub struct PluginHost {
+    #[cfg(feature = "plugins")]
+    plugins: Vec<runtime::Plugin>,
+}
+
+impl PluginHost {
+    pub fn load(config: &PluginConfig) -> ApiResult<Self> {
+        if config.paths.is_empty() {
+            return Ok(Self::default());
+        }
+        #[cfg(feature = "plugins")]
+        {
+            let engine = runtime::engine()?;
+            let plugins = config
+                .paths
+                .iter()
+                .map(|path| runtime::Plugin::load(&engine, path, config))
+                .collect::<ApiResult<Vec<_>>>()?;
+            for plugin in &plugins {
+                tracing::info!("Loaded plugin {} (hooks: {:?})", plugin.name, plugin.hooks);
+            }
+            Ok(Self { plugins })
+        }
+        #[cfg(not(feature = "plugins"))]
+        Err(ApiError::ConfigError("PLUGIN_PATHS需要以 --features plugins 编译".to_string()))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        Hook::ALL.into_iter().any(|hook| self.has_hook(hook))
+    }
+
+    pub fn has_hook(&self, hook: Hook) -> bool {
+        #[cfg(feature = "plugins")]
+        return self.plugins.iter().any(|plugin| plugin.hooks.contains(&hook));
+        #[cfg(not(feature = "plugins"))]
+        {
+            let _ = hook;
+            false
+        }
+    }
+
+    /// 依次调用各插件的钩子；插件返回0表示不修改，出错时中止请求
+    fn call(&self, hook: Hook, input: Value) -> ApiResult<Value> {
+        #[cfg(feature = "plugins")]
+        {
+            let mut value = input;
+            for plugin in self.plugins.iter().filter(|plugin| plugin.hooks.contains(&hook)) {
+                if let Some(Value::Object(output)) = plugin.call(hook, &value)? {
+                    for (key, field) in output {
+                        value[key] = field;
+                    }
+                }
+            }
+            Ok(value)
+        }
+        #[cfg(not(feature = "plugins"))]
+        {
+            let _ = hook;
+            Ok(input)
+        }
+    }
+
+    /// before_prompt钩子：拼接提示词之前改写消息
+    pub fn before_prompt(&self, model: &str, messages: &mut Vec<ChatMessage>) -> ApiResult<()> {
+        if !self.has_hook(Hook::BeforePrompt) {
+            return Ok(());
+        }
+        let output = self.call(Hook::BeforePrompt, json!({ "model": model, "messages": messages }))?;
+        *messages = serde_json::from_value(output["messages"].clone())
+            .map_err(|e| ApiError::InternalError(format!("插件返回的messages无效: {}", e)))?;
+        Ok(())
+    }
+
+    /// before_upstream钩子：发往上游之前改写拼接好的提示词
+    pub fn before_upstream(&self, model: &str, prompt: &mut String) -> ApiResult<()> {
+        if !self.has_hook(Hook::BeforeUpstream) {
+            return Ok(());
+        }
+        let output = self.call(Hook::BeforeUpstream, json!({ "model": model, "prompt": prompt }))?;
+        *prompt = output["prompt"]
+            .as_str()
+            .ok_or_else(|| ApiError::InternalError("插件返回的prompt不是字符串".to_string()))?
+            .to_string();
+        Ok(())
+    }
+
+    /// on_chunk钩子：改写一段输出的正文和思考过程
+    fn on_chunk(&self, model: &str, content: &mut Option<String>, reasoning: &mut Option<String>) -> ApiResult<()> {
+        let output = self.call(
+            Hook::OnChunk,
+            json!({ "model": model, "content": content, "reasoning_content": reasoning }),
+        )?;
+        *content = output["content"].as_str().map(str::to_string);
+        *reasoning = output["reasoning_content"].as_str().map(str::to_string);
+        Ok(())
+    }
+
+    /// 非流式响应的完整回复作为一段输出经过on_chunk钩子
+    pub fn transform_response(&self, response: &mut ChatCompletionResponse) -> ApiResult<()> {
+        if !self.has_hook(Hook::OnChunk) {
+            return Ok(());
+        }
+        for message in response.choices.iter_mut().filter_map(|choice| choice.message.as_mut()) {
+            let ChatMessageContent::Text(text) = &mut message.content else {
+                continue;
+            };
+            let mut content = Some(std::mem::take(text));
+            self.on_chunk(&response.model, &mut content, &mut message.reasoning_content)?;
+            *text = content.unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    /// 流式响应的一个数据块（带SSE的`data: `前缀）经过on_chunk钩子；没有正文的数据块和[DONE]原样返回
+    pub fn transform_chunk(&self, data: String) -> ApiResult<String> {
+        if !self.has_hook(Hook::OnChunk) {
+            return Ok(data);
+        }
+        let payload = data.strip_prefix("data: ").unwrap_or(&data).trim_end();
+        let Ok(mut chunk) = serde_json::from_str::<Value>(payload) else {
+            return Ok(data);
+        };
+
+        let model = chunk["model"].as_str().unwrap_or_default().to_string();
+        let mut changed = false;
+        for delta in chunk["choices"].as_array_mut().into_iter().flatten().map(|choice| &mut choice["delta"]) {
+            let mut content = delta["content"].as_str().map(str::to_string);
+            let mut reasoning = delta["reasoning_content"].as_str().map(str::to_string);
+            if content.as_deref().unwrap_or_default().is_empty() && reasoning.as_deref().unwrap_or_default().is_empty() {
+                continue;
+            }
+            self.on_chunk(&model, &mut content, &mut reasoning)?;
+            delta["content"] = json!(content);
+            delta["reasoning_content"] = json!(reasoning);
+            changed = true;
+        }
+
+        if !changed {
+            return Ok(data);
+        }
+        let suffix = &data[data.trim_end().len()..];
+        Ok(format!("data: {}{}", chunk, suffix))
+    }
+}
+
+/// 插件以WASI模块的形式运行，每次调用都使用新的实例，互不共享状态。约定：
+/// - 导出 `memory` 和 `alloc(len: i32) -> i32`，宿主在alloc返回的地址写入UTF-8编码的JSON输入
+/// - 钩子函数签名为 `(ptr: i32, len: i32) -> i64`，返回 `(输出地址 << 32) | 输出长度`，0表示不修改
+#[cfg(feature = "plugins")]
+mod runtime {
+    use super::Hook;
+    use crate::config::PluginConfig;
+    use crate::error::{ApiError, ApiResult};
+    use serde_json::Value;
+    use std::path::Path;
+    use wasmtime::{Engine, InstancePre, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+    use wasmtime_wasi::sync::WasiCtxBuilder;
+    use wasmtime_wasi::WasiCtx;
+
+    struct HostState {
+        wasi: WasiCtx,
+        limits: StoreLimits,
+    }
+
+    pub(super) struct Plugin {
+        pub(super) name: String,
+        pub(super) hooks: Vec<Hook>,
+        engine: Engine,
+        pre: InstancePre<HostState>,
+        fuel: u64,
+        max_memory_bytes: usize,
+    }
+
+    pub(super) fn engine() -> ApiResult<Engine> {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        Engine::new(&config).map_err(|e| ApiError::ConfigError(format!("初始化WASM引擎失败: {}", e)))
+    }
+
+    impl Plugin {
+        /// 加载.wasm或.wat文件，只允许导入WASI接口
+        pub(super) fn load(engine: &Engine, path: &str, config: &PluginConfig) -> ApiResult<Self> {
+            let error = |e: anyhow::Error| ApiError::ConfigError(format!("加载插件 {} 失败: {:#}", path, e));
+            let module = Module::from_file(engine, path).map_err(error)?;
+            Self::from_module(engine, &module, path, config).map_err(error)
+        }
+
+        pub(super) fn from_module(engine: &Engine, module: &Module, path: &str, config: &PluginConfig) -> anyhow::Result<Self> {
+            let mut linker = Linker::new(engine);
+            wasmtime_wasi::sync::add_to_linker(&mut linker, |state: &mut HostState| &mut state.wasi)?;
+            let pre = linker.instantiate_pre(module)?;
+            let hooks: Vec<Hook> = Hook::ALL
+                .into_iter()
+                .filter(|hook| module.get_export(hook.export_name()).is_some())
+                .collect();
+            anyhow::ensure!(!hooks.is_empty(), "没有导出任何钩子");
+
+            let name = Path::new(path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.to_string());
+            Ok(Self {
+                name,
+                hooks,
+                engine: engine.clone(),
+                pre,
+                fuel: config.fuel,
+                max_memory_bytes: config.max_memory_bytes,
+            })
+        }
+
+        pub(super) fn call(&self, hook: Hook, input: &Value) -> ApiResult<Option<Value>> {
+            self.invoke(hook, input.to_string().as_bytes())
+                .map_err(|e| ApiError::InternalError(format!("插件 {} 的 {} 执行失败: {:#}", self.name, hook.export_name(), e)))
+        }
+
+        fn invoke(&self, hook: Hook, input: &[u8]) -> anyhow::Result<Option<Value>> {
+            let state = HostState {
+                // 插件的日志写到服务的标准错误输出，不开放文件系统和网络
+                wasi: WasiCtxBuilder::new().inherit_stderr().build(),
+                limits: StoreLimitsBuilder::new().memory_size(self.max_memory_bytes).build(),
+            };
+            let mut store = Store::new(&self.engine, state);
+            store.limiter(|state| &mut state.limits);
+            store.add_fuel(self.fuel)?;
+
+            let instance = self.pre.instantiate(&mut store)?;
+            // WASI reactor模块需要先初始化
+            if let Ok(initialize) = instance.get_typed_func::<(), ()>(&mut store, "_initialize") {
+                initialize.call(&mut store, ())?;
+            }
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .ok_or_else(|| anyhow::anyhow!("没有导出memory"))?;
+            let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+            let function = instance.get_typed_func::<(i32, i32), i64>(&mut store, hook.export_name())?;
+
+            let len = i32::try_from(input.len())?;
+            let ptr = alloc.call(&mut store, len)?;
+            memory.write(&mut store, ptr as u32 as usize, input)?;
+            let packed = function.call(&mut store, (ptr, len))? as u64;
+            if packed == 0 {
+                return Ok(None);
+            }
+
+            let mut output = vec![0; (packed & 0xffff_ffff) as usize];
+            memory.read(&store, (packed >> 32) as usize, &mut output)?;
+            Ok(Some(serde_json::from_slice(&output)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_without_plugins() {
+        let host = PluginHost::default();
+        assert!(!host.is_enabled());
+        let data = "data: {\"model\":\"deepseek\",\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\n".to_string();
+        assert_eq!(host.transform_chunk(data.clone()).unwrap(), data);
+    }
+
+    #[cfg(feature = "plugins")]
+    #[test]
+    fn test_on_chunk_plugin() {
+        // on_chunk把正文替换为固定内容，before_prompt返回0表示不修改
+        let wat = r#"
+            (module
+              (memory (export "memory") 1)
+              (data (i32.const 1024) "{\"content\":\"[redacted]\"}")
+              (func (export "alloc") (param i32) (result i32) (i32.const 2048))
+              (func (export "before_prompt") (param i32 i32) (result i64) (i64.const 0))
+              (func (export "on_chunk") (param i32 i32) (result i64)
+                (i64.or (i64.shl (i64.const 1024) (i64.const 32)) (i64.const 24))))
+        "#;
+        let config = crate::config::Config::default().plugins;
+        let engine = runtime::engine().unwrap();
+        let module = wasmtime::Module::new(&engine, wat).unwrap();
+        let plugin = runtime::Plugin::from_module(&engine, &module, "redact.wat", &config).unwrap();
+        assert_eq!(plugin.hooks, vec![Hook::BeforePrompt, Hook::OnChunk]);
+        let host = PluginHost { plugins: vec![plugin] };
+
+        let mut messages: Vec<ChatMessage> = serde_json::from_value(json!([{"role": "user", "content": "secret"}])).unwrap();
+        host.before_prompt("deepseek", &mut messages).unwrap();
+        assert!(matches!(&messages[0].content, ChatMessageContent::Text(text) if text == "secret"));
+
+        let data = "data: {\"model\":\"deepseek\",\"choices\":[{\"delta\":{\"content\":\"secret\"}}]}\n\n".to_string();
+        let chunk: Value = serde_json::from_str(host.transform_chunk(data).unwrap().trim_start_matches("data: ")).unwrap();
+        assert_eq!(chunk["choices"][0]["delta"]["content"], "[redacted]");
+    }
+}
@@ -0,0 +1,87 @@
+use crate::utils::unix_timestamp;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+
+/// 缓存的账号功能配额
+#[derive(Debug, Clone)]
+struct CachedQuota {
+    thinking_remaining: u32,
+    fetched_at: u64,
+}
+
+/// 账号功能配额缓存（按userToken）
+pub struct QuotaCache {
+    quotas: RwLock<HashMap<String, CachedQuota>>,
+    ttl: u64,
+}
+
+impl QuotaCache {
+    pub fn new(ttl: u64) -> Self {
+        Self {
+            quotas: RwLock::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// 获取未过期的剩余深度思考配额
+    pub fn thinking_remaining(&self, user_token: &str) -> Option<u32> {
+        let now = unix_timestamp();
+        let quotas = self.quotas.read();
+        quotas
+            .get(user_token)
+            .filter(|quota| now.saturating_sub(quota.fetched_at) <= self.ttl)
+            .map(|quota| quota.thinking_remaining)
+    }
+
+    /// 账号是否可能还有深度思考配额（未知时视为有）
+    pub fn has_thinking_quota(&self, user_token: &str) -> bool {
+        self.thinking_remaining(user_token)
+            .map(|remaining| remaining > 0)
+            .unwrap_or(true)
+    }
+
+    /// 查询时间，配额未缓存时为None
+    pub fn fetched_at(&self, user_token: &str) -> Option<u64> {
+        self.quotas.read().get(user_token).map(|quota| quota.fetched_at)
+    }
+
+    /// 请求开始使用深度思考时乐观地扣减一次，下次轮询时以上游为准
+    pub fn consume_thinking(&self, user_token: &str) {
+        if let Some(quota) = self.quotas.write().get_mut(user_token) {
+            quota.thinking_remaining = quota.thinking_remaining.saturating_sub(1);
+        }
+    }
+
+    /// 记录最新查询到的配额
+    pub fn set_thinking_remaining(&self, user_token: &str, remaining: u32) {
+        let mut quotas = self.quotas.write();
+        quotas.insert(
+            user_token.to_string(),
+            CachedQuota {
+                thinking_remaining: remaining,
+                fetched_at: unix_timestamp(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consume_decrements_cached_quota() {
+        let cache = QuotaCache::new(300);
+        cache.consume_thinking("token");
+        assert_eq!(cache.thinking_remaining("token"), None);
+        assert!(cache.has_thinking_quota("token"));
+
+        cache.set_thinking_remaining("token", 1);
+        cache.consume_thinking("token");
+        assert_eq!(cache.thinking_remaining("token"), Some(0));
+        assert!(!cache.has_thinking_quota("token"));
+        cache.consume_thinking("token");
+        assert_eq!(cache.thinking_remaining("token"), Some(0));
+        assert!(cache.fetched_at("token").is_some());
+    }
+}
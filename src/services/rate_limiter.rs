@@ -0,0 +1,304 @@
+use crate::config::ClusterConfig;
+use crate::error::{AppError, AppResult};
+use parking_lot::Mutex;
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncConnectionConfig, Script};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// 令牌桶限流器
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_sec,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// 按每小时配额创建令牌桶，burst为允许的突发数量
+    pub fn per_hour(limit: u32, burst: u32) -> Self {
+        Self::new(burst.min(limit), limit as f64 / 3600.0)
+    }
+
+    /// 补充令牌
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// 当前是否至少有一个令牌
+    pub fn has_token(&mut self) -> bool {
+        self.refill();
+        self.tokens >= 1.0
+    }
+
+    /// 尝试消耗一个令牌
+    pub fn try_acquire(&mut self) -> bool {
+        self.refill();
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 距离下一个令牌可用的秒数
+    pub fn seconds_until_available(&mut self) -> u64 {
+        self.refill();
+        if self.tokens >= 1.0 || self.refill_per_sec <= 0.0 {
+            return 0;
+        }
+        ((1.0 - self.tokens) / self.refill_per_sec).ceil() as u64
+    }
+}
+
+/// 请求令牌桶从空恢复到满额的时间：容量为每分钟请求数，每分钟补满一次
+const REQUEST_REFILL_MS: u64 = 60_000;
+
+/// 一次请求计数后的配额状态，用于x-ratelimit-*响应头
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestQuota {
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset_secs: u64,       // 令牌桶恢复到满额的秒数
+    pub retry_after_secs: u64, // 被拒绝时距下一个请求可用的秒数
+    pub allowed: bool,
+}
+
+impl RequestQuota {
+    /// 按扣减后剩余的令牌数计算配额状态
+    fn from_tokens(limit: u32, tokens: f64, allowed: bool) -> Self {
+        let ms_per_token = REQUEST_REFILL_MS as f64 / limit.max(1) as f64;
+        let secs_until = |target: f64| (((target - tokens).max(0.0) * ms_per_token).round() as u64).div_ceil(1000);
+        Self {
+            limit,
+            remaining: tokens.max(0.0) as u32,
+            reset_secs: secs_until(limit as f64),
+            retry_after_secs: if allowed { 0 } else { secs_until(1.0).max(1) },
+            allowed,
+        }
+    }
+}
+
+/// 按经过的时间补充令牌，再尝试扣减一个；返回(剩余令牌数, 是否放行)
+fn take_token(tokens: f64, updated_ms: u64, limit: u32, now_ms: u64) -> (f64, bool) {
+    let refilled = now_ms.saturating_sub(updated_ms) as f64 * limit as f64 / REQUEST_REFILL_MS as f64;
+    let tokens = (tokens + refilled).min(limit as f64);
+    if tokens >= 1.0 {
+        (tokens - 1.0, true)
+    } else {
+        (tokens, false)
+    }
+}
+
+/// 与内存令牌桶相同的算法：按Redis服务器时间补充令牌（各实例时钟不一致也不影响），足够时扣减一个，桶恢复满额后键过期；
+/// 返回{剩余令牌数（字符串，保留小数）, 是否放行}
+const BUCKET_SCRIPT: &str = r"
+redis.replicate_commands()
+local limit = tonumber(ARGV[1])
+local refill_ms = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+local tokens = tonumber(state[1]) or limit
+local updated = tonumber(state[2]) or now
+tokens = math.min(limit, tokens + math.max(0, now - updated) * limit / refill_ms)
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', now)
+redis.call('PEXPIRE', KEYS[1], refill_ms)
+return {tostring(tokens), allowed}
+";
+
+/// 连接Redis和执行计数脚本的超时，超时按Redis不可用处理
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+/// Redis出错后直接使用本实例计数的时间，避免每个请求都等待超时
+const REDIS_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// 多实例共享的Redis令牌桶，只限制每分钟请求数
+struct RedisBuckets {
+    client: redis::Client,
+    prefix: String,
+    connection: Mutex<Option<MultiplexedConnection>>, // 复用的连接，出错后下次重新建立
+    retry_at: Mutex<Option<Instant>>,                // 最近一次出错后恢复尝试Redis的时间
+}
+
+impl RedisBuckets {
+    /// 最近出错后的等待期内返回false，调用方直接使用本实例计数
+    fn available(&self) -> bool {
+        self.retry_at.lock().is_none_or(|retry_at| Instant::now() >= retry_at)
+    }
+
+    /// 取得复用的连接，没有时在锁外建立，并发建立的多余连接直接丢弃
+    async fn connection(&self) -> redis::RedisResult<MultiplexedConnection> {
+        if let Some(connection) = self.connection.lock().clone() {
+            return Ok(connection);
+        }
+        let config = AsyncConnectionConfig::new()
+            .set_connection_timeout(REDIS_TIMEOUT)
+            .set_response_timeout(REDIS_TIMEOUT);
+        let connection = self.client.get_multiplexed_async_connection_with_config(&config).await?;
+        Ok(self.connection.lock().get_or_insert(connection).clone())
+    }
+
+    async fn check(&self, key_id: &str, limit: u32) -> redis::RedisResult<RequestQuota> {
+        let result: redis::RedisResult<(String, u8)> = async {
+            let mut connection = self.connection().await?;
+            Script::new(BUCKET_SCRIPT)
+                .key(format!("{}:{}", self.prefix, key_id))
+                .arg(limit)
+                .arg(REQUEST_REFILL_MS)
+                .invoke_async(&mut connection)
+                .await
+        }
+        .await;
+        let (tokens, allowed) = match result {
+            Ok(reply) => {
+                *self.retry_at.lock() = None;
+                reply
+            }
+            Err(e) => {
+                *self.connection.lock() = None;
+                *self.retry_at.lock() = Some(Instant::now() + REDIS_RETRY_DELAY);
+                return Err(e);
+            }
+        };
+
+        Ok(RequestQuota::from_tokens(limit, tokens.parse().unwrap_or(0.0), allowed == 1))
+    }
+}
+
+/// 按API密钥限制每分钟请求数的令牌桶：容量为每分钟请求数，匀速补充，没有固定窗口边界的双倍突发；
+/// 配置了Redis时各实例共享同一个桶
+#[derive(Default)]
+pub struct RequestLimiter {
+    buckets: Mutex<HashMap<String, (f64, u64)>>, // 密钥ID -> (剩余令牌数, 更新时间毫秒)
+    redis: Option<RedisBuckets>,
+}
+
+impl RequestLimiter {
+    /// 设置了REDIS_URL时使用Redis计数，否则只在本实例内计数
+    pub fn from_config(config: &ClusterConfig) -> AppResult<Self> {
+        let redis = config
+            .redis_url
+            .as_deref()
+            .map(redis::Client::open)
+            .transpose()
+            .map_err(|e| AppError::Internal(format!("无效的REDIS_URL: {}", e)))?
+            .map(|client| RedisBuckets {
+                client,
+                prefix: config.rate_limit_prefix.clone(),
+                connection: Mutex::new(None),
+                retry_at: Mutex::new(None),
+            });
+
+        Ok(Self {
+            buckets: Mutex::default(),
+            redis,
+        })
+    }
+
+    /// 记录一次请求；Redis不可用或超时时退回本实例的计数，限流不会因此失效
+    pub async fn acquire(&self, key_id: &str, limit: u32, now_ms: u64) -> RequestQuota {
+        if let Some(redis) = self.redis.as_ref().filter(|redis| redis.available()) {
+            match redis.check(key_id, limit).await {
+                Ok(quota) => return quota,
+                Err(e) => tracing::warn!("Redis rate limit check failed, using local counter: {}", e),
+            }
+        }
+        self.check(key_id, limit, now_ms)
+    }
+
+    /// 记录一次请求，超过限制的请求不扣减令牌
+    pub fn check(&self, key_id: &str, limit: u32, now_ms: u64) -> RequestQuota {
+        let mut buckets = self.buckets.lock();
+        // 顺便清理已恢复满额的桶，避免停用的密钥一直占用内存
+        buckets.retain(|_, (_, updated_ms)| now_ms < *updated_ms + REQUEST_REFILL_MS);
+
+        let (tokens, updated_ms) = buckets.entry(key_id.to_string()).or_insert((limit as f64, now_ms));
+        let (remaining, allowed) = take_token(*tokens, *updated_ms, limit, now_ms);
+        (*tokens, *updated_ms) = (remaining, now_ms);
+        RequestQuota::from_tokens(limit, remaining, allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_exhausts_burst() {
+        let mut bucket = TokenBucket::per_hour(60, 2);
+        assert!(bucket.try_acquire());
+        assert!(bucket.try_acquire());
+        assert!(!bucket.try_acquire());
+        assert!(!bucket.has_token());
+        assert!(bucket.seconds_until_available() > 0);
+    }
+
+    #[test]
+    fn test_request_limiter_bucket() {
+        let limiter = RequestLimiter::default();
+        assert_eq!(limiter.check("key", 2, 100_000).remaining, 1);
+        assert!(limiter.check("key", 2, 100_000).allowed);
+        let rejected = limiter.check("key", 2, 110_000);
+        assert!(!rejected.allowed);
+        // 每30秒补充一个令牌，10秒时已补充1/3个
+        assert_eq!((rejected.remaining, rejected.retry_after_secs, rejected.reset_secs), (0, 20, 50));
+        assert!(limiter.check("other", 2, 110_000).allowed);
+        assert!(limiter.check("key", 2, 130_000).allowed);
+        assert!(!limiter.check("key", 2, 130_000).allowed);
+    }
+
+    #[test]
+    fn test_request_limiter_has_no_window_edge_burst() {
+        // 固定窗口在边界两侧各放行一次满额，令牌桶只按补充速度放行
+        let limiter = RequestLimiter::default();
+        assert!((0..60).all(|_| limiter.check("key", 60, 59_000).allowed));
+        assert!(!limiter.check("key", 60, 59_000).allowed);
+        assert_eq!((0..60).filter(|_| limiter.check("key", 60, 61_000).allowed).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_request_limiter_without_redis_counts_locally() {
+        let limiter = RequestLimiter::from_config(&crate::config::Config::default().cluster).unwrap();
+        assert!(limiter.acquire("key", 1, 100_000).await.allowed);
+        assert!(!limiter.acquire("key", 1, 101_000).await.allowed);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_redis_falls_back_quickly() {
+        let mut config = crate::config::Config::default().cluster;
+        config.redis_url = Some("redis://127.0.0.1:1".to_string());
+        let limiter = RequestLimiter::from_config(&config).unwrap();
+        assert!(limiter.acquire("key", 1, 100_000).await.allowed);
+        // 出错后的等待期内不再尝试Redis
+        assert!(!limiter.redis.as_ref().unwrap().available());
+        assert!(!limiter.acquire("key", 1, 101_000).await.allowed);
+    }
+
+    #[test]
+    fn test_bucket_refills() {
+        let mut bucket = TokenBucket::new(1, 1_000_000.0);
+        assert!(bucket.try_acquire());
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        assert!(bucket.try_acquire());
+    }
+}
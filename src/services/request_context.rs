@@ -0,0 +1,172 @@
+use crate::utils::generate_random_string;
+use reqwest::header::{HeaderMap, HeaderValue};
+use parking_lot::Mutex;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// 补全流程的计时阶段，用作Server-Timing的指标名
+pub const STAGE_TOKEN: &str = "token";
+pub const STAGE_POW: &str = "pow";
+pub const STAGE_SESSION: &str = "session";
+pub const STAGE_UPSTREAM: &str = "upstream"; // 发出补全请求到收到上游响应头
+
+/// 单次补全请求的上下文，在处理器和DeepSeek客户端之间传递
+#[derive(Debug, Default)]
+pub struct RequestContext {
+    retries: AtomicU32, // 上游重试次数
+    stages: Mutex<Vec<(&'static str, Duration)>>, // 各阶段耗时，重试时累加
+}
+
+impl RequestContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一次上游重试
+    pub fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// 执行并记录一个阶段的耗时
+    pub async fn timed<F: Future>(&self, stage: &'static str, future: F) -> F::Output {
+        let started = Instant::now();
+        let output = future.await;
+        self.record_stage(stage, started.elapsed());
+        output
+    }
+
+    fn record_stage(&self, stage: &'static str, elapsed: Duration) {
+        let mut stages = self.stages.lock();
+        match stages.iter_mut().find(|(name, _)| *name == stage) {
+            Some((_, total)) => *total += elapsed,
+            None => stages.push((stage, elapsed)),
+        }
+    }
+
+    /// 某阶段的累计耗时（毫秒），没有经过该阶段时为None
+    pub fn stage_ms(&self, stage: &str) -> Option<u64> {
+        self.stages
+            .lock()
+            .iter()
+            .find(|(name, _)| *name == stage)
+            .map(|(_, elapsed)| elapsed.as_millis() as u64)
+    }
+
+    /// Server-Timing响应头，例如 `pow;dur=35.2, upstream;dur=812.0, total;dur=1020.4`
+    pub fn server_timing(&self, total: Duration) -> String {
+        let mut header = String::new();
+        for (stage, elapsed) in self.stages.lock().iter().chain(std::iter::once(&("total", total))) {
+            if !header.is_empty() {
+                header.push_str(", ");
+            }
+            let _ = write!(header, "{};dur={:.1}", stage, elapsed.as_secs_f64() * 1000.0);
+        }
+        header
+    }
+}
+
+tokio::task_local! {
+    /// 当前请求的W3C追踪上下文，请求处理期间发往上游的请求都会带上
+    static TRACE: TraceContext;
+}
+
+/// W3C Trace Context：保留调用方的trace-id和tracestate，本服务作为新的一跳生成自己的span-id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: String,
+    pub parent_id: String, // 调用方的span-id
+    pub span_id: String,   // 本服务的span-id，作为上游请求的parent-id
+    pub flags: String,
+    pub tracestate: Option<String>,
+}
+
+impl TraceContext {
+    /// 解析traceparent（`00-<32位trace-id>-<16位parent-id>-<2位flags>`），格式无效时忽略
+    pub fn parse(traceparent: &str, tracestate: Option<&str>) -> Option<Self> {
+        let parts: Vec<&str> = traceparent.trim().split('-').collect();
+        let [version, trace_id, parent_id, flags] = parts[..] else {
+            return None;
+        };
+        let is_hex = |value: &str, len: usize| {
+            value.len() == len && value.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        };
+        let is_zero = |value: &str| value.bytes().all(|b| b == b'0');
+        if !is_hex(version, 2) || version == "ff" || !is_hex(trace_id, 32) || !is_hex(parent_id, 16) || !is_hex(flags, 2) {
+            return None;
+        }
+        if is_zero(trace_id) || is_zero(parent_id) {
+            return None;
+        }
+
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            parent_id: parent_id.to_string(),
+            span_id: generate_random_string(16, "hex"),
+            flags: flags.to_string(),
+            tracestate: tracestate.map(str::trim).filter(|state| !state.is_empty()).map(str::to_string),
+        })
+    }
+
+    /// 发往上游的traceparent
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{}", self.trace_id, self.span_id, self.flags)
+    }
+
+    /// 在追踪上下文中执行请求处理
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        TRACE.scope(self, future).await
+    }
+
+    /// 当前请求的追踪上下文，不在请求处理中或调用方未提供时为None
+    pub fn current() -> Option<Self> {
+        TRACE.try_with(Clone::clone).ok()
+    }
+
+    /// 写入traceparent和tracestate请求头
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        if let Ok(value) = HeaderValue::from_str(&self.traceparent()) {
+            headers.insert("traceparent", value);
+        }
+        if let Some(value) = self.tracestate.as_deref().and_then(|state| HeaderValue::from_str(state).ok()) {
+            headers.insert("tracestate", value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_timing_accumulates_stages() {
+        let ctx = RequestContext::new();
+        ctx.record_stage(STAGE_POW, Duration::from_millis(10));
+        ctx.record_stage(STAGE_UPSTREAM, Duration::from_millis(200));
+        ctx.record_stage(STAGE_POW, Duration::from_millis(5));
+        assert_eq!(ctx.stage_ms(STAGE_POW), Some(15));
+        assert_eq!(ctx.stage_ms(STAGE_SESSION), None);
+        assert_eq!(
+            ctx.server_timing(Duration::from_millis(300)),
+            "pow;dur=15.0, upstream;dur=200.0, total;dur=300.0"
+        );
+    }
+
+    #[test]
+    fn test_traceparent_is_validated_and_forwarded_as_child() {
+        let trace = TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01", Some("rojo=00f067aa0ba902b7")).unwrap();
+        assert_eq!(trace.parent_id, "00f067aa0ba902b7");
+        assert!(trace.traceparent().starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(trace.traceparent().ends_with("-01"));
+        assert_ne!(trace.span_id, trace.parent_id);
+
+        assert!(TraceContext::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01", None).is_none());
+        assert!(TraceContext::parse("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01", None).is_none());
+        assert!(TraceContext::parse("garbage", None).is_none());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::utils::unix_timestamp;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// 每个统计维度最多保留的样本数
+const MAX_SAMPLES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: u64,
+    latency_ms: u64,
+    error: bool,
+    retries: u32,
+}
+
+/// 统计摘要
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StatsSummary {
+    pub requests: usize,
+    pub errors: usize,
+    pub error_rate: f64,
+    pub p50_latency_ms: u64,
+    pub p95_latency_ms: u64,
+    pub upstream_retries: u64,
+}
+
+/// 按API密钥和模型汇总的统计报告
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestStatsReport {
+    pub window_secs: u64,
+    pub by_api_key: BTreeMap<String, StatsSummary>,
+    pub by_model: BTreeMap<String, StatsSummary>,
+}
+
+/// 滚动窗口内的请求延迟和错误统计
+pub struct RequestStats {
+    by_api_key: RwLock<HashMap<String, VecDeque<Sample>>>,
+    by_model: RwLock<HashMap<String, VecDeque<Sample>>>,
+    window_secs: u64,
+}
+
+impl RequestStats {
+    pub fn new(window_secs: u64) -> Self {
+        Self {
+            by_api_key: RwLock::new(HashMap::new()),
+            by_model: RwLock::new(HashMap::new()),
+            window_secs,
+        }
+    }
+
+    /// 记录一次请求，api_key_id为None表示未使用API密钥的兼容模式请求
+    pub fn record(&self, api_key_id: Option<&str>, model: &str, latency_ms: u64, error: bool, retries: u32) {
+        let sample = Sample {
+            at: unix_timestamp(),
+            latency_ms,
+            error,
+            retries,
+        };
+
+        if let Some(key_id) = api_key_id {
+            push_sample(&mut self.by_api_key.write(), key_id, sample, self.window_secs);
+        }
+        push_sample(&mut self.by_model.write(), model, sample, self.window_secs);
+    }
+
+    /// 生成当前窗口内的统计报告
+    pub fn report(&self) -> RequestStatsReport {
+        let cutoff = unix_timestamp().saturating_sub(self.window_secs);
+        RequestStatsReport {
+            window_secs: self.window_secs,
+            by_api_key: summarize_all(&self.by_api_key.read(), cutoff),
+            by_model: summarize_all(&self.by_model.read(), cutoff),
+        }
+    }
+}
+
+fn push_sample(samples: &mut HashMap<String, VecDeque<Sample>>, key: &str, sample: Sample, window_secs: u64) {
+    let window = samples.entry(key.to_string()).or_default();
+    let cutoff = sample.at.saturating_sub(window_secs);
+    while window.front().is_some_and(|s| s.at < cutoff) || window.len() >= MAX_SAMPLES {
+        window.pop_front();
+    }
+    window.push_back(sample);
+}
+
+fn summarize_all(samples: &HashMap<String, VecDeque<Sample>>, cutoff: u64) -> BTreeMap<String, StatsSummary> {
+    samples
+        .iter()
+        .filter_map(|(key, window)| {
+            let recent: Vec<Sample> = window.iter().filter(|s| s.at >= cutoff).copied().collect();
+            summarize(&recent).map(|summary| (key.clone(), summary))
+        })
+        .collect()
+}
+
+fn summarize(samples: &[Sample]) -> Option<StatsSummary> {
+    if samples.is_empty() {
+        return None;
+    }
+
+    let mut latencies: Vec<u64> = samples.iter().map(|s| s.latency_ms).collect();
+    latencies.sort_unstable();
+    let errors = samples.iter().filter(|s| s.error).count();
+
+    Some(StatsSummary {
+        requests: samples.len(),
+        errors,
+        error_rate: errors as f64 / samples.len() as f64,
+        p50_latency_ms: percentile(&latencies, 50.0),
+        p95_latency_ms: percentile(&latencies, 95.0),
+        upstream_retries: samples.iter().map(|s| s.retries as u64).sum(),
+    })
+}
+
+/// 最近秩法计算百分位，sorted必须已排序且非空
+pub(crate) fn percentile(sorted: &[u64], p: f64) -> u64 {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let latencies: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&latencies, 50.0), 50);
+        assert_eq!(percentile(&latencies, 95.0), 95);
+        assert_eq!(percentile(&[7], 95.0), 7);
+    }
+
+    #[test]
+    fn test_report_groups_by_key_and_model() {
+        let stats = RequestStats::new(3600);
+        stats.record(Some("key-a"), "deepseek", 100, false, 0);
+        stats.record(Some("key-a"), "deepseek-r1", 300, true, 2);
+        stats.record(None, "deepseek", 200, false, 0);
+
+        let report = stats.report();
+        let key_a = &report.by_api_key["key-a"];
+        assert_eq!(key_a.requests, 2);
+        assert_eq!(key_a.errors, 1);
+        assert_eq!(key_a.upstream_retries, 2);
+        assert_eq!(key_a.p95_latency_ms, 300);
+        assert_eq!(report.by_model["deepseek"].requests, 2);
+        assert_eq!(report.by_api_key.len(), 1);
+    }
+}
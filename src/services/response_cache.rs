@@ -0,0 +1,188 @@
+use crate::models::{ChatCompletionResponse, ChatMessage, ChatMessageContent, CompletionOptions};
+use crate::utils::unix_timestamp;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+struct CacheEntry {
+    response: ChatCompletionResponse,
+    inserted_at: u64,
+    last_used: u64, // 访问序号，用于LRU淘汰
+}
+
+/// 缓存命中统计
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+/// 相同非流式请求的响应缓存（LRU + TTL）
+pub struct ResponseCache {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    ttl: u64,
+    capacity: usize,
+    clock: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: u64, capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+            capacity,
+            clock: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ttl > 0 && self.capacity > 0
+    }
+
+    /// 生成缓存键：(模型, 规范化后的消息, 是否续写最后一条消息, 调用方)
+    pub fn cache_key(model: &str, messages: &[ChatMessage], options: CompletionOptions, caller: &str) -> String {
+        let normalized: Vec<_> = messages
+            .iter()
+            .map(|message| {
+                let content = match &message.content {
+                    ChatMessageContent::Text(text) => json!(text.trim()),
+                    ChatMessageContent::Array(parts) => json!(parts),
+                };
+                json!([
+                    message.role.trim().to_lowercase(),
+                    content,
+                    message.name,
+                    message.tool_call_id,
+                    message.tool_calls
+                ])
+            })
+            .collect();
+
+        let mut hasher = Sha256::new();
+        hasher.update(model.as_bytes());
+        hasher.update([0]);
+        hasher.update(caller.as_bytes());
+        hasher.update([0, options.continue_final_message as u8]);
+        hasher.update(json!(options.features).to_string().as_bytes());
+        hasher.update(json!(normalized).to_string().as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    pub fn get(&self, key: &str) -> Option<ChatCompletionResponse> {
+        let now = unix_timestamp();
+        let mut entries = self.entries.lock();
+
+        let hit = match entries.get_mut(key) {
+            Some(entry) if now.saturating_sub(entry.inserted_at) <= self.ttl => {
+                entry.last_used = self.clock.fetch_add(1, Ordering::Relaxed);
+                Some(entry.response.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        };
+
+        let counter = if hit.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        hit
+    }
+
+    pub fn put(&self, key: String, response: ChatCompletionResponse) {
+        let mut entries = self.entries.lock();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            // 淘汰最久未使用的条目
+            if let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+
+        entries.insert(
+            key,
+            CacheEntry {
+                response,
+                inserted_at: unix_timestamp(),
+                last_used: self.clock.fetch_add(1, Ordering::Relaxed),
+            },
+        );
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: "user".to_string(),
+            content: ChatMessageContent::Text(content.to_string()),
+            name: None,
+            tool_call_id: None,
+            tool_calls: None,
+            reasoning_content: None,
+        }
+    }
+
+    fn response(id: &str) -> ChatCompletionResponse {
+        ChatCompletionResponse {
+            id: id.to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "deepseek".to_string(),
+            choices: Vec::new(),
+            usage: None,
+            x_upstream: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_key_normalizes_whitespace() {
+        let options = CompletionOptions {
+            continue_final_message: true,
+            features: Default::default(),
+            debug_upstream: false,
+            overrides: Default::default(),
+            chaos: None,
+        };
+        let a = ResponseCache::cache_key("deepseek", &[message("hello ")], options, "key");
+        let b = ResponseCache::cache_key("deepseek", &[message(" hello")], options, "key");
+        let c = ResponseCache::cache_key("deepseek", &[message("hello")], options, "other-key");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_lru_eviction_and_stats() {
+        let cache = ResponseCache::new(60, 2);
+        cache.put("a".to_string(), response("a"));
+        cache.put("b".to_string(), response("b"));
+        assert!(cache.get("a").is_some());
+        cache.put("c".to_string(), response("c"));
+
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 1, 2));
+    }
+}
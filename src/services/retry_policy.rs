@@ -0,0 +1,138 @@
+use crate::config::DeepSeekConfig;
+use crate::error::ApiResult;
+use crate::services::RequestContext;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+/// 上游请求重试策略：指数退避加随机抖动，并遵循上游的Retry-After
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &DeepSeekConfig) -> Self {
+        Self {
+            max_retries: config.max_retry_count,
+            base_delay: Duration::from_millis(config.retry_delay_ms),
+            max_delay: Duration::from_millis(config.retry_max_delay_ms),
+        }
+    }
+
+    /// 使用单独设置的最大重试次数，None时保持不变
+    pub fn with_max_retries(self, max_retries: Option<u32>) -> Self {
+        Self {
+            max_retries: max_retries.unwrap_or(self.max_retries),
+            ..self
+        }
+    }
+
+    /// 第attempt次重试（从0开始）前的等待时间，None表示不应重试
+    pub fn delay_for(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+
+        // Retry-After超过最大退避时间时直接放弃，避免请求长时间挂起
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= self.max_delay).then_some(retry_after);
+        }
+
+        // 全抖动：在[0, min(max, base * 2^attempt)]内随机
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let jittered = rand::thread_rng().gen_range(0..=ceiling.as_millis() as u64);
+        Some(Duration::from_millis(jittered))
+    }
+
+    /// 执行操作，可重试的错误按策略退避后重试
+    pub async fn run<T, F, Fut>(&self, ctx: &RequestContext, label: &str, mut op: F) -> ApiResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ApiResult<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let error = match op().await {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+
+            if !error.is_retryable() {
+                return Err(error);
+            }
+            let Some(delay) = self.delay_for(attempt, error.retry_after()) else {
+                return Err(error);
+            };
+
+            tracing::warn!("{} failed, retrying in {}ms: {}", label, delay.as_millis(), error);
+            attempt += 1;
+            ctx.record_retry();
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ApiError;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(250),
+        }
+    }
+
+    #[test]
+    fn test_delay_is_capped_and_jittered() {
+        let policy = policy();
+        for attempt in 0..3 {
+            let delay = policy.delay_for(attempt, None).unwrap();
+            assert!(delay <= Duration::from_millis(250));
+        }
+        assert_eq!(policy.delay_for(3, None), None);
+    }
+
+    #[test]
+    fn test_retry_after_is_honored() {
+        let policy = policy();
+        assert_eq!(
+            policy.delay_for(0, Some(Duration::from_millis(200))),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(policy.delay_for(0, Some(Duration::from_secs(60))), None);
+    }
+
+    #[tokio::test]
+    async fn test_fatal_errors_are_not_retried() {
+        let policy = RetryPolicy { base_delay: Duration::ZERO, ..policy() };
+        let ctx = RequestContext::new();
+        let calls = AtomicU32::new(0);
+
+        let result: ApiResult<()> = policy
+            .run(&ctx, "test", || async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err(ApiError::TokenError("invalid token".to_string()))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        let result: ApiResult<()> = policy
+            .run(&ctx, "test", || async {
+                Err(ApiError::Timeout("upstream".to_string()))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(ctx.retries(), 3);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::config::ScriptConfig;
+use crate::error::{ApiError, ApiResult};
+use crate::models::{ApiKeyInfo, ChatCompletionRequest, ChatCompletionResponse};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+const PRE_REQUEST: &str = "pre_request";
+const POST_RESPONSE: &str = "post_response";
+
+/// Rhai钩子脚本，脚本中可定义：
+/// - `pre_request(request, key)`：请求进入后、内容过滤之前调用，返回修改后的请求
+/// - `post_response(response, key)`：非流式响应的完整回复和流式响应的每个数据块都会调用，返回修改后的响应
+///
+/// request/response为OpenAI格式的JSON对象，key为API密钥信息（未使用API密钥时为()）；
+/// 函数返回()表示不修改，`throw "原因"` 以403拒绝请求
+pub struct ScriptHooks {
+    engine: Engine,
+    ast: Option<AST>,
+}
+
+impl ScriptHooks {
+    pub fn load(config: &ScriptConfig) -> ApiResult<Self> {
+        let engine = new_engine(config);
+        let ast = match &config.path {
+            Some(path) => Some(
+                engine
+                    .compile_file(path.into())
+                    .map_err(|e| ApiError::ConfigError(format!("加载脚本 {} 失败: {}", path, e)))?,
+            ),
+            None => None,
+        };
+        Ok(Self { engine, ast })
+    }
+
+    fn has_fn(&self, name: &str) -> bool {
+        self.ast.as_ref().is_some_and(|ast| ast.iter_functions().any(|f| f.name == name && f.params.len() == 2))
+    }
+
+    pub fn has_pre_request(&self) -> bool {
+        self.has_fn(PRE_REQUEST)
+    }
+
+    pub fn has_post_response(&self) -> bool {
+        self.has_fn(POST_RESPONSE)
+    }
+
+    /// 调用脚本函数，返回()时保持原值
+    fn call<T: Serialize + DeserializeOwned>(&self, name: &str, value: &mut T, key: Option<&ApiKeyInfo>) -> ApiResult<()> {
+        let Some(ast) = self.ast.as_ref().filter(|_| self.has_fn(name)) else {
+            return Ok(());
+        };
+        let input = to_dynamic(&*value)?;
+        let key = match key {
+            Some(key) => to_dynamic(key)?,
+            None => Dynamic::UNIT,
+        };
+
+        let output: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), ast, name, (input, key))
+            .map_err(|e| script_error(name, *e))?;
+        if output.is_unit() {
+            return Ok(());
+        }
+        let invalid = |e: String| ApiError::InternalError(format!("脚本 {} 的返回值无效: {}", name, e));
+        let output = serde_json::to_value(&output).map_err(|e| invalid(e.to_string()))?;
+        *value = serde_json::from_value(output).map_err(|e| invalid(e.to_string()))?;
+        Ok(())
+    }
+
+    pub fn pre_request(&self, request: &mut ChatCompletionRequest, key: Option<&ApiKeyInfo>) -> ApiResult<()> {
+        self.call(PRE_REQUEST, request, key)
+    }
+
+    pub fn post_response(&self, response: &mut ChatCompletionResponse, key: Option<&ApiKeyInfo>) -> ApiResult<()> {
+        self.call(POST_RESPONSE, response, key)
+    }
+
+    /// 流式响应的一个数据块（带SSE的`data: `前缀）经过post_response；[DONE]等非JSON数据原样返回
+    pub fn post_chunk(&self, data: String, key: Option<&ApiKeyInfo>) -> ApiResult<String> {
+        if !self.has_post_response() {
+            return Ok(data);
+        }
+        let payload = data.strip_prefix("data: ").unwrap_or(&data).trim_end();
+        let Ok(mut chunk) = serde_json::from_str::<serde_json::Value>(payload) else {
+            return Ok(data);
+        };
+        self.call(POST_RESPONSE, &mut chunk, key)?;
+        let suffix = &data[data.trim_end().len()..];
+        Ok(format!("data: {}{}", chunk, suffix))
+    }
+}
+
+/// 脚本中throw的值作为拒绝原因，其他错误视为脚本故障
+fn script_error(name: &str, error: EvalAltResult) -> ApiError {
+    match error {
+        EvalAltResult::ErrorRuntime(value, _) => ApiError::Forbidden(value.to_string()),
+        EvalAltResult::ErrorInFunctionCall(_, _, inner, _) => script_error(name, *inner),
+        error => ApiError::InternalError(format!("脚本 {} 执行失败: {}", name, error)),
+    }
+}
+
+fn new_engine(config: &ScriptConfig) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(config.max_operations);
+    // 脚本中的print和debug写入服务日志
+    engine.on_print(|text| tracing::info!(target: "script", "{}", text));
+    engine.on_debug(|text, _, pos| tracing::debug!(target: "script", "{:?}: {}", pos, text));
+    engine
+}
+
+/// 经过JSON转换，f32字段在脚本中为普通浮点数，而不是无法再序列化的自定义类型
+fn to_dynamic<T: Serialize>(value: &T) -> ApiResult<Dynamic> {
+    let error = |e: String| ApiError::InternalError(format!("脚本参数转换失败: {}", e));
+    let value = serde_json::to_value(value).map_err(|e| error(e.to_string()))?;
+    rhai::serde::to_dynamic(value).map_err(|e| error(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use serde_json::json;
+
+    fn hooks(source: &str) -> ScriptHooks {
+        let engine = new_engine(&Config::default().scripts);
+        let ast = engine.compile(source).unwrap();
+        ScriptHooks { engine, ast: Some(ast) }
+    }
+
+    fn request(content: &str) -> ChatCompletionRequest {
+        serde_json::from_value(json!({"model": "deepseek", "temperature": 0.5, "messages": [{"role": "user", "content": content}]}))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_pre_request() {
+        let hooks = hooks(r#"
+            fn pre_request(request, key) {
+                if request.messages[0].content.contains("forbidden") {
+                    throw "policy violation";
+                }
+                if key == () {
+                    return ();
+                }
+                request.messages.insert(0, #{ role: "system", content: `Org: ${key.name}` });
+                request
+            }
+        "#);
+        assert!(hooks.has_pre_request());
+        assert!(!hooks.has_post_response());
+
+        let key: ApiKeyInfo = serde_json::from_value(json!({
+            "id": "k1", "name": "acme", "accounts_count": 0, "usage_count": 0, "created_at": 0, "expires_at": null,
+            "is_active": true, "weight": 1, "priority": "normal", "default_model": null, "scopes": [],
+            "content_filter": null, "requests_per_minute": null, "account_group": null
+        }))
+        .unwrap();
+        let mut with_key = request("hi");
+        hooks.pre_request(&mut with_key, Some(&key)).unwrap();
+        assert_eq!(with_key.messages.len(), 2);
+        assert_eq!(with_key.messages[0].role, "system");
+        assert_eq!(serde_json::to_value(&with_key.messages[0].content).unwrap(), "Org: acme");
+
+        let mut without_key = request("hi");
+        hooks.pre_request(&mut without_key, None).unwrap();
+        assert_eq!(without_key.messages.len(), 1);
+
+        let result = hooks.pre_request(&mut request("forbidden"), None);
+        assert!(matches!(result, Err(ApiError::Forbidden(reason)) if reason == "policy violation"));
+    }
+
+    #[test]
+    fn test_post_chunk() {
+        let hooks = hooks(r#"
+            fn post_response(response, key) {
+                // for循环得到的是副本，修改需要通过下标
+                for i in 0..response.choices.len() {
+                    let content = response.choices[i].delta?.content;
+                    if content != () {
+                        content.replace("secret", "***");
+                        response.choices[i].delta.content = content;
+                    }
+                }
+                response
+            }
+        "#);
+        let data = "data: {\"model\":\"deepseek\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"a secret\"}}]}\n\n";
+        let output = hooks.post_chunk(data.to_string(), None).unwrap();
+        assert!(output.ends_with("\n\n"));
+        let chunk: serde_json::Value = serde_json::from_str(output.trim_start_matches("data: ")).unwrap();
+        assert_eq!(chunk["choices"][0]["delta"]["content"], "a ***");
+        assert_eq!(hooks.post_chunk("data: [DONE]\n\n".to_string(), None).unwrap(), "data: [DONE]\n\n");
+    }
+}
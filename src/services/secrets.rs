@@ -0,0 +1,308 @@
+use crate::config::SecretsConfig;
+use crate::error::{AppError, AppResult};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use parking_lot::RwLock;
+use reqwest::Client;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 外部密钥引用，例如 `vault:secret/deepseek#token` 或 `aws-sm:deepseek/prod#token`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecretRef {
+    Vault { path: String, field: Option<String> },
+    AwsSecretsManager { secret_id: String, field: Option<String> },
+}
+
+impl SecretRef {
+    /// 解析密钥引用，普通明文返回None
+    pub fn parse(value: &str) -> Option<Self> {
+        let (scheme, rest) = value.split_once(':')?;
+        let (target, field) = match rest.rsplit_once('#') {
+            Some((target, field)) => (target, Some(field.to_string())),
+            None => (rest, None),
+        };
+        if target.is_empty() {
+            return None;
+        }
+
+        match scheme {
+            "vault" => Some(SecretRef::Vault {
+                path: target.trim_matches('/').to_string(),
+                field,
+            }),
+            "aws-sm" => Some(SecretRef::AwsSecretsManager {
+                secret_id: target.to_string(),
+                field,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// 外部密钥存储：启动时解析配置中的引用，并定期刷新以支持密钥轮换
+pub struct SecretStore {
+    client: Client,
+    config: SecretsConfig,
+    resolved: RwLock<HashMap<String, String>>, // 引用 -> 解析后的值
+}
+
+impl SecretStore {
+    pub fn new(config: SecretsConfig) -> Self {
+        Self {
+            client: Client::new(),
+            config,
+            resolved: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// 解析配置值，明文原样返回，引用从外部存储读取并缓存
+    pub async fn resolve(&self, value: &str) -> AppResult<String> {
+        let Some(secret_ref) = SecretRef::parse(value) else {
+            return Ok(value.to_string());
+        };
+
+        let secret = self.fetch(&secret_ref).await?;
+        self.resolved.write().insert(value.to_string(), secret.clone());
+        Ok(secret)
+    }
+
+    /// 获取已解析的值，未解析的明文原样返回
+    pub fn get(&self, value: &str) -> String {
+        self.resolved
+            .read()
+            .get(value)
+            .cloned()
+            .unwrap_or_else(|| value.to_string())
+    }
+
+    /// 重新读取所有已解析的引用，失败时保留旧值
+    pub async fn refresh(&self) -> usize {
+        let references: Vec<String> = self.resolved.read().keys().cloned().collect();
+        let mut rotated = 0;
+
+        for reference in references {
+            let previous = self.get(&reference);
+            match self.resolve(&reference).await {
+                Ok(secret) if secret != previous => {
+                    info!("外部密钥已轮换: {}", reference);
+                    rotated += 1;
+                }
+                Ok(_) => {}
+                Err(e) => warn!("刷新外部密钥失败 {}: {}", reference, e),
+            }
+        }
+
+        rotated
+    }
+
+    async fn fetch(&self, secret_ref: &SecretRef) -> AppResult<String> {
+        match secret_ref {
+            SecretRef::Vault { path, field } => self.fetch_vault(path, field.as_deref()).await,
+            SecretRef::AwsSecretsManager { secret_id, field } => {
+                self.fetch_aws(secret_id, field.as_deref()).await
+            }
+        }
+    }
+
+    /// 从Vault KV v2读取密钥，path的第一段为挂载点
+    async fn fetch_vault(&self, path: &str, field: Option<&str>) -> AppResult<String> {
+        let addr = self.config.vault_addr.as_deref()
+            .ok_or_else(|| AppError::ConfigError("引用了Vault密钥但未配置VAULT_ADDR".to_string()))?;
+        let token = self.config.vault_token.as_deref()
+            .ok_or_else(|| AppError::ConfigError("引用了Vault密钥但未配置VAULT_TOKEN".to_string()))?;
+
+        let (mount, key) = path.split_once('/').unwrap_or((path, ""));
+        let url = format!("{}/v1/{}/data/{}", addr.trim_end_matches('/'), mount, key);
+
+        let mut request = self.client.get(&url).header("X-Vault-Token", token);
+        if let Some(namespace) = &self.config.vault_namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(AppError::ExternalApi(format!(
+                "Vault读取{}失败: {}",
+                path,
+                response.status()
+            )));
+        }
+
+        let body: Value = response.json().await?;
+        let data = &body["data"]["data"];
+        select_field(data, field)
+            .ok_or_else(|| AppError::ExternalApi(format!("Vault密钥{}中不存在字段{:?}", path, field)))
+    }
+
+    /// 从AWS Secrets Manager读取密钥
+    async fn fetch_aws(&self, secret_id: &str, field: Option<&str>) -> AppResult<String> {
+        let region = self.config.aws_region.as_deref()
+            .ok_or_else(|| AppError::ConfigError("引用了AWS密钥但未配置AWS_REGION".to_string()))?;
+        let (access_key, secret_key) = match (&self.config.aws_access_key_id, &self.config.aws_secret_access_key) {
+            (Some(access_key), Some(secret_key)) => (access_key, secret_key),
+            _ => {
+                return Err(AppError::ConfigError(
+                    "引用了AWS密钥但未配置AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY".to_string(),
+                ))
+            }
+        };
+
+        let host = format!("secretsmanager.{}.amazonaws.com", region);
+        let body = json!({ "SecretId": secret_id }).to_string();
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date_stamp = now.format("%Y%m%d").to_string();
+        let target = "secretsmanager.GetSecretValue";
+        let content_type = "application/x-amz-json-1.1";
+
+        // SigV4签名，头部按名称排序
+        let mut headers = vec![
+            ("content-type", content_type.to_string()),
+            ("host", host.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(session_token) = &self.config.aws_session_token {
+            headers.push(("x-amz-security-token", session_token.clone()));
+        }
+        headers.push(("x-amz-target", target.to_string()));
+
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let canonical_request = format!(
+            "POST\n/\n\n{}\n{}\n{}",
+            canonical_headers,
+            signed_headers,
+            hex::encode(Sha256::digest(body.as_bytes()))
+        );
+
+        let scope = format!("{}/{}/secretsmanager/aws4_request", date_stamp, region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = aws_signing_key(secret_key, &date_stamp, region, "secretsmanager");
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            access_key, scope, signed_headers, signature
+        );
+
+        let mut request = self.client
+            .post(format!("https://{}/", host))
+            .header("Content-Type", content_type)
+            .header("X-Amz-Date", &amz_date)
+            .header("X-Amz-Target", target)
+            .header("Authorization", authorization)
+            .body(body);
+        if let Some(session_token) = &self.config.aws_session_token {
+            request = request.header("X-Amz-Security-Token", session_token);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(AppError::ExternalApi(format!(
+                "AWS Secrets Manager读取{}失败: {}",
+                secret_id,
+                response.status()
+            )));
+        }
+
+        let body: Value = response.json().await?;
+        let secret_string = body["SecretString"].as_str()
+            .ok_or_else(|| AppError::ExternalApi(format!("AWS密钥{}没有SecretString", secret_id)))?;
+
+        match field {
+            None => Ok(secret_string.to_string()),
+            Some(_) => {
+                let data: Value = serde_json::from_str(secret_string)?;
+                select_field(&data, field).ok_or_else(|| {
+                    AppError::ExternalApi(format!("AWS密钥{}中不存在字段{:?}", secret_id, field))
+                })
+            }
+        }
+    }
+}
+
+/// 从JSON对象中取出字段，未指定字段时对象只能有一个值
+fn select_field(data: &Value, field: Option<&str>) -> Option<String> {
+    let value = match field {
+        Some(field) => data.get(field)?,
+        None => {
+            let object = data.as_object()?;
+            if object.len() != 1 {
+                return None;
+            }
+            object.values().next()?
+        }
+    };
+
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// 派生SigV4签名密钥
+fn aws_signing_key(secret_key: &str, date_stamp: &str, region: &str, service: &str) -> Vec<u8> {
+    let k_date = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date_stamp.as_bytes());
+    let k_region = hmac_sha256(&k_date, region.as_bytes());
+    let k_service = hmac_sha256(&k_region, service.as_bytes());
+    hmac_sha256(&k_service, b"aws4_request")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_secret_refs() {
+        assert_eq!(
+            SecretRef::parse("vault:secret/deepseek#token"),
+            Some(SecretRef::Vault {
+                path: "secret/deepseek".to_string(),
+                field: Some("token".to_string()),
+            })
+        );
+        assert_eq!(
+            SecretRef::parse("aws-sm:deepseek/prod"),
+            Some(SecretRef::AwsSecretsManager {
+                secret_id: "deepseek/prod".to_string(),
+                field: None,
+            })
+        );
+        assert_eq!(SecretRef::parse("plain-token"), None);
+        assert_eq!(SecretRef::parse("Bearer abc:def"), None);
+    }
+
+    #[test]
+    fn test_aws_signing_key() {
+        // AWS SigV4文档中的示例
+        let key = aws_signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+}
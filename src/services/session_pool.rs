@@ -1,10 +1,14 @@
+use crate::config::{Config, PoolConfig};
 use crate::error::{AppError, AppResult};
 use crate::models::*;
-use std::collections::HashMap;
+use crate::services::quota_cache::QuotaCache;
+use crate::services::rate_limiter::TokenBucket;
+use crate::utils::{generate_uuid, parse_conversation_id, rendezvous_score};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use parking_lot::RwLock;
 use std::time::{SystemTime, UNIX_EPOCH, Duration};
-use uuid::Uuid;
 use tracing::{info, warn, debug, error};
 use tokio::sync::Semaphore;
 
@@ -29,6 +33,7 @@ pub struct DeepSeekSession {
     pub created_at: u64,
     pub messages_count: usize,
     pub api_key: String,  // 关联的API密钥
+    pub model: Option<String>,  // 最近一次请求使用的模型
 }
 
 /// 账号会话池
@@ -37,38 +42,154 @@ pub struct AccountSessionPool {
     pub account_email: String,
     pub user_token: String,
     pub sessions: HashMap<String, DeepSeekSession>,  // conversation_id -> session
-    pub active_session: Option<String>,  // 当前活跃的会话ID
+    pub active_sessions: HashSet<String>,  // 当前活跃的会话ID
     pub last_activity: u64,
-    pub semaphore: Arc<Semaphore>,  // 并发控制，每个账号同时只能有1个活跃会话
+    pub policy: PoolConfig,  // 合并账号单独设置后的策略
+    pub recent_errors: VecDeque<u64>,  // 最近的上游错误时间戳
+    pub cooldown_until: Option<u64>,  // 冷却截止时间，冷却结束并探测成功后才恢复
+}
+
+/// 同一账号在所有API密钥下共享的并发和消息速率限制，账号关联多个API密钥时上限不会成倍放大
+#[derive(Debug)]
+struct AccountLimits {
+    semaphore: Arc<Semaphore>,  // 串行化同一账号的会话获取
+    rate_bucket: Option<TokenBucket>,  // 消息速率预算，None表示不限制
+    policy: PoolConfig,
+}
+
+/// 会话获取选项
+#[derive(Debug, Clone, Default)]
+pub struct AcquireOptions {
+    /// 请求需要深度思考配额（R1/think模型）
+    pub requires_thinking: bool,
+    /// OpenAI `user` 字段，同一用户固定路由到同一账号
+    pub user: Option<String>,
+    /// 请求的模型，记录在会话上供对话列表展示
+    pub model: Option<String>,
+    /// 路由规则选择的账号分组
+    pub account_group: Option<String>,
+    /// 分组内账号的userToken，由API密钥管理器按account_group填充
+    pub group_tokens: Option<HashSet<String>>,
+}
+
+/// 账号池的调度标识（排序后的账号token）和容量（各账号并发上限之和）
+fn scheduling_pool(api_pools: &HashMap<String, AccountSessionPool>, limits: &HashMap<String, AccountLimits>) -> (String, usize) {
+    let mut tokens: Vec<&str> = api_pools.values().map(|pool| pool.user_token.as_str()).collect();
+    tokens.sort_unstable();
+    let capacity = tokens.iter()
+        .map(|token| limits.get(*token).map_or(1, AccountLimits::max_concurrency))
+        .sum();
+    (tokens.join("\n"), capacity)
+}
+
+/// 按策略创建消息速率令牌桶，未设置上限时返回None
+fn rate_bucket(policy: &PoolConfig) -> Option<TokenBucket> {
+    (policy.account_messages_per_hour > 0).then(|| {
+        TokenBucket::per_hour(policy.account_messages_per_hour, policy.account_message_burst.max(1))
+    })
+}
+
+impl AccountLimits {
+    fn new(policy: PoolConfig) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(policy.account_max_concurrency.max(1))),
+            rate_bucket: rate_bucket(&policy),
+            policy,
+        }
+    }
+
+    /// 更新策略：并发数或消息速率变化时重建信号量和令牌桶，正在处理的请求不受影响
+    fn set_policy(&mut self, policy: PoolConfig) {
+        if policy.account_max_concurrency != self.policy.account_max_concurrency {
+            self.semaphore = Arc::new(Semaphore::new(policy.account_max_concurrency.max(1)));
+        }
+        if (policy.account_messages_per_hour, policy.account_message_burst)
+            != (self.policy.account_messages_per_hour, self.policy.account_message_burst)
+        {
+            self.rate_bucket = rate_bucket(&policy);
+        }
+        self.policy = policy;
+    }
+
+    fn max_concurrency(&self) -> usize {
+        self.policy.account_max_concurrency.max(1)
+    }
+
+    /// 账号是否还有消息速率预算
+    fn has_rate_budget(&mut self) -> bool {
+        self.rate_bucket.as_mut().map(|bucket| bucket.has_token()).unwrap_or(true)
+    }
+
+    /// 消耗一条消息的速率预算
+    fn consume_rate_budget(&mut self, account_email: &str) -> AppResult<()> {
+        if let Some(bucket) = self.rate_bucket.as_mut() {
+            if !bucket.try_acquire() {
+                let retry_after_secs = bucket.seconds_until_available();
+                return Err(AppError::Unavailable {
+                    message: format!(
+                        "Account {} exceeded its message rate budget, retry in {}s",
+                        account_email, retry_after_secs
+                    ),
+                    retry_after_secs,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// 距恢复消息速率预算的秒数
+    fn rate_budget_wait_secs(&mut self) -> u64 {
+        self.rate_bucket.as_mut().map_or(0, |bucket| bucket.seconds_until_available())
+    }
+}
+
+type Pools = HashMap<String, HashMap<String, AccountSessionPool>>;
+
+/// 账号在所有API密钥下的活跃会话数
+fn account_active_sessions(pools: &Pools, user_token: &str) -> usize {
+    pools
+        .values()
+        .flat_map(|api_pools| api_pools.values())
+        .filter(|pool| pool.user_token == user_token)
+        .map(|pool| pool.active_sessions.len())
+        .sum()
 }
 
 /// 会话池管理器
 pub struct SessionPoolManager {
     /// 按API密钥分组的账号池: api_key -> [account_email -> SessionPool]
-    pools: Arc<RwLock<HashMap<String, HashMap<String, AccountSessionPool>>>>,
-    /// 会话映射: conversation_id -> (api_key, account_email)
+    pools: Arc<RwLock<Pools>>,
+    /// 按userToken共享的账号限制；需要同时持有时先锁pools再锁limits
+    limits: Arc<RwLock<HashMap<String, AccountLimits>>>,
+    /// 会话映射: conversation_id -> (api_key, account_email)；需要同时持有时先锁pools再锁映射
     session_mapping: Arc<RwLock<HashMap<String, (String, String)>>>,
     /// 全局会话超时时间（秒）
     session_timeout: u64,
+    /// 账号功能配额缓存
+    quota_cache: Arc<QuotaCache>,
+    /// 账号池策略配置
+    config: PoolConfig,
 }
 
 impl AccountSessionPool {
-    pub fn new(account_email: String, user_token: String) -> Self {
+    pub fn new(account_email: String, user_token: String, policy: PoolConfig) -> Self {
         Self {
             account_email,
             user_token,
             sessions: HashMap::new(),
-            active_session: None,
+            active_sessions: HashSet::new(),
             last_activity: SystemTime::now().duration_since(UNIX_EPOCH)
                 .unwrap_or_default().as_secs(),
-            semaphore: Arc::new(Semaphore::new(1)), // 每个账号同时只能处理1个请求
+            recent_errors: VecDeque::new(),
+            cooldown_until: None,
+            policy,
         }
     }
 
     /// 创建新会话
     pub fn create_session(&mut self, conversation_id: Option<String>, api_key: String) -> String {
-        let session_id = Uuid::new_v4().to_string();
-        let conv_id = conversation_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        let session_id = generate_uuid();
+        let conv_id = conversation_id.unwrap_or_else(generate_uuid);
         
         let session = DeepSeekSession {
             session_id: session_id.clone(),
@@ -82,6 +203,7 @@ impl AccountSessionPool {
                 .unwrap_or_default().as_secs(),
             messages_count: 0,
             api_key,
+            model: None,
         };
 
         self.sessions.insert(conv_id.clone(), session);
@@ -113,20 +235,18 @@ impl AccountSessionPool {
         }
     }
 
-    /// 设置会话为活跃状态
-    pub fn activate_session(&mut self, conversation_id: &str) -> AppResult<()> {
+    /// 设置会话为活跃状态，account_busy表示账号在所有API密钥下的活跃会话已达到并发上限
+    pub fn activate_session(&mut self, conversation_id: &str, account_busy: bool) -> AppResult<()> {
         if let Some(session) = self.sessions.get_mut(conversation_id) {
-            // 如果已有活跃会话且不是当前会话，需要等待
-            if let Some(active_id) = &self.active_session {
-                if active_id != conversation_id {
-                    return Err(AppError::ServiceUnavailable(
-                        "Account is busy with another session".to_string()
-                    ));
-                }
+            // 活跃会话已达到并发上限且不包含当前会话，需要等待
+            if !self.active_sessions.contains(conversation_id) && account_busy {
+                return Err(AppError::ServiceUnavailable(
+                    "Account is busy with another session".to_string()
+                ));
             }
 
             session.state = SessionState::Active;
-            self.active_session = Some(conversation_id.to_string());
+            self.active_sessions.insert(conversation_id.to_string());
             self.last_activity = SystemTime::now().duration_since(UNIX_EPOCH)
                 .unwrap_or_default().as_secs();
             
@@ -137,6 +257,14 @@ impl AccountSessionPool {
         }
     }
 
+    /// 撤销激活，不计入消息数
+    fn deactivate_session(&mut self, conversation_id: &str) {
+        if let Some(session) = self.sessions.get_mut(conversation_id) {
+            session.state = SessionState::Idle;
+        }
+        self.active_sessions.remove(conversation_id);
+    }
+
     /// 释放会话
     pub fn release_session(&mut self, conversation_id: &str) {
         if let Some(session) = self.sessions.get_mut(conversation_id) {
@@ -144,9 +272,7 @@ impl AccountSessionPool {
             session.messages_count += 1;
         }
         
-        if self.active_session.as_ref() == Some(&conversation_id.to_string()) {
-            self.active_session = None;
-        }
+        self.active_sessions.remove(conversation_id);
         
         debug!("Released session {} for account {}", conversation_id, self.account_email);
     }
@@ -160,8 +286,8 @@ impl AccountSessionPool {
         
         self.sessions.retain(|conv_id, session| {
             let is_expired = (now - session.last_used) > timeout;
-            if is_expired && self.active_session.as_ref() == Some(conv_id) {
-                self.active_session = None;
+            if is_expired {
+                self.active_sessions.remove(conv_id);
             }
             !is_expired
         });
@@ -169,14 +295,46 @@ impl AccountSessionPool {
         initial_count - self.sessions.len()
     }
 
-    /// 检查账号是否可用
-    pub fn is_available(&self) -> bool {
-        self.active_session.is_none()
+    /// 记录一次上游错误，返回是否因此进入冷却
+    pub fn record_error(&mut self, severe: bool) -> bool {
+        let policy = &self.policy;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)
+            .unwrap_or_default().as_secs();
+
+        self.recent_errors.push_back(now);
+        while let Some(&oldest) = self.recent_errors.front() {
+            if now.saturating_sub(oldest) > policy.cooldown_window_secs {
+                self.recent_errors.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        // 封禁/限流类错误立即冷却，其他错误累计到阈值后冷却
+        let threshold_reached = self.recent_errors.len() as u32 >= policy.cooldown_error_threshold.max(1);
+        if self.cooldown_until.is_none() && (severe || threshold_reached) {
+            self.cooldown_until = Some(now + policy.cooldown_secs);
+            return true;
+        }
+
+        false
+    }
+
+    /// 账号是否处于冷却中（包括冷却结束但尚未探测）
+    pub fn is_cooling_down(&self) -> bool {
+        self.cooldown_until.is_some()
+    }
+
+    /// 距冷却结束的秒数，冷却已到期但尚未探测时为0
+    pub fn cooldown_remaining_secs(&self) -> u64 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)
+            .unwrap_or_default().as_secs();
+        self.cooldown_until.map_or(0, |until| until.saturating_sub(now))
     }
 
-    /// 获取负载分数（越低越好）
-    pub fn get_load_score(&self) -> f64 {
-        let base_score = if self.is_available() { 0.0 } else { 1000.0 };
+    /// 获取负载分数（越低越好），available表示账号未达到并发上限
+    pub fn get_load_score(&self, available: bool) -> f64 {
+        let base_score = if available { 0.0 } else { 1000.0 };
         let session_count_penalty = self.sessions.len() as f64 * 0.1;
         let age_penalty = {
             let now = SystemTime::now().duration_since(UNIX_EPOCH)
@@ -189,33 +347,102 @@ impl AccountSessionPool {
 }
 
 impl SessionPoolManager {
-    pub fn new() -> Self {
+    pub fn new(config: PoolConfig, quota_cache: Arc<QuotaCache>) -> Self {
         Self {
             pools: Arc::new(RwLock::new(HashMap::new())),
+            limits: Arc::new(RwLock::new(HashMap::new())),
             session_mapping: Arc::new(RwLock::new(HashMap::new())),
             session_timeout: 3600, // 1小时超时
+            quota_cache,
+            config,
         }
     }
 
-    /// 添加账号到指定API密钥
-    pub fn add_account(&self, api_key: String, account_email: String, user_token: String) {
+    /// 添加账号到指定API密钥，overrides为账号单独设置的策略
+    pub fn add_account(&self, api_key: String, account_email: String, user_token: String, overrides: &AccountOverrides) {
         let mut pools = self.pools.write();
         let api_pools = pools.entry(api_key).or_insert_with(HashMap::new);
         
         if !api_pools.contains_key(&account_email) {
+            let policy = self.config.for_account(overrides);
+            self.limits
+                .write()
+                .entry(user_token.clone())
+                .or_insert_with(|| AccountLimits::new(policy.clone()));
             api_pools.insert(
                 account_email.clone(),
-                AccountSessionPool::new(account_email.clone(), user_token)
+                AccountSessionPool::new(account_email.clone(), user_token, policy)
             );
             info!("Added account {} to API key pool", account_email);
         }
     }
 
+    /// 移除不再属于任何账号池的账号限制
+    fn retain_limits(&self, pools: &Pools) {
+        self.limits.write().retain(|token, _| {
+            pools.values().flat_map(|api_pools| api_pools.values()).any(|pool| pool.user_token == *token)
+        });
+    }
+
+    /// 获取账号的信号量，串行化同一账号的会话获取
+    fn account_semaphore(&self, api_key: &str, account_email: &str) -> AppResult<Arc<Semaphore>> {
+        let pools = self.pools.read();
+        let pool = pools.get(api_key)
+            .and_then(|api_pools| api_pools.get(account_email))
+            .ok_or_else(|| AppError::NotFound("Account not found".to_string()))?;
+        self.limits
+            .read()
+            .get(&pool.user_token)
+            .map(|limits| limits.semaphore.clone())
+            .ok_or_else(|| AppError::NotFound("Account not found".to_string()))
+    }
+
+    /// 激活会话，激活成功后才消耗账号的消息速率预算，预算不足时撤销激活
+    fn activate_with_budget(&self, pools: &mut Pools, api_key: &str, account_email: &str, conversation_id: &str, options: &AcquireOptions) -> AppResult<()> {
+        let user_token = pools.get(api_key)
+            .and_then(|api_pools| api_pools.get(account_email))
+            .map(|pool| pool.user_token.clone())
+            .ok_or_else(|| AppError::NotFound("Account not found".to_string()))?;
+        let mut limits = self.limits.write();
+        let account_limits = limits.get_mut(&user_token)
+            .ok_or_else(|| AppError::NotFound("Account not found".to_string()))?;
+        let account_busy = account_active_sessions(pools, &user_token) >= account_limits.max_concurrency();
+
+        let account_pool = pools.get_mut(api_key)
+            .and_then(|api_pools| api_pools.get_mut(account_email))
+            .ok_or_else(|| AppError::NotFound("Account not found".to_string()))?;
+        account_pool.activate_session(conversation_id, account_busy)?;
+        if let Err(e) = account_limits.consume_rate_budget(account_email) {
+            account_pool.deactivate_session(conversation_id);
+            return Err(e);
+        }
+        if let Some(session) = account_pool.sessions.get_mut(conversation_id) {
+            session.model = options.model.clone();
+        }
+        Ok(())
+    }
+
+    /// 从账号池移除账号，指定API密钥时只从该密钥下移除；账号上的会话一并失效
+    pub fn remove_account(&self, api_key: Option<&str>, user_token: &str) {
+        let mut pools = self.pools.write();
+        pools.retain(|key, api_pools| {
+            if api_key.is_none_or(|api_key| api_key == key) {
+                api_pools.retain(|_, pool| pool.user_token != user_token);
+            }
+            !api_pools.is_empty()
+        });
+        self.retain_limits(&pools);
+        self.session_mapping
+            .write()
+            .retain(|_, (api_key, email)| pools.get(api_key).is_some_and(|api_pools| api_pools.contains_key(email)));
+    }
+
     /// 获取最佳账号进行会话处理
     pub async fn acquire_session(
         &self,
         api_key: &str,
         conversation_id: Option<String>,
+        options: &AcquireOptions,
     ) -> AppResult<(String, DeepSeekSession)> {
         // 1. 如果有conversation_id，先尝试找到对应的会话
         if let Some(conv_id) = &conversation_id {
@@ -225,23 +452,17 @@ impl SessionPoolManager {
             };
             
             if let Some((mapped_api_key, account_email)) = existing_mapping {
-                if mapped_api_key == api_key {
-                    return self.reuse_existing_session(api_key, &account_email, conv_id).await;
+                if mapped_api_key == api_key && !self.is_account_cooling_down(api_key, &account_email) {
+                    return self.reuse_existing_session(api_key, &account_email, conv_id, options).await;
                 }
             }
         }
 
         // 2. 寻找最佳可用账号
-        let best_account = self.find_best_available_account(api_key)?;
+        let best_account = self.find_best_available_account(api_key, options)?;
         
-        // 3. 获取账号的信号量
-        let semaphore = {
-            let pools = self.pools.read();
-            pools.get(api_key)
-                .and_then(|api_pools| api_pools.get(&best_account))
-                .map(|pool| pool.semaphore.clone())
-                .ok_or_else(|| AppError::NotFound("Account not found".to_string()))?
-        };
+        // 3. 获取账号的信号量（所有API密钥共享）
+        let semaphore = self.account_semaphore(api_key, &best_account)?;
 
         // 4. 等待获取信号量（确保同时只有一个请求）
         let _permit = semaphore.acquire().await
@@ -256,7 +477,7 @@ impl SessionPoolManager {
                 .ok_or_else(|| AppError::NotFound("Account not found".to_string()))?;
             
             let conv_id = account_pool.get_or_create_session(conversation_id, api_key.to_string())?;
-            account_pool.activate_session(&conv_id)?;
+            self.activate_with_budget(&mut pools, api_key, &best_account, &conv_id, options)?;
             conv_id
         };
 
@@ -286,29 +507,16 @@ impl SessionPoolManager {
         api_key: &str,
         account_email: &str,
         conversation_id: &str,
+        options: &AcquireOptions,
     ) -> AppResult<(String, DeepSeekSession)> {
         // 获取信号量
-        let semaphore = {
-            let pools = self.pools.read();
-            pools.get(api_key)
-                .and_then(|api_pools| api_pools.get(account_email))
-                .map(|pool| pool.semaphore.clone())
-                .ok_or_else(|| AppError::NotFound("Account not found".to_string()))?
-        };
+        let semaphore = self.account_semaphore(api_key, account_email)?;
 
         let _permit = semaphore.acquire().await
             .map_err(|e| AppError::Internal(format!("Failed to acquire semaphore: {}", e)))?;
 
         // 激活会话
-        {
-            let mut pools = self.pools.write();
-            let api_pools = pools.get_mut(api_key)
-                .ok_or_else(|| AppError::NotFound("API key not found".to_string()))?;
-            let account_pool = api_pools.get_mut(account_email)
-                .ok_or_else(|| AppError::NotFound("Account not found".to_string()))?;
-            
-            account_pool.activate_session(conversation_id)?;
-        }
+        self.activate_with_budget(&mut self.pools.write(), api_key, account_email, conversation_id, options)?;
 
         let session = {
             let pools = self.pools.read();
@@ -325,11 +533,12 @@ impl SessionPoolManager {
 
     /// 释放会话
     pub fn release_session(&self, conversation_id: &str) {
-        let mapping = self.session_mapping.read();
-        if let Some((api_key, account_email)) = mapping.get(conversation_id) {
+        // 先复制映射再锁pools，避免与先锁pools再锁映射的rebuild等操作互相等待
+        let mapped = self.session_mapping.read().get(conversation_id).cloned();
+        if let Some((api_key, account_email)) = mapped {
             let mut pools = self.pools.write();
-            if let Some(api_pools) = pools.get_mut(api_key) {
-                if let Some(account_pool) = api_pools.get_mut(account_email) {
+            if let Some(api_pools) = pools.get_mut(&api_key) {
+                if let Some(account_pool) = api_pools.get_mut(&account_email) {
                     account_pool.release_session(conversation_id);
                     info!("Released session {} for account {}", conversation_id, account_email);
                 }
@@ -337,9 +546,71 @@ impl SessionPoolManager {
         }
     }
 
+    /// 列出API密钥下已知的对话，最近活跃的在前
+    pub fn list_conversations(&self, api_key: &str) -> Vec<DeepSeekSession> {
+        let pools = self.pools.read();
+        let mapping = self.session_mapping.read();
+        let mut sessions: Vec<DeepSeekSession> = mapping
+            .iter()
+            .filter(|(_, (mapped_api_key, _))| mapped_api_key == api_key)
+            .filter_map(|(conv_id, (_, account_email))| {
+                pools.get(api_key)?.get(account_email)?.sessions.get(conv_id).cloned()
+            })
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_used));
+        sessions
+    }
+
+    /// 查找对话所在的会话，同一上游会话的其他轮次也算
+    pub fn find_conversation(&self, api_key: &str, conversation_id: &str) -> Option<DeepSeekSession> {
+        let upstream = parse_conversation_id(conversation_id).map(|(session_id, _)| session_id);
+        let pools = self.pools.read();
+        let mapping = self.session_mapping.read();
+        mapping
+            .iter()
+            .filter(|(conv_id, (mapped_api_key, _))| {
+                mapped_api_key == api_key
+                    && (*conv_id == conversation_id
+                        || (upstream.is_some() && parse_conversation_id(conv_id).map(|(session_id, _)| session_id) == upstream))
+            })
+            .find_map(|(conv_id, (_, account_email))| pools.get(api_key)?.get(account_email)?.sessions.get(conv_id).cloned())
+    }
+
+    /// 结束对话：移除该API密钥下属于同一上游会话的所有对话及映射，返回其中一个会话
+    pub fn end_conversation(&self, api_key: &str, conversation_id: &str) -> Option<DeepSeekSession> {
+        // 每轮回复都会产生新的 `会话ID@消息ID`，同一上游会话的各轮对话一起结束
+        let upstream = parse_conversation_id(conversation_id).map(|(session_id, _)| session_id);
+        let same_thread = |conv_id: &str| {
+            conv_id == conversation_id
+                || (upstream.is_some() && parse_conversation_id(conv_id).map(|(session_id, _)| session_id) == upstream)
+        };
+
+        let mut pools = self.pools.write();
+        let mut mapping = self.session_mapping.write();
+        let mut ended = None;
+        mapping.retain(|conv_id, (mapped_api_key, account_email)| {
+            if mapped_api_key != api_key || !same_thread(conv_id) {
+                return true;
+            }
+            if let Some(pool) = pools.get_mut(api_key).and_then(|api_pools| api_pools.get_mut(account_email)) {
+                pool.active_sessions.remove(conv_id.as_str());
+                if let Some(session) = pool.sessions.remove(conv_id) {
+                    ended.get_or_insert(session);
+                }
+            }
+            false
+        });
+
+        if let Some(session) = &ended {
+            info!("Ended conversation {} on account {}", conversation_id, session.account_email);
+        }
+        ended
+    }
+
     /// 找到最佳可用账号
-    fn find_best_available_account(&self, api_key: &str) -> AppResult<String> {
+    fn find_best_available_account(&self, api_key: &str, options: &AcquireOptions) -> AppResult<String> {
         let pools = self.pools.read();
+        let mut limits = self.limits.write();
         let api_pools = pools.get(api_key)
             .ok_or_else(|| AppError::NotFound("API key not found".to_string()))?;
 
@@ -347,11 +618,91 @@ impl SessionPoolManager {
             return Err(AppError::NotFound("No accounts available for this API key".to_string()));
         }
 
+        // 路由规则选择了账号分组时只在分组内选择
+        let in_group = |pool: &AccountSessionPool| {
+            options.group_tokens.as_ref().is_none_or(|tokens| tokens.contains(&pool.user_token))
+        };
+        if !api_pools.values().any(in_group) {
+            return Err(AppError::ServiceUnavailable(format!(
+                "账号分组 {} 中没有可用的账号",
+                options.account_group.as_deref().unwrap_or_default()
+            )));
+        }
+
+        // 跳过冷却中的账号
+        let candidates: Vec<(&String, &AccountSessionPool)> = api_pools.iter()
+            .filter(|(_, pool)| in_group(pool) && !pool.is_cooling_down())
+            .collect();
+
+        if candidates.is_empty() {
+            // 最早结束冷却的账号决定客户端的等待时间
+            let retry_after_secs = api_pools.values().filter(|pool| in_group(pool)).map(|pool| pool.cooldown_remaining_secs()).min().unwrap_or(0);
+            return Err(AppError::Unavailable {
+                message: "所有账号都在冷却中，请稍后再试".to_string(),
+                retry_after_secs,
+            });
+        }
+
+        // 跳过消息速率预算已用完的账号，预算按账号在所有API密钥间共享
+        let mut budget_wait_secs = None;
+        let candidates: Vec<(&String, &AccountSessionPool)> = candidates.into_iter()
+            .filter_map(|(email, pool)| {
+                let Some(account_limits) = limits.get_mut(&pool.user_token) else {
+                    return Some((email, pool));
+                };
+                if account_limits.has_rate_budget() {
+                    return Some((email, pool));
+                }
+                let wait = account_limits.rate_budget_wait_secs();
+                budget_wait_secs = Some(budget_wait_secs.map_or(wait, |current: u64| current.min(wait)));
+                None
+            })
+            .collect();
+
+        if candidates.is_empty() {
+            return Err(AppError::Unavailable {
+                message: "所有账号都已达到消息速率上限，请稍后再试".to_string(),
+                retry_after_secs: budget_wait_secs.unwrap_or(0),
+            });
+        }
+
+        // 深度思考请求跳过配额已耗尽的账号
+        let candidates: Vec<(&String, &AccountSessionPool)> = candidates.into_iter()
+            .filter(|(_, pool)| {
+                !options.requires_thinking || self.quota_cache.has_thinking_quota(&pool.user_token)
+            })
+            .collect();
+
+        if candidates.is_empty() {
+            return Err(AppError::ServiceUnavailable(
+                "所有账号的深度思考配额均已耗尽".to_string()
+            ));
+        }
+
+        // 账号在所有API密钥下的活跃会话未达到并发上限
+        let is_available = |pool: &AccountSessionPool| {
+            let max = limits.get(&pool.user_token).map_or(1, AccountLimits::max_concurrency);
+            account_active_sessions(&pools, &pool.user_token) < max
+        };
+
+        // 指定了终端用户时按一致性哈希选择账号，首选账号繁忙时顺延
+        if let Some(user) = &options.user {
+            let mut ranked = candidates;
+            ranked.sort_by_key(|(email, _)| std::cmp::Reverse(rendezvous_score(user, email)));
+            let (email, _) = ranked.iter()
+                .find(|(_, pool)| is_available(pool))
+                .unwrap_or(&ranked[0]);
+
+            // user和API密钥属于调用方信息，不写入日志
+            debug!("Routing user to account {}", email);
+            return Ok((*email).clone());
+        }
+
         // 寻找负载最低的可用账号
-        let best_account = api_pools.iter()
+        let best_account = candidates.into_iter()
             .min_by(|(_, pool_a), (_, pool_b)| {
-                pool_a.get_load_score()
-                    .partial_cmp(&pool_b.get_load_score())
+                pool_a.get_load_score(is_available(pool_a))
+                    .partial_cmp(&pool_b.get_load_score(is_available(pool_b)))
                     .unwrap_or(std::cmp::Ordering::Equal)
             })
             .map(|(email, _)| email.clone())
@@ -395,6 +746,195 @@ impl SessionPoolManager {
         Ok(total_cleaned)
     }
 
+    /// 账号是否处于冷却中
+    fn is_account_cooling_down(&self, api_key: &str, account_email: &str) -> bool {
+        let pools = self.pools.read();
+        pools.get(api_key)
+            .and_then(|api_pools| api_pools.get(account_email))
+            .map(|pool| pool.is_cooling_down())
+            .unwrap_or(false)
+    }
+
+    /// 记录账号的上游错误，错误集中爆发时自动冷却
+    pub fn record_account_error(&self, user_token: &str, error: &AppError) {
+        let severe = error.is_ban_like();
+        let mut pools = self.pools.write();
+
+        for pool in pools.values_mut().flat_map(|api_pools| api_pools.values_mut()) {
+            if pool.user_token == user_token && pool.record_error(severe) {
+                warn!("Account {} entered cooldown for {}s after upstream errors: {}",
+                      pool.account_email, pool.policy.cooldown_secs, error);
+            }
+        }
+    }
+
+    /// 冷却已结束、等待重新探测的账号token
+    pub fn accounts_due_for_probe(&self) -> Vec<String> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)
+            .unwrap_or_default().as_secs();
+        let pools = self.pools.read();
+        let mut tokens: Vec<String> = Vec::new();
+
+        for pool in pools.values().flat_map(|api_pools| api_pools.values()) {
+            let due = pool.cooldown_until.map(|until| until <= now).unwrap_or(false);
+            if due && !tokens.contains(&pool.user_token) {
+                tokens.push(pool.user_token.clone());
+            }
+        }
+
+        tokens
+    }
+
+    /// 记录探测结果：成功则恢复轮换，失败则继续冷却
+    pub fn finish_probe(&self, user_token: &str, healthy: bool) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)
+            .unwrap_or_default().as_secs();
+        let mut pools = self.pools.write();
+
+        for pool in pools.values_mut().flat_map(|api_pools| api_pools.values_mut()) {
+            if pool.user_token != user_token {
+                continue;
+            }
+            if healthy {
+                pool.cooldown_until = None;
+                pool.recent_errors.clear();
+                info!("Account {} passed probe and returned to rotation", pool.account_email);
+            } else {
+                pool.cooldown_until = Some(now + pool.policy.cooldown_secs);
+                warn!("Account {} failed probe, extending cooldown", pool.account_email);
+            }
+        }
+    }
+
+    /// 按token查找账号邮箱
+    pub fn account_email(&self, user_token: &str) -> Option<String> {
+        let pools = self.pools.read();
+        pools.values()
+            .flat_map(|api_pools| api_pools.values())
+            .find(|pool| pool.user_token == user_token)
+            .map(|pool| pool.account_email.clone())
+    }
+
+    /// 账号重新登录后替换token，已有会话一并更新
+    pub fn replace_user_token(&self, old_token: &str, new_token: &str) {
+        let mut pools = self.pools.write();
+
+        for pool in pools.values_mut().flat_map(|api_pools| api_pools.values_mut()) {
+            if pool.user_token != old_token {
+                continue;
+            }
+            pool.user_token = new_token.to_string();
+            for session in pool.sessions.values_mut() {
+                session.user_token = new_token.to_string();
+            }
+        }
+        let mut limits = self.limits.write();
+        if let Some(account_limits) = limits.remove(old_token) {
+            limits.insert(new_token.to_string(), account_limits);
+        }
+    }
+
+    /// 更新账号单独设置的策略，所有关联API密钥下的账号池一并生效
+    pub fn set_account_overrides(&self, user_token: &str, overrides: &AccountOverrides) {
+        let policy = self.config.for_account(overrides);
+        let mut pools = self.pools.write();
+        for pool in pools.values_mut().flat_map(|api_pools| api_pools.values_mut()) {
+            if pool.user_token == user_token {
+                pool.policy = policy.clone();
+            }
+        }
+        if let Some(account_limits) = self.limits.write().get_mut(user_token) {
+            account_limits.set_policy(policy);
+        }
+    }
+
+    /// 按存储重建账号池：仍在列表中的账号保留现有会话并更新策略，其余账号移除，新账号补充进来；返回移除的账号数
+    pub fn rebuild(&self, accounts: HashMap<String, Vec<(String, String, AccountOverrides)>>) -> usize {
+        let mut pools = self.pools.write();
+        let mut removed = 0;
+
+        pools.retain(|api_key, api_pools| {
+            let wanted = accounts.get(api_key);
+            let before = api_pools.len();
+            api_pools.retain(|_, pool| {
+                wanted.is_some_and(|list| list.iter().any(|(_, token, _)| *token == pool.user_token))
+            });
+            removed += before - api_pools.len();
+            !api_pools.is_empty()
+        });
+
+        for (api_key, list) in accounts {
+            let api_pools = pools.entry(api_key).or_default();
+            for (account_email, user_token, overrides) in list {
+                let policy = self.config.for_account(&overrides);
+                match self.limits.write().entry(user_token.clone()) {
+                    Entry::Occupied(mut entry) => entry.get_mut().set_policy(policy.clone()),
+                    Entry::Vacant(entry) => {
+                        entry.insert(AccountLimits::new(policy.clone()));
+                    }
+                }
+                match api_pools.values_mut().find(|pool| pool.user_token == user_token) {
+                    Some(pool) => pool.policy = policy,
+                    None => {
+                        api_pools.insert(
+                            account_email.clone(),
+                            AccountSessionPool::new(account_email, user_token, policy),
+                        );
+                    }
+                }
+            }
+        }
+
+        self.retain_limits(&pools);
+        // 已移除账号上的会话不再可用
+        self.session_mapping
+            .write()
+            .retain(|_, (api_key, email)| pools.get(api_key).is_some_and(|api_pools| api_pools.contains_key(email)));
+        removed
+    }
+
+    /// 去重后的账号总数
+    pub fn total_accounts(&self) -> usize {
+        let pools = self.pools.read();
+        let mut tokens: Vec<&String> = pools.values()
+            .flat_map(|api_pools| api_pools.values())
+            .map(|pool| &pool.user_token)
+            .collect();
+        tokens.sort();
+        tokens.dedup();
+        tokens.len()
+    }
+
+    /// API密钥所在账号池的调度标识和容量：账号集合相同的API密钥共用一个公平队列，
+    /// 容量为这些账号的并发上限之和；账号不相交的API密钥互不排队
+    pub fn scheduling_pool(&self, api_key: &str) -> (String, usize) {
+        let pools = self.pools.read();
+        let limits = self.limits.read();
+        pools.get(api_key).map_or((String::new(), 0), |api_pools| scheduling_pool(api_pools, &limits))
+    }
+
+    /// 所有账号池的调度标识和容量
+    pub fn scheduling_pools(&self) -> HashMap<String, usize> {
+        let pools = self.pools.read();
+        let limits = self.limits.read();
+        pools.values().map(|api_pools| scheduling_pool(api_pools, &limits)).collect()
+    }
+
+    /// 账号token是否处于冷却中
+    pub fn is_token_cooling_down(&self, user_token: &str) -> bool {
+        let pools = self.pools.read();
+        pools.values()
+            .flat_map(|api_pools| api_pools.values())
+            .any(|pool| pool.user_token == user_token && pool.is_cooling_down())
+    }
+
+    /// 账号token是否已达到并发上限
+    pub fn is_token_busy(&self, user_token: &str) -> bool {
+        let pools = self.pools.read();
+        let max = self.limits.read().get(user_token).map_or(1, AccountLimits::max_concurrency);
+        account_active_sessions(&pools, user_token) >= max
+    }
+
     /// 获取API密钥的统计信息
     pub fn get_api_key_stats(&self, api_key: &str) -> Option<SessionPoolStats> {
         let pools = self.pools.read();
@@ -408,13 +948,13 @@ impl SessionPoolManager {
             total_sessions: 0,
         };
 
+        let limits = self.limits.read();
         for (_, pool) in api_pools.iter() {
-            if pool.is_available() {
+            let max = limits.get(&pool.user_token).map_or(1, AccountLimits::max_concurrency);
+            if account_active_sessions(&pools, &pool.user_token) < max {
                 stats.available_accounts += 1;
             }
-            if pool.active_session.is_some() {
-                stats.active_sessions += 1;
-            }
+            stats.active_sessions += pool.active_sessions.len();
             stats.total_sessions += pool.sessions.len();
         }
 
@@ -433,6 +973,127 @@ pub struct SessionPoolStats {
 
 impl Default for SessionPoolManager {
     fn default() -> Self {
-        Self::new()
+        let config = Config::default();
+        Self::new(config.pool, Arc::new(QuotaCache::new(config.deepseek.quota_cache_ttl_secs)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "token-a";
+
+    fn manager(messages_per_hour: u32, burst: u32) -> SessionPoolManager {
+        let mut config = Config::default();
+        config.pool.account_messages_per_hour = messages_per_hour;
+        config.pool.account_message_burst = burst;
+        config.pool.account_max_concurrency = 1;
+        let manager = SessionPoolManager::new(config.pool, Arc::new(QuotaCache::new(60)));
+        // 同一账号关联到两个API密钥
+        for api_key in ["key-1", "key-2"] {
+            manager.add_account(api_key.to_string(), "a@example.com".to_string(), TOKEN.to_string(), &AccountOverrides::default());
+        }
+        manager
+    }
+
+    #[tokio::test]
+    async fn test_rate_budget_is_shared_across_api_keys() {
+        let manager = manager(1, 1);
+        let (conv_id, _) = manager.acquire_session("key-1", None, &AcquireOptions::default()).await.unwrap();
+        manager.release_session(&conv_id);
+
+        let result = manager.acquire_session("key-2", None, &AcquireOptions::default()).await;
+        assert!(matches!(result, Err(AppError::Unavailable { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_is_shared_and_busy_activation_keeps_budget() {
+        let manager = manager(60, 2);
+        let (first, _) = manager.acquire_session("key-1", None, &AcquireOptions::default()).await.unwrap();
+        assert!(manager.is_token_busy(TOKEN));
+
+        // 另一个API密钥下的同一账号也算作繁忙，激活失败不消耗预算
+        let busy = manager.acquire_session("key-2", None, &AcquireOptions::default()).await;
+        assert!(matches!(busy, Err(AppError::ServiceUnavailable(_))));
+
+        manager.release_session(&first);
+        assert!(!manager.is_token_busy(TOKEN));
+        assert!(manager.acquire_session("key-2", None, &AcquireOptions::default()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_user_affinity_prefers_same_account_and_spills_over_when_busy() {
+        let manager = SessionPoolManager::new(Config::default().pool, Arc::new(QuotaCache::new(60)));
+        for (email, token) in [("a@example.com", "token-a"), ("b@example.com", "token-b"), ("c@example.com", "token-c")] {
+            manager.add_account("key-1".to_string(), email.to_string(), token.to_string(), &AccountOverrides::default());
+        }
+        let options = AcquireOptions { user: Some("user-1".to_string()), ..AcquireOptions::default() };
+
+        let (first, session) = manager.acquire_session("key-1", None, &options).await.unwrap();
+        let preferred = session.account_email.clone();
+        manager.release_session(&first);
+        let (second, session) = manager.acquire_session("key-1", None, &options).await.unwrap();
+        assert_eq!(session.account_email, preferred);
+
+        // 首选账号繁忙时顺延到其他账号
+        let (_, session) = manager.acquire_session("key-1", None, &options).await.unwrap();
+        assert_ne!(session.account_email, preferred);
+        manager.release_session(&second);
+    }
+
+    #[test]
+    fn test_errors_cool_down_account_until_probe_passes() {
+        let mut config = Config::default();
+        config.pool.cooldown_error_threshold = 2;
+        config.pool.cooldown_secs = 0;
+        let manager = SessionPoolManager::new(config.pool, Arc::new(QuotaCache::new(60)));
+        manager.add_account("key-1".to_string(), "a@example.com".to_string(), TOKEN.to_string(), &AccountOverrides::default());
+
+        // 一般错误累计到阈值后冷却
+        let error = AppError::ExternalApi("upstream error".to_string());
+        manager.record_account_error(TOKEN, &error);
+        assert!(!manager.is_token_cooling_down(TOKEN));
+        manager.record_account_error(TOKEN, &error);
+        assert!(manager.is_token_cooling_down(TOKEN));
+
+        // 冷却结束后等待探测，探测失败继续冷却，成功后恢复轮换
+        assert_eq!(manager.accounts_due_for_probe(), vec![TOKEN.to_string()]);
+        manager.finish_probe(TOKEN, false);
+        assert!(manager.is_token_cooling_down(TOKEN));
+        manager.finish_probe(TOKEN, true);
+        assert!(!manager.is_token_cooling_down(TOKEN));
+        assert!(manager.accounts_due_for_probe().is_empty());
+
+        // 限流类错误立即冷却
+        manager.record_account_error(TOKEN, &AppError::RateLimited { message: String::new(), retry_after_secs: 1, model: None });
+        assert!(manager.is_token_cooling_down(TOKEN));
+    }
+
+    #[tokio::test]
+    async fn test_release_does_not_deadlock_with_rebuild() {
+        let manager = Arc::new(manager(0, 0));
+        let (conv_id, _) = manager.acquire_session("key-1", None, &AcquireOptions::default()).await.unwrap();
+        let accounts: HashMap<String, Vec<(String, String, AccountOverrides)>> = ["key-1", "key-2"]
+            .into_iter()
+            .map(|api_key| (api_key.to_string(), vec![("a@example.com".to_string(), TOKEN.to_string(), AccountOverrides::default())]))
+            .collect();
+
+        let releaser = {
+            let manager = manager.clone();
+            std::thread::spawn(move || (0..20_000).for_each(|_| manager.release_session(&conv_id)))
+        };
+        let rebuilder = {
+            let manager = manager.clone();
+            std::thread::spawn(move || (0..20_000).for_each(|_| { manager.rebuild(accounts.clone()); }))
+        };
+
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            releaser.join().unwrap();
+            rebuilder.join().unwrap();
+            let _ = done_tx.send(());
+        });
+        assert!(done_rx.recv_timeout(Duration::from_secs(30)).is_ok(), "release_session deadlocked with rebuild");
     }
 }
@@ -0,0 +1,102 @@
+use crate::utils::unix_timestamp;
+use parking_lot::RwLock;
+use std::collections::{HashMap, VecDeque};
+
+/// 预热的上游会话
+#[derive(Debug, Clone)]
+struct PrewarmedSession {
+    session_id: String,
+    created_at: u64,
+}
+
+/// 会话预热池：在空闲时为每个账号提前创建少量DeepSeek会话，新对话可直接领取
+pub struct SessionPrewarmer {
+    sessions: RwLock<HashMap<String, VecDeque<PrewarmedSession>>>, // user_token -> 预热会话
+    target: usize,
+    ttl: u64,
+}
+
+impl SessionPrewarmer {
+    pub fn new(target: usize, ttl: u64) -> Self {
+        Self {
+            sessions: RwLock::new(HashMap::new()),
+            target,
+            ttl,
+        }
+    }
+
+    /// 是否启用预热
+    pub fn is_enabled(&self) -> bool {
+        self.target > 0
+    }
+
+    /// 领取一个预热会话
+    pub fn claim(&self, user_token: &str) -> Option<String> {
+        let now = unix_timestamp();
+        let mut sessions = self.sessions.write();
+        let queue = sessions.get_mut(user_token)?;
+
+        while let Some(session) = queue.pop_front() {
+            if now.saturating_sub(session.created_at) <= self.ttl {
+                tracing::debug!("Claimed prewarmed session {}", session.session_id);
+                return Some(session.session_id);
+            }
+        }
+
+        None
+    }
+
+    /// 计算需要补充的会话数量（顺带清理过期会话）
+    pub fn deficit(&self, user_token: &str) -> usize {
+        let now = unix_timestamp();
+        let mut sessions = self.sessions.write();
+        let queue = sessions.entry(user_token.to_string()).or_default();
+        queue.retain(|session| now.saturating_sub(session.created_at) <= self.ttl);
+
+        self.target.saturating_sub(queue.len())
+    }
+
+    /// 放入新创建的会话
+    pub fn put(&self, user_token: &str, session_id: String) {
+        let mut sessions = self.sessions.write();
+        sessions
+            .entry(user_token.to_string())
+            .or_default()
+            .push_back(PrewarmedSession {
+                session_id,
+                created_at: unix_timestamp(),
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_and_deficit() {
+        let prewarmer = SessionPrewarmer::new(2, 60);
+        assert_eq!(prewarmer.deficit("token"), 2);
+        assert!(prewarmer.claim("token").is_none());
+
+        prewarmer.put("token", "session-1".to_string());
+        prewarmer.put("token", "session-2".to_string());
+        assert_eq!(prewarmer.deficit("token"), 0);
+
+        assert_eq!(prewarmer.claim("token").as_deref(), Some("session-1"));
+        assert_eq!(prewarmer.deficit("token"), 1);
+        assert!(prewarmer.claim("other").is_none());
+    }
+
+    #[test]
+    fn test_expired_sessions_are_dropped() {
+        let prewarmer = SessionPrewarmer::new(1, 0);
+        prewarmer.sessions.write().entry("token".to_string()).or_default().push_back(
+            PrewarmedSession {
+                session_id: "stale".to_string(),
+                created_at: 0,
+            },
+        );
+        assert!(prewarmer.claim("token").is_none());
+    }
+}
@@ -0,0 +1,82 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 签名API密钥携带的声明
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedKeyClaims {
+    pub kid: String, // 密钥ID，用于停用和日志
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>, // 为空表示不限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<u64>, // 过期时间（Unix秒）
+}
+
+/// 自描述的签名API密钥：`dsk-<base64url(声明)>.<base64url(HMAC-SHA256)>`，校验时无需查询存储
+pub struct ApiKeySigner {
+    secret: Vec<u8>,
+}
+
+impl ApiKeySigner {
+    pub fn new(secret: &str) -> Self {
+        Self { secret: secret.as_bytes().to_vec() }
+    }
+
+    /// 普通密钥只包含十六进制字符，签名密钥以`.`分隔声明和签名
+    pub fn is_signed(api_key: &str) -> bool {
+        api_key.starts_with("dsk-") && api_key.contains('.')
+    }
+
+    pub fn issue(&self, claims: &SignedKeyClaims) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).expect("claims serialize to JSON"));
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(payload.as_bytes()).finalize().into_bytes());
+        format!("dsk-{}.{}", payload, signature)
+    }
+
+    /// 校验签名和过期时间，通过时返回声明
+    pub fn verify(&self, api_key: &str, now: u64) -> Option<SignedKeyClaims> {
+        let (payload, signature) = api_key.strip_prefix("dsk-")?.split_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        self.mac(payload.as_bytes()).verify_slice(&signature).ok()?;
+
+        let claims: SignedKeyClaims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        match claims.exp {
+            Some(exp) if now > exp => None,
+            _ => Some(claims),
+        }
+    }
+
+    fn mac(&self, data: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(data);
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_key_round_trip() {
+        let signer = ApiKeySigner::new("0123456789abcdef0123456789abcdef");
+        let claims = SignedKeyClaims {
+            kid: "key-1".to_string(),
+            scopes: vec!["chat".to_string()],
+            exp: Some(1_000),
+        };
+        let key = signer.issue(&claims);
+
+        assert!(ApiKeySigner::is_signed(&key));
+        assert_eq!(signer.verify(&key, 999), Some(claims));
+        assert_eq!(signer.verify(&key, 1_001), None);
+
+        // 篡改声明或使用其他密钥签名都无法通过校验
+        let tampered = key.replacen("dsk-e", "dsk-f", 1);
+        assert_eq!(signer.verify(&tampered, 0), None);
+        assert_eq!(ApiKeySigner::new("another-secret-another-secret-xx").verify(&key, 0), None);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::error::{ApiError, ApiResult};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+type Outcome<T> = Result<T, Arc<ApiError>>;
+
+/// 合并相同的并发请求：第一个请求访问上游，其余请求等待并共享其结果
+pub struct Singleflight<T: Clone> {
+    calls: Arc<Mutex<HashMap<String, broadcast::Sender<Outcome<T>>>>>,
+}
+
+/// 加入合并组的结果
+pub enum Flight<T: Clone> {
+    /// 第一个请求，负责访问上游并通过complete分发结果
+    Leader(FlightLeader<T>),
+    /// 已有相同请求在进行中
+    Follower(broadcast::Receiver<Outcome<T>>),
+}
+
+/// 领头请求的句柄，未调用complete就被丢弃时，等待者会收到取消错误
+pub struct FlightLeader<T: Clone> {
+    key: String,
+    sender: Option<broadcast::Sender<Outcome<T>>>, // complete后为None，Drop不再清理
+    calls: Arc<Mutex<HashMap<String, broadcast::Sender<Outcome<T>>>>>,
+}
+
+impl<T: Clone> Singleflight<T> {
+    pub fn new() -> Self {
+        Self {
+            calls: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// 按键加入合并组
+    pub fn join(&self, key: &str) -> Flight<T> {
+        let mut calls = self.calls.lock();
+        if let Some(sender) = calls.get(key) {
+            return Flight::Follower(sender.subscribe());
+        }
+
+        let (sender, _) = broadcast::channel(1);
+        calls.insert(key.to_string(), sender.clone());
+        Flight::Leader(FlightLeader {
+            key: key.to_string(),
+            sender: Some(sender),
+            calls: self.calls.clone(),
+        })
+    }
+}
+
+impl<T: Clone> Default for Singleflight<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> FlightLeader<T> {
+    /// 将结果分发给所有等待者
+    pub fn complete(mut self, result: &ApiResult<T>) {
+        // 先移出发送端再发送，保证之后到达的请求开启新的一组
+        if let Some(sender) = self.sender.take() {
+            self.unregister(&sender);
+            let outcome = match result {
+                Ok(value) => Ok(value.clone()),
+                Err(e) => Err(Arc::new(e.duplicate())),
+            };
+            let _ = sender.send(outcome);
+        }
+    }
+
+    /// 只移除本组的登记，同一个键可能已经由新的领头请求登记
+    fn unregister(&self, sender: &broadcast::Sender<Outcome<T>>) {
+        let mut calls = self.calls.lock();
+        if calls.get(&self.key).is_some_and(|current| current.same_channel(sender)) {
+            calls.remove(&self.key);
+        }
+    }
+}
+
+impl<T: Clone> Drop for FlightLeader<T> {
+    fn drop(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            self.unregister(&sender);
+        }
+    }
+}
+
+/// 等待领头请求的结果
+pub async fn wait<T: Clone>(mut receiver: broadcast::Receiver<Outcome<T>>) -> ApiResult<T> {
+    match receiver.recv().await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(e.duplicate()),
+        Err(_) => Err(ApiError::ServiceUnavailable(
+            "合并的相同请求已取消，请重试".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_followers_share_leader_result() {
+        let flights = Singleflight::<String>::new();
+
+        let Flight::Leader(leader) = flights.join("key") else {
+            panic!("first caller should lead");
+        };
+        let Flight::Follower(receiver) = flights.join("key") else {
+            panic!("second caller should follow");
+        };
+
+        leader.complete(&Ok("response".to_string()));
+        assert_eq!(wait(receiver).await.unwrap(), "response");
+        assert_eq!(flights.calls.lock().len(), 0);
+        assert!(matches!(flights.join("key"), Flight::Leader(_)));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_leader_releases_followers() {
+        let flights = Singleflight::<String>::new();
+
+        let leader = flights.join("key");
+        let Flight::Follower(receiver) = flights.join("key") else {
+            panic!("second caller should follow");
+        };
+
+        drop(leader);
+        assert!(wait(receiver).await.is_err());
+        assert_eq!(flights.calls.lock().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_completed_leader_keeps_next_flight() {
+        let flights = Singleflight::<String>::new();
+
+        let Flight::Leader(first) = flights.join("key") else {
+            panic!("first caller should lead");
+        };
+        first.complete(&Ok("first".to_string()));
+
+        let Flight::Leader(second) = flights.join("key") else {
+            panic!("caller after completion should lead");
+        };
+        let Flight::Follower(receiver) = flights.join("key") else {
+            panic!("caller during second flight should follow");
+        };
+        assert_eq!(flights.calls.lock().len(), 1);
+
+        second.complete(&Ok("second".to_string()));
+        assert_eq!(wait(receiver).await.unwrap(), "second");
+        assert_eq!(flights.calls.lock().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stale_leader_drop_keeps_new_flight() {
+        let flights = Singleflight::<String>::new();
+
+        let first = flights.join("key");
+        // 模拟第一组已移出登记、尚未析构时新的领头请求登记了同一个键
+        flights.calls.lock().remove("key");
+        let Flight::Leader(second) = flights.join("key") else {
+            panic!("caller after removal should lead");
+        };
+
+        drop(first);
+        let Flight::Follower(receiver) = flights.join("key") else {
+            panic!("second flight should still be registered");
+        };
+        second.complete(&Ok("second".to_string()));
+        assert_eq!(wait(receiver).await.unwrap(), "second");
+    }
+}
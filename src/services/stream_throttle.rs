@@ -0,0 +1,149 @@
+use crate::error::ApiError;
+use crate::models::StreamChunk;
+use crate::utils::estimate_tokens;
+use futures_util::{Stream, StreamExt};
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+
+type ChunkStream = Pin<Box<dyn Stream<Item = Result<String, ApiError>> + Send>>;
+
+/// 每份拆分内容对应的发送时长（秒），速率为每秒N个token时每份约N/10个token
+const SLICE_SECS: f64 = 0.1;
+
+/// 按目标速率（估算的token/秒）发送流式数据块，0表示不限速。
+/// 内容较多的数据块拆成约100毫秒一份再发送；上游停顿期间不积累额度，停顿后积压的内容同样按速率发出
+pub fn throttle(stream: ChunkStream, tokens_per_sec: u32) -> ChunkStream {
+    if tokens_per_sec == 0 {
+        return stream;
+    }
+
+    let rate = tokens_per_sec as f64;
+    let slice_tokens = ((rate * SLICE_SECS) as usize).max(1);
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut stream = stream;
+        let mut next_at = Instant::now();
+        while let Some(item) = stream.next().await {
+            let pieces = match item {
+                Ok(data) => split_chunk(&data, slice_tokens).into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            };
+            for piece in pieces {
+                let tokens = piece.as_ref().map(|data| chunk_tokens(data)).unwrap_or(0);
+                if tokens > 0 {
+                    next_at = next_at.max(Instant::now());
+                    tokio::time::sleep_until(next_at).await;
+                    next_at += Duration::from_secs_f64(tokens as f64 / rate);
+                }
+                // 客户端断开时停止读取上游
+                if tx.send(piece).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Box::pin(ReceiverStream::new(rx))
+}
+
+/// 数据块中正文和思考过程的估算token数，[DONE] 等非数据块为0
+fn chunk_tokens(data: &str) -> usize {
+    parse_chunk(data).map_or(0, |chunk| {
+        chunk
+            .choices
+            .iter()
+            .map(|choice| {
+                let delta = &choice.delta;
+                estimate_tokens(delta.content.as_deref().unwrap_or_default())
+                    + estimate_tokens(delta.reasoning_content.as_deref().unwrap_or_default())
+            })
+            .sum()
+    })
+}
+
+fn parse_chunk(data: &str) -> Option<StreamChunk> {
+    let payload = data.strip_prefix("data: ")?.trim_end();
+    serde_json::from_str(payload).ok()
+}
+
+/// 把正文超过slice_tokens的数据块拆成多个，调试数据只保留在第一块，finish_reason只保留在最后一块
+fn split_chunk(data: &str, slice_tokens: usize) -> Vec<String> {
+    let Some(chunk) = parse_chunk(data).filter(|chunk| chunk.choices.len() == 1) else {
+        return vec![data.to_string()];
+    };
+    let delta = &chunk.choices[0].delta;
+    let content = match (&delta.content, &delta.reasoning_content) {
+        (Some(content), None) if estimate_tokens(content) > slice_tokens => content,
+        _ => return vec![data.to_string()],
+    };
+
+    let mut slices = Vec::new();
+    let mut current = String::new();
+    for c in content.chars() {
+        current.push(c);
+        if estimate_tokens(&current) >= slice_tokens {
+            slices.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        slices.push(current);
+    }
+
+    let last = slices.len() - 1;
+    slices
+        .into_iter()
+        .enumerate()
+        .map(|(index, slice)| {
+            let mut piece = chunk.clone();
+            piece.choices[0].delta.content = Some(slice);
+            if index > 0 {
+                piece.x_upstream = None;
+            }
+            if index < last {
+                piece.choices[0].finish_reason = None;
+            }
+            format!("data: {}\n\n", serde_json::to_string(&piece).unwrap_or_default())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+
+    fn chunk(content: &str, finish_reason: Option<&str>) -> String {
+        let value = serde_json::json!({
+            "id": "1", "object": "chat.completion.chunk", "created": 0, "model": "deepseek",
+            "choices": [{"index": 0, "delta": {"role": "assistant", "content": content}, "finish_reason": finish_reason}]
+        });
+        format!("data: {}\n\n", value)
+    }
+
+    #[test]
+    fn test_split_chunk() {
+        let pieces = split_chunk(&chunk("abcdefghijklmno", Some("stop")), 2);
+        assert_eq!(pieces.len(), 3);
+        let parsed: Vec<StreamChunk> = pieces.iter().map(|piece| parse_chunk(piece).unwrap()).collect();
+        let content: String = parsed.iter().filter_map(|chunk| chunk.choices[0].delta.content.clone()).collect();
+        assert_eq!(content, "abcdefghijklmno");
+        assert_eq!(parsed[0].choices[0].finish_reason, None);
+        assert_eq!(parsed[2].choices[0].finish_reason.as_deref(), Some("stop"));
+
+        assert_eq!(split_chunk("data: [DONE]\n\n", 2), vec!["data: [DONE]\n\n"]);
+        assert_eq!(split_chunk(&chunk("abcd", None), 2).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_throttle_paces_tokens() {
+        // 72个字符约18个token，每秒100个token时每份10个token，拆成两份，第二份在约0.1秒后发出
+        let input: ChunkStream = Box::pin(stream::iter(vec![Ok(chunk(&"a".repeat(72), None)), Ok("data: [DONE]\n\n".to_string())]));
+        let started = Instant::now();
+        let output: Vec<String> = throttle(input, 100).map(Result::unwrap).collect().await;
+        assert_eq!(output.len(), 3);
+        assert_eq!(output.last().unwrap(), "data: [DONE]\n\n");
+        assert!(started.elapsed() >= Duration::from_millis(100), "{:?}", started.elapsed());
+    }
+}
@@ -0,0 +1,101 @@
+use crate::utils::unix_timestamp;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// 心跳超过该秒数（在两个周期之外）仍未更新时认为任务已停止
+const STALL_GRACE_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Copy)]
+struct TaskBeat {
+    interval_secs: u64,
+    started_at: u64,
+    last_beat: Option<u64>,
+}
+
+/// 后台任务的运行状态
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TaskStatus {
+    pub name: &'static str,
+    pub interval_secs: u64,
+    pub last_beat: Option<u64>,
+    pub running: bool,
+}
+
+/// 记录周期性后台任务的心跳，用于健康检查发现退出或卡住的任务
+pub struct TaskMonitor {
+    tasks: RwLock<BTreeMap<&'static str, TaskBeat>>,
+}
+
+impl TaskMonitor {
+    pub fn new() -> Self {
+        Self {
+            tasks: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// 登记后台任务，interval_secs为任务的执行周期
+    pub fn register(&self, name: &'static str, interval_secs: u64) {
+        self.tasks.write().insert(name, TaskBeat {
+            interval_secs,
+            started_at: unix_timestamp(),
+            last_beat: None,
+        });
+    }
+
+    /// 任务每个周期开始时调用
+    pub fn beat(&self, name: &'static str) {
+        if let Some(task) = self.tasks.write().get_mut(name) {
+            task.last_beat = Some(unix_timestamp());
+        }
+    }
+
+    /// 所有已登记任务的状态
+    pub fn report(&self) -> Vec<TaskStatus> {
+        self.report_at(unix_timestamp())
+    }
+
+    fn report_at(&self, now: u64) -> Vec<TaskStatus> {
+        self.tasks
+            .read()
+            .iter()
+            .map(|(name, task)| {
+                let since = task.last_beat.unwrap_or(task.started_at);
+                TaskStatus {
+                    name,
+                    interval_secs: task.interval_secs,
+                    last_beat: task.last_beat,
+                    running: now.saturating_sub(since) <= task.interval_secs * 2 + STALL_GRACE_SECS,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for TaskMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stalled_tasks_are_reported() {
+        let monitor = TaskMonitor::new();
+        monitor.register("quota_poll", 60);
+        monitor.register("probe", 10);
+        monitor.beat("probe");
+        monitor.beat("unknown");
+
+        let now = unix_timestamp();
+        assert!(monitor.report_at(now).iter().all(|task| task.running));
+
+        let later = monitor.report_at(now + 100);
+        assert_eq!(later.len(), 2);
+        assert!(later.iter().find(|task| task.name == "quota_poll").unwrap().running);
+        assert!(!later.iter().find(|task| task.name == "probe").unwrap().running);
+    }
+}
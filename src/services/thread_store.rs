@@ -0,0 +1,208 @@
+use crate::config::ThreadConfig;
+use crate::error::{ApiError, ApiResult};
+use crate::models::{ChatMessage, ChatMessageContent};
+use crate::utils::{parse_conversation_id, unix_timestamp};
+use parking_lot::Mutex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// 服务端托管的一个对话：完整的消息历史和可继续使用的DeepSeek会话
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Thread {
+    pub messages: Vec<ChatMessage>,
+    pub conversation_id: Option<String>, // 上一轮回复的对话ID（会话ID@消息ID）
+    pub account: Option<String>,         // 上游会话所在的账号，直接使用userToken时为None
+    pub updated_at: u64,
+    #[serde(skip)]
+    max_messages: usize, // 保留的消息数上限，0为不限制
+}
+
+impl Thread {
+    /// 上游会话仍可继续时的对话ID，需要在同一个账号上
+    pub fn continuation(&self, account: Option<&str>) -> Option<&str> {
+        self.conversation_id.as_deref().filter(|_| self.account.as_deref() == account)
+    }
+
+    /// 完整上下文：历史消息加上本轮新增的消息，上游会话不可用时整体重新发送
+    pub fn context(&self, new_messages: &[ChatMessage]) -> Vec<ChatMessage> {
+        self.messages.iter().chain(new_messages).cloned().collect()
+    }
+
+    /// 一轮对话成功后追加本轮消息和回复
+    pub fn commit(&mut self, new_messages: Vec<ChatMessage>, reply: String, conversation_id: &str, account: Option<String>) {
+        self.messages.extend(new_messages);
+        self.messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: ChatMessageContent::Text(reply),
+            name: None,
+            tool_call_id: None,
+            tool_calls: None,
+            reasoning_content: None,
+        });
+        // 超过上限时丢弃最早的消息，上游会话中的上下文不受影响
+        if self.max_messages > 0 && self.messages.len() > self.max_messages {
+            let excess = self.messages.len() - self.max_messages;
+            self.messages.drain(..excess);
+        }
+        // 回复没有可继续的对话ID时下一轮重新发送完整上下文
+        self.conversation_id = parse_conversation_id(conversation_id).map(|_| conversation_id.to_string());
+        self.account = account;
+        self.updated_at = unix_timestamp();
+    }
+}
+
+/// 按（调用方, user, thread_id）保存的托管对话，只在内存中，超过空闲时间后清除。
+/// 同一对话的请求依次执行，上一轮完成后下一轮才能读取历史
+pub struct ThreadStore {
+    threads: Mutex<HashMap<String, Arc<AsyncMutex<Thread>>>>,
+    idle_ttl_secs: u64,
+    max_threads: usize,
+    max_messages: usize,
+}
+
+impl ThreadStore {
+    pub fn new(config: &ThreadConfig) -> Self {
+        Self {
+            threads: Mutex::new(HashMap::new()),
+            idle_ttl_secs: config.idle_ttl_secs,
+            max_threads: config.max_threads,
+            max_messages: config.max_messages,
+        }
+    }
+
+    /// 对话的存储键，调用方（API密钥或userToken）只保存哈希
+    pub fn key(caller: &str, user: Option<&str>, thread_id: &str) -> String {
+        let caller = hex::encode(&Sha256::digest(caller.as_bytes())[..16]);
+        format!("{}:{}:{}", caller, user.unwrap_or_default(), thread_id)
+    }
+
+    /// 锁定对话（不存在时创建），持有期间同一对话的其他请求等待。
+    /// 对话数达到上限时清除最久未使用的对话，所有对话都在使用中时返回错误
+    pub async fn lock(&self, key: &str) -> ApiResult<OwnedMutexGuard<Thread>> {
+        let thread = {
+            let mut threads = self.threads.lock();
+            self.prune(&mut threads);
+            match threads.get(key) {
+                Some(thread) => thread.clone(),
+                None => {
+                    if threads.len() >= self.max_threads && !Self::evict_oldest(&mut threads) {
+                        return Err(ApiError::ServiceUnavailable("托管对话数量已达上限".to_string()));
+                    }
+                    let thread = Arc::new(AsyncMutex::new(Thread {
+                        updated_at: unix_timestamp(),
+                        max_messages: self.max_messages,
+                        ..Thread::default()
+                    }));
+                    threads.insert(key.to_string(), thread.clone());
+                    thread
+                }
+            }
+        };
+        Ok(thread.lock_owned().await)
+    }
+
+    /// 对话的当前内容，正在进行中的对话等待本轮完成
+    pub async fn get(&self, key: &str) -> Option<Thread> {
+        let thread = self.threads.lock().get(key).cloned()?;
+        let thread = thread.lock().await.clone();
+        (!thread.messages.is_empty()).then_some(thread)
+    }
+
+    pub fn remove(&self, key: &str) -> bool {
+        self.threads.lock().remove(key).is_some()
+    }
+
+    /// 清除最久未使用且不在使用中的对话，没有可清除的对话时返回false
+    fn evict_oldest(threads: &mut HashMap<String, Arc<AsyncMutex<Thread>>>) -> bool {
+        let oldest = threads
+            .iter()
+            .filter_map(|(key, thread)| thread.try_lock().ok().map(|thread| (thread.updated_at, key.clone())))
+            .min();
+        match oldest {
+            Some((_, key)) => threads.remove(&key).is_some(),
+            None => false,
+        }
+    }
+
+    /// 清除空闲超时的对话，正在使用的对话不清除
+    fn prune(&self, threads: &mut HashMap<String, Arc<AsyncMutex<Thread>>>) {
+        if self.idle_ttl_secs == 0 {
+            return;
+        }
+        let cutoff = unix_timestamp().saturating_sub(self.idle_ttl_secs);
+        threads.retain(|_, thread| match thread.try_lock() {
+            Ok(thread) => thread.updated_at >= cutoff,
+            Err(_) => true,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn user(content: &str) -> ChatMessage {
+        serde_json::from_value(serde_json::json!({"role": "user", "content": content})).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_thread_lifecycle() {
+        let store = ThreadStore::new(&Config::default().threads);
+        let key = ThreadStore::key("dsk-test", Some("alice"), "t1");
+        assert_ne!(key, ThreadStore::key("dsk-other", Some("alice"), "t1"));
+        assert!(store.get(&key).await.is_none());
+
+        {
+            let mut thread = store.lock(&key).await.unwrap();
+            assert!(thread.continuation(Some("a@example.com")).is_none());
+            assert_eq!(thread.context(&[user("hi")]).len(), 1);
+            let session = "0b1f6e0c-5f0e-4d8e-9a67-6a3c0f1c2d3e@2";
+            thread.commit(vec![user("hi")], "hello".to_string(), session, Some("a@example.com".to_string()));
+            assert_eq!(thread.continuation(Some("a@example.com")), Some(session));
+            // 换了账号时上游会话不可用
+            assert!(thread.continuation(Some("b@example.com")).is_none());
+            assert_eq!(thread.context(&[user("again")]).len(), 3);
+        }
+
+        let thread = store.get(&key).await.unwrap();
+        assert_eq!(thread.messages.len(), 2);
+        assert_eq!(thread.messages[1].role, "assistant");
+
+        // 回复的ID不是对话ID时下一轮重新发送完整上下文
+        store.lock(&key).await.unwrap().commit(vec![user("more")], "ok".to_string(), "", None);
+        assert!(store.get(&key).await.unwrap().continuation(None).is_none());
+
+        assert!(store.remove(&key));
+        assert!(store.get(&key).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_thread_limits() {
+        let mut config = Config::default().threads;
+        config.max_threads = 2;
+        config.max_messages = 3;
+        let store = ThreadStore::new(&config);
+
+        // 新建的对话不会在下一次清理中被当作空闲对话清除
+        let first = store.lock("first").await.unwrap();
+        assert!(first.updated_at > 0);
+        let second = store.lock("second").await.unwrap();
+        assert!(store.lock("third").await.is_err());
+
+        // 达到上限时清除最久未使用且不在使用中的对话
+        drop(first);
+        let mut third = store.lock("third").await.unwrap();
+        drop(second);
+        assert!(store.threads.lock().contains_key("second"));
+        assert!(!store.threads.lock().contains_key("first"));
+
+        third.commit(vec![user("1"), user("2")], "3".to_string(), "", None);
+        third.commit(vec![user("4")], "5".to_string(), "", None);
+        assert_eq!(third.messages.len(), 3);
+        assert!(matches!(&third.messages[0].content, ChatMessageContent::Text(text) if text == "3"));
+    }
+}
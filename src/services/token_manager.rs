@@ -1,6 +1,7 @@
 use crate::error::{ApiError, ApiResult};
 use crate::models::{DeepSeekResponse, UserInfo};
-use crate::utils::{generate_cookie, unix_timestamp};
+use crate::services::{BrowserProfiles, CookieStore, MirrorSelector};
+use crate::utils::unix_timestamp;
 use parking_lot::RwLock;
 use reqwest::Client;
 use std::collections::HashMap;
@@ -22,12 +23,24 @@ pub struct TokenManager {
     tokens: Arc<RwLock<HashMap<String, TokenInfo>>>,
     request_semaphores: Arc<RwLock<HashMap<String, Arc<Semaphore>>>>,
     access_token_expires: u64,
+    profiles: Arc<BrowserProfiles>,
+    cookies: Arc<CookieStore>,
+    mirrors: Arc<MirrorSelector>,
 }
 
 impl TokenManager {
-    pub fn new(client: Client, access_token_expires: u64) -> Self {
+    pub fn new(
+        client: Client,
+        access_token_expires: u64,
+        profiles: Arc<BrowserProfiles>,
+        cookies: Arc<CookieStore>,
+        mirrors: Arc<MirrorSelector>,
+    ) -> Self {
         Self {
             client,
+            profiles,
+            cookies,
+            mirrors,
             tokens: Arc::new(RwLock::new(HashMap::new())),
             request_semaphores: Arc::new(RwLock::new(HashMap::new())),
             access_token_expires,
@@ -90,13 +103,15 @@ impl TokenManager {
 
         let headers = self.create_headers(Some(refresh_token));
         
+        let base_url = self.mirrors.current();
         let response = self
             .client
-            .get("https://chat.deepseek.com/api/v0/users/current")
+            .get(format!("{}/api/v0/users/current", base_url))
             .headers(headers)
             .timeout(Duration::from_secs(15))
             .send()
-            .await?;
+            .await
+            .map_err(|e| self.mirrors.on_send_error(&base_url, e))?;
 
         let result: DeepSeekResponse<UserInfo> = response.json().await?;
         
@@ -135,6 +150,11 @@ impl TokenManager {
         }
     }
 
+    /// 缓存的访问令牌过期时间
+    pub fn expire_time(&self, refresh_token: &str) -> Option<u64> {
+        self.tokens.read().get(refresh_token).map(|info| info.expire_time)
+    }
+
     /// 移除无效的token
     pub fn remove_token(&self, refresh_token: &str) {
         let mut tokens = self.tokens.write();
@@ -147,31 +167,16 @@ impl TokenManager {
         
         headers.insert("Accept", "*/*".parse().unwrap());
         headers.insert("Accept-Encoding", "gzip, deflate, br, zstd".parse().unwrap());
-        headers.insert("Accept-Language", "zh-CN,zh;q=0.9,en;q=0.8".parse().unwrap());
         headers.insert("Origin", "https://chat.deepseek.com".parse().unwrap());
         headers.insert("Pragma", "no-cache".parse().unwrap());
         headers.insert("Priority", "u=1, i".parse().unwrap());
         headers.insert("Referer", "https://chat.deepseek.com/".parse().unwrap());
-        headers.insert(
-            "Sec-Ch-Ua",
-            r#""Chromium";v="134", "Not:A-Brand";v="24", "Google Chrome";v="134""#.parse().unwrap()
-        );
-        headers.insert("Sec-Ch-Ua-Mobile", "?0".parse().unwrap());
-        headers.insert("Sec-Ch-Ua-Platform", r#""macOS""#.parse().unwrap());
         headers.insert("Sec-Fetch-Dest", "empty".parse().unwrap());
         headers.insert("Sec-Fetch-Mode", "cors".parse().unwrap());
         headers.insert("Sec-Fetch-Site", "same-origin".parse().unwrap());
-        headers.insert(
-            "User-Agent",
-            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/134.0.0.0 Safari/537.36".parse().unwrap()
-        );
-        headers.insert("X-App-Version", "20241129.1".parse().unwrap());
-        headers.insert("X-Client-Locale", "zh-CN".parse().unwrap());
-        headers.insert("X-Client-Platform", "web".parse().unwrap());
-        headers.insert("X-Client-Version", "1.0.0-always".parse().unwrap());
-        headers.insert("Cookie", generate_cookie().parse().unwrap());
-
         if let Some(token) = auth_token {
+            self.profiles.apply(token, &mut headers);
+            headers.insert("Cookie", self.cookies.cookie_for(token).parse().unwrap());
             headers.insert(
                 "Authorization",
                 format!("Bearer {}", token).parse().unwrap()
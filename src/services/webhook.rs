@@ -0,0 +1,109 @@
+use crate::config::WebhookConfig;
+use crate::services::TraceContext;
+use crate::utils::unix_timestamp;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::Sha256;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// 单次通知的超时
+const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+
+/// 发往webhook的事件
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookEvent {
+    pub event: &'static str,
+    pub timestamp: u64,
+    pub data: Value,
+}
+
+impl WebhookEvent {
+    pub fn new(event: &'static str, data: Value) -> Self {
+        Self {
+            event,
+            timestamp: unix_timestamp(),
+            data,
+        }
+    }
+}
+
+/// 事件通知：向配置的webhook POST JSON，在后台发送，失败只记录警告
+pub struct WebhookNotifier {
+    client: Client,
+    url: Option<String>,
+    secret: Option<String>,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: &WebhookConfig) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+                .build()
+                .expect("Failed to create webhook HTTP client"),
+            url: config.url.clone().filter(|url| !url.trim().is_empty()),
+            secret: config.secret.clone().filter(|secret| !secret.is_empty()),
+        }
+    }
+
+    /// 发送事件，不阻塞调用方；请求带上当前请求的trace上下文
+    pub fn notify(&self, event: WebhookEvent) {
+        let Some(url) = &self.url else {
+            return;
+        };
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("序列化webhook事件失败: {}", e);
+                return;
+            }
+        };
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("Content-Type", "application/json".parse().unwrap());
+        if let Some(secret) = &self.secret {
+            headers.insert("X-Webhook-Signature", format!("sha256={}", sign(secret, &body)).parse().unwrap());
+        }
+        if let Some(trace) = TraceContext::current() {
+            trace.apply(&mut headers);
+        }
+
+        let request = self.client.post(url).headers(headers).body(body);
+        let name = event.event;
+        tokio::spawn(async move {
+            match request.send().await {
+                Ok(response) if response.status().is_success() => debug!("Webhook事件已发送: {}", name),
+                Ok(response) => warn!("Webhook事件 {} 发送失败: HTTP {}", name, response.status()),
+                Err(e) => warn!("Webhook事件 {} 发送失败: {}", name, e),
+            }
+        });
+    }
+}
+
+/// 请求体的HMAC-SHA256签名（十六进制）
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_matches_hmac_sha256() {
+        assert_eq!(
+            sign("key", b"The quick brown fox jumps over the lazy dog"),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+        let notifier = WebhookNotifier::new(&WebhookConfig {
+            url: Some(" ".to_string()),
+            secret: None,
+        });
+        assert!(notifier.url.is_none());
+    }
+}
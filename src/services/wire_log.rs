@@ -0,0 +1,126 @@
+use crate::services::cassette::sanitize_body;
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::{RequestBuilder, Response};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// 日志target，可用 RUST_LOG=upstream_wire=info 单独控制
+const TARGET: &str = "upstream_wire";
+
+/// 值替换为占位符的请求头和响应头
+const REDACTED_HEADERS: &[&str] = &["authorization", "cookie", "set-cookie", "proxy-authorization"];
+
+/// 上游线路日志，用于排查DeepSeek接口格式变化；开启后记录发往上游的完整请求（请求头和请求体）、
+/// 响应头和SSE行，token、cookie、密码等敏感内容替换为占位符，可通过管理接口在运行时开关
+pub struct WireLog {
+    enabled: AtomicBool,
+}
+
+impl WireLog {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// 发送请求，开启时记录请求和响应头；未开启时与 `request.send()` 相同
+    pub async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        if !self.is_enabled() {
+            return request.send().await;
+        }
+        let (client, request) = request.build_split();
+        let request = request?;
+        let body = request.body().map(|body| match body.as_bytes() {
+            Some(bytes) => sanitize_body(content_type(request.headers()), bytes),
+            None => "<stream>".to_string(),
+        });
+        tracing::info!(
+            target: TARGET,
+            method = %request.method(),
+            url = %request.url(),
+            headers = %redact_headers(request.headers()),
+            body = body.as_deref().unwrap_or_default(),
+            "Upstream request"
+        );
+
+        let url = request.url().clone();
+        match client.execute(request).await {
+            Ok(response) => {
+                tracing::info!(
+                    target: TARGET,
+                    url = %url,
+                    status = response.status().as_u16(),
+                    headers = %redact_headers(response.headers()),
+                    "Upstream response"
+                );
+                Ok(response)
+            }
+            Err(e) => {
+                tracing::info!(target: TARGET, url = %url, error = %e, "Upstream request failed");
+                Err(e)
+            }
+        }
+    }
+
+    /// 开启时记录一行上游SSE数据，data行中的JSON按字段脱敏
+    pub fn log_line(&self, line: &str) {
+        if self.is_enabled() && !line.is_empty() {
+            tracing::info!(target: TARGET, line = %redact_line(line), "Upstream SSE");
+        }
+    }
+}
+
+fn content_type(headers: &HeaderMap) -> Option<&str> {
+    headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok())
+}
+
+/// 以 `name: value` 格式用 ` | ` 拼接成一行，敏感请求头只保留名称
+fn redact_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+                "[REDACTED]"
+            } else {
+                value.to_str().unwrap_or("<binary>")
+            };
+            format!("{}: {}", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+fn redact_line(line: &str) -> String {
+    match line.strip_prefix("data: ") {
+        Some(data) => format!("data: {}", sanitize_body(None, data.as_bytes())),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_redaction() {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer secret-access-token"));
+        headers.insert("cookie", HeaderValue::from_static("ds_session_id=secret"));
+        headers.insert("x-app-version", HeaderValue::from_static("20241129.1"));
+        let redacted = redact_headers(&headers);
+        assert!(!redacted.contains("secret"));
+        assert!(redacted.contains("authorization: [REDACTED]"));
+        assert!(redacted.contains("x-app-version: 20241129.1"));
+
+        assert_eq!(redact_line(r#"data: {"token":"abc","v":"hi"}"#), r#"data: {"token":"[REDACTED]","v":"hi"}"#);
+        assert_eq!(redact_line("event: close"), "event: close");
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// 是否由systemd以 `Type=notify` 方式启动
+pub fn is_supervised() -> bool {
+    env::var_os("NOTIFY_SOCKET").is_some()
+}
+
+/// 向systemd发送状态通知（sd_notify协议），未设置NOTIFY_SOCKET时不做任何事
+pub fn notify(state: &str) {
+    if let Err(e) = send(state) {
+        tracing::warn!("systemd notification failed: {}", e);
+    }
+}
+
+#[cfg(unix)]
+fn send(state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let path = path.to_string_lossy().into_owned();
+    let socket = UnixDatagram::unbound()?;
+
+    // `@` 开头为Linux抽象命名空间套接字
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(state.as_bytes(), &addr)?;
+        return Ok(());
+    }
+
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_state: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// 启用了systemd看门狗时，按WatchdogSec的一半间隔发送 `WATCHDOG=1`
+pub fn spawn_watchdog() {
+    let usec = env::var("WATCHDOG_USEC").ok();
+    let pid = env::var("WATCHDOG_PID").ok();
+    let Some(interval) = watchdog_interval(usec.as_deref(), pid.as_deref(), std::process::id()) else {
+        return;
+    };
+
+    tracing::info!("systemd watchdog enabled, pinging every {:?}", interval);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
+
+/// 解析WATCHDOG_USEC/WATCHDOG_PID，返回发送心跳的间隔；看门狗未启用或针对其他进程时返回None
+fn watchdog_interval(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.trim().parse::<u32>().ok() != Some(own_pid)) {
+        return None;
+    }
+    let usec: u64 = usec?.trim().parse().ok().filter(|usec| *usec > 0)?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+/// systemd套接字激活（`LISTEN_FDS`）传入的第一个监听套接字；重启期间由systemd保持监听，新连接排队等待新进程
+#[cfg(unix)]
+pub fn inherited_listener() -> Option<std::net::TcpListener> {
+    use std::os::fd::FromRawFd;
+
+    let pid = env::var("LISTEN_PID").ok();
+    let fds = env::var("LISTEN_FDS").ok();
+    if listen_fds(pid.as_deref(), fds.as_deref(), std::process::id()) == 0 {
+        return None;
+    }
+    // SAFETY: 按sd_listen_fds协议，LISTEN_PID指向本进程时fd 3起为systemd传入的套接字，由本进程独占
+    Some(unsafe { std::net::TcpListener::from_raw_fd(SD_LISTEN_FDS_START) })
+}
+
+#[cfg(not(unix))]
+pub fn inherited_listener() -> Option<std::net::TcpListener> {
+    None
+}
+
+/// 套接字激活传入的第一个文件描述符
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+/// 解析LISTEN_PID/LISTEN_FDS，返回传给本进程的套接字数量
+fn listen_fds(pid: Option<&str>, fds: Option<&str>, own_pid: u32) -> usize {
+    if pid.and_then(|pid| pid.trim().parse::<u32>().ok()) != Some(own_pid) {
+        return 0;
+    }
+    fds.and_then(|fds| fds.trim().parse().ok()).unwrap_or(0)
+}
+
+/// PID文件，退出时删除
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    pub fn create(path: &str) -> Result<Self> {
+        let path = PathBuf::from(path);
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).with_context(|| format!("无法创建目录 {}", dir.display()))?;
+        }
+        fs::write(&path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("无法写入PID文件 {}", path.display()))?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PidFile {
+    /// 新进程已接管并改写了PID文件时保留文件
+    fn drop(&mut self) {
+        let owned = fs::read_to_string(&self.path)
+            .is_ok_and(|content| content.trim() == std::process::id().to_string());
+        if owned {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_interval() {
+        assert_eq!(watchdog_interval(Some("30000000"), None, 42), Some(Duration::from_secs(15)));
+        assert_eq!(watchdog_interval(Some("30000000"), Some("42"), 42), Some(Duration::from_secs(15)));
+        assert_eq!(watchdog_interval(Some("30000000"), Some("7"), 42), None);
+        assert_eq!(watchdog_interval(Some("0"), None, 42), None);
+        assert_eq!(watchdog_interval(None, None, 42), None);
+    }
+
+    #[test]
+    fn test_listen_fds() {
+        assert_eq!(listen_fds(Some("42"), Some("1"), 42), 1);
+        assert_eq!(listen_fds(Some("7"), Some("1"), 42), 0);
+        assert_eq!(listen_fds(None, Some("1"), 42), 0);
+        assert_eq!(listen_fds(Some("42"), None, 42), 0);
+    }
+
+    #[test]
+    fn test_pid_file_kept_after_takeover() {
+        let path = std::env::temp_dir().join(format!("deepseek-pid-{}", std::process::id()));
+        let pid_file = PidFile::create(path.to_str().unwrap()).unwrap();
+        fs::write(&path, "1\n").unwrap();
+        drop(pid_file);
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+
+        drop(PidFile::create(path.to_str().unwrap()).unwrap());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::config::Config;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::create_router;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// 测试账号的邮箱，模拟上游对任意密码都登录成功
+pub const TEST_ACCOUNT_EMAIL: &str = "test@example.com";
+
+/// 在本机随机端口上运行的完整服务，上游使用内置的模拟DeepSeek，API密钥和cookie只保存在内存中
+///
+/// ```no_run
+/// # async fn example() -> deepseek_free_api::error::ApiResult<()> {
+/// let server = deepseek_free_api::TestServer::start().await?;
+/// let api_key = server.create_api_key().await?;
+/// let response = reqwest::Client::new()
+///     .post(server.url("/v1/chat/completions"))
+///     .bearer_auth(&api_key)
+///     .json(&serde_json::json!({"model": "deepseek", "messages": [{"role": "user", "content": "hi"}]}))
+///     .send()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TestServer {
+    base_url: String,
+    config: Config,
+    client: reqwest::Client,
+    shutdown: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl TestServer {
+    /// 使用默认配置启动
+    pub async fn start() -> ApiResult<Self> {
+        Self::with_config(Config::default()).await
+    }
+
+    /// 在给定配置上强制使用模拟上游、内存存储和随机端口后启动
+    pub async fn with_config(mut config: Config) -> ApiResult<Self> {
+        config.deepseek.mock = true;
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = 0;
+        config.storage.api_keys_path = String::new();
+        config.storage.cookies_path = String::new();
+
+        let shutdown = Arc::new(Notify::new());
+        let app = create_router(config.clone(), shutdown.clone()).await?;
+        let listener = tokio::net::TcpListener::bind((config.server.host.as_str(), 0)).await?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+
+        let stop = shutdown.clone();
+        let task = tokio::spawn(async move {
+            let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(async move { stop.notified().await });
+            if let Err(e) = server.await {
+                tracing::error!("Test server stopped: {}", e);
+            }
+        });
+
+        Ok(Self {
+            base_url,
+            config,
+            client: reqwest::Client::new(),
+            shutdown,
+            task,
+        })
+    }
+
+    /// 服务地址，如 http://127.0.0.1:41234
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// 实际生效的配置
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// 创建API密钥并添加一个模拟账号，返回可直接用于/v1接口的密钥
+    pub async fn create_api_key(&self) -> ApiResult<String> {
+        let created = self.admin_post("/api_keys/create", json!({ "name": "test" })).await?;
+        let api_key = created
+            .get("api_key")
+            .and_then(Value::as_str)
+            .ok_or_else(|| ApiError::InternalError(format!("创建API密钥的响应无效: {}", created)))?
+            .to_string();
+        self.admin_post(
+            "/api_keys/add_account",
+            json!({ "api_key": api_key, "email": TEST_ACCOUNT_EMAIL, "password": "test" }),
+        )
+        .await?;
+        Ok(api_key)
+    }
+
+    /// 以配置中的第一个管理令牌调用管理接口
+    pub async fn admin_post(&self, path: &str, body: Value) -> ApiResult<Value> {
+        let mut request = self.client.post(self.url(path)).json(&body);
+        if let Some(token) = self.config.access.admin_tokens.values().next() {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let body: Value = response.json().await?;
+        if !status.is_success() {
+            return Err(ApiError::InternalError(format!("{} 返回 HTTP {}: {}", path, status, body)));
+        }
+        Ok(body)
+    }
+
+    /// 停止接受新连接并等待处理中的请求结束
+    pub async fn stop(mut self) {
+        self.shutdown.notify_one();
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_completion_through_full_stack() {
+        let server = TestServer::start().await.unwrap();
+        let api_key = server.create_api_key().await.unwrap();
+
+        let response: Value = reqwest::Client::new()
+            .post(server.url("/v1/chat/completions"))
+            .bearer_auth(&api_key)
+            .json(&json!({"model": "deepseek", "messages": [{"role": "user", "content": "hello"}]}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(response["choices"][0]["message"]["content"], "Mock reply to: hello");
+
+        let accounts: Value = reqwest::get(server.url("/accounts")).await.unwrap().json().await.unwrap();
+        assert_eq!(accounts["data"][0]["email"], TEST_ACCOUNT_EMAIL);
+        server.stop().await;
+    }
+}
@@ -0,0 +1,261 @@
+use crate::config::ServerConfig;
+use anyhow::{anyhow, bail, Context, Result};
+use axum::http::{header::AUTHORIZATION, HeaderValue, Request};
+use axum_server::accept::Accept;
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use rustls::pki_types::CertificateDer;
+use rustls::server::WebPkiClientVerifier;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::{self, BufReader};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpStream;
+use tokio_rustls::server::TlsStream;
+use tower::Service;
+use x509_parser::extensions::GeneralName;
+
+/// 从PEM格式的证书链和私钥构建rustls服务端配置
+pub fn build_server_config(server: &ServerConfig) -> Result<rustls::ServerConfig> {
+    let cert_path = server.tls_cert_path.as_deref().ok_or_else(|| anyhow!("TLS_CERT_PATH is not set"))?;
+    let key_path = server.tls_key_path.as_deref().ok_or_else(|| anyhow!("TLS_KEY_PATH is not set"))?;
+
+    let cert_file = File::open(cert_path).with_context(|| format!("无法读取证书 {}", cert_path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(cert_file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("无法解析证书 {}", cert_path))?;
+    if certs.is_empty() {
+        bail!("{} 中没有证书", cert_path);
+    }
+
+    let key_file = File::open(key_path).with_context(|| format!("无法读取私钥 {}", key_path))?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(key_file))
+        .with_context(|| format!("无法解析私钥 {}", key_path))?
+        .ok_or_else(|| anyhow!("{} 中没有私钥", key_path))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    // 配置了客户端CA时要求客户端证书（mTLS）
+    let builder = match &server.tls_client_ca_path {
+        Some(ca_path) => {
+            let ca_file = File::open(ca_path).with_context(|| format!("无法读取客户端CA {}", ca_path))?;
+            let mut roots = rustls::RootCertStore::empty();
+            for ca in rustls_pemfile::certs(&mut BufReader::new(ca_file)) {
+                roots.add(ca.with_context(|| format!("无法解析客户端CA {}", ca_path))?)?;
+            }
+            if roots.is_empty() {
+                bail!("{} 中没有CA证书", ca_path);
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder
+        .with_single_cert(certs, key)
+        .context("证书与私钥不匹配")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+/// 定期检查证书文件，修改后重新加载，证书轮换时无需重启
+pub fn spawn_reload(server: ServerConfig, tls: RustlsConfig) {
+    if server.tls_reload_secs == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut last_modified = modified_at(&server);
+        let mut interval = tokio::time::interval(Duration::from_secs(server.tls_reload_secs));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let modified = modified_at(&server);
+            if modified == last_modified {
+                continue;
+            }
+
+            match build_server_config(&server) {
+                Ok(config) => {
+                    tls.reload_from_config(Arc::new(config));
+                    last_modified = modified;
+                    tracing::info!("Reloaded TLS certificate");
+                }
+                // 文件可能只写了一半，保留旧证书，下次检查时重试
+                Err(e) => tracing::warn!("Failed to reload TLS certificate, keeping the previous one: {:#}", e),
+            }
+        }
+    });
+}
+
+/// 证书、私钥和客户端CA文件的修改时间
+fn modified_at(server: &ServerConfig) -> Option<(SystemTime, SystemTime, Option<SystemTime>)> {
+    let modified = |path: &Option<String>| fs::metadata(path.as_deref()?).and_then(|meta| meta.modified()).ok();
+    Some((
+        modified(&server.tls_cert_path)?,
+        modified(&server.tls_key_path)?,
+        modified(&server.tls_client_ca_path),
+    ))
+}
+
+/// 客户端证书中的身份（CN和SAN，小写），以及按 TLS_CLIENT_IDENTITIES 映射到的API密钥
+#[derive(Debug, Clone)]
+pub struct ClientIdentity {
+    pub names: Vec<String>,
+    pub api_key: Option<String>,
+}
+
+impl ClientIdentity {
+    fn from_certificate(cert: &CertificateDer<'_>, identities: &BTreeMap<String, String>) -> Self {
+        let names = certificate_names(cert);
+        let api_key = names.iter().find_map(|name| identities.get(name)).cloned();
+        Self { names, api_key }
+    }
+}
+
+/// 提取证书的CN以及DNS、邮箱和URI类型的SAN
+fn certificate_names(cert: &[u8]) -> Vec<String> {
+    let Ok((_, cert)) = x509_parser::parse_x509_certificate(cert) else {
+        return Vec::new();
+    };
+
+    let mut names: Vec<String> = cert
+        .subject()
+        .iter_common_name()
+        .filter_map(|cn| cn.as_str().ok())
+        .map(str::to_lowercase)
+        .collect();
+    if let Ok(Some(san)) = cert.subject_alternative_name() {
+        for name in &san.value.general_names {
+            match name {
+                GeneralName::DNSName(name) | GeneralName::RFC822Name(name) | GeneralName::URI(name) => {
+                    names.push(name.to_lowercase())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    names
+}
+
+/// 在TLS握手后读取客户端证书，把身份附加到该连接的每个请求上
+#[derive(Clone)]
+pub struct ClientCertAcceptor {
+    inner: RustlsAcceptor,
+    identities: Arc<BTreeMap<String, String>>,
+}
+
+impl ClientCertAcceptor {
+    pub fn new(config: RustlsConfig, identities: BTreeMap<String, String>) -> Self {
+        Self {
+            inner: RustlsAcceptor::new(config),
+            identities: Arc::new(identities),
+        }
+    }
+}
+
+impl<S: Send + 'static> Accept<TcpStream, S> for ClientCertAcceptor {
+    type Stream = TlsStream<TcpStream>;
+    type Service = ClientIdentityService<S>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: TcpStream, service: S) -> Self::Future {
+        let acceptor = self.inner.clone();
+        let identities = self.identities.clone();
+
+        Box::pin(async move {
+            let (stream, service) = acceptor.accept(stream, service).await?;
+            let identity = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .map(|cert| ClientIdentity::from_certificate(cert, &identities));
+            if let Some(identity) = &identity {
+                tracing::debug!(names = ?identity.names, mapped = identity.api_key.is_some(), "Client certificate accepted");
+            }
+
+            Ok((stream, ClientIdentityService { inner: service, identity }))
+        })
+    }
+}
+
+/// 请求未携带Authorization时，使用客户端证书映射的API密钥
+#[derive(Clone)]
+pub struct ClientIdentityService<S> {
+    inner: S,
+    identity: Option<ClientIdentity>,
+}
+
+impl<S, B> Service<Request<B>> for ClientIdentityService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        if let Some(identity) = &self.identity {
+            let bearer = identity
+                .api_key
+                .as_ref()
+                .and_then(|key| HeaderValue::from_str(&format!("Bearer {}", key)).ok());
+            if let Some(bearer) = bearer {
+                if !request.headers().contains_key(AUTHORIZATION) {
+                    request.headers_mut().insert(AUTHORIZATION, bearer);
+                }
+            }
+            request.extensions_mut().insert(identity.clone());
+        }
+        self.inner.call(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // openssl req -x509 -subj "/CN=Billing-Service" -addext "subjectAltName=DNS:billing.internal,email:ops@example.com"
+    const CLIENT_CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBujCCAWCgAwIBAgIUBiCaydBiBc+i7Ee53U6976FVMeowCgYIKoZIzj0EAwIw
+GjEYMBYGA1UEAwwPQmlsbGluZy1TZXJ2aWNlMCAXDTI2MTAxNjE1NTUwMloYDzIx
+MjYwOTIyMTU1NTAyWjAaMRgwFgYDVQQDDA9CaWxsaW5nLVNlcnZpY2UwWTATBgcq
+hkjOPQIBBggqhkjOPQMBBwNCAASnXGhVqWy2GHbRWPsPHcxCcTHLe5qGyTwoF8Nr
+oGhWeJZPhjVT0PFVBPM7e+U/acKL979jKwbjesGvVjzpBFtYo4GBMH8wHQYDVR0O
+BBYEFNOew+T5yncwROKjEcNUR6XGT0qSMB8GA1UdIwQYMBaAFNOew+T5yncwROKj
+EcNUR6XGT0qSMA8GA1UdEwEB/wQFMAMBAf8wLAYDVR0RBCUwI4IQYmlsbGluZy5p
+bnRlcm5hbIEPb3BzQGV4YW1wbGUuY29tMAoGCCqGSM49BAMCA0gAMEUCIQDyZgWm
+MGUHZ/k/uV83kPP4LoWa6bC4YdbzSUEE6008zAIgWdRZ2cDj4/2CqZg11OFKT0Oq
+lNfSRMiyWkQi9nSMjEQ=
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn test_client_identity_from_cn_and_san() {
+        let cert = rustls_pemfile::certs(&mut CLIENT_CERT.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+        let identities = BTreeMap::from([("billing.internal".to_string(), "dsk-billing".to_string())]);
+
+        let identity = ClientIdentity::from_certificate(&cert, &identities);
+        assert_eq!(identity.names, vec!["billing-service", "billing.internal", "ops@example.com"]);
+        assert_eq!(identity.api_key.as_deref(), Some("dsk-billing"));
+
+        let identity = ClientIdentity::from_certificate(&cert, &BTreeMap::new());
+        assert_eq!(identity.api_key, None);
+    }
+}
@@ -1,5 +1,9 @@
+use base64::{engine::general_purpose, Engine as _};
 use chrono::{DateTime, Utc};
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use rand::{distributions::Alphanumeric, thread_rng, Rng, RngCore};
+#[cfg(any(test, feature = "deterministic-ids"))]
+use rand::{rngs::StdRng, SeedableRng};
+use sha2::{Digest, Sha256};
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
@@ -19,9 +23,52 @@ pub fn unix_timestamp_ms() -> u64 {
         .as_millis() as u64
 }
 
+/// 设置种子后随机标识（UUID、cookie、设备ID、账号选择）按种子确定性生成，None时使用线程随机源。
+/// 只在测试或启用deterministic-ids特性时编译，正式构建不经过全局锁
+#[cfg(any(test, feature = "deterministic-ids"))]
+static IDENTIFIER_RNG: parking_lot::Mutex<Option<StdRng>> = parking_lot::Mutex::new(None);
+
+/// 固定生成随机标识的种子，用于对请求头、cookie和响应ID做快照测试；seed为0时恢复系统随机源
+#[cfg(any(test, feature = "deterministic-ids"))]
+pub fn seed_identifiers(seed: u64) {
+    *IDENTIFIER_RNG.lock() = (seed != 0).then(|| StdRng::seed_from_u64(seed));
+}
+
+/// 用生成标识的随机源执行
+#[cfg(any(test, feature = "deterministic-ids"))]
+fn with_rng<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+    match IDENTIFIER_RNG.lock().as_mut() {
+        Some(rng) => f(rng),
+        None => f(&mut thread_rng()),
+    }
+}
+
+#[cfg(not(any(test, feature = "deterministic-ids")))]
+fn with_rng<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+    f(&mut thread_rng())
+}
+
+/// 生成API密钥等凭据使用的随机串，始终使用系统随机源，不受IDENTIFIER_SEED影响
+pub fn generate_secret_token() -> String {
+    random_uuid(&mut thread_rng()).simple().to_string()
+}
+
+/// 用随机字节填充
+pub fn fill_random(bytes: &mut [u8]) {
+    with_rng(|rng| rng.fill_bytes(bytes));
+}
+
+/// 随机选择下标，len不能为0
+pub fn random_index(len: usize) -> usize {
+    with_rng(|rng| rng.gen_range(0..len))
+}
+
 /// 生成随机字符串
 pub fn generate_random_string(length: usize, charset: &str) -> String {
-    let mut rng = thread_rng();
+    with_rng(|rng| random_string(rng, length, charset))
+}
+
+fn random_string(rng: &mut dyn RngCore, length: usize, charset: &str) -> String {
     match charset {
         "hex" => {
             let chars: Vec<char> = "0123456789abcdef".chars().collect();
@@ -46,38 +93,18 @@ pub fn generate_random_string(length: usize, charset: &str) -> String {
 
 /// 生成UUID
 pub fn generate_uuid() -> String {
-    Uuid::new_v4().to_string()
+    with_rng(random_uuid).to_string()
 }
 
 /// 生成UUID（不带连字符）
 pub fn generate_uuid_simple() -> String {
-    Uuid::new_v4().simple().to_string()
-}
-
-/// 生成Cookie字符串
-pub fn generate_cookie() -> String {
-    let timestamp = unix_timestamp_ms();
-    let uuid1 = generate_uuid_simple();
-    let uuid2 = generate_uuid_simple();
-    let uuid3 = generate_uuid_simple();
-    let uuid4 = generate_uuid_simple();
-    let session_id = generate_random_string(18, "hex");
-    let unix_ts = unix_timestamp();
-    
-    format!(
-        "intercom-HWWAFSESTIME={}; HWWAFSESID={}; Hm_lvt_{}={},{},{}; Hm_lpvt_{}={}; _frid={}; _fr_ssid={}; _fr_pvid={}",
-        timestamp,
-        session_id,
-        uuid1,
-        unix_ts,
-        unix_ts,
-        unix_ts,
-        uuid2,
-        unix_ts,
-        uuid3,
-        uuid4,
-        generate_uuid_simple()
-    )
+    with_rng(random_uuid).simple().to_string()
+}
+
+fn random_uuid(rng: &mut dyn RngCore) -> Uuid {
+    let mut bytes = [0u8; 16];
+    rng.fill_bytes(&mut bytes);
+    uuid::Builder::from_random_bytes(bytes).into_uuid()
 }
 
 /// 分割Token字符串
@@ -95,9 +122,7 @@ pub fn select_random_token(tokens: &[String]) -> Option<&String> {
     if tokens.is_empty() {
         return None;
     }
-    let mut rng = thread_rng();
-    let index = rng.gen_range(0..tokens.len());
-    Some(&tokens[index])
+    Some(&tokens[random_index(tokens.len())])
 }
 
 /// 解析对话ID
@@ -112,6 +137,17 @@ pub fn parse_conversation_id(conv_id: &str) -> Option<(String, String)> {
     }
 }
 
+/// 计算一致性哈希（rendezvous）权重，同一key总是优先选择权重最高的节点。
+/// 使用SHA-256而不是DefaultHasher，映射不随Rust版本和重启变化
+pub fn rendezvous_score(key: &str, node: &str) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(key.as_bytes())
+        .chain_update([0])
+        .chain_update(node.as_bytes())
+        .finalize();
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
+}
+
 /// 检查模型类型
 pub fn is_search_model(model: &str) -> bool {
     model.contains("search")
@@ -129,6 +165,38 @@ pub fn is_fold_model(model: &str) -> bool {
     model.contains("fold")
 }
 
+/// 解析 `data:image/png;base64,...` 形式的data URI，返回MIME类型和解码后的内容
+pub fn parse_data_uri(url: &str) -> Option<(String, Vec<u8>)> {
+    let (meta, data) = url.strip_prefix("data:")?.split_once(',')?;
+    let mime = meta.strip_suffix(";base64")?;
+    let bytes = general_purpose::STANDARD.decode(data.trim()).ok()?;
+    Some((if mime.is_empty() { "application/octet-stream" } else { mime }.to_string(), bytes))
+}
+
+/// 粗略估算token数：中日韩字符按1个token，其他字符按4个字符1个token
+pub fn estimate_tokens(text: &str) -> usize {
+    let (cjk, other) = text.chars().fold((0usize, 0usize), |(cjk, other), c| {
+        if matches!(c as u32, 0x3000..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF | 0xFF00..=0xFFEF) {
+            (cjk + 1, other)
+        } else {
+            (cjk, other + 1)
+        }
+    });
+    cjk + other.div_ceil(4)
+}
+
+/// 从DeepSeek网页或脚本中提取X-App-Version（形如20241129.1）
+pub fn extract_app_version(text: &str) -> Option<String> {
+    let regex = regex::Regex::new(r#"(?i)["']?(?:x-)?app[-_]?version["']?\s*[:=]\s*["'](\d{8}\.\d+)["']"#).unwrap();
+    regex.captures(text).map(|captures| captures[1].to_string())
+}
+
+/// 提取HTML中同源脚本的路径
+pub fn extract_script_paths(html: &str) -> Vec<String> {
+    let regex = regex::Regex::new(r#"<script[^>]+src=["'](/[^"'/][^"']*)["']"#).unwrap();
+    regex.captures_iter(html).map(|captures| captures[1].to_string()).collect()
+}
+
 /// 格式化时间
 pub fn format_timestamp(timestamp: u64) -> String {
     let datetime = DateTime::from_timestamp(timestamp as i64, 0).unwrap_or_else(|| Utc::now());
@@ -139,6 +207,17 @@ pub fn format_timestamp(timestamp: u64) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_seeded_identifiers_are_reproducible() {
+        let generate = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (random_uuid(&mut rng), random_string(&mut rng, 12, "alphanumeric"))
+        };
+        assert_eq!(generate(42), generate(42));
+        assert_ne!(generate(42), generate(43));
+        assert_eq!(generate(42).0.get_version_num(), 4);
+    }
+
     #[test]
     fn test_generate_random_string() {
         let hex_str = generate_random_string(16, "hex");
@@ -146,6 +225,35 @@ mod tests {
         assert!(hex_str.chars().all(|c| "0123456789abcdef".contains(c)));
     }
 
+    #[test]
+    fn test_parse_data_uri() {
+        let (mime, bytes) = parse_data_uri("data:image/png;base64,aGVsbG8=").unwrap();
+        assert_eq!(mime, "image/png");
+        assert_eq!(bytes, b"hello");
+        assert!(parse_data_uri("https://example.com/a.png").is_none());
+        assert!(parse_data_uri("data:text/plain,hello").is_none());
+    }
+
+    #[test]
+    fn test_extract_app_version() {
+        assert_eq!(
+            extract_app_version(r#"headers:{"x-app-version":"20250317.2"}"#).as_deref(),
+            Some("20250317.2")
+        );
+        assert_eq!(extract_app_version("appVersion='20241129.1'").as_deref(), Some("20241129.1"));
+        assert!(extract_app_version("version: 1.0.0").is_none());
+
+        let html = r#"<script src="/static/main.js"></script><script src="https://cdn.example.com/x.js"></script>"#;
+        assert_eq!(extract_script_paths(html), vec!["/static/main.js"]);
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("hello world!"), 3);
+        assert_eq!(estimate_tokens("你好abcd"), 3);
+    }
+
     #[test]
     fn test_split_tokens() {
         let auth = "Bearer token1,token2,token3";
@@ -164,6 +272,26 @@ mod tests {
         assert_eq!(parent_id, "123");
     }
 
+    #[test]
+    fn test_rendezvous_score_is_stable() {
+        fn pick<'a>(key: &str, nodes: &[&'a str]) -> &'a str {
+            nodes.iter().copied().max_by_key(|node| rendezvous_score(key, node)).unwrap()
+        }
+
+        // 权重与Rust版本无关，升级后已有的用户到账号映射不变
+        assert_eq!(rendezvous_score("user-1", "a@example.com"), 9705134069328986421);
+
+        let nodes = ["a@example.com", "b@example.com", "c@example.com"];
+        let chosen = pick("user-1", &nodes);
+        assert_eq!(chosen, pick("user-1", &nodes));
+
+        // 移除未被选中的节点不影响已有映射
+        let remaining: Vec<&str> = nodes.iter().copied()
+            .filter(|node| *node == chosen || node.starts_with('a'))
+            .collect();
+        assert_eq!(chosen, pick("user-1", &remaining));
+    }
+
     #[test]
     fn test_model_checks() {
         assert!(is_search_model("deepseek-search"));